@@ -0,0 +1,23 @@
+//! 生成した AST を書き戻したパターンをコンパイルする
+//!
+//! コンパイルできた場合は、書き出したコンパイル結果を読み込めることも確かめる
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use regexp::{AST, Flags, Regex, RegexBuilder, parse};
+
+fuzz_target!(|ast: AST| {
+    let pattern = ast.to_string();
+    // 空のパターンはパースできない
+    if pattern.is_empty() {
+        return;
+    }
+    let reparsed = parse(&pattern, Flags::default()).expect("printed pattern must parse");
+    assert_eq!(reparsed.to_string(), pattern);
+
+    let Ok(regex) = RegexBuilder::new(&pattern).size_limit(1 << 16).build() else {
+        return;
+    };
+    let bytes = regex.to_bytes();
+    let loaded = Regex::from_bytes(&bytes).expect("serialized regex must load");
+    assert_eq!(loaded.to_bytes(), bytes);
+});
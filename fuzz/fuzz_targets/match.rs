@@ -0,0 +1,118 @@
+//! 生成した AST と検索対象の文字列で、regex クレートと検索結果を比べる
+//!
+//! AST を regex クレートの構文に書き直し、マッチの範囲と捕獲グループの範囲が一致することを確かめる
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use regexp::{AST, AstNode, Look, NodeId, RegexBuilder};
+use std::fmt::Write;
+
+fuzz_target!(|input: (AST, String)| {
+    let (ast, haystack) = input;
+    let Ok(ours) = RegexBuilder::new(&ast.to_string())
+        .size_limit(1 << 16)
+        .build()
+    else {
+        return;
+    };
+    let mut pattern = String::new();
+    translate(&ast, ast.root(), &mut pattern);
+    let Ok(theirs) = regex::RegexBuilder::new(&pattern)
+        .size_limit(1 << 24)
+        .build()
+    else {
+        return;
+    };
+
+    let found: Vec<_> = ours
+        .find_iter(&haystack)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    let expected: Vec<_> = theirs
+        .find_iter(&haystack)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    assert_eq!(found, expected, "pattern = {pattern:?}");
+
+    // regex クレートは {0} で繰り返すグループを数えない場合があるため、番号を指定して比べる
+    let groups = 0..ours.captures_len();
+    let found: Vec<Vec<_>> = ours
+        .captures_iter(&haystack)
+        .map(|caps| {
+            groups
+                .clone()
+                .map(|i| caps.get(i).map(|m| (m.start(), m.end())))
+                .collect()
+        })
+        .collect();
+    let expected: Vec<Vec<_>> = theirs
+        .captures_iter(&haystack)
+        .map(|caps| {
+            groups
+                .clone()
+                .map(|i| caps.get(i).map(|m| (m.start(), m.end())))
+                .collect()
+        })
+        .collect();
+    assert_eq!(found, expected, "pattern = {pattern:?}");
+});
+
+/// ノードを regex クレートの構文で書く。限量子の対象は常に `(?:...)` で囲む
+fn translate(ast: &AST, id: NodeId, out: &mut String) {
+    match &ast[id] {
+        AstNode::Char(c) => out.push_str(&regex::escape(&c.to_string())),
+        AstNode::Class(class) => {
+            if class.ranges().is_empty() {
+                out.push_str(r"[^\x{0}-\x{10FFFF}]");
+                return;
+            }
+            out.push('[');
+            for &(lo, hi) in class.ranges() {
+                let _ = write!(out, r"\x{{{:x}}}-\x{{{:x}}}", lo as u32, hi as u32);
+            }
+            out.push(']');
+        }
+        AstNode::Look(look) => out.push_str(match look {
+            Look::Start => r"\A",
+            Look::End => r"\z",
+            Look::StartLine => "(?m:^)",
+            Look::EndLine => "(?m:$)",
+            Look::WordBoundary(false) => r"\b",
+            Look::WordBoundary(true) => r"(?-u:\b)",
+            Look::NotWordBoundary(false) => r"\B",
+            Look::NotWordBoundary(true) => r"(?-u:\B)",
+        }),
+        AstNode::Plus(e) => quantified(ast, *e, "+", out),
+        AstNode::Star(e) => quantified(ast, *e, "*", out),
+        AstNode::Question(e) => quantified(ast, *e, "?", out),
+        AstNode::Repeat(e, min, Some(max)) => quantified(ast, *e, &format!("{{{min},{max}}}"), out),
+        AstNode::Repeat(e, min, None) => quantified(ast, *e, &format!("{{{min},}}"), out),
+        AstNode::Group(e, _) => {
+            out.push('(');
+            translate(ast, *e, out);
+            out.push(')');
+        }
+        AstNode::Or(e1, e2) => {
+            out.push_str("(?:");
+            translate(ast, *e1, out);
+            out.push('|');
+            translate(ast, *e2, out);
+            out.push(')');
+        }
+        AstNode::Seq(v) => {
+            out.push_str("(?:");
+            for &e in v {
+                translate(ast, e, out);
+            }
+            out.push(')');
+        }
+        _ => unreachable!("unknown node"),
+    }
+}
+
+/// 限量子の対象を `(?:...)` で囲んで書く
+fn quantified(ast: &AST, id: NodeId, op: &str, out: &mut String) {
+    out.push_str("(?:");
+    translate(ast, id, out);
+    out.push(')');
+    out.push_str(op);
+}
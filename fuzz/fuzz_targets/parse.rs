@@ -0,0 +1,19 @@
+//! 任意の文字列をパースする
+//!
+//! パースできた場合は、AST を書き戻したパターンもパースでき、元と等しい AST になることを確かめる
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use regexp::{Flags, parse};
+
+fuzz_target!(|pattern: &str| {
+    let Ok(ast) = parse(pattern, Flags::default()) else {
+        return;
+    };
+    // 書き戻す処理は再帰呼び出しを使うため、深すぎる AST は読み飛ばす
+    if ast.stats().depth > 64 {
+        return;
+    }
+    let printed = ast.to_string();
+    let reparsed = parse(&printed, Flags::default()).expect("printed pattern must parse");
+    assert_eq!(reparsed, ast, "{pattern:?} -> {printed:?}");
+});
@@ -0,0 +1,147 @@
+//! ビルドスクリプトで名前付きのパターンをコンパイルし、静的変数を定義するモジュールを生成する
+//!
+//! パターンはビルド時にコンパイルして検査するため、誤ったパターンはビルドエラーになる。
+//! 生成したモジュールでは、各パターンを [`regexp::LazyRegex`] の静的変数として宣言する。
+//! 静的変数には [`Regex::to_bytes`] で書き出したコンパイル結果を埋め込み、初回の利用時に
+//! [`Regex::from_bytes`] で読み込むため、実行時に命令列や DFA を生成し直さない。
+//! 書き出す形式は版ごとに異なるため、ビルドスクリプトとアプリケーションでは同じ版の regexp を使う
+//!
+//! パターンのファイルには、1行に1つずつ `名前 = パターン` の形で書く。
+//! 空行と `#` で始まる行は読み飛ばす
+//!
+//! ```text
+//! # patterns.txt
+//! DATE = \d{4}-\d{2}-\d{2}
+//! WORD = \w+
+//! ```
+//!
+//! `build.rs` で次のように呼び出すと、`$OUT_DIR/patterns.rs` が生成される
+//!
+//! ```text
+//! fn main() {
+//!     regexp_build::Builder::new()
+//!         .file("patterns.txt")
+//!         .unwrap()
+//!         .compile("patterns.rs")
+//!         .unwrap();
+//! }
+//! ```
+//!
+//! 生成したモジュールは `include!(concat!(env!("OUT_DIR"), "/patterns.rs"));` で取り込む
+use regexp::{Regex, is_rust_ident};
+use std::{
+    error::Error,
+    fmt::{self, Display, Write},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// モジュールの生成に失敗したことを表すエラー
+#[derive(Debug)]
+pub enum BuildError {
+    Io(PathBuf, io::Error),         // ファイルの読み書きに失敗した
+    Syntax(PathBuf, usize),         // パターンのファイルの行が `名前 = パターン` の形でない
+    InvalidName(String),            // 名前が Rust の識別子でないか、キーワード
+    DuplicateName(String),          // 同じ名前のパターンが複数ある
+    Pattern(String, regexp::Error), // パターンのコンパイルに失敗した
+    NoOutDir,                       // 環境変数 OUT_DIR が設定されていない
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Io(path, e) => write!(f, "BuildError: {}: {e}", path.display()),
+            BuildError::Syntax(path, line) => {
+                write!(
+                    f,
+                    "BuildError: expected `NAME = PATTERN`: {}:{line}",
+                    path.display()
+                )
+            }
+            BuildError::InvalidName(name) => write!(f, "BuildError: invalid name: {name}"),
+            BuildError::DuplicateName(name) => write!(f, "BuildError: duplicate name: {name}"),
+            BuildError::Pattern(name, e) => write!(f, "BuildError: {name}: {e}"),
+            BuildError::NoOutDir => write!(f, "BuildError: OUT_DIR is not set"),
+        }
+    }
+}
+
+impl Error for BuildError {} // エラー用に Error トレイトを実装
+
+/// 名前付きのパターンを集め、モジュールを生成するビルダー
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    patterns: Vec<(String, String)>, // (名前, パターン)
+    files: Vec<PathBuf>,             // パターンを読み込んだファイル
+}
+
+impl Builder {
+    /// 空のビルダーを生成する
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    /// パターンを追加する。`name` は生成する静的変数の名前になる
+    pub fn pattern(&mut self, name: &str, pattern: &str) -> &mut Builder {
+        self.patterns.push((name.to_string(), pattern.to_string()));
+        self
+    }
+
+    /// パターンのファイルを読み込み、すべてのパターンを追加する
+    ///
+    /// 名前と `=` の前後の空白は取り除くが、パターンの末尾の空白は残す
+    pub fn file(&mut self, path: impl AsRef<Path>) -> Result<&mut Builder, BuildError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| BuildError::Io(path.to_path_buf(), e))?;
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let Some((name, pattern)) = line.split_once('=') else {
+                return Err(BuildError::Syntax(path.to_path_buf(), i + 1));
+            };
+            self.pattern(name.trim(), pattern.trim_start());
+        }
+        self.files.push(path.to_path_buf());
+        Ok(self)
+    }
+
+    /// すべてのパターンをコンパイルして検査し、モジュールのソースを返す
+    pub fn generate(&self) -> Result<String, BuildError> {
+        let mut out = String::from("// regexp-build で生成したファイル。直接編集しないこと\n");
+        let mut names: Vec<&str> = Vec::new();
+        for (name, pattern) in &self.patterns {
+            if !is_rust_ident(name) {
+                return Err(BuildError::InvalidName(name.clone()));
+            }
+            if names.contains(&name.as_str()) {
+                return Err(BuildError::DuplicateName(name.clone()));
+            }
+            names.push(name);
+            let regex = Regex::new(pattern).map_err(|e| BuildError::Pattern(name.clone(), e))?;
+
+            // Debug での出力は改行をエスケープするため、そのままコメントに書ける
+            let _ = writeln!(out, "// {name} = {pattern:?}");
+            let _ = writeln!(
+                out,
+                "pub static {name}: regexp::LazyRegex = regexp::LazyRegex::from_bytes(b\"{}\");",
+                regex.to_bytes().escape_ascii()
+            );
+        }
+        Ok(out)
+    }
+
+    /// モジュールを生成し、`$OUT_DIR/file_name` に書き込む
+    ///
+    /// 読み込んだパターンのファイルが変更された場合に再びビルドするよう、Cargo に伝える
+    pub fn compile(&self, file_name: &str) -> Result<(), BuildError> {
+        let out_dir = std::env::var_os("OUT_DIR").ok_or(BuildError::NoOutDir)?;
+        let source = self.generate()?;
+        let path = Path::new(&out_dir).join(file_name);
+        fs::write(&path, source).map_err(|e| BuildError::Io(path, e))?;
+        for file in &self.files {
+            println!("cargo:rerun-if-changed={}", file.display());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+//! 生成したモジュールのソースと、パターンや名前の検査を確かめる
+//!
+//! ソースに埋め込んだバイト列を読み戻し、元のパターンと同じようにマッチすることを確かめる
+use regexp::{LazyRegex, Regex};
+use regexp_build::{BuildError, Builder};
+use std::{env, fs, process};
+
+/// 生成したソースから、各静的変数の名前と埋め込んだバイト列を取り出す
+fn statics(source: &str) -> Vec<(String, Vec<u8>)> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let rest = line.strip_prefix("pub static ")?;
+            let (name, rest) = rest.split_once(':')?;
+            let (_, escaped) = rest.split_once("from_bytes(b\"")?;
+            let escaped = escaped.strip_suffix("\");")?;
+            Some((name.to_string(), unescape(escaped)))
+        })
+        .collect()
+}
+
+/// `escape_ascii` でエスケープしたバイト列を元に戻す
+fn unescape(s: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        out.push(match bytes.next().unwrap() {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'x' => {
+                let hex = [bytes.next().unwrap(), bytes.next().unwrap()];
+                u8::from_str_radix(std::str::from_utf8(&hex).unwrap(), 16).unwrap()
+            }
+            b => b, // \\ と \' と \"
+        });
+    }
+    out
+}
+
+#[test]
+fn generated_bytes_load_and_match() {
+    let source = Builder::new()
+        .pattern("DATE", r"\d{4}-\d{2}-\d{2}")
+        .pattern("WORD", r"\w+")
+        .pattern("QUOTED", "\"[^\"\n]*\"")
+        .generate()
+        .unwrap();
+    let statics = statics(&source);
+    let names: Vec<&str> = statics.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["DATE", "WORD", "QUOTED"]);
+    assert!(source.contains(r#"// QUOTED = "\"[^\"\n]*\"""#));
+
+    let haystack = "on 2024-01-31, \"日本語\" said";
+    for (name, bytes) in statics {
+        let loaded = Regex::from_bytes(&bytes).unwrap();
+        let pattern = match name.as_str() {
+            "DATE" => r"\d{4}-\d{2}-\d{2}",
+            "WORD" => r"\w+",
+            _ => "\"[^\"\n]*\"",
+        };
+        assert_eq!(loaded.as_str(), pattern);
+        let expected: Vec<_> = Regex::new(pattern)
+            .unwrap()
+            .find_iter(haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        let found: Vec<_> = loaded
+            .find_iter(haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        assert_eq!(found, expected, "{name}");
+        assert!(!found.is_empty(), "{name}");
+
+        // 生成したソースと同じく、静的変数として読み込む
+        let lazy = LazyRegex::from_bytes(bytes.leak());
+        assert_eq!(
+            lazy.find(haystack).map(|m| (m.start(), m.end())),
+            expected.first().cloned()
+        );
+    }
+}
+
+#[test]
+fn file() {
+    let dir = env::temp_dir().join(format!("regexp-build-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("patterns.txt");
+    fs::write(&path, "# comment\n\nDIGITS = [0-9]+\n  SPACE=  a b \n").unwrap();
+    let source = Builder::new().file(&path).unwrap().generate().unwrap();
+    assert!(source.contains("// DIGITS = \"[0-9]+\"\n"));
+    // パターンの末尾の空白は残す
+    assert!(source.contains("// SPACE = \"a b \"\n"));
+
+    fs::write(&path, "DIGITS [0-9]+\n").unwrap();
+    let mut builder = Builder::new();
+    assert!(matches!(builder.file(&path), Err(BuildError::Syntax(_, 1))));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_name() {
+    for name in ["fn", "self", "_", "1x", "a-b", "", "a b"] {
+        let result = Builder::new().pattern(name, "a").generate();
+        assert!(
+            matches!(&result, Err(BuildError::InvalidName(n)) if n == name),
+            "{name:?}: {result:?}"
+        );
+    }
+    for name in ["a", "_a", "A1", "名前", "fn_"] {
+        let result = Builder::new().pattern(name, "a").generate();
+        assert!(result.is_ok(), "{name:?}: {result:?}");
+    }
+}
+
+#[test]
+fn duplicate_name_and_bad_pattern() {
+    let result = Builder::new()
+        .pattern("A", "a")
+        .pattern("A", "b")
+        .generate();
+    assert!(matches!(result, Err(BuildError::DuplicateName(n)) if n == "A"));
+
+    let result = Builder::new().pattern("A", "(").generate();
+    assert!(matches!(result, Err(BuildError::Pattern(n, _)) if n == "A"));
+}
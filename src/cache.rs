@@ -0,0 +1,196 @@
+//! コンパイル済みのパターンをプロセス全体で共有するキャッシュ
+//!
+//! [`find`] や [`is_match`] のようにパターン文字列を直接受け取る関数と
+//! [`RegexBuilder::build_cached`] は、呼び出しのたびにパターンをコンパイルする。
+//! [`set_cache_capacity`] で容量を設定するとキャッシュが有効になり、
+//! 最近使われたパターンのコンパイル結果を再利用する。
+//! キャッシュはパターンと設定の組で引くため、設定の異なる同じパターンは別に保持する
+use crate::{
+    error::Error,
+    regex::{Config, Match, Regex, RegexBuilder},
+};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+/// キャッシュの統計情報
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,       // キャッシュにあったパターンの数
+    pub misses: u64,     // コンパイルが必要だったパターンの数
+    pub evictions: u64,  // 容量を超えたために捨てたパターンの数
+    pub len: usize,      // 現在キャッシュにあるパターンの数
+    pub capacity: usize, // キャッシュの容量。0 の場合は無効
+}
+
+/// キャッシュを引くキー。パターンと設定の組
+type Key = (String, Config);
+
+/// 最近使われた順にパターンを保持するキャッシュ
+///
+/// 項目を最近使われた順に双方向リストでつなぎ、使われた項目を先頭に移して末尾から捨てる。
+/// どの操作も、保持するパターンの数によらない時間で済む
+#[derive(Debug)]
+struct LruCache {
+    index: HashMap<Key, usize>,       // キーから項目の番号
+    slots: Vec<Option<(Key, Regex)>>, // 項目。0 番はリストの両端を表す番兵で、常に None
+    links: Vec<(usize, usize)>,       // 各項目の (前, 次) の番号。0 番の次が最も最近使われた項目
+    free: Vec<usize>,                 // 捨てた項目の、再利用できる番号
+    stats: CacheStats,
+}
+
+static CACHE: Mutex<Option<LruCache>> = Mutex::new(None);
+
+/// キャッシュをロックする
+fn lock() -> MutexGuard<'static, Option<LruCache>> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// キャッシュの容量を設定する
+///
+/// 0 を指定するとキャッシュを無効にする。
+/// 容量を減らした場合は、使われていない順にパターンを捨てる
+pub fn set_cache_capacity(capacity: usize) {
+    let mut guard = lock();
+    let cache = guard.get_or_insert_with(LruCache::new);
+    cache.stats.capacity = capacity;
+    cache.shrink();
+}
+
+/// キャッシュの統計情報を返す
+pub fn cache_stats() -> CacheStats {
+    lock().as_ref().map(|cache| cache.stats).unwrap_or_default()
+}
+
+/// キャッシュにあるパターンをすべて捨て、統計情報をリセットする。容量は変更しない
+pub fn clear_cache() {
+    if let Some(cache) = lock().as_mut() {
+        let capacity = cache.stats.capacity;
+        *cache = LruCache::new();
+        cache.stats.capacity = capacity;
+    }
+}
+
+/// `pattern` が `haystack` のどこかにマッチするかを判定する
+///
+/// キャッシュが有効な場合は、コンパイル結果を再利用する
+pub fn is_match(pattern: &str, haystack: &str) -> Result<bool, Error> {
+    Ok(cached(pattern, Config::default())?.is_match(haystack))
+}
+
+/// `haystack` 中で `pattern` に最も左でマッチする範囲を返す
+///
+/// キャッシュが有効な場合は、コンパイル結果を再利用する
+pub fn find<'h>(pattern: &str, haystack: &'h str) -> Result<Option<Match<'h>>, Error> {
+    Ok(cached(pattern, Config::default())?.find(haystack))
+}
+
+impl RegexBuilder {
+    /// [`RegexBuilder::build`] と同様にコンパイルする。キャッシュが有効な場合は、
+    /// 同じパターンと設定のコンパイル結果をキャッシュから取り出して再利用する
+    ///
+    /// 失敗した場合のエラーは [`RegexBuilder::build`] と同じ。失敗した結果はキャッシュしない
+    pub fn build_cached(&self) -> Result<Regex, Error> {
+        cached(&self.pattern, self.config.clone())
+    }
+}
+
+/// キャッシュからコンパイル結果を取り出す。ない場合はコンパイルしてキャッシュに追加する
+fn cached(pattern: &str, config: Config) -> Result<Regex, Error> {
+    let key = (pattern.to_string(), config);
+    {
+        let mut guard = lock();
+        let Some(cache) = guard.as_mut().filter(|cache| cache.stats.capacity > 0) else {
+            drop(guard);
+            return Regex::with_config(&key.0, key.1);
+        };
+        if let Some(regex) = cache.get(&key) {
+            cache.stats.hits += 1;
+            return Ok(regex);
+        }
+        cache.stats.misses += 1;
+    }
+
+    // コンパイル中は他のスレッドを待たせないよう、ロックを外しておく
+    let regex = Regex::with_config(&key.0, key.1.clone())?;
+
+    if let Some(cache) = lock().as_mut().filter(|cache| cache.stats.capacity > 0) {
+        cache.insert(key, regex.clone());
+        cache.shrink();
+    }
+    Ok(regex)
+}
+
+impl LruCache {
+    /// 空のキャッシュを生成する。容量は 0
+    fn new() -> LruCache {
+        LruCache {
+            index: HashMap::new(),
+            slots: vec![None],
+            links: vec![(0, 0)],
+            free: Vec::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// `key` のコンパイル結果を取り出し、最も最近使われた項目にする
+    fn get(&mut self, key: &Key) -> Option<Regex> {
+        let i = *self.index.get(key)?;
+        self.unlink(i);
+        self.push_front(i);
+        self.slots[i].as_ref().map(|(_, regex)| regex.clone())
+    }
+
+    /// コンパイル結果を、最も最近使われた項目として加える
+    ///
+    /// 他のスレッドが先に同じキーを加えていた場合は、その項目を置き換える
+    fn insert(&mut self, key: Key, regex: Regex) {
+        let i = match self.index.get(&key) {
+            Some(&i) => {
+                self.unlink(i);
+                i
+            }
+            None => {
+                let i = self.free.pop().unwrap_or_else(|| {
+                    self.slots.push(None);
+                    self.links.push((0, 0));
+                    self.slots.len() - 1
+                });
+                self.index.insert(key.clone(), i);
+                i
+            }
+        };
+        self.slots[i] = Some((key, regex));
+        self.push_front(i);
+    }
+
+    /// 容量を超えている間、最も長く使われていないパターンを捨てる
+    fn shrink(&mut self) {
+        while self.index.len() > self.stats.capacity {
+            let oldest = self.links[0].0;
+            self.unlink(oldest);
+            if let Some((key, _)) = self.slots[oldest].take() {
+                self.index.remove(&key);
+            }
+            self.free.push(oldest);
+            self.stats.evictions += 1;
+        }
+        self.stats.len = self.index.len();
+    }
+
+    /// 項目 `i` をリストから外す
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = self.links[i];
+        self.links[prev].1 = next;
+        self.links[next].0 = prev;
+    }
+
+    /// 項目 `i` をリストの先頭につなぐ
+    fn push_front(&mut self, i: usize) {
+        let first = self.links[0].1;
+        self.links[i] = (0, first);
+        self.links[first].0 = i;
+        self.links[0].1 = i;
+    }
+}
@@ -0,0 +1,1133 @@
+//! パターンにマッチする行を表示する、grep のようなコマンド
+mod archive;
+mod args;
+mod color;
+mod dump;
+mod encoding;
+mod follow;
+mod format;
+mod gzip;
+mod ignore;
+mod input;
+mod json;
+mod pre;
+mod repl;
+mod search;
+mod walk;
+
+use args::{BinaryFiles, ColorChoice, Command, Options, Output};
+use color::{Painter, Style};
+use encoding::{Encoding, Replacements};
+use input::Contents;
+use pre::Preprocessor;
+use regexp::{Captures, Error, RegexSetBuilder, SearchStats};
+use search::{Event, Line, Searcher};
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+use walk::{Filter, Walk, WalkError};
+
+/// 選んだ行があった場合の終了コード
+const EXIT_MATCH: i32 = 0;
+
+/// 選んだ行がなかった場合の終了コード
+const EXIT_NO_MATCH: i32 = 1;
+
+/// エラーがあった場合の終了コード
+const EXIT_ERROR: i32 = 2;
+
+/// NUL バイトを探して、バイナリファイルかを判定する先頭の長さ（バイト）
+const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+/// 標準入力を表すファイル名
+const STDIN_NAME: &str = "(standard input)";
+
+/// コマンドライン引数に従って検索し、終了コードを返す
+pub fn run() -> i32 {
+    let options = match args::parse(env::args_os().skip(1)) {
+        Ok(Command::Search(options)) => options,
+        Ok(Command::Repl(options)) => return repl::run(&options),
+        Ok(Command::Help) => {
+            println!("{}", args::USAGE);
+            return EXIT_MATCH;
+        }
+        Err(message) => {
+            eprintln!("regexp: {message}");
+            eprintln!("{}", args::USAGE);
+            return EXIT_ERROR;
+        }
+    };
+    let (patterns, origins) = match load_patterns(&options) {
+        Ok(patterns) => patterns,
+        Err(message) => {
+            eprintln!("regexp: {message}");
+            return EXIT_ERROR;
+        }
+    };
+    let compile_start = Instant::now();
+    let mut builder = RegexSetBuilder::new(&patterns);
+    builder.options().lenient(options.lenient);
+    if let Some(engine) = options.engine {
+        builder.options().engine(engine);
+    }
+    let mut searcher = match Searcher::new(&patterns, &mut builder) {
+        Ok(searcher) => searcher,
+        // コマンドラインで指定したパターンが1つだけの場合は、どのパターンかを表示しない
+        Err(Error::Set(i, e)) => {
+            let message = render_error(&e, &patterns[i]);
+            if options.pattern_files.is_empty() && patterns.len() == 1 {
+                eprintln!("regexp: {message}");
+            } else {
+                eprintln!("regexp: {}: {message}", origins[i]);
+            }
+            return EXIT_ERROR;
+        }
+        Err(e) => {
+            eprintln!("regexp: {e}");
+            return EXIT_ERROR;
+        }
+    };
+    let compile_time = compile_start.elapsed();
+    if let Some(dump) = options.dump {
+        return dump::run(dump, &patterns, &origins, &options);
+    }
+    if options.show_engine {
+        let used = searcher.engine();
+        match options.engine {
+            Some(engine) if engine != used => eprintln!(
+                "regexp: engine: {} ({} cannot search for the pattern)",
+                args::engine_name(used),
+                args::engine_name(engine)
+            ),
+            _ => eprintln!("regexp: engine: {}", args::engine_name(used)),
+        }
+    }
+    searcher
+        .invert(options.invert)
+        .max_count(options.max_count)
+        .multiline(options.multiline)
+        .terminator(if options.null_data { '\0' } else { '\n' });
+    let filter = match Filter::new(
+        !options.no_ignore,
+        &options.include,
+        &options.exclude,
+        &options.exclude_dir,
+    ) {
+        Ok(filter) => filter,
+        Err(message) => {
+            eprintln!("regexp: {message}");
+            return EXIT_ERROR;
+        }
+    };
+    let pre = options
+        .pre
+        .as_deref()
+        .map(|command| Preprocessor::new(command, &options.pre_glob));
+    let pre = match pre.transpose() {
+        Ok(pre) => pre,
+        Err(message) => {
+            eprintln!("regexp: {message}");
+            return EXIT_ERROR;
+        }
+    };
+
+    let stdout = io::stdout();
+    let color = match options.color {
+        ColorChoice::Auto => stdout.is_terminal(),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    let mut out = BufWriter::new(stdout.lock());
+    let mut grep = Grep::new(&options, &searcher, Painter::new(color), pre.as_ref());
+    let search_start = Instant::now();
+    let result = grep.run(&filter, &mut out).and_then(|()| {
+        if options.stats {
+            write_stats(&mut out, grep.totals, search_start.elapsed(), &searcher)?;
+        }
+        out.flush()
+    });
+    if options.time {
+        print_time(compile_time, search_start.elapsed(), grep.totals.bytes);
+    }
+    match result {
+        // 出力先が先に閉じられた場合は、それまでの結果で終了する
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        Err(e) => {
+            eprintln!("regexp: {e}");
+            return EXIT_ERROR;
+        }
+        Ok(()) => (),
+    }
+    // 何も出力しない場合は、エラーがあっても選んだ行があれば成功とする
+    if grep.failed && !grep.finished() {
+        EXIT_ERROR
+    } else if grep.matched {
+        EXIT_MATCH
+    } else {
+        EXIT_NO_MATCH
+    }
+}
+
+/// パターンのコンパイルと検索にかかった時間と、検索の速さを標準エラー出力に表示する
+///
+/// 検索の時間には、入力の読み込みと結果の出力を含む
+fn print_time(compile: Duration, search: Duration, bytes: u64) {
+    eprintln!("regexp: compile time: {compile:.2?}");
+    let secs = search.as_secs_f64();
+    if secs > 0.0 {
+        let throughput = bytes as f64 / secs / (1024.0 * 1024.0);
+        eprintln!("regexp: search time: {search:.2?} ({bytes} bytes, {throughput:.1} MiB/s)");
+    } else {
+        eprintln!("regexp: search time: {search:.2?} ({bytes} bytes)");
+    }
+}
+
+/// ファイルの内容を置き換える
+///
+/// 同じディレクトリの一時ファイルに書き出し、元のファイルの許可属性を写してから名前を変えて
+/// 置き換えるため、途中で失敗しても元のファイルは壊れない。シンボリックリンクはリンク先を置き換える
+fn replace_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let permissions = fs::metadata(&path)?.permissions();
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".regexp-{}.tmp", process::id()));
+    let temp = path.with_file_name(name);
+
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.set_permissions(permissions)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// 検索した入力とマッチの数、読み込んだ長さ、かかった時間と、評価器が行った処理の回数を出力する
+///
+/// 評価器の処理の回数は、行を選ぶための検索で数えたもの
+fn write_stats(
+    out: &mut impl Write,
+    totals: Totals,
+    elapsed: Duration,
+    searcher: &Searcher,
+) -> io::Result<()> {
+    let Totals {
+        files,
+        matched_files,
+        lines,
+        matches,
+        bytes,
+    } = totals;
+    let SearchStats {
+        steps,
+        backtracks,
+        peak_threads,
+        prefilter_hits,
+    } = searcher.stats();
+    writeln!(out)?;
+    writeln!(out, "{files} files searched")?;
+    writeln!(out, "{matched_files} files with selected lines")?;
+    writeln!(out, "{lines} lines selected")?;
+    writeln!(out, "{matches} matches")?;
+    writeln!(out, "{bytes} bytes searched")?;
+    writeln!(out, "{elapsed:.2?} elapsed")?;
+    writeln!(out, "engine: {}", args::engine_name(searcher.engine()))?;
+    writeln!(out, "{steps} steps, {backtracks} backtracks")?;
+    writeln!(
+        out,
+        "{peak_threads} peak threads, {prefilter_hits} prefilter hits"
+    )
+}
+
+/// パターンのコンパイルに失敗したエラーを表示する文字列
+///
+/// パースのエラーは、パターンとその下にエラーの範囲を示す印を付けて表示する
+fn render_error(e: &Error, pattern: &str) -> String {
+    match e {
+        Error::Parse(e) => e.at_pattern(pattern).to_string(),
+        Error::ParseAll(errors) => errors
+            .0
+            .iter()
+            .map(|e| e.at_pattern(pattern).to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Error::Set(_, e) => render_error(e, pattern),
+        e => e.to_string(),
+    }
+}
+
+/// コマンドラインとファイルで指定したパターンを集める
+///
+/// 集めたパターンと、それぞれを指定した場所を返す。場所は、コマンドラインで指定した
+/// パターンでは `pattern 2` のような番号、ファイルでは `patterns.txt:3` のような行とする。
+/// ファイル中の空行と、空白を除いて `#` で始まる行は読み飛ばす
+fn load_patterns(options: &Options) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut patterns = options.patterns.clone();
+    let mut origins: Vec<String> = (1..=patterns.len())
+        .map(|i| format!("pattern {i}"))
+        .collect();
+    for path in &options.pattern_files {
+        let (name, input) = if path.as_os_str() == "-" {
+            let mut buf = String::new();
+            let input = io::stdin().lock().read_to_string(&mut buf).map(|_| buf);
+            (STDIN_NAME.into(), input)
+        } else {
+            (path.to_string_lossy(), fs::read_to_string(path))
+        };
+        let text = input.map_err(|e| format!("{name}: {e}"))?;
+        for (i, line) in text.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            patterns.push(line.to_string());
+            origins.push(format!("{name}:{}", i + 1));
+        }
+    }
+    Ok((patterns, origins))
+}
+
+/// 別のスレッドで1つのファイルを検索した結果
+struct Searched {
+    output: Vec<u8>, // 書き出す出力
+    matched: bool,   // 選んだ行があったか
+    failed: bool,    // 読めなかったか
+    grouped: bool,   // 前後の行とともに行を出力したか
+    totals: Totals,  // 検索した入力の集計
+}
+
+/// 1つの入力でこれまでに選んだ行
+#[derive(Debug, Clone, Copy, Default)]
+struct Selected {
+    found: bool,  // 選んだ行があったか
+    count: usize, // -c などで表示する数
+}
+
+/// 検索した入力とマッチの数などの集計
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    files: u64,         // 検索した入力の数
+    matched_files: u64, // 選んだ行があった入力の数
+    lines: u64,         // 選んだ行の数
+    matches: u64,       // 選んだ行の中のマッチの数。--stats の場合だけ数える
+    bytes: u64,         // 読み込んだ入力の長さの合計（バイト）
+}
+
+impl Totals {
+    /// 別のスレッドで集計した結果を加える
+    fn add(&mut self, other: Totals) {
+        self.files += other.files;
+        self.matched_files += other.matched_files;
+        self.lines += other.lines;
+        self.matches += other.matches;
+        self.bytes += other.bytes;
+    }
+}
+
+/// 検索の状態
+struct Grep<'a> {
+    options: &'a Options,
+    searcher: &'a Searcher,
+    painter: Painter,
+    pre: Option<&'a Preprocessor>, // ファイルの内容の代わりに出力を検索するコマンド
+    matched: bool,                 // 選んだ行があったか
+    failed: bool,                  // 読めないファイルやディレクトリがあったか
+    grouped: bool,                 // 前後の行とともに行を出力したことがあるか
+    totals: Totals,                // 検索した入力の集計
+    line_base: usize, // 表示する行番号に加える数。追記を待つ場合に、それまでの行を数える
+    replaced: Replacements, // 検索している入力で U+FFFD に置き換えた箇所
+}
+
+impl<'a> Grep<'a> {
+    fn new(
+        options: &'a Options,
+        searcher: &'a Searcher,
+        painter: Painter,
+        pre: Option<&'a Preprocessor>,
+    ) -> Grep<'a> {
+        Grep {
+            options,
+            searcher,
+            painter,
+            pre,
+            matched: false,
+            failed: false,
+            grouped: false,
+            totals: Totals::default(),
+            line_base: 0,
+            replaced: Replacements::default(),
+        }
+    }
+
+    /// 残りの入力を検索する必要がないか。何も出力しない場合は、最初に選んだ行で終える
+    ///
+    /// ファイルを書き換える場合は、すべての入力を検索する
+    fn finished(&self) -> bool {
+        self.options.output == Output::Quiet && !self.options.in_place && self.matched
+    }
+
+    /// すべての入力を検索する。出力に失敗した場合はエラーを返す
+    ///
+    /// ディレクトリを再帰的に検索する場合は、`filter` の条件でたどるパスを選ぶ
+    fn run(&mut self, filter: &Filter, out: &mut impl Write) -> io::Result<()> {
+        // 入力を指定しない場合は、標準入力か、再帰的に検索する場合は現在のディレクトリを検索する
+        let default = [PathBuf::from(if self.options.recursive {
+            "."
+        } else {
+            "-"
+        })];
+        let paths = match self.options.paths.as_slice() {
+            [] => &default[..],
+            paths => paths,
+        };
+        let with_filename = self
+            .options
+            .with_filename
+            .unwrap_or(paths.len() > 1 || self.options.recursive);
+        if self.options.follow {
+            return self.follow(paths, with_filename, out);
+        }
+        for path in paths {
+            if self.finished() {
+                break;
+            }
+            if path.as_os_str() == "-" && self.options.in_place {
+                eprintln!("regexp: {STDIN_NAME}: cannot edit in place");
+                self.failed = true;
+            } else if path.as_os_str() == "-" {
+                let mut buf = Vec::new();
+                let input = io::stdin().lock().read_to_end(&mut buf);
+                let input = input.map(|_| Contents::Read(buf));
+                self.search_input(STDIN_NAME, input, with_filename, out)?;
+            } else if self.options.recursive {
+                self.search_tree(path, filter, with_filename, out)?;
+            } else {
+                self.search_file(path, &path.to_string_lossy(), with_filename, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `root` 以下のファイルを、複数のスレッドで並列に検索する
+    ///
+    /// 各スレッドはたどったファイルを1つずつ受け取り、出力を溜めて返す。
+    /// 出力は、既定ではたどった順に並べ直し、`--no-sort` の場合は検索し終えた順に書き出す
+    fn search_tree(
+        &mut self,
+        root: &Path,
+        filter: &Filter,
+        with_filename: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let threads = match self.options.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let (options, searcher, painter, pre) =
+            (self.options, self.searcher, self.painter, self.pre);
+        let entries = Mutex::new(Walk::new(root, filter).enumerate());
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|s| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let entries = &entries;
+                s.spawn(move || {
+                    // 出力する側が検索をやめると送れなくなるため、そこで終える
+                    loop {
+                        let next = entries.lock().unwrap_or_else(|e| e.into_inner()).next();
+                        let Some((i, entry)) = next else { break };
+                        let mut grep = Grep::new(options, searcher, painter, pre);
+                        let searched = grep.search_entry(entry, with_filename);
+                        if sender.send((i, searched)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (i, searched) in receiver {
+                if options.unordered {
+                    self.write_searched(searched, out)?;
+                } else {
+                    pending.insert(i, searched);
+                    while let Some(searched) = pending.remove(&next) {
+                        self.write_searched(searched, out)?;
+                        next += 1;
+                    }
+                }
+                if self.finished() {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// たどったファイルを1つ検索し、その出力と結果を返す。たどれなかった場合は警告を表示する
+    fn search_entry(&mut self, entry: Result<PathBuf, WalkError>, with_filename: bool) -> Searched {
+        let mut output = Vec::new();
+        match entry {
+            Ok(file) => {
+                // 現在のディレクトリを暗黙に検索する場合は、先頭の ./ を表示しない
+                let name = if self.options.paths.is_empty() {
+                    file.strip_prefix(".").unwrap_or(&file)
+                } else {
+                    &file
+                };
+                let result =
+                    self.search_file(&file, &name.to_string_lossy(), with_filename, &mut output);
+                result.expect("writing to a Vec never fails");
+            }
+            Err(e) => {
+                eprintln!("regexp: {e}");
+                self.failed = true;
+            }
+        }
+        Searched {
+            output,
+            matched: self.matched,
+            failed: self.failed,
+            grouped: self.grouped,
+            totals: self.totals,
+        }
+    }
+
+    /// 別のスレッドで検索した1つのファイルの出力を書き出し、結果を反映する
+    ///
+    /// 前後の行とともに行を出力した入力の間には `--` を表示する
+    fn write_searched(&mut self, searched: Searched, out: &mut impl Write) -> io::Result<()> {
+        if searched.grouped && self.grouped {
+            self.write_break(out)?;
+        }
+        self.matched |= searched.matched;
+        self.failed |= searched.failed;
+        self.grouped |= searched.grouped;
+        self.totals.add(searched.totals);
+        out.write_all(&searched.output)
+    }
+
+    /// ファイルを検索するか、書き換える設定の場合は書き換える。`name` は表示する名前
+    ///
+    /// `--pre` の場合は、条件に合うファイルの内容の代わりにコマンドの出力を検索する
+    fn search_file(
+        &mut self,
+        path: &Path,
+        name: &str,
+        with_filename: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        if self.options.in_place {
+            self.edit(path, name);
+            return Ok(());
+        }
+        let input = match self.pre {
+            Some(pre) if pre.applies(path) => pre.run(path).map(Contents::Read),
+            _ => input::read(path, self.options.mmap),
+        };
+        self.search_input(name, input, with_filename, out)
+    }
+
+    /// 選んだ行のマッチを置き換えて、ファイルを書き換える。失敗した場合は警告を表示して続ける
+    ///
+    /// UTF-8 として読めないファイルと、テキストとして扱わないバイナリファイルは書き換えない。
+    /// 接尾辞を指定した場合は、書き換える前の内容をファイル名に接尾辞を付けたファイルに残す
+    fn edit(&mut self, path: &Path, name: &str) {
+        let template = self
+            .options
+            .replace
+            .as_deref()
+            .expect("--in-place requires --replace");
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("regexp: {name}: {e}");
+                self.failed = true;
+                return;
+            }
+        };
+        self.totals.files += 1;
+        self.totals.bytes += bytes.len() as u64;
+        // NUL で区切る場合は、NUL バイトでバイナリファイルとは判定しない
+        let binary =
+            !self.options.null_data && bytes[..bytes.len().min(BINARY_CHECK_LEN)].contains(&0);
+        if binary && self.options.binary_files != BinaryFiles::Text {
+            return;
+        }
+        let Ok(text) = String::from_utf8(bytes) else {
+            eprintln!("regexp: {name}: not valid UTF-8, not edited");
+            self.failed = true;
+            return;
+        };
+
+        let mut edited = String::with_capacity(text.len());
+        let mut last = 0;
+        let lines = self.totals.lines;
+        for line in self.searcher.lines(&text) {
+            self.matched = true;
+            self.count_line(line);
+            edited.push_str(&text[last..line.offset]);
+            edited.push_str(&self.searcher.replace(line.text, template));
+            last = line.offset + line.text.len();
+        }
+        edited.push_str(&text[last..]);
+        if self.totals.lines > lines {
+            self.totals.matched_files += 1;
+        }
+        if edited == text {
+            return;
+        }
+        let result = match &self.options.backup {
+            Some(suffix) => {
+                let mut backup = path.as_os_str().to_owned();
+                backup.push(suffix);
+                fs::copy(path, backup).map(|_| ())
+            }
+            None => Ok(()),
+        };
+        if let Err(e) = result.and_then(|()| replace_file(path, edited.as_bytes())) {
+            eprintln!("regexp: {name}: {e}");
+            self.failed = true;
+        }
+    }
+
+    /// アーカイブの中のファイルを、`アーカイブ!中のパス` という名前の入力として順に検索する
+    ///
+    /// ファイル名は、`-h` でない限り常に表示する
+    fn search_archive(
+        &mut self,
+        name: &str,
+        kind: archive::Kind,
+        bytes: &[u8],
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let entries = match archive::entries(kind, bytes) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("regexp: {name}: {e}");
+                self.failed = true;
+                return Ok(());
+            }
+        };
+        let with_filename = self.options.with_filename.unwrap_or(true);
+        for entry in entries {
+            if self.finished() {
+                break;
+            }
+            let input = entry.contents().map_err(io::Error::other);
+            let input = input.map(|contents| Contents::Read(contents.into_owned()));
+            self.search_input(&format!("{name}!{}", entry.path), input, with_filename, out)?;
+        }
+        Ok(())
+    }
+
+    /// 読み込んだ入力を検索する。読み込みに失敗した場合は警告を表示して続ける
+    ///
+    /// `-z` の場合は、gzip で圧縮した入力を展開しながら検索する
+    fn search_input(
+        &mut self,
+        name: &str,
+        input: io::Result<Contents>,
+        with_filename: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let bytes = match input {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("regexp: {name}: {e}");
+                self.failed = true;
+                return Ok(());
+            }
+        };
+        if self.options.search_zip && gzip::is_gzip(&bytes) {
+            return self.search_gzip(name, &bytes, with_filename, out);
+        }
+        self.search_contents(name, &bytes, with_filename, out)
+    }
+
+    /// gzip で圧縮した入力を展開しながら、展開した部分の行ごとに検索する
+    ///
+    /// 展開した内容全体を保持しないよう、行の区切りまでの部分を順に検索する。
+    /// 入力全体で行を探す場合と前後の行を表示する場合、選ぶ行の数に上限がある場合、
+    /// UTF-8 でない文字コードの場合と、展開した内容がアーカイブの場合は、
+    /// すべてを展開してから検索する。展開に失敗した場合は、それまでの結果に加えて警告を表示する
+    fn search_gzip(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        with_filename: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let options = self.options;
+        let mut decoder = gzip::Decoder::new(bytes);
+        // 先頭の部分で、アーカイブかバイナリファイルかを判定する
+        let mut pending = Vec::new(); // 展開したが、まだ検索していない部分
+        let mut done = false;
+        while !done && pending.len() < BINARY_CHECK_LEN {
+            match decoder.next_chunk() {
+                Ok(Some(chunk)) => pending.extend_from_slice(chunk),
+                Ok(None) => done = true,
+                Err(e) => {
+                    self.gzip_failed(name, e);
+                    return Ok(());
+                }
+            }
+        }
+        let streams = !options.multiline
+            && options.before_context == 0
+            && options.after_context == 0
+            && options.max_count.is_none()
+            && options.encoding == Encoding::Utf8
+            && archive::detect(&pending).is_none();
+        if !streams {
+            loop {
+                match decoder.next_chunk() {
+                    Ok(Some(chunk)) => pending.extend_from_slice(chunk),
+                    Ok(None) => break,
+                    Err(e) => {
+                        self.gzip_failed(name, e);
+                        return Ok(());
+                    }
+                }
+            }
+            return self.search_contents(name, &pending, with_filename, out);
+        }
+
+        self.totals.files += 1;
+        let lines = self.totals.lines;
+        let binary =
+            !options.null_data && pending[..pending.len().min(BINARY_CHECK_LEN)].contains(&0);
+        if binary && options.binary_files == BinaryFiles::WithoutMatch {
+            return Ok(());
+        }
+        let summary = binary
+            && options.binary_files == BinaryFiles::Binary
+            && options.output == Output::Lines;
+        let passthru =
+            options.output == Output::Lines && options.passthru && !options.only_matching;
+        let terminator = if options.null_data { b'\0' } else { b'\n' };
+        let name_shown = with_filename.then_some(name);
+        let mut selected = Selected::default();
+        let mut offset = 0; // pending の先頭の、展開した内容の先頭からの位置
+        loop {
+            // 最後まで展開した後は、区切りで終わらない末尾の行も検索する
+            let end = if done {
+                pending.len()
+            } else {
+                pending
+                    .iter()
+                    .rposition(|&b| b == terminator)
+                    .map_or(0, |i| i + 1)
+            };
+            if end > 0 {
+                let part: Vec<u8> = pending.drain(..end).collect();
+                self.totals.bytes += part.len() as u64;
+                let (text, mut replaced) = encoding::decode_utf8(&part);
+                replaced.set_origin(offset);
+                self.replaced = replaced;
+                let stop = if summary {
+                    let line = self.searcher.lines(&text).next();
+                    if let Some(line) = line {
+                        self.matched = true;
+                        self.count_line(line);
+                        writeln!(out, "Binary file {name} matches")?;
+                    }
+                    line.is_some()
+                } else if passthru {
+                    for event in self.searcher.passthru(&text) {
+                        self.write_event(event, name_shown, out)?;
+                    }
+                    false
+                } else {
+                    self.search_lines(&text, name, name_shown, &mut selected, out)?
+                };
+                self.line_base += part.iter().filter(|&&b| b == terminator).count();
+                offset += part.len();
+                if stop {
+                    break;
+                }
+            }
+            if done {
+                break;
+            }
+            match decoder.next_chunk() {
+                Ok(Some(chunk)) => pending.extend_from_slice(chunk),
+                Ok(None) => done = true,
+                Err(e) => {
+                    self.gzip_failed(name, e);
+                    break;
+                }
+            }
+        }
+        self.line_base = 0;
+        self.replaced = Replacements::default();
+        if !summary && !passthru {
+            self.finish_lines(name, name_shown, selected, out)?;
+        }
+        if self.totals.lines > lines {
+            self.totals.matched_files += 1;
+        }
+        Ok(())
+    }
+
+    /// gzip の展開に失敗したことを警告する
+    fn gzip_failed(&mut self, name: &str, e: gzip::GzipError) {
+        eprintln!("regexp: {name}: {e}");
+        self.failed = true;
+    }
+
+    /// 読み込んだ内容を検索する
+    ///
+    /// 先頭に NUL バイトを含む入力はバイナリファイルとして扱い、既定では行を表示する代わりに
+    /// マッチすることだけを表示する。`-z` の場合は、tar と zip のアーカイブは中のファイルを検索する
+    fn search_contents(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        with_filename: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        if self.options.search_zip
+            && let Some(kind) = archive::detect(bytes)
+        {
+            return self.search_archive(name, kind, bytes, out);
+        }
+        self.totals.files += 1;
+        self.totals.bytes += bytes.len() as u64;
+        let lines = self.totals.lines;
+        // UTF-16 の文字に含まれる NUL バイトで判定しないよう、変換してから判定する
+        let (text, replaced) = self.options.encoding.decode(bytes);
+        self.replaced = replaced;
+        let binary = !self.options.null_data
+            && text.as_bytes()[..text.len().min(BINARY_CHECK_LEN)].contains(&0);
+        match self.options.binary_files {
+            BinaryFiles::WithoutMatch if binary => {}
+            BinaryFiles::Binary if binary && self.options.output == Output::Lines => {
+                if let Some(line) = self.searcher.lines(&text).next() {
+                    self.matched = true;
+                    self.count_line(line);
+                    writeln!(out, "Binary file {name} matches")?;
+                }
+            }
+            _ => self.search(&text, name, with_filename.then_some(name), out)?,
+        }
+        self.replaced = Replacements::default();
+        if self.totals.lines > lines {
+            self.totals.matched_files += 1;
+        }
+        Ok(())
+    }
+
+    /// 選んだ行を集計に加える。`--stats` の場合は、行の中のマッチも数える
+    fn count_line(&mut self, line: Line<'_>) {
+        self.totals.lines += 1;
+        if self.options.stats {
+            self.totals.matches += self.searcher.matches(line.text).count() as u64;
+        }
+    }
+
+    /// 1つの入力を検索し、選んだ行か、その数や入力の名前を表示する
+    ///
+    /// `name` は行の前に表示するファイル名。表示しない場合は None。
+    /// JSON や書式で出力する場合と名前だけを表示する場合は、`file` を常に出力する
+    fn search(
+        &mut self,
+        text: &str,
+        file: &str,
+        name: Option<&str>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let options = self.options;
+        let context = options.before_context > 0 || options.after_context > 0;
+        if options.output == Output::Lines && options.passthru && !options.only_matching {
+            for event in self.searcher.passthru(text) {
+                self.write_event(event, name, out)?;
+            }
+            return Ok(());
+        }
+        if options.output == Output::Lines && context && !options.only_matching {
+            return self.search_context(text, name, out);
+        }
+        let mut selected = Selected::default();
+        self.search_lines(text, file, name, &mut selected, out)?;
+        self.finish_lines(file, name, selected, out)
+    }
+
+    /// 入力の一部を検索し、選んだ行を出力するか `selected` に数える
+    ///
+    /// 名前だけを表示する場合と何も出力しない場合は、最初に選んだ行で検索をやめて true を返す。
+    /// 行番号には `line_base` を加える
+    fn search_lines(
+        &mut self,
+        text: &str,
+        file: &str,
+        name: Option<&str>,
+        selected: &mut Selected,
+        out: &mut impl Write,
+    ) -> io::Result<bool> {
+        let options = self.options;
+        for line in self.searcher.lines(text) {
+            selected.found = true;
+            self.count_line(line);
+            let numbered = Line {
+                number: line.number + self.line_base,
+                last: line.last + self.line_base,
+                ..line
+            };
+            match options.output {
+                Output::Lines => self.write_line(name, line, out)?,
+                Output::Count => selected.count += 1,
+                Output::CountMatches if options.invert => selected.count += 1,
+                Output::CountMatches => selected.count += self.searcher.matches(line.text).count(),
+                Output::Json => {
+                    for caps in self.searcher.captures(line.text) {
+                        let Some(m) = caps.get(0) else { continue };
+                        let groups = caps.iter().skip(1);
+                        let (number, offset) = (numbered.number, line.offset);
+                        json::write_match(out, file, number, offset, m, groups, &self.replaced)?;
+                    }
+                }
+                Output::Format => {
+                    let format = options.format.as_ref().expect("--format sets the format");
+                    for caps in self.searcher.captures(line.text) {
+                        format.write(out, file, numbered, &caps, &self.replaced)?;
+                        self.end_line(out)?;
+                    }
+                }
+                Output::FilesWithMatches | Output::FilesWithoutMatch | Output::Quiet => {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// 入力を検索し終え、選んだ行の数や入力の名前を表示する
+    fn finish_lines(
+        &mut self,
+        file: &str,
+        name: Option<&str>,
+        selected: Selected,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        match self.options.output {
+            Output::Count | Output::CountMatches => {
+                self.write_name(name, ":", out)?;
+                writeln!(out, "{}", selected.count)?;
+            }
+            // 選んだ行がない入力の名前を表示する場合は、表示したことを成功とみなす
+            Output::FilesWithoutMatch => {
+                if !selected.found {
+                    self.write_path(file, out)?;
+                    self.matched = true;
+                }
+                return Ok(());
+            }
+            Output::FilesWithMatches if selected.found => self.write_path(file, out)?,
+            _ => {}
+        }
+        self.matched |= selected.found;
+        Ok(())
+    }
+
+    /// 1つの入力を検索し、選んだ行を前後の行とともに表示する
+    ///
+    /// 連続しない行の間と、前の入力で表示した行との間には `--` を表示する
+    fn search_context(
+        &mut self,
+        text: &str,
+        name: Option<&str>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let options = self.options;
+        let lines =
+            self.searcher
+                .context_lines(text, options.before_context, options.after_context);
+        let mut first = true;
+        for event in lines {
+            if first && self.grouped && event != Event::Break {
+                self.write_break(out)?;
+            }
+            first = false;
+            self.write_event(event, name, out)?;
+            self.grouped = true;
+        }
+        Ok(())
+    }
+
+    /// 選んだ行か、その前後の行か、区切りを出力する
+    fn write_event(
+        &mut self,
+        event: Event<'_>,
+        name: Option<&str>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        match event {
+            Event::Selected(line) => {
+                self.matched = true;
+                self.count_line(line);
+                self.write_line(name, line, out)
+            }
+            Event::Context(line) => {
+                self.write_prefix(name, line.number, "-", out)?;
+                out.write_all(&self.replaced.restore(line.offset, line.text))?;
+                self.end_line(out)
+            }
+            Event::Break => self.write_break(out),
+        }
+    }
+
+    /// 連続しない行の間の区切りを出力する
+    fn write_break(&self, out: &mut impl Write) -> io::Result<()> {
+        self.painter.write(out, Style::Separator, "--")?;
+        self.end_line(out)
+    }
+
+    /// 行の終わりを出力する。`--null-data` の場合は NUL、それ以外は改行とする
+    fn end_line(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(if self.options.null_data { b"\0" } else { b"\n" })
+    }
+
+    /// ファイル名だけの行を出力する。`--null` の場合は、改行の代わりに NUL で終える
+    fn write_path(&self, file: &str, out: &mut impl Write) -> io::Result<()> {
+        self.painter.write(out, Style::Path, file)?;
+        out.write_all(if self.options.null { b"\0" } else { b"\n" })
+    }
+
+    /// ファイル名と区切りを出力する。`name` が None の場合は何もしない
+    ///
+    /// 区切りは、選んだ行では `:`、前後の行では `-` とする。`--null` の場合は NUL とする
+    fn write_name(
+        &self,
+        name: Option<&str>,
+        separator: &str,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        if let Some(name) = name {
+            self.painter.write(out, Style::Path, name)?;
+            if self.options.null {
+                out.write_all(b"\0")?;
+            } else {
+                self.painter.write(out, Style::Separator, separator)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 行の前にファイル名と、表示する設定の場合は行番号を出力する
+    fn write_prefix(
+        &self,
+        name: Option<&str>,
+        number: usize,
+        separator: &str,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        self.write_name(name, separator, out)?;
+        if self.options.line_number {
+            let number = number + self.line_base;
+            self.painter
+                .write(out, Style::LineNumber, &number.to_string())?;
+            self.painter.write(out, Style::Separator, separator)?;
+        }
+        Ok(())
+    }
+
+    /// 選んだ行を、ファイル名と行番号に続けて出力する
+    ///
+    /// マッチした部分だけを表示する場合は、空でないマッチを1つずつ行として出力する。
+    /// 置き換える場合は、マッチの代わりに置き換えた文字列を出力する。
+    /// 色を付ける場合は、行中のマッチを強調する。
+    /// 複数行にわたる場合は、それぞれの行の前にファイル名と行番号を出力する
+    fn write_line(
+        &self,
+        name: Option<&str>,
+        line: Line<'_>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        if self.options.only_matching {
+            for caps in self.searcher.captures(line.text) {
+                let Some(m) = caps.get(0).filter(|m| !m.as_str().is_empty()) else {
+                    continue;
+                };
+                // 複数行の場合は、マッチが始まる行の行番号を表示する
+                let (number, _) = line.locate(m.start());
+                self.write_prefix(name, number, ":", out)?;
+                self.write_match(&caps, line.offset, out)?;
+                self.end_line(out)?;
+            }
+            return Ok(());
+        }
+        if line.number == line.last {
+            self.write_prefix(name, line.number, ":", out)?;
+            self.write_text(line.text, line.offset, out)?;
+            return self.end_line(out);
+        }
+        let mut text = Vec::new();
+        self.write_text(line.text, line.offset, &mut text)?;
+        for (i, part) in text.split(|&b| b == b'\n').enumerate() {
+            self.write_prefix(name, line.number + i, ":", out)?;
+            out.write_all(part)?;
+            self.end_line(out)?;
+        }
+        Ok(())
+    }
+
+    /// 行の内容を出力する。色を付ける場合と置き換える場合は、マッチを強調するか置き換える
+    ///
+    /// `offset` は行の先頭のバイト位置。U+FFFD に置き換えた部分は、元のバイト列に戻して出力する
+    fn write_text(&self, text: &str, offset: usize, out: &mut impl Write) -> io::Result<()> {
+        let mut last = 0;
+        if self.painter.is_enabled() || self.options.replace.is_some() {
+            for caps in self.searcher.captures(text) {
+                let Some(m) = caps.get(0) else { continue };
+                let before = &text[last..m.start()];
+                out.write_all(&self.replaced.restore(offset + last, before))?;
+                self.write_match(&caps, offset, out)?;
+                last = m.end();
+            }
+        }
+        out.write_all(&self.replaced.restore(offset + last, &text[last..]))
+    }
+
+    /// マッチした部分か、置き換える場合は置き換えた文字列を強調して出力する
+    ///
+    /// `offset` はマッチを探した行の先頭のバイト位置
+    fn write_match(
+        &self,
+        caps: &Captures<'_>,
+        offset: usize,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        match &self.options.replace {
+            Some(template) => {
+                let mut replaced = String::new();
+                caps.expand(template, &mut replaced);
+                self.painter.write(out, Style::Match, &replaced)
+            }
+            None => {
+                let Some(m) = caps.get(0) else { return Ok(()) };
+                let m = self.replaced.restore(offset + m.start(), m.as_str());
+                self.painter.write_bytes(out, Style::Match, &m)
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! tar と zip のアーカイブの中のファイルを列挙する
+//!
+//! tar は ustar 形式と、GNU の長い名前、pax 拡張ヘッダーのパスを読む。
+//! zip は中央ディレクトリの順に、圧縮しないエントリーと DEFLATE で圧縮したエントリーを読む。
+//! ディレクトリやリンクなど、通常のファイルでないエントリーは返さない
+use super::gzip;
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// tar の1つのブロックの長さ
+const BLOCK_LEN: usize = 512;
+
+/// アーカイブの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Tar, // ustar 形式の tar
+    Zip, // zip
+}
+
+/// アーカイブを読めないことを表すエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveError {
+    Truncated,   // 途中でデータが終わっている
+    Header,      // ヘッダーが正しくない
+    Zip64,       // Zip64 形式の zip
+    Encrypted,   // 暗号化したエントリー
+    Method(u16), // 対応しない圧縮方式
+    Compressed,  // 圧縮したデータが正しくない
+    Checksum,    // CRC-32 か展開した長さが一致しない
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Truncated => write!(f, "unexpected end of archive"),
+            ArchiveError::Header => write!(f, "invalid archive header"),
+            ArchiveError::Zip64 => write!(f, "Zip64 archives are not supported"),
+            ArchiveError::Encrypted => write!(f, "encrypted entries are not supported"),
+            ArchiveError::Method(method) => write!(f, "unsupported compression method {method}"),
+            ArchiveError::Compressed => write!(f, "invalid compressed data"),
+            ArchiveError::Checksum => write!(f, "checksum mismatch"),
+        }
+    }
+}
+
+impl Error for ArchiveError {} // エラー用に Error トレイトを実装
+
+/// アーカイブの中の1つのファイル
+#[derive(Debug)]
+pub struct Entry<'a> {
+    pub path: String,       // アーカイブの中のパス
+    data: &'a [u8],         // アーカイブの中の、圧縮したままの内容
+    packed: Option<Packed>, // 圧縮の方法と展開した内容の確かめ方。圧縮しない tar は None
+}
+
+/// zip のエントリーの圧縮の方法と、展開した内容の CRC-32 と長さ
+#[derive(Debug, Clone, Copy)]
+struct Packed {
+    method: u16,
+    crc: u32,
+    len: usize,
+    encrypted: bool, // 暗号化しているか
+}
+
+impl<'a> Entry<'a> {
+    /// ファイルの内容。圧縮したエントリーは展開する
+    pub fn contents(&self) -> Result<Cow<'a, [u8]>, ArchiveError> {
+        let Some(packed) = self.packed else {
+            return Ok(Cow::Borrowed(self.data));
+        };
+        if packed.encrypted {
+            return Err(ArchiveError::Encrypted);
+        }
+        let contents = match packed.method {
+            0 => Cow::Borrowed(self.data),
+            8 => Cow::Owned(gzip::inflate_raw(self.data).map_err(|_| ArchiveError::Compressed)?),
+            method => return Err(ArchiveError::Method(method)),
+        };
+        if contents.len() != packed.len || gzip::crc32(&contents) != packed.crc {
+            return Err(ArchiveError::Checksum);
+        }
+        Ok(contents)
+    }
+}
+
+/// `bytes` がアーカイブであれば、その種類を返す
+pub fn detect(bytes: &[u8]) -> Option<Kind> {
+    if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        return Some(Kind::Zip);
+    }
+    (bytes.len() >= BLOCK_LEN && bytes[257..262] == *b"ustar").then_some(Kind::Tar)
+}
+
+/// アーカイブの中のファイルを、アーカイブに並んだ順に返す
+pub fn entries(kind: Kind, bytes: &[u8]) -> Result<Vec<Entry<'_>>, ArchiveError> {
+    match kind {
+        Kind::Tar => tar_entries(bytes),
+        Kind::Zip => zip_entries(bytes),
+    }
+}
+
+/// tar のエントリーを、すべてが 0 のブロックか末尾まで読む
+fn tar_entries(bytes: &[u8]) -> Result<Vec<Entry<'_>>, ArchiveError> {
+    let mut entries = Vec::new();
+    let mut long_name = None; // GNU の長い名前か pax のパスで、次のエントリーに使う名前
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let header = bytes
+            .get(pos..pos + BLOCK_LEN)
+            .ok_or(ArchiveError::Truncated)?;
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if tar_number(&header[148..156])? != tar_checksum(header) {
+            return Err(ArchiveError::Header);
+        }
+        let len =
+            usize::try_from(tar_number(&header[124..136])?).map_err(|_| ArchiveError::Header)?;
+        let start = pos + BLOCK_LEN;
+        let data = bytes
+            .get(start..start + len)
+            .ok_or(ArchiveError::Truncated)?;
+        pos = start + len.div_ceil(BLOCK_LEN) * BLOCK_LEN;
+        match header[156] {
+            b'L' => long_name = Some(tar_str(data)),
+            b'x' => long_name = pax_path(data).or(long_name),
+            // 通常のファイル
+            b'0' | b'\0' | b'7' => {
+                let path = long_name.take().unwrap_or_else(|| {
+                    let name = tar_str(&header[..100]);
+                    let prefix = tar_str(&header[345..500]);
+                    if prefix.is_empty() {
+                        name
+                    } else {
+                        format!("{prefix}/{name}")
+                    }
+                });
+                entries.push(Entry {
+                    path,
+                    data,
+                    packed: None,
+                });
+            }
+            _ => long_name = None,
+        }
+    }
+    Ok(entries)
+}
+
+/// tar のヘッダーの数値。8進数の文字列か、先頭のビットが立った 256 進数で書く
+fn tar_number(field: &[u8]) -> Result<u64, ArchiveError> {
+    if field[0] & 0x80 != 0 {
+        let value = field[1..].iter().fold(0u64, |n, &b| n << 8 | u64::from(b));
+        return Ok(value);
+    }
+    let mut digits = field
+        .iter()
+        .copied()
+        .skip_while(|&b| b == b' ')
+        .take_while(|&b| b != b' ' && b != 0);
+    digits.try_fold(0u64, |n, b| match b {
+        b'0'..=b'7' => Ok(n << 3 | u64::from(b - b'0')),
+        _ => Err(ArchiveError::Header),
+    })
+}
+
+/// ヘッダーのチェックサム。チェックサムの欄は空白として数える
+fn tar_checksum(header: &[u8]) -> u64 {
+    let sum: u64 = header.iter().map(|&b| u64::from(b)).sum();
+    let field: u64 = header[148..156].iter().map(|&b| u64::from(b)).sum();
+    sum - field + 8 * u64::from(b' ')
+}
+
+/// NUL で終わる文字列
+fn tar_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// pax 拡張ヘッダーの `長さ キー=値\n` の並びから、path の値を探す
+fn pax_path(data: &[u8]) -> Option<String> {
+    let mut rest = data;
+    let mut path = None;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let len: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..len)?.strip_suffix(b"\n")?;
+        if let Some(value) = record.strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        rest = &rest[len..];
+    }
+    path
+}
+
+/// zip の中央ディレクトリにあるエントリーを読む
+fn zip_entries(bytes: &[u8]) -> Result<Vec<Entry<'_>>, ArchiveError> {
+    // 中央ディレクトリの終端は、最大 65535 バイトのコメントの前にある
+    let end = (0..=bytes.len().saturating_sub(22))
+        .rev()
+        .take(22 + usize::from(u16::MAX))
+        .find(|&i| bytes[i..].starts_with(b"PK\x05\x06"))
+        .ok_or(ArchiveError::Header)?;
+    let count = le16(bytes, end + 10)?;
+    let offset = le32(bytes, end + 16)?;
+    if count == u16::MAX || offset == u32::MAX {
+        return Err(ArchiveError::Zip64);
+    }
+    let mut entries = Vec::new();
+    let mut pos = offset as usize;
+    for _ in 0..count {
+        if !bytes[pos.min(bytes.len())..].starts_with(b"PK\x01\x02") {
+            return Err(ArchiveError::Header);
+        }
+        let flags = le16(bytes, pos + 8)?;
+        let method = le16(bytes, pos + 10)?;
+        let crc = le32(bytes, pos + 16)?;
+        let packed_len = le32(bytes, pos + 20)?;
+        let len = le32(bytes, pos + 24)?;
+        let name_len = usize::from(le16(bytes, pos + 28)?);
+        let extra_len = usize::from(le16(bytes, pos + 30)?);
+        let comment_len = usize::from(le16(bytes, pos + 32)?);
+        let local = le32(bytes, pos + 42)?;
+        let name = bytes
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or(ArchiveError::Truncated)?;
+        pos += 46 + name_len + extra_len + comment_len;
+        if [packed_len, len, local].contains(&u32::MAX) {
+            return Err(ArchiveError::Zip64);
+        }
+        if name.ends_with(b"/") {
+            continue;
+        }
+        // 内容は、各エントリーのローカルヘッダーの後ろにある
+        let local = local as usize;
+        if !bytes[local.min(bytes.len())..].starts_with(b"PK\x03\x04") {
+            return Err(ArchiveError::Header);
+        }
+        let start = local
+            + 30
+            + usize::from(le16(bytes, local + 26)?)
+            + usize::from(le16(bytes, local + 28)?);
+        let data = bytes
+            .get(start..start + packed_len as usize)
+            .ok_or(ArchiveError::Truncated)?;
+        entries.push(Entry {
+            path: String::from_utf8_lossy(name).into_owned(),
+            data,
+            packed: Some(Packed {
+                method,
+                crc,
+                len: len as usize,
+                encrypted: flags & 1 != 0,
+            }),
+        });
+    }
+    Ok(entries)
+}
+
+/// `pos` から始まる2バイトの整数
+fn le16(bytes: &[u8], pos: usize) -> Result<u16, ArchiveError> {
+    let b = bytes.get(pos..pos + 2).ok_or(ArchiveError::Truncated)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+/// `pos` から始まる4バイトの整数
+fn le32(bytes: &[u8], pos: usize) -> Result<u32, ArchiveError> {
+    let b = bytes.get(pos..pos + 4).ok_or(ArchiveError::Truncated)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
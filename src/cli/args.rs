@@ -0,0 +1,590 @@
+//! コマンドライン引数を解析する
+//!
+//! `-Hh` のように短いオプションをまとめて書くことができる。
+//! 値をとるオプションは `--name=value` と `--name value` のどちらでも書ける。
+//! 値を省略できるオプションの値は `--name=value` か `-Nvalue` の形でだけ書ける。
+//! `--` より後ろの引数は、すべてオプションでない引数として扱う
+use super::{encoding::Encoding, format::Format};
+use regexp::Engine;
+use std::{ffi::OsString, path::PathBuf, vec};
+
+/// 使い方の説明
+pub const USAGE: &str = "\
+usage: regexp [OPTIONS] PATTERN [FILE...]
+       regexp [OPTIONS] -e PATTERN... [FILE...]
+       regexp [OPTIONS] -f PATTERN_FILE... [FILE...]
+       regexp [OPTIONS] repl [PATTERN]
+
+Search each FILE for lines matching PATTERN. With no FILE, or when FILE is -,
+read standard input. With -r and no FILE, search the current directory.
+With repl, read test strings from standard input and print the matches in each
+(type :help there for commands); use -e repl to search for the word repl.
+
+options:
+  -e, --regexp=PATTERN     use PATTERN; repeat to match any of several
+  -f, --file=FILE          read patterns from FILE, one per line; blank lines and
+                           lines starting with # are ignored
+  -H, --with-filename      print the file name for each match
+  -h, --no-filename        never print file names
+  -c, --count              print only the number of matching lines per file
+      --count-matches      print only the number of matches per file (with -v, selected lines)
+  -l, --files-with-matches print only the names of files with a selected line
+  -L, --files-without-match
+                           print only the names of files without a selected line
+  -q, --quiet              print nothing and exit with status 0 at the first selected line
+      --json               print each match as a JSON object on its own line
+      --format=TEMPLATE    print each match on its own line as TEMPLATE, where {file},
+                           {line}, {column}, {start}, {end}, {match} and {1}, {2}, ...
+                           are replaced with the match details ({{ and }} for braces)
+  -v, --invert-match       select lines that do not match
+  -m, --max-count=NUM      stop searching each file after NUM selected lines
+  -o, --only-matching      print only the matched parts, each on its own line
+  -n, --line-number        print the line number for each match
+  -U, --multiline          search each input as a whole so that matches can span lines,
+                           and print every line a match touches
+      --replace=TEMPLATE   print matches replaced with TEMPLATE, where $1 or ${1} is
+                           the text of group 1, $0 the whole match and $$ a literal $
+  -i, --in-place[=SUFFIX]  with --replace, rewrite the selected lines in each file
+                           instead of printing; keep a copy of the original in
+                           FILE plus SUFFIX if given (e.g. -i.bak)
+  -A, --after-context=N    print N lines after each selected line
+  -B, --before-context=N   print N lines before each selected line
+  -C, --context=N          print N lines before and after each selected line
+      --passthru           print every line, not only the selected ones, highlighting
+                           matches as usual; other lines use - instead of : after the
+                           file name and line number
+  -a, --text               search binary files as if they were text
+      --null-data          treat input and output lines as terminated by NUL bytes
+                           instead of newlines, as with find -print0
+  -Z, --null               print a NUL byte instead of the : or newline after each
+                           file name
+      --pre=COMMAND        search the output of running COMMAND with each FILE as its
+                           only argument instead of the file itself (not standard input)
+      --pre-glob=GLOB      with --pre, only preprocess files whose name matches GLOB;
+                           repeat to allow several
+  -z, --search-zip         decompress gzip files (detected by their first bytes) before
+                           searching them, and search each file inside tar and zip
+                           archives, printed as ARCHIVE!PATH
+      --encoding=ENC       decode input from ENC before matching: utf-8 (default),
+                           utf-16 (by BOM), utf-16le, utf-16be, shift_jis, euc-jp or
+                           latin1; offsets in the output refer to the decoded text,
+                           except for utf-8, which prints the input bytes unchanged
+      --binary-files=TYPE  how to treat files containing NUL bytes: binary (print
+                           only whether they match), text or without-match (skip)
+      --follow             keep reading each FILE as it grows, like tail -f, and print
+                           selected lines as they are appended (also --watch); standard
+                           input is read as it arrives until it ends
+  -r, --recursive          search directories recursively, skipping .git and the files
+                           excluded by .gitignore and .ignore
+      --no-ignore          with -r, do not skip files excluded by .gitignore or .ignore
+                           (.git is still skipped unless given as FILE)
+      --include=GLOB       with -r, search only files whose name matches GLOB; repeat
+                           to allow several (a GLOB with / matches the whole path)
+      --exclude=GLOB       with -r, skip files whose name matches GLOB
+      --exclude-dir=GLOB   with -r, skip directories whose name matches GLOB
+      --mmap               map files into memory instead of reading them (default:
+                           only files of 1 MiB or more); --no-mmap to never map
+  -j, --threads=NUM        search files on NUM threads with -r (default: 0, one per CPU)
+      --no-sort            with -r, print each file's results as soon as it is searched
+                           instead of in directory order
+      --color=WHEN         highlight matches: auto, always or never (default: auto)
+      --stats              after searching, print the numbers of files, lines, matches
+                           and bytes searched, the elapsed time and the engine counters
+      --time               print the time taken to compile the patterns and to search,
+                           and the search throughput, to standard error
+      --engine=NAME        search with NAME: auto (default), literal, backtrack, pikevm
+                           or dfa, and print the engine used to standard error; an engine
+                           that cannot run the pattern falls back to the automatic choice
+      --lenient            treat unknown escapes and dangling quantifiers as literals
+      --debug-ast          print the syntax tree of each pattern instead of searching
+      --debug-program      print the compiled instructions of each pattern instead of searching
+      --emit-dot           print the automaton of each pattern as a Graphviz DOT graph
+      --emit-mermaid       print the automaton of each pattern as a Mermaid state diagram
+      --trace=TEXT         print each instruction the matcher runs while searching TEXT
+                           for each pattern, with its thread and input offset
+      --trace-limit=NUM    with --trace, stop after NUM instructions
+      --help               print this help
+
+exit status: 0 if a line is selected, 1 if no line is selected, 2 if an error occurred
+(with -q, 0 whenever a line is selected)";
+
+/// 検索の設定
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub patterns: Vec<String>,       // 検索するパターン
+    pub pattern_files: Vec<PathBuf>, // パターンを1行に1つずつ書いたファイル
+    pub paths: Vec<PathBuf>,         // 検索するファイル。"-" は標準入力を表す
+    pub with_filename: Option<bool>, // ファイル名を表示するか。None は既定の動作
+    pub lenient: bool,               // 寛容なモードでパースするか
+    pub engine: Option<Engine>,      // 利用するエンジン。None は自動的に選ぶ
+    pub show_engine: bool,           // 利用するエンジンを表示するか
+    pub invert: bool,                // マッチしない行を選ぶか
+    pub max_count: Option<usize>,    // 1つの入力で選ぶ行の数の上限
+    pub only_matching: bool,         // マッチした部分だけを表示するか
+    pub line_number: bool,           // 行番号を表示するか
+    pub multiline: bool,             // 行をまたぐマッチを探すか
+    pub null_data: bool,             // 入力と出力の行を NUL で区切るか
+    pub null: bool,                  // ファイル名の後ろに NUL を出力するか
+    pub pre: Option<String>,         // ファイルの内容の代わりに出力を検索するコマンド
+    pub pre_glob: Vec<String>,       // --pre で変換するファイルのグロブ
+    pub search_zip: bool,            // gzip で圧縮した入力を展開し、アーカイブの中を検索するか
+    pub replace: Option<String>,     // マッチを置き換える文字列
+    pub format: Option<Format>,      // マッチを出力する書式
+    pub in_place: bool,              // 出力せず、ファイルを書き換えるか
+    pub backup: Option<String>,      // 元の内容を残すファイル名の接尾辞
+    pub after_context: usize,        // 選んだ行の後ろに表示する行の数
+    pub before_context: usize,       // 選んだ行の前に表示する行の数
+    pub passthru: bool,              // 選ばなかった行も表示するか
+    pub recursive: bool,             // ディレクトリを再帰的に検索するか
+    pub follow: bool,                // 追記される行を待ち続けて検索するか
+    pub no_ignore: bool,             // .gitignore などの規則に従わないか
+    pub include: Vec<String>,        // 検索するファイルのグロブ
+    pub exclude: Vec<String>,        // 検索しないファイルのグロブ
+    pub exclude_dir: Vec<String>,    // たどらないディレクトリのグロブ
+    pub threads: usize,              // 並列に検索するスレッドの数。0 は CPU の数
+    pub unordered: bool,             // 検索し終えた順に結果を出力するか
+    pub binary_files: BinaryFiles,   // バイナリファイルをどう扱うか
+    pub encoding: Encoding,          // 入力の文字コード
+    pub mmap: Option<bool>,          // ファイルをメモリにマップするか。None は大きさで決める
+    pub color: ColorChoice,          // 出力に色を付けるか
+    pub time: bool,                  // かかった時間を表示するか
+    pub stats: bool,                 // 検索の後に集計を出力するか
+    pub output: Output,              // 何を出力するか
+    pub dump: Option<Dump>,          // 検索せずに出力するパターンの内部表現
+    pub trace_input: String,         // 実行した命令を出力する際に検索する文字列
+    pub trace_limit: Option<usize>,  // 実行した命令を出力する際の、命令数の上限
+}
+
+/// 入力ごとに何を出力するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Output {
+    #[default]
+    Lines, // マッチした行
+    Count,             // マッチした行の数
+    CountMatches,      // マッチの数。マッチしない行を選ぶ場合は、選んだ行の数
+    Json,              // マッチごとの JSON オブジェクト
+    Format,            // マッチごとに書式に従った行
+    FilesWithMatches,  // 選んだ行がある入力の名前
+    FilesWithoutMatch, // 選んだ行がない入力の名前
+    Quiet,             // 何も出力しない
+}
+
+/// 検索せずに出力するパターンの内部表現
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dump {
+    Ast,     // 抽象構文木
+    Program, // 命令列
+    Trace,   // 文字列を検索する際に実行した命令
+    Dot,     // Graphviz の DOT 形式のグラフ
+    Mermaid, // Mermaid の状態遷移図
+}
+
+/// NUL バイトを含むバイナリファイルをどう扱うか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryFiles {
+    #[default]
+    Binary, // マッチする行を表示せず、マッチすることだけを表示する
+    Text,         // テキストファイルと同じく扱う
+    WithoutMatch, // マッチしないものとして読み飛ばす
+}
+
+/// 出力に色を付けるか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto, // 標準出力が端末の場合だけ付ける
+    Always, // 常に付ける
+    Never,  // 付けない
+}
+
+/// 引数を解析した結果
+#[derive(Debug)]
+pub enum Command {
+    Search(Box<Options>), // 検索する
+    Repl(Box<Options>),   // 対話的にマッチを確かめる
+    Help,                 // 使い方を表示する
+}
+
+/// エンジンの名前
+pub const ENGINES: &[(&str, Engine)] = &[
+    ("literal", Engine::Literal),
+    ("backtrack", Engine::Backtrack),
+    ("pikevm", Engine::PikeVm),
+    ("dfa", Engine::Dfa),
+];
+
+/// エンジンの名前
+pub fn engine_name(engine: Engine) -> &'static str {
+    ENGINES
+        .iter()
+        .find(|&&(_, e)| e == engine)
+        .map_or("", |&(name, _)| name)
+}
+
+/// オプションの長い名前と短い名前。短い名前がないものは None
+const NAMES: &[(&str, Option<char>)] = &[
+    ("regexp", Some('e')),
+    ("file", Some('f')),
+    ("with-filename", Some('H')),
+    ("no-filename", Some('h')),
+    ("count", Some('c')),
+    ("count-matches", None),
+    ("files-with-matches", Some('l')),
+    ("files-without-match", Some('L')),
+    ("quiet", Some('q')),
+    ("silent", None),
+    ("json", None),
+    ("format", None),
+    ("invert-match", Some('v')),
+    ("max-count", Some('m')),
+    ("only-matching", Some('o')),
+    ("line-number", Some('n')),
+    ("multiline", Some('U')),
+    ("null-data", None),
+    ("null", Some('Z')),
+    ("search-zip", Some('z')),
+    ("pre", None),
+    ("pre-glob", None),
+    ("replace", None),
+    ("in-place", Some('i')),
+    ("after-context", Some('A')),
+    ("before-context", Some('B')),
+    ("context", Some('C')),
+    ("passthru", None),
+    ("text", Some('a')),
+    ("binary-files", None),
+    ("encoding", None),
+    ("follow", None),
+    ("watch", None),
+    ("recursive", Some('r')),
+    ("no-ignore", None),
+    ("include", None),
+    ("exclude", None),
+    ("exclude-dir", None),
+    ("threads", Some('j')),
+    ("no-sort", None),
+    ("mmap", None),
+    ("no-mmap", None),
+    ("color", None),
+    ("time", None),
+    ("stats", None),
+    ("engine", None),
+    ("lenient", None),
+    ("debug-ast", None),
+    ("debug-program", None),
+    ("emit-dot", None),
+    ("emit-mermaid", None),
+    ("trace", None),
+    ("trace-limit", None),
+    ("help", None),
+];
+
+/// コマンドライン引数を解析する。誤っている場合は理由を返す
+///
+/// `args` はプログラム名を含まない引数の列
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Command, String> {
+    let mut args = Args::new(args);
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    // -A と -B は、順序によらず -C より優先する
+    let (mut after, mut before, mut context) = (None, None, 0);
+    while let Some(arg) = args.next()? {
+        match arg {
+            Arg::Positional(arg) => positional.push(arg),
+            Arg::Option("regexp") => options.patterns.push(args.value()?),
+            Arg::Option("file") => options.pattern_files.push(args.value()?.into()),
+            Arg::Option("with-filename") => options.with_filename = Some(true),
+            Arg::Option("no-filename") => options.with_filename = Some(false),
+            Arg::Option("count") => options.output = Output::Count,
+            Arg::Option("count-matches") => options.output = Output::CountMatches,
+            Arg::Option("files-with-matches") => options.output = Output::FilesWithMatches,
+            Arg::Option("files-without-match") => options.output = Output::FilesWithoutMatch,
+            Arg::Option("quiet" | "silent") => options.output = Output::Quiet,
+            Arg::Option("json") => options.output = Output::Json,
+            Arg::Option("format") => {
+                let value = args.value()?;
+                let format = Format::parse(&value)
+                    .map_err(|e| format!("invalid value for --format: {value}: {e}"))?;
+                options.format = Some(format);
+                options.output = Output::Format;
+            }
+            Arg::Option("invert-match") => options.invert = true,
+            Arg::Option("max-count") => options.max_count = Some(args.number()?),
+            Arg::Option("only-matching") => options.only_matching = true,
+            Arg::Option("line-number") => options.line_number = true,
+            Arg::Option("multiline") => options.multiline = true,
+            Arg::Option("null-data") => options.null_data = true,
+            Arg::Option("null") => options.null = true,
+            Arg::Option("search-zip") => options.search_zip = true,
+            Arg::Option("pre") => options.pre = Some(args.value()?),
+            Arg::Option("pre-glob") => options.pre_glob.push(args.value()?),
+            Arg::Option("replace") => options.replace = Some(args.value()?),
+            Arg::Option("in-place") => {
+                options.in_place = true;
+                options.backup = args.optional_value().filter(|s| !s.is_empty());
+            }
+            Arg::Option("after-context") => after = Some(args.number()?),
+            Arg::Option("before-context") => before = Some(args.number()?),
+            Arg::Option("context") => context = args.number()?,
+            Arg::Option("passthru") => options.passthru = true,
+            Arg::Option("text") => options.binary_files = BinaryFiles::Text,
+            Arg::Option("binary-files") => {
+                options.binary_files = match args.value()?.as_str() {
+                    "binary" => BinaryFiles::Binary,
+                    "text" => BinaryFiles::Text,
+                    "without-match" => BinaryFiles::WithoutMatch,
+                    value => return Err(format!("invalid value for --binary-files: {value}")),
+                }
+            }
+            Arg::Option("encoding") => {
+                let value = args.value()?;
+                options.encoding = Encoding::from_name(&value)
+                    .ok_or_else(|| format!("unknown encoding for --encoding: {value}"))?;
+            }
+            Arg::Option("follow" | "watch") => options.follow = true,
+            Arg::Option("recursive") => options.recursive = true,
+            Arg::Option("no-ignore") => options.no_ignore = true,
+            Arg::Option("include") => options.include.push(args.value()?),
+            Arg::Option("exclude") => options.exclude.push(args.value()?),
+            Arg::Option("exclude-dir") => options.exclude_dir.push(args.value()?),
+            Arg::Option("threads") => options.threads = args.number()?,
+            Arg::Option("no-sort") => options.unordered = true,
+            Arg::Option("mmap") => options.mmap = Some(true),
+            Arg::Option("no-mmap") => options.mmap = Some(false),
+            Arg::Option("color") => {
+                options.color = match args.value()?.as_str() {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    value => return Err(format!("invalid value for --color: {value}")),
+                }
+            }
+            Arg::Option("time") => options.time = true,
+            Arg::Option("stats") => options.stats = true,
+            Arg::Option("engine") => {
+                let value = args.value()?;
+                options.engine = match ENGINES.iter().find(|(name, _)| *name == value) {
+                    Some(&(_, engine)) => Some(engine),
+                    None if value == "auto" => None,
+                    None => return Err(format!("invalid value for --engine: {value}")),
+                };
+                options.show_engine = true;
+            }
+            Arg::Option("lenient") => options.lenient = true,
+            Arg::Option("debug-ast") => options.dump = Some(Dump::Ast),
+            Arg::Option("debug-program") => options.dump = Some(Dump::Program),
+            Arg::Option("emit-dot") => options.dump = Some(Dump::Dot),
+            Arg::Option("emit-mermaid") => options.dump = Some(Dump::Mermaid),
+            Arg::Option("trace") => {
+                options.dump = Some(Dump::Trace);
+                options.trace_input = args.value()?;
+            }
+            Arg::Option("trace-limit") => options.trace_limit = Some(args.number()?),
+            Arg::Option("help") => return Ok(Command::Help),
+            Arg::Option(name) => unreachable!("unhandled option --{name}"),
+        }
+    }
+    options.after_context = after.unwrap_or(context);
+    options.before_context = before.unwrap_or(context);
+    if options.in_place && options.replace.is_none() {
+        return Err("option --in-place requires --replace".to_string());
+    }
+    if options.in_place && options.encoding != Encoding::Utf8 {
+        return Err("option --in-place cannot be used with --encoding".to_string());
+    }
+    if options.in_place && options.search_zip {
+        return Err("option --in-place cannot be used with -z".to_string());
+    }
+    if options.null_data && options.multiline {
+        return Err("option --null-data cannot be used with -U".to_string());
+    }
+    if options.in_place && options.pre.is_some() {
+        return Err("option --in-place cannot be used with --pre".to_string());
+    }
+    if !options.pre_glob.is_empty() && options.pre.is_none() {
+        return Err("option --pre-glob requires --pre".to_string());
+    }
+    if options.follow {
+        // 追記された部分ごとに検索するため、入力全体で数えるものや前後の行は扱えない
+        let conflict = [
+            (options.recursive, "-r"),
+            (options.in_place, "--in-place"),
+            (
+                options.output != Output::Lines,
+                "-c, -l, -L, -q, --json or --format",
+            ),
+            (
+                options.after_context > 0 || options.before_context > 0,
+                "-A, -B or -C",
+            ),
+            (options.max_count.is_some(), "-m"),
+            (options.multiline, "-U"),
+            (options.null_data, "--null-data"),
+            (options.search_zip, "-z"),
+            (options.pre.is_some(), "--pre"),
+            (options.encoding != Encoding::Utf8, "--encoding"),
+        ];
+        if let Some((_, name)) = conflict.iter().find(|(conflict, _)| *conflict) {
+            return Err(format!("option --follow cannot be used with {name}"));
+        }
+    }
+    if options.trace_limit.is_some() && options.dump != Some(Dump::Trace) {
+        return Err("option --trace-limit requires --trace".to_string());
+    }
+
+    // -e と -f を指定しない場合は、最初の引数をパターンとする。
+    // ただし repl の場合は、その次の引数を最初のパターンとする
+    let mut positional = positional.into_iter();
+    if options.patterns.is_empty() && options.pattern_files.is_empty() {
+        if positional
+            .as_slice()
+            .first()
+            .is_some_and(|arg| arg == "repl")
+        {
+            positional.next();
+            if let Some(pattern) = positional.next() {
+                let pattern = pattern
+                    .into_string()
+                    .map_err(|_| "the pattern is not valid UTF-8".to_string())?;
+                options.patterns.push(pattern);
+            }
+            if positional.len() > 0 {
+                return Err("too many arguments for repl".to_string());
+            }
+            return Ok(Command::Repl(Box::new(options)));
+        }
+        let Some(pattern) = positional.next() else {
+            return Err("no pattern given".to_string());
+        };
+        let pattern = pattern
+            .into_string()
+            .map_err(|_| "the pattern is not valid UTF-8".to_string())?;
+        options.patterns.push(pattern);
+    }
+    options.paths = positional.map(PathBuf::from).collect();
+    Ok(Command::Search(Box::new(options)))
+}
+
+/// 1つのオプションか、オプションでない引数
+#[derive(Debug)]
+enum Arg {
+    Option(&'static str), // オプションの長い名前
+    Positional(OsString), // オプションでない引数
+}
+
+/// 引数を先頭から順に、オプションとオプションでない引数に分ける
+struct Args {
+    args: vec::IntoIter<OsString>,
+    name: &'static str,    // 最後に返したオプションの長い名前
+    short: Vec<char>,      // まとめて書いた短いオプションのうち、まだ読んでいないもの。末尾が先頭
+    value: Option<String>, // --name=value の値のうち、まだ読んでいないもの
+    positional_only: bool, // `--` の後ろか
+}
+
+impl Args {
+    fn new(args: impl IntoIterator<Item = OsString>) -> Args {
+        Args {
+            args: args.into_iter().collect::<Vec<_>>().into_iter(),
+            name: "",
+            short: Vec::new(),
+            value: None,
+            positional_only: false,
+        }
+    }
+
+    /// 次のオプションか引数を返す
+    fn next(&mut self) -> Result<Option<Arg>, String> {
+        if let Some(value) = self.value.take() {
+            return Err(format!("option --{} takes no value: {value}", self.name));
+        }
+        if let Some(c) = self.short.pop() {
+            return self.short_option(c).map(Some);
+        }
+        let Some(arg) = self.args.next() else {
+            return Ok(None);
+        };
+        if self.positional_only {
+            return Ok(Some(Arg::Positional(arg)));
+        }
+        // オプションは UTF-8 に限る。そうでない引数は、ファイル名などとして扱う
+        let Some(text) = arg.to_str() else {
+            return Ok(Some(Arg::Positional(arg)));
+        };
+        if text == "--" {
+            self.positional_only = true;
+            return self.next();
+        }
+        if let Some(long) = text.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let Some(&(name, _)) = NAMES.iter().find(|&&(n, _)| n == name) else {
+                return Err(format!("unknown option --{name}"));
+            };
+            self.name = name;
+            self.value = value;
+            return Ok(Some(Arg::Option(name)));
+        }
+        match text.strip_prefix('-') {
+            // "-" だけの引数は標準入力を表す
+            Some(short) if !short.is_empty() => {
+                self.short = short.chars().rev().collect();
+                let c = self.short.pop().unwrap();
+                self.short_option(c).map(Some)
+            }
+            _ => Ok(Some(Arg::Positional(arg))),
+        }
+    }
+
+    /// 最後に返したオプションの値を読む
+    ///
+    /// `--name=value` の値、`-Nvalue` のように短い名前に続く文字列、次の引数の順に探す
+    fn value(&mut self) -> Result<String, String> {
+        if let Some(value) = self.value.take() {
+            return Ok(value);
+        }
+        if !self.short.is_empty() {
+            let value = self.short.drain(..).rev().collect();
+            return Ok(value);
+        }
+        match self.args.next().map(OsString::into_string) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(_)) => Err(format!("the value of --{} is not valid UTF-8", self.name)),
+            None => Err(format!("option --{} requires a value", self.name)),
+        }
+    }
+
+    /// 最後に返したオプションの、省略できる値を読む
+    ///
+    /// `--name=value` の値か、`-Nvalue` のように短い名前に続く文字列を値とする。
+    /// 次の引数は値とみなさない
+    fn optional_value(&mut self) -> Option<String> {
+        if let Some(value) = self.value.take() {
+            return Some(value);
+        }
+        if self.short.is_empty() {
+            return None;
+        }
+        Some(self.short.drain(..).rev().collect())
+    }
+
+    /// 最後に返したオプションの値を、0以上の整数として読む
+    fn number(&mut self) -> Result<usize, String> {
+        let value = self.value()?;
+        value
+            .parse()
+            .map_err(|_| format!("invalid number for --{}: {value}", self.name))
+    }
+
+    /// 短い名前のオプションを返す
+    fn short_option(&mut self, c: char) -> Result<Arg, String> {
+        match NAMES.iter().find(|&&(_, short)| short == Some(c)) {
+            Some(&(name, _)) => {
+                self.name = name;
+                Ok(Arg::Option(name))
+            }
+            None => Err(format!("unknown option -{c}")),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! ANSI エスケープシーケンスで出力に色を付ける
+use std::io::{self, Write};
+
+/// 色を付ける部分の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Match,      // マッチした部分
+    Path,       // ファイル名
+    LineNumber, // 行番号
+    Separator,  // ファイル名や行番号の後ろの区切り
+}
+
+impl Style {
+    /// 色を指定する SGR のパラメータ
+    fn code(self) -> &'static str {
+        match self {
+            Style::Match => "1;31",    // 太字の赤
+            Style::Path => "35",       // マゼンタ
+            Style::LineNumber => "32", // 緑
+            Style::Separator => "36",  // シアン
+        }
+    }
+}
+
+/// 色を付けるかどうかを保持して出力する
+#[derive(Debug, Clone, Copy)]
+pub struct Painter {
+    enabled: bool, // 色を付けるか
+}
+
+impl Painter {
+    pub fn new(enabled: bool) -> Painter {
+        Painter { enabled }
+    }
+
+    /// 色を付けるか
+    pub fn is_enabled(self) -> bool {
+        self.enabled
+    }
+
+    /// `text` を `style` の色で出力する。色を付けない場合はそのまま出力する
+    ///
+    /// `text` が改行を含む場合は、行の前に付けるものに色が続かないよう、行ごとに色を付ける
+    pub fn write(self, out: &mut impl Write, style: Style, text: &str) -> io::Result<()> {
+        self.write_bytes(out, style, text.as_bytes())
+    }
+
+    /// UTF-8 として正しいとは限らない `text` を `style` の色で出力する
+    pub fn write_bytes(self, out: &mut impl Write, style: Style, text: &[u8]) -> io::Result<()> {
+        if !self.enabled {
+            return out.write_all(text);
+        }
+        for (i, part) in text.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                out.write_all(b"\n")?;
+            }
+            if !part.is_empty() {
+                write!(out, "\x1b[{}m", style.code())?;
+                out.write_all(part)?;
+                out.write_all(b"\x1b[0m")?;
+            }
+        }
+        Ok(())
+    }
+}
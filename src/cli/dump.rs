@@ -0,0 +1,101 @@
+//! 検索せずに、パターンをどう解釈したかを出力する
+use super::{EXIT_ERROR, EXIT_MATCH, args::Dump, args::Options, render_error};
+use regexp::{
+    Error, EvalError, Flags, ParserErrors, Regex, RegexBuilder, parse_all, parse_all_lenient,
+};
+use std::io::{self, BufWriter, Write};
+
+/// 各パターンの内部表現を標準出力に出力し、終了コードを返す
+///
+/// パターンが複数ある場合は、それぞれの前に指定した場所とパターンを出力する。
+/// グラフの場合は、そのまま描画できるようにコメントとして出力する
+pub fn run(dump: Dump, patterns: &[String], origins: &[String], options: &Options) -> i32 {
+    let mut out = BufWriter::new(io::stdout().lock());
+    let comment = match dump {
+        Dump::Dot => "// ",
+        Dump::Mermaid => "%% ",
+        Dump::Ast | Dump::Program | Dump::Trace => "",
+    };
+    let result = patterns
+        .iter()
+        .zip(origins)
+        .try_for_each(|(pattern, origin)| {
+            if patterns.len() > 1 {
+                writeln!(out, "{comment}{origin}: {pattern}")?;
+            }
+            match dump {
+                Dump::Ast => write_ast(&mut out, pattern, options),
+                Dump::Program => write_program(&mut out, pattern, options),
+                Dump::Trace => write_trace(&mut out, pattern, options),
+                Dump::Dot => out.write_all(compile(pattern, options)?.to_dot().as_bytes()),
+                Dump::Mermaid => out.write_all(compile(pattern, options)?.to_mermaid().as_bytes()),
+            }
+        })
+        .and_then(|()| out.flush());
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("regexp: {e}");
+            EXIT_ERROR
+        }
+        _ => EXIT_MATCH,
+    }
+}
+
+/// 検索と同じく複数行モードでパースし、AST を木の形で出力する
+fn write_ast(out: &mut impl Write, pattern: &str, options: &Options) -> io::Result<()> {
+    let flags = Flags {
+        multi_line: true,
+        ..Flags::default()
+    };
+    let ast = if options.lenient {
+        parse_all_lenient(pattern, flags)
+    } else {
+        parse_all(pattern, flags)
+    };
+    let ast = ast.map_err(|errors| {
+        let e = Error::ParseAll(ParserErrors(errors));
+        io::Error::other(render_error(&e, pattern))
+    })?;
+    out.write_all(ast.tree().as_bytes())
+}
+
+/// 検索と同じ設定でコンパイルし、命令列をアドレスを付けて出力する
+fn write_program(out: &mut impl Write, pattern: &str, options: &Options) -> io::Result<()> {
+    out.write_all(compile(pattern, options)?.program().as_bytes())
+}
+
+/// `--trace` の文字列を検索し、実行した命令を1行に1つずつ出力する。最後に検索の結果を出力する
+///
+/// ```text
+/// thread    pos  instruction
+///      0      0  0000: split 0001, 0003
+/// ```
+fn write_trace(out: &mut impl Write, pattern: &str, options: &Options) -> io::Result<()> {
+    let regex = compile(pattern, options)?;
+    let limit = options.trace_limit.map(|limit| limit as u64);
+    let trace = regex.trace(&options.trace_input, limit);
+    writeln!(out, "thread    pos  instruction")?;
+    for step in &trace.steps {
+        writeln!(
+            out,
+            "{:>6} {:>6}  {:>04}: {}",
+            step.thread, step.pos, step.pc, step.inst
+        )?;
+    }
+    match trace.result {
+        Ok(Some(m)) => writeln!(out, "match {}..{}: {}", m.start(), m.end(), m.as_str()),
+        Ok(None) => writeln!(out, "no match"),
+        Err(EvalError::StepLimitExceeded) => {
+            writeln!(out, "stopped after {} instructions", trace.steps.len())
+        }
+    }
+}
+
+/// パターンを検索と同じ設定でコンパイルする
+fn compile(pattern: &str, options: &Options) -> io::Result<Regex> {
+    RegexBuilder::new(pattern)
+        .multi_line(true)
+        .lenient(options.lenient)
+        .build()
+        .map_err(|e| io::Error::other(render_error(&e, pattern)))
+}
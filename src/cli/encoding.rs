@@ -0,0 +1,268 @@
+//! 入力の文字コードを UTF-8 に変換する
+//!
+//! 変換できないバイト列は U+FFFD に置き換える。UTF-8 の場合は置き換えた箇所を記録し、
+//! 出力する行と位置を元の入力のバイト列に戻す。ほかの文字コードでは、変換した後の文字列を出力し、
+//! マッチの位置も変換した後の文字列で数える。
+//! Shift_JIS と EUC-JP は同じ JIS X 0208 の表で復号するため、EUC-JP の `〜` などの文字も
+//! Windows-31J と同じく `～` などの全角の文字になる
+mod jis0208;
+
+use jis0208::JIS0208;
+use std::{borrow::Cow, char::REPLACEMENT_CHARACTER};
+
+/// U+FFFD を UTF-8 で表した長さ
+const REPLACEMENT_LEN: usize = REPLACEMENT_CHARACTER.len_utf8();
+
+/// 入力の文字コード
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8, // UTF-8
+    Utf16,    // UTF-16。先頭の BOM で順序を決め、ない場合はリトルエンディアン
+    Utf16Le,  // UTF-16 のリトルエンディアン
+    Utf16Be,  // UTF-16 のビッグエンディアン
+    ShiftJis, // Shift_JIS。Windows-31J（CP932）の拡張文字を含む
+    EucJp,    // EUC-JP。JIS X 0212 の補助漢字は変換しない
+    Latin1,   // ISO-8859-1
+}
+
+impl Encoding {
+    /// 名前から文字コードを選ぶ。大文字と小文字、`-` と `_` は区別しない
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        let name = name.to_ascii_lowercase().replace('_', "-");
+        let encoding = match name.as_str() {
+            "utf-8" | "utf8" => Encoding::Utf8,
+            "utf-16" | "utf16" => Encoding::Utf16,
+            "utf-16le" | "utf16le" => Encoding::Utf16Le,
+            "utf-16be" | "utf16be" => Encoding::Utf16Be,
+            "shift-jis" | "sjis" | "cp932" | "windows-31j" => Encoding::ShiftJis,
+            "euc-jp" | "eucjp" => Encoding::EucJp,
+            "latin1" | "iso-8859-1" => Encoding::Latin1,
+            _ => return None,
+        };
+        Some(encoding)
+    }
+
+    /// `bytes` を UTF-8 の文字列に変換する。UTF-8 の場合は、正しければそのまま借用する
+    ///
+    /// UTF-8 の場合は、U+FFFD に置き換えた箇所も返す。ほかの文字コードでは空にする。
+    /// UTF-16 の先頭の BOM は取り除く
+    pub fn decode(self, bytes: &[u8]) -> (Cow<'_, str>, Replacements) {
+        let text = match self {
+            Encoding::Utf8 => return decode_utf8(bytes),
+            Encoding::Utf16 => match bytes {
+                [0xfe, 0xff, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+                [0xff, 0xfe, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+                _ => decode_utf16(bytes, u16::from_le_bytes),
+            },
+            Encoding::Utf16Le => {
+                let bytes = bytes.strip_prefix(&[0xff, 0xfe]).unwrap_or(bytes);
+                decode_utf16(bytes, u16::from_le_bytes)
+            }
+            Encoding::Utf16Be => {
+                let bytes = bytes.strip_prefix(&[0xfe, 0xff]).unwrap_or(bytes);
+                decode_utf16(bytes, u16::from_be_bytes)
+            }
+            Encoding::ShiftJis => Cow::Owned(decode_shift_jis(bytes)),
+            Encoding::EucJp => Cow::Owned(decode_euc_jp(bytes)),
+            Encoding::Latin1 => Cow::Owned(bytes.iter().map(|&b| char::from(b)).collect()),
+        };
+        (text, Replacements::default())
+    }
+}
+
+/// UTF-8 として正しくないバイト列を U+FFFD に置き換えた箇所
+///
+/// 変換した文字列の位置を元の入力の位置に戻し、出力する部分を元のバイト列に戻すために使う
+#[derive(Debug, Clone, Default)]
+pub struct Replacements {
+    origin: usize,           // 変換した文字列の先頭の、元の入力での位置
+    spans: Vec<Replacement>, // 置き換えた箇所。位置の順に並ぶ
+}
+
+/// U+FFFD に置き換えた1つのバイト列
+#[derive(Debug, Clone, Copy)]
+struct Replacement {
+    at: usize,      // 変換した文字列での U+FFFD の位置
+    source: usize,  // 元の入力での位置
+    bytes: [u8; 3], // 置き換えたバイト列。正しくないバイト列は3バイトまで
+    len: usize,     // 置き換えたバイト列の長さ
+}
+
+impl Replacements {
+    /// 変換した文字列が、元の入力の `origin` から始まる部分を変換したものとする
+    pub fn set_origin(&mut self, origin: usize) {
+        self.origin = origin;
+    }
+
+    /// 変換した文字列の位置 `pos` に当たる、元の入力の位置。`pos` は文字の境界とする
+    pub fn source(&self, pos: usize) -> usize {
+        let i = self.spans.partition_point(|span| span.at < pos);
+        let pos = match i.checked_sub(1) {
+            Some(i) => {
+                let span = &self.spans[i];
+                span.source + span.len + (pos - span.at - REPLACEMENT_LEN)
+            }
+            None => pos,
+        };
+        self.origin + pos
+    }
+
+    /// 変換した文字列の `start` から始まる部分 `text` を、元の入力のバイト列に戻す
+    ///
+    /// 置き換えた箇所を含まない場合は、そのまま借用する
+    pub fn restore<'t>(&self, start: usize, text: &'t str) -> Cow<'t, [u8]> {
+        let end = start + text.len();
+        let first = self.spans.partition_point(|span| span.at < start);
+        let spans = &self.spans[first..];
+        let spans = &spans[..spans.partition_point(|span| span.at < end)];
+        if spans.is_empty() {
+            return Cow::Borrowed(text.as_bytes());
+        }
+        let mut bytes = Vec::with_capacity(text.len());
+        let mut last = 0;
+        for span in spans {
+            bytes.extend_from_slice(&text.as_bytes()[last..span.at - start]);
+            bytes.extend_from_slice(&span.bytes[..span.len]);
+            last = span.at - start + REPLACEMENT_LEN;
+        }
+        bytes.extend_from_slice(&text.as_bytes()[last..]);
+        Cow::Owned(bytes)
+    }
+}
+
+/// UTF-8 として正しくないバイト列を U+FFFD に置き換え、置き換えた箇所とともに返す
+///
+/// 正しいバイト列であれば、そのまま借用する
+pub fn decode_utf8(bytes: &[u8]) -> (Cow<'_, str>, Replacements) {
+    let text = String::from_utf8_lossy(bytes);
+    let mut spans = Vec::new();
+    if let Cow::Owned(_) = text {
+        let (mut at, mut source) = (0, 0);
+        for chunk in bytes.utf8_chunks() {
+            at += chunk.valid().len();
+            source += chunk.valid().len();
+            let invalid = chunk.invalid();
+            if invalid.is_empty() {
+                continue;
+            }
+            let mut replaced = [0; 3];
+            replaced[..invalid.len()].copy_from_slice(invalid);
+            spans.push(Replacement {
+                at,
+                source,
+                bytes: replaced,
+                len: invalid.len(),
+            });
+            at += REPLACEMENT_LEN;
+            source += invalid.len();
+        }
+    }
+    (text, Replacements { origin: 0, spans })
+}
+
+/// 2バイトずつ `unit` で符号単位に変換し、UTF-16 として復号する。末尾の1バイトは U+FFFD にする
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Cow<'static, str> {
+    let chunks = bytes.chunks_exact(2);
+    let odd = !chunks.remainder().is_empty();
+    let units = chunks.map(|pair| unit([pair[0], pair[1]]));
+    let mut text: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+        .collect();
+    if odd {
+        text.push(REPLACEMENT_CHARACTER);
+    }
+    Cow::Owned(text)
+}
+
+/// Shift_JIS のバイト列を復号する
+///
+/// 2バイト目が不正な場合、それが ASCII の文字であれば U+FFFD の後に改めて1文字として読む
+fn decode_shift_jis(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let lead = bytes[i];
+        i += 1;
+        match lead {
+            0x00..=0x80 => text.push(char::from(lead)),
+            0xa1..=0xdf => text.push(half_width_katakana(lead)),
+            0x81..=0x9f | 0xe0..=0xfc => {
+                let Some(&trail) = bytes.get(i) else {
+                    text.push(REPLACEMENT_CHARACTER);
+                    break;
+                };
+                let c = match trail {
+                    0x40..=0x7e | 0x80..=0xfc => {
+                        let row = usize::from(lead - if lead < 0xa0 { 0x81 } else { 0xc1 });
+                        let cell = usize::from(trail - if trail < 0x7f { 0x40 } else { 0x41 });
+                        jis0208(row * 188 + cell)
+                    }
+                    _ => None,
+                };
+                text.push(c.unwrap_or(REPLACEMENT_CHARACTER));
+                if c.is_some() || !trail.is_ascii() {
+                    i += 1;
+                }
+            }
+            _ => text.push(REPLACEMENT_CHARACTER),
+        }
+    }
+    text
+}
+
+/// EUC-JP のバイト列を復号する
+///
+/// JIS X 0212 の3バイトの文字は U+FFFD にする。
+/// 2バイト目以降が不正な場合、それが ASCII の文字であれば U+FFFD の後に改めて1文字として読む
+fn decode_euc_jp(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let lead = bytes[i];
+        i += 1;
+        if lead.is_ascii() {
+            text.push(char::from(lead));
+            continue;
+        }
+        if !matches!(lead, 0x8e | 0x8f | 0xa1..=0xfe) {
+            text.push(REPLACEMENT_CHARACTER);
+            continue;
+        }
+        let Some(&trail) = bytes.get(i) else {
+            text.push(REPLACEMENT_CHARACTER);
+            break;
+        };
+        let c = match (lead, trail) {
+            (0x8e, 0xa1..=0xdf) => Some(half_width_katakana(trail)),
+            // JIS X 0212 の文字は、3バイト目まで読み飛ばす
+            (0x8f, 0xa1..=0xfe) if matches!(bytes.get(i + 1), Some(0xa1..=0xfe)) => {
+                i += 1;
+                Some(REPLACEMENT_CHARACTER)
+            }
+            (0xa1..=0xfe, 0xa1..=0xfe) => {
+                jis0208(usize::from(lead - 0xa1) * 94 + usize::from(trail - 0xa1))
+            }
+            _ => None,
+        };
+        text.push(c.unwrap_or(REPLACEMENT_CHARACTER));
+        if c.is_some() || !trail.is_ascii() {
+            i += 1;
+        }
+    }
+    text
+}
+
+/// 1バイトの半角カタカナ（0xA1 から 0xDF）を U+FF61 以降の文字にする
+fn half_width_katakana(b: u8) -> char {
+    char::from_u32(0xff61 + u32::from(b - 0xa1)).unwrap_or(REPLACEMENT_CHARACTER)
+}
+
+/// JIS X 0208 の表で `pointer` 番目の文字。対応する文字がない場合は `None`
+fn jis0208(pointer: usize) -> Option<char> {
+    let &c = JIS0208.get(pointer)?;
+    if c == 0 {
+        return None;
+    }
+    char::from_u32(u32::from(c))
+}
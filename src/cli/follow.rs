@@ -0,0 +1,216 @@
+//! 入力の末尾に追記される行を、tail -f のように待ち続けて検索する
+//!
+//! 入力を先頭から読み、末尾に達した後は追記を待って続きを読む。
+//! [`Searcher`](super::search::Searcher) は行ごとにマッチを確かめるため、
+//! 読み込んだ部分を改行で区切り、改行で終わっていない末尾の行は改行が追記されるまで検索しない。
+//! 標準入力は末尾に達した時点で終え、改行で終わっていない最後の行も検索する。
+//! NUL バイトを含む入力も、テキストとして検索する
+use super::{
+    Grep, STDIN_NAME,
+    encoding::{self, Replacements},
+};
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+/// 追記がない場合に、次に読むまで待つ時間
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 1回に読み込む長さの上限（バイト）
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// 追記を待っている入力
+struct Followed {
+    name: String,
+    source: Source,
+    partial: Vec<u8>, // 改行で終わっていない末尾の行
+    lines: usize,     // 検索し終えた行の数
+    matched: bool,    // 選んだ行があったか
+}
+
+/// 読み込む入力
+enum Source {
+    File(File, u64),                      // ファイルと、読み込んだ長さ
+    Stdin(Receiver<io::Result<Vec<u8>>>), // 別のスレッドで標準入力から読み込んだもの
+}
+
+/// 続きを読み込んだ結果
+enum Poll {
+    Data(usize), // 読み込んだ長さ
+    Waiting,     // まだ追記がない
+    End,         // 入力の終わりに達した
+}
+
+impl Grep<'_> {
+    /// `paths` の入力を読み、追記を待ちながら選んだ行を出力する。出力に失敗した場合はエラーを返す
+    ///
+    /// 開けない入力と、読めなくなった入力は警告を表示して除く。
+    /// ファイルが読み込んだ長さより短くなった場合は、先頭から読み直す
+    pub(super) fn follow(
+        &mut self,
+        paths: &[PathBuf],
+        with_filename: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let mut inputs = Vec::new();
+        for path in paths {
+            let (name, source) = if path.as_os_str() == "-" {
+                (STDIN_NAME.to_string(), Source::Stdin(read_stdin()))
+            } else {
+                let name = path.to_string_lossy().into_owned();
+                match File::open(path) {
+                    Ok(file) => (name, Source::File(file, 0)),
+                    Err(e) => {
+                        eprintln!("regexp: {name}: {e}");
+                        self.failed = true;
+                        continue;
+                    }
+                }
+            };
+            self.totals.files += 1;
+            inputs.push(Followed {
+                name,
+                source,
+                partial: Vec::new(),
+                lines: 0,
+                matched: false,
+            });
+        }
+
+        let mut buf = vec![0; CHUNK_LEN];
+        while !inputs.is_empty() {
+            let mut idle = true;
+            let mut i = 0;
+            while i < inputs.len() {
+                let input = &mut inputs[i];
+                match input.read(&mut buf) {
+                    Ok(Poll::Data(len)) => {
+                        idle = false;
+                        self.totals.bytes += len as u64;
+                        input.partial.extend_from_slice(&buf[..len]);
+                        // 最後の改行までを検索し、残りは次に読み込む部分とつなげる
+                        if let Some(end) = input.partial.iter().rposition(|&b| b == b'\n') {
+                            let text: Vec<u8> = input.partial.drain(..=end).collect();
+                            self.follow_lines(input, &text, with_filename, out)?;
+                        }
+                        i += 1;
+                    }
+                    Ok(Poll::Waiting) => i += 1,
+                    Ok(Poll::End) => {
+                        let text = std::mem::take(&mut input.partial);
+                        self.follow_lines(input, &text, with_filename, out)?;
+                        inputs.remove(i);
+                    }
+                    Err(e) => {
+                        eprintln!("regexp: {}: {e}", input.name);
+                        self.failed = true;
+                        inputs.remove(i);
+                    }
+                }
+            }
+            out.flush()?;
+            if idle {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        Ok(())
+    }
+
+    /// `input` から読み込んだ行を検索し、選んだ行を出力する
+    ///
+    /// 行番号は、`input` でそれまでに検索した行の続きから数える
+    fn follow_lines(
+        &mut self,
+        input: &mut Followed,
+        text: &[u8],
+        with_filename: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let (text, replaced) = encoding::decode_utf8(text);
+        self.replaced = replaced;
+        let name = with_filename.then_some(input.name.as_str());
+        let lines = self.totals.lines;
+        self.line_base = input.lines;
+        if self.options.passthru {
+            for event in self.searcher.passthru(&text) {
+                self.write_event(event, name, out)?;
+            }
+        } else {
+            for line in self.searcher.lines(&text) {
+                self.matched = true;
+                self.count_line(line);
+                self.write_line(name, line, out)?;
+            }
+        }
+        self.line_base = 0;
+        self.replaced = Replacements::default();
+        input.lines += text.lines().count();
+        if self.totals.lines > lines && !input.matched {
+            input.matched = true;
+            self.totals.matched_files += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Followed {
+    /// 続きを `buf` に読み込む
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<Poll> {
+        match &mut self.source {
+            Source::File(file, offset) => {
+                let len = file.read(buf)?;
+                if len > 0 {
+                    *offset += len as u64;
+                    return Ok(Poll::Data(len));
+                }
+                if file.metadata()?.len() < *offset {
+                    eprintln!("regexp: {}: file truncated", self.name);
+                    file.seek(SeekFrom::Start(0))?;
+                    *offset = 0;
+                    self.partial.clear();
+                    self.lines = 0;
+                }
+                Ok(Poll::Waiting)
+            }
+            Source::Stdin(receiver) => match receiver.try_recv() {
+                Ok(Ok(chunk)) => {
+                    buf[..chunk.len()].copy_from_slice(&chunk);
+                    Ok(Poll::Data(chunk.len()))
+                }
+                Ok(Err(e)) => Err(e),
+                Err(TryRecvError::Empty) => Ok(Poll::Waiting),
+                Err(TryRecvError::Disconnected) => Ok(Poll::End),
+            },
+        }
+    }
+}
+
+/// 標準入力を別のスレッドで読み、読み込んだものを順に送る
+///
+/// 読み込みを待つ間もファイルの追記を読めるよう、別のスレッドで読む。
+/// 末尾に達するか読み込みに失敗すると、送るのをやめる
+fn read_stdin() -> Receiver<io::Result<Vec<u8>>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut buf = vec![0; CHUNK_LEN];
+        loop {
+            let result = match stdin.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => Ok(buf[..len].to_vec()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            if sender.send(result).is_err() || failed {
+                break;
+            }
+        }
+    });
+    receiver
+}
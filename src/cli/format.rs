@@ -0,0 +1,119 @@
+//! マッチを `--format` の書式で出力する
+//!
+//! 書式中の `{名前}` をマッチの値に置き換え、1つのマッチを1行として出力する。
+//! `{{` と `}}` は `{` と `}` 1文字にする
+//!
+//! ```text
+//! {file}    入力の名前
+//! {line}    マッチが始まる行の行番号
+//! {column}  マッチが始まる位置の、行の先頭からの1から始まるバイト位置
+//! {start}   マッチの開始位置。入力の先頭からのバイト位置
+//! {end}     マッチの終了位置。入力の先頭からのバイト位置
+//! {match}   マッチした文字列。{0} と同じ
+//! {N}       N 番目の捕獲グループにマッチした文字列。関わらなかった場合やない場合は空
+//! ```
+use super::{encoding::Replacements, search::Line};
+use regexp::Captures;
+use std::io::{self, Write};
+
+/// 読んだ書式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    pieces: Vec<Piece>, // 出力する順の部分
+}
+
+/// 書式の一部
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String), // そのまま出力する文字列
+    File,         // 入力の名前
+    Line,         // 行番号
+    Column,       // 行の中の位置
+    Start,        // マッチの開始位置
+    End,          // マッチの終了位置
+    Group(usize), // 捕獲グループにマッチした文字列
+}
+
+impl Format {
+    /// 書式を読む。閉じていない `{` や対応しない `}`、知らない名前がある場合は理由を返す
+    pub fn parse(template: &str) -> Result<Format, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(i) = rest.find(['{', '}']) {
+            text.push_str(&rest[..i]);
+            let brace = &rest[i..i + 1];
+            rest = &rest[i + 1..];
+            if let Some(after) = rest.strip_prefix(brace) {
+                text.push_str(brace);
+                rest = after;
+                continue;
+            }
+            if brace == "}" {
+                return Err("unmatched '}'".to_string());
+            }
+            let end = rest.find('}').ok_or("unclosed '{'")?;
+            let name = &rest[..end];
+            let piece = match name {
+                "file" => Piece::File,
+                "line" => Piece::Line,
+                "column" => Piece::Column,
+                "start" => Piece::Start,
+                "end" => Piece::End,
+                "match" => Piece::Group(0),
+                _ => match name.parse() {
+                    Ok(i) if name.bytes().all(|b| b.is_ascii_digit()) => Piece::Group(i),
+                    _ => return Err(format!("unknown field: {{{name}}}")),
+                },
+            };
+            if !text.is_empty() {
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+            }
+            pieces.push(piece);
+            rest = &rest[end + 1..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Format { pieces })
+    }
+
+    /// 1つのマッチを書式に従って出力する。行の終わりは出力しない
+    ///
+    /// `caps` は `line` の中を検索した結果。`line` が複数行にわたる場合も、
+    /// 行番号と行の中の位置はマッチが始まる行で数える。
+    /// 位置と文字列は、`replaced` で U+FFFD に置き換える前の入力に戻す
+    pub fn write(
+        &self,
+        out: &mut impl Write,
+        file: &str,
+        line: Line<'_>,
+        caps: &Captures<'_>,
+        replaced: &Replacements,
+    ) -> io::Result<()> {
+        let Some(m) = caps.get(0) else {
+            return Ok(());
+        };
+        let (number, start) = line.locate(m.start());
+        let source = |pos| replaced.source(line.offset + pos);
+        let column = source(m.start()) - source(start) + 1;
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => out.write_all(text.as_bytes())?,
+                Piece::File => out.write_all(file.as_bytes())?,
+                Piece::Line => write!(out, "{number}")?,
+                Piece::Column => write!(out, "{column}")?,
+                Piece::Start => write!(out, "{}", source(m.start()))?,
+                Piece::End => write!(out, "{}", source(m.end()))?,
+                Piece::Group(i) => {
+                    if let Some(group) = caps.get(*i) {
+                        let start = line.offset + group.start();
+                        out.write_all(&replaced.restore(start, group.as_str()))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,518 @@
+//! gzip で圧縮した入力を展開する
+//!
+//! RFC 1952 の gzip 形式と、RFC 1951 の DEFLATE 形式を読む。
+//! 複数のメンバーを連結したファイルは、すべてのメンバーを展開して連結する。
+//! 最後のメンバーの後ろに続く gzip でないバイト列は無視する。
+//! [`Decoder`] は展開した内容を少しずつ返し、距離で参照する直前の部分だけを保持する
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    mem,
+};
+
+/// gzip のメンバーの先頭の2バイト
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// 長さの符号 257 から 285 が表す長さの最小値と、続く追加のビット数
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// 距離の符号 0 から 29 が表す距離の最小値と、続く追加のビット数
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// 動的なハフマン符号のブロックで、符号長の符号の長さを並べる順
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// 符号の最大の長さ
+const MAX_BITS: usize = 15;
+
+/// 距離で参照できる、直前に展開した内容の長さ
+const WINDOW_LEN: usize = 32 * 1024;
+
+/// [`Decoder::next_chunk`] が1回に展開する長さの目安（バイト）
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// CRC-32 の表
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// 展開に失敗したことを表すエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GzipError {
+    Truncated, // 途中でデータが終わっている
+    Header,    // ヘッダーが正しくない
+    Block,     // ブロックの種類か、圧縮しないブロックの長さが正しくない
+    Code,      // ハフマン符号か、符号が表す値が正しくない
+    Distance,  // 距離が展開した内容の先頭より前を指す
+    Checksum,  // CRC-32 か展開した長さが一致しない
+}
+
+impl Display for GzipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            GzipError::Truncated => "unexpected end of data",
+            GzipError::Header => "invalid header",
+            GzipError::Block => "invalid block",
+            GzipError::Code => "invalid Huffman code",
+            GzipError::Distance => "distance too far back",
+            GzipError::Checksum => "checksum mismatch",
+        };
+        write!(f, "invalid gzip data: {message}")
+    }
+}
+
+impl Error for GzipError {} // エラー用に Error トレイトを実装
+
+/// `bytes` が gzip の先頭の2バイトで始まるか
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// gzip で圧縮した入力を、先頭から少しずつ展開する
+///
+/// 展開した内容は、距離で参照する直前の [`WINDOW_LEN`] バイトと、
+/// 最後に返した部分だけを保持する
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    bits: Bits<'a>,
+    state: State,
+    last: bool,      // 読んでいるブロックがメンバーの最後のブロックか
+    window: Vec<u8>, // 直前に展開した内容
+    checked: usize,  // window のうち、CRC-32 に加えた部分の長さ
+    crc: u32,        // 読んでいるメンバーの、ここまでに展開した内容の反転しない CRC-32
+    len: u32,        // 読んでいるメンバーの、ここまでに展開した長さ。2^32 を法とする
+}
+
+/// 次に読むもの
+#[derive(Debug)]
+enum State {
+    Header,                    // メンバーのヘッダー
+    Block,                     // ブロックの先頭
+    Stored(usize),             // 圧縮しないブロックの、残りの長さ
+    Huffman(Huffman, Huffman), // ハフマン符号で圧縮したブロックの、長さの符号と距離の符号
+    Trailer,                   // メンバーの末尾の CRC-32 と長さ
+    Done,                      // 最後のメンバーを読み終えた
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Decoder<'a> {
+        Decoder {
+            bits: Bits::new(bytes),
+            state: State::Header,
+            last: false,
+            window: Vec::new(),
+            checked: 0,
+            crc: !0,
+            len: 0,
+        }
+    }
+
+    /// 続きを展開し、展開した部分を返す。最後まで展開し終えた場合は None を返す
+    ///
+    /// 1回に返す長さは [`CHUNK_LEN`] 前後で、メンバーやブロックの区切りで短くなることもある
+    pub fn next_chunk(&mut self) -> Result<Option<&[u8]>, GzipError> {
+        if self.window.len() > WINDOW_LEN {
+            let excess = self.window.len() - WINDOW_LEN;
+            self.window.drain(..excess);
+            self.checked -= excess;
+        }
+        let start = self.window.len();
+        let limit = start + CHUNK_LEN;
+        while self.window.len() < limit {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Header => {
+                    read_header(&mut self.bits)?;
+                    self.state = State::Block;
+                }
+                State::Block => {
+                    self.last = self.bits.bits(1)? == 1;
+                    self.state = match self.bits.bits(2)? {
+                        0 => {
+                            let header = self.bits.bytes(4)?;
+                            let len = u16::from_le_bytes([header[0], header[1]]);
+                            let complement = u16::from_le_bytes([header[2], header[3]]);
+                            if len != !complement {
+                                return Err(GzipError::Block);
+                            }
+                            State::Stored(usize::from(len))
+                        }
+                        1 => {
+                            let (literal, distance) = fixed_codes();
+                            State::Huffman(literal, distance)
+                        }
+                        2 => {
+                            let (literal, distance) = dynamic_codes(&mut self.bits)?;
+                            State::Huffman(literal, distance)
+                        }
+                        _ => return Err(GzipError::Block),
+                    };
+                }
+                State::Stored(0) => self.end_block(),
+                State::Stored(rest) => {
+                    let len = rest.min(limit - self.window.len());
+                    self.window.extend_from_slice(self.bits.bytes(len)?);
+                    self.state = State::Stored(rest - len);
+                }
+                State::Huffman(literal, distance) => {
+                    let bits = &mut self.bits;
+                    if inflate_block(bits, &mut self.window, &literal, &distance, limit)? {
+                        self.end_block();
+                    } else {
+                        self.state = State::Huffman(literal, distance);
+                    }
+                }
+                State::Trailer => {
+                    self.check();
+                    let trailer = self.bits.bytes(8)?;
+                    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+                    let len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+                    if crc != !self.crc || len != self.len {
+                        return Err(GzipError::Checksum);
+                    }
+                    self.crc = !0;
+                    self.len = 0;
+                    if is_gzip(self.bits.rest()) {
+                        self.state = State::Header;
+                    }
+                }
+                State::Done => break,
+            }
+        }
+        self.check();
+        Ok((self.window.len() > start).then(|| &self.window[start..]))
+    }
+
+    /// ブロックを読み終え、次のブロックかメンバーの末尾に進む
+    fn end_block(&mut self) {
+        self.state = if self.last {
+            State::Trailer
+        } else {
+            State::Block
+        };
+    }
+
+    /// まだ CRC-32 に加えていない展開した内容を加える
+    fn check(&mut self) {
+        let bytes = &self.window[self.checked..];
+        self.crc = update_crc(self.crc, bytes);
+        self.len = self.len.wrapping_add(bytes.len() as u32);
+        self.checked = self.window.len();
+    }
+}
+
+/// ヘッダーのない DEFLATE 形式の `bytes` を展開する。zip のエントリーに利用する
+pub fn inflate_raw(bytes: &[u8]) -> Result<Vec<u8>, GzipError> {
+    let mut out = Vec::new();
+    inflate(&mut Bits::new(bytes), &mut out)?;
+    Ok(out)
+}
+
+/// メンバーのヘッダーを読み飛ばす
+fn read_header(bits: &mut Bits<'_>) -> Result<(), GzipError> {
+    const FHCRC: u8 = 0x02; // ヘッダーの CRC-16 がある
+    const FEXTRA: u8 = 0x04; // 追加のフィールドがある
+    const FNAME: u8 = 0x08; // 元のファイル名がある
+    const FCOMMENT: u8 = 0x10; // コメントがある
+
+    let header = bits.bytes(10)?;
+    // 圧縮方式は DEFLATE (8) だけで、予約したビットは 0
+    if header[..2] != MAGIC || header[2] != 8 || header[3] & 0xe0 != 0 {
+        return Err(GzipError::Header);
+    }
+    let flags = header[3];
+    if flags & FEXTRA != 0 {
+        let len = bits.bytes(2)?;
+        bits.bytes(usize::from(u16::from_le_bytes([len[0], len[1]])))?;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            while bits.bytes(1)? != [0] {}
+        }
+    }
+    if flags & FHCRC != 0 {
+        bits.bytes(2)?;
+    }
+    Ok(())
+}
+
+/// DEFLATE 形式のデータを最後のブロックまで展開し、`out` に追加する
+fn inflate(bits: &mut Bits<'_>, out: &mut Vec<u8>) -> Result<(), GzipError> {
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                let header = bits.bytes(4)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if len != !complement {
+                    return Err(GzipError::Block);
+                }
+                out.extend_from_slice(bits.bytes(usize::from(len))?);
+            }
+            1 => {
+                let (literal, distance) = fixed_codes();
+                inflate_block(bits, out, &literal, &distance, usize::MAX)?;
+            }
+            2 => {
+                let (literal, distance) = dynamic_codes(bits)?;
+                inflate_block(bits, out, &literal, &distance, usize::MAX)?;
+            }
+            _ => return Err(GzipError::Block),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+/// ハフマン符号で圧縮したブロックを、ブロックの終わりを表す符号まで展開する
+///
+/// `out` の長さが `limit` 以上になった場合は、途中でやめて false を返す。
+/// ブロックの終わりまで展開した場合は true を返す
+fn inflate_block(
+    bits: &mut Bits<'_>,
+    out: &mut Vec<u8>,
+    literal: &Huffman,
+    distance: &Huffman,
+    limit: usize,
+) -> Result<bool, GzipError> {
+    while out.len() < limit {
+        let symbol = usize::from(literal.decode(bits)?);
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(true);
+        }
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(GzipError::Code);
+        }
+        let len = usize::from(LENGTH_BASE[i]) + bits.bits(LENGTH_EXTRA[i].into())? as usize;
+        let i = usize::from(distance.decode(bits)?);
+        if i >= DISTANCE_BASE.len() {
+            return Err(GzipError::Code);
+        }
+        let dist = usize::from(DISTANCE_BASE[i]) + bits.bits(DISTANCE_EXTRA[i].into())? as usize;
+        if dist > out.len() {
+            return Err(GzipError::Distance);
+        }
+        // 距離より長い場合は、コピーした内容をさらにコピーする
+        let from = out.len() - dist;
+        for k in 0..len {
+            out.push(out[from + k]);
+        }
+    }
+    Ok(false)
+}
+
+/// 固定のハフマン符号
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literal = Huffman::new(&lengths).expect("fixed codes are complete");
+    let distance = Huffman::new(&[5; 30]).expect("fixed codes are complete");
+    (literal, distance)
+}
+
+/// ブロックの先頭から、動的なハフマン符号を読む
+fn dynamic_codes(bits: &mut Bits<'_>) -> Result<(Huffman, Huffman), GzipError> {
+    let literals = bits.bits(5)? as usize + 257;
+    let distances = bits.bits(5)? as usize + 1;
+    let code_lengths = bits.bits(4)? as usize + 4;
+    let mut lengths = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths)?;
+
+    // 長さの符号と距離の符号の符号長は、続けて1つの列として書く
+    let mut lengths = vec![0; literals + distances];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code.decode(bits)?;
+        let (len, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 if i > 0 => (lengths[i - 1], 3 + bits.bits(2)?),
+            17 => (0, 3 + bits.bits(3)?),
+            18 => (0, 11 + bits.bits(7)?),
+            _ => return Err(GzipError::Code),
+        };
+        let end = i + repeat as usize;
+        if end > lengths.len() {
+            return Err(GzipError::Code);
+        }
+        lengths[i..end].fill(len);
+        i = end;
+    }
+    // ブロックの終わりを表す符号がないブロックは終わらない
+    if lengths[256] == 0 {
+        return Err(GzipError::Code);
+    }
+    let literal = Huffman::new(&lengths[..literals])?;
+    let distance = Huffman::new(&lengths[literals..])?;
+    Ok((literal, distance))
+}
+
+/// 符号長から作った正準ハフマン符号
+#[derive(Debug)]
+struct Huffman {
+    counts: [u16; MAX_BITS + 1], // 長さごとの符号の数
+    symbols: Vec<u16>,           // 符号の順に並べた値
+}
+
+impl Huffman {
+    /// 値ごとの符号長から符号を作る。符号長 0 の値は使わない
+    ///
+    /// 符号が多すぎる場合はエラーにする。足りない場合は、使わない符号を読んだ時点でエラーにする
+    fn new(lengths: &[u8]) -> Result<Huffman, GzipError> {
+        let mut counts = [0; MAX_BITS + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return Err(GzipError::Code);
+            }
+        }
+        let mut offsets = [0; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; usize::from(offsets[MAX_BITS + 1])];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len > 0 {
+                let offset = &mut offsets[usize::from(len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    /// 1つの符号を読み、その値を返す
+    ///
+    /// 符号は上位のビットから1ビットずつ読み、同じ長さの符号の中で何番目かを求める
+    fn decode(&self, bits: &mut Bits<'_>) -> Result<u16, GzipError> {
+        let mut code = 0; // ここまでに読んだ符号
+        let mut first = 0; // 同じ長さの最初の符号
+        let mut index = 0; // 同じ長さの最初の符号の、symbols での位置
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)?;
+            let count = u32::from(count);
+            if code < first + count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(GzipError::Code)
+    }
+}
+
+/// 下位のビットから順にビットを読む
+#[derive(Debug)]
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize, // 次に buf に加えるバイトの位置
+    buf: u64,   // 読んだがまだ使っていないビット
+    count: u32, // buf のビット数
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Bits<'a> {
+        Bits {
+            data,
+            pos: 0,
+            buf: 0,
+            count: 0,
+        }
+    }
+
+    /// `n` ビットを読む。`n` は 16 以下
+    fn bits(&mut self, n: u32) -> Result<u32, GzipError> {
+        while self.count < n {
+            let &byte = self.data.get(self.pos).ok_or(GzipError::Truncated)?;
+            self.pos += 1;
+            self.buf |= u64::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = (self.buf & ((1 << n) - 1)) as u32;
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// バイトの途中のビットを捨て、続く `n` バイトを読む
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], GzipError> {
+        self.align();
+        let bytes = self.data.get(self.pos..self.pos + n);
+        let bytes = bytes.ok_or(GzipError::Truncated)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    /// まだ読んでいないバイト列。バイトの途中のビットは捨てる
+    fn rest(&mut self) -> &'a [u8] {
+        self.align();
+        &self.data[self.pos..]
+    }
+
+    /// バイトの途中のビットを捨て、buf に残したバイトを読み直せるようにする
+    fn align(&mut self) {
+        self.pos -= (self.count / 8) as usize;
+        self.buf = 0;
+        self.count = 0;
+    }
+}
+
+/// `bytes` の CRC-32
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !update_crc(!0, bytes)
+}
+
+/// 反転しない CRC-32 の値 `crc` に、`bytes` を加える
+fn update_crc(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, &b| {
+        CRC_TABLE[((crc ^ u32::from(b)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
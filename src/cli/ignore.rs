@@ -0,0 +1,112 @@
+//! `.gitignore` と `.ignore` の規則に従って、たどらないパスを判定する
+//!
+//! 各ディレクトリの規則は、そのディレクトリより下のパスに適用する。
+//! 深いディレクトリの規則を優先し、同じファイルの中では後ろに書いた規則を優先する。
+//! `.ignore` の規則は、同じディレクトリの `.gitignore` より優先する
+use regexp::{Regex, glob_to_regex};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// 規則を読むファイルの名前。後ろのものを優先する
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// 1つのディレクトリの規則と、親のディレクトリの規則
+#[derive(Debug)]
+pub struct Ignore {
+    dir: PathBuf,                // 規則を読んだディレクトリ
+    rules: Vec<Rule>,            // 書いた順の規則
+    parent: Option<Arc<Ignore>>, // 親のディレクトリの規則
+}
+
+/// 1行の規則
+#[derive(Debug)]
+struct Rule {
+    regex: Regex,   // ディレクトリからの相対パスにマッチするパターン
+    negated: bool,  // `!` で始まり、無視しないことを表すか
+    dir_only: bool, // `/` で終わり、ディレクトリだけに適用するか
+}
+
+impl Ignore {
+    /// `dir` の規則を読む。規則がない場合は親の規則をそのまま返す
+    ///
+    /// 読めないファイルと、パターンに変換できない行は読み飛ばす
+    pub fn load(dir: &Path, parent: Option<Arc<Ignore>>) -> Option<Arc<Ignore>> {
+        let rules: Vec<Rule> = IGNORE_FILES
+            .iter()
+            .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+            .flat_map(|text| text.lines().filter_map(Rule::parse).collect::<Vec<_>>())
+            .collect();
+        if rules.is_empty() {
+            return parent;
+        }
+        Some(Arc::new(Ignore {
+            dir: dir.to_path_buf(),
+            rules,
+            parent,
+        }))
+    }
+
+    /// `path` を無視するか。`is_dir` は `path` がディレクトリか
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignore = Some(self);
+        while let Some(current) = ignore {
+            if let Ok(relative) = path.strip_prefix(&current.dir) {
+                let relative = relative.to_string_lossy();
+                let rule = current
+                    .rules
+                    .iter()
+                    .rev()
+                    .find(|rule| (is_dir || !rule.dir_only) && rule.regex.is_match(&relative));
+                if let Some(rule) = rule {
+                    return !rule.negated;
+                }
+            }
+            ignore = current.parent.as_deref();
+        }
+        false
+    }
+}
+
+impl Rule {
+    /// 1行を規則として読む。空行とコメント、読めない行は `None`
+    ///
+    /// `/` を含まないパターンはどの深さのファイル名にもマッチし、
+    /// 含むパターンは規則を読んだディレクトリからの相対パスにマッチする
+    fn parse(line: &str) -> Option<Rule> {
+        // 末尾の空白は、`\` でエスケープしない限り取り除く
+        let trimmed = line.trim_end();
+        let line = if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+            &line[..trimmed.len() + 1]
+        } else {
+            trimmed
+        };
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let glob = match line.strip_prefix('/') {
+            Some(rest) => rest.to_string(),
+            None if line.contains('/') => line.to_string(),
+            None => format!("**/{line}"),
+        };
+        if glob.is_empty() {
+            return None;
+        }
+        let regex = Regex::new(&glob_to_regex(&glob)).ok()?;
+        Some(Rule {
+            regex,
+            negated,
+            dir_only,
+        })
+    }
+}
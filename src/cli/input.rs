@@ -0,0 +1,136 @@
+//! 検索するファイルの内容を読む
+//!
+//! 大きいファイルは、読み込む代わりにメモリにマップして1つの連続した領域として検索する。
+//! マップできないファイルや、マップに対応しない環境では、通常どおり読み込む
+use std::{
+    fs::File,
+    io::{self, Read},
+    ops::Deref,
+    path::Path,
+};
+
+/// 既定でメモリにマップするファイルの最小の大きさ（バイト）
+const MMAP_MIN_LEN: u64 = 1024 * 1024;
+
+/// ファイルの内容
+#[derive(Debug)]
+pub enum Contents {
+    Mapped(Mmap),  // メモリにマップした内容
+    Read(Vec<u8>), // 読み込んだ内容
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Mapped(mmap) => mmap,
+            Contents::Read(bytes) => bytes,
+        }
+    }
+}
+
+/// `path` の内容を読む
+///
+/// `mmap` が true の場合はメモリへのマップを試み、None の場合は大きいファイルだけを
+/// マップする。マップできない場合は読み込む
+pub fn read(path: &Path, mmap: Option<bool>) -> io::Result<Contents> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let len = metadata.len();
+    // 空のファイルはマップできない
+    if metadata.is_file()
+        && len > 0
+        && mmap.unwrap_or(len >= MMAP_MIN_LEN)
+        && let Ok(mmap) = Mmap::map(&file, len)
+    {
+        return Ok(Contents::Mapped(mmap));
+    }
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(Contents::Read(bytes))
+}
+
+/// 読み取り専用でメモリにマップしたファイル
+///
+/// マップしている間に他のプロセスがファイルを書き換えたり切り詰めたりすると、
+/// 内容が変わったり、読んだ時点でプロセスがシグナルで終了したりする
+#[derive(Debug)]
+pub struct Mmap {
+    ptr: *const u8, // マップした領域の先頭
+    len: usize,     // マップした領域の長さ
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::ffi::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+
+    unsafe extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+impl Mmap {
+    /// `file` の先頭から `len` バイトをマップする
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    fn map(file: &File, len: u64) -> io::Result<Mmap> {
+        use std::{os::fd::AsRawFd, ptr};
+
+        let len = usize::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        // SAFETY: 読み取り専用で新しい領域にマップするため、既存のメモリには影響しない
+        let ptr = unsafe {
+            sys::mmap(
+                ptr::null_mut(),
+                len,
+                sys::PROT_READ,
+                sys::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        // 失敗した場合は MAP_FAILED (-1) を返す
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    /// マップに対応しない環境では、常に失敗する
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    fn map(_file: &File, _len: u64) -> io::Result<Mmap> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: ptr から len バイトは、この値が破棄されるまで読み取り可能にマップされている
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: map でマップした領域を、一度だけ解放する
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        unsafe {
+            sys::munmap(self.ptr.cast_mut().cast(), self.len);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! マッチを JSON で出力する
+//!
+//! 1つのマッチを1行の JSON オブジェクトとして出力する。位置は入力の先頭からのバイト位置。
+//! UTF-8 として正しくない入力の文字列は、元のバイト列を base64 にした `{"bytes":...}` で出力する
+//!
+//! ```text
+//! {"file":"a.txt","line":2,"start":10,"end":17,"text":"foo=bar","captures":[{"start":14,"end":17,"text":"bar"},null]}
+//! {"file":"b.txt","line":1,"start":0,"end":3,"text":{"bytes":"Yv9j"},"captures":[]}
+//! ```
+use super::encoding::Replacements;
+use regexp::Match;
+use std::io::{self, Write};
+
+/// マッチを1行の JSON オブジェクトとして出力する
+///
+/// `offset` はマッチを探した行の先頭のバイト位置、`groups` は 1 番目以降の捕獲グループの範囲。
+/// 位置と文字列は、`replaced` で U+FFFD に置き換える前の入力に戻す
+pub fn write_match<'h>(
+    out: &mut impl Write,
+    file: &str,
+    line: usize,
+    offset: usize,
+    m: Match<'h>,
+    groups: impl Iterator<Item = Option<Match<'h>>>,
+    replaced: &Replacements,
+) -> io::Result<()> {
+    out.write_all(b"{\"file\":")?;
+    write_str(out, file)?;
+    write!(out, ",\"line\":{line},")?;
+    write_span(out, offset, m, replaced)?;
+    out.write_all(b",\"captures\":[")?;
+    for (i, group) in groups.enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        match group {
+            Some(group) => {
+                out.write_all(b"{")?;
+                write_span(out, offset, group, replaced)?;
+                out.write_all(b"}")?;
+            }
+            None => out.write_all(b"null")?,
+        }
+    }
+    out.write_all(b"]}\n")
+}
+
+/// マッチの範囲と文字列を、オブジェクトのメンバーとして出力する
+fn write_span(
+    out: &mut impl Write,
+    offset: usize,
+    m: Match<'_>,
+    replaced: &Replacements,
+) -> io::Result<()> {
+    let start = offset + m.start();
+    write!(
+        out,
+        "\"start\":{},\"end\":{},\"text\":",
+        replaced.source(start),
+        replaced.source(offset + m.end())
+    )?;
+    let bytes = replaced.restore(start, m.as_str());
+    match std::str::from_utf8(&bytes) {
+        Ok(text) => write_str(out, text),
+        Err(_) => {
+            out.write_all(b"{\"bytes\":\"")?;
+            write_base64(out, &bytes)?;
+            out.write_all(b"\"}")
+        }
+    }
+}
+
+/// バイト列を、パディングを付けた標準の base64 で出力する
+fn write_base64(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        let mut digits = [b'='; 4];
+        for (i, digit) in digits.iter_mut().take(chunk.len() + 1).enumerate() {
+            *digit = DIGITS[(n >> (18 - 6 * i)) as usize & 0x3f];
+        }
+        out.write_all(&digits)?;
+    }
+    Ok(())
+}
+
+/// 文字列を JSON の文字列として出力する
+///
+/// `"` と `\`、制御文字はエスケープする
+fn write_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut last = 0;
+    for (i, c) in s.char_indices() {
+        if !matches!(c, '"' | '\\' | '\0'..='\x1f') {
+            continue;
+        }
+        out.write_all(&s.as_bytes()[last..i])?;
+        match c {
+            '"' | '\\' => write!(out, "\\{c}")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            _ => write!(out, "\\u{:04x}", c as u32)?,
+        }
+        last = i + 1;
+    }
+    out.write_all(&s.as_bytes()[last..])?;
+    out.write_all(b"\"")
+}
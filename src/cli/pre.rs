@@ -0,0 +1,51 @@
+//! `--pre` で指定したコマンドの出力を、ファイルの内容の代わりに検索する
+//!
+//! コマンドはファイルのパスを1つの引数として実行し、標準出力を読む。
+//! コマンドの標準エラー出力は、そのまま表示する
+use super::walk::{Glob, matches_any};
+use std::{
+    io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// 入力を変換するコマンドと、変換するファイルを選ぶ条件
+#[derive(Debug)]
+pub struct Preprocessor {
+    command: String,  // 実行するコマンド
+    globs: Vec<Glob>, // 変換するファイル。空の場合はすべて
+}
+
+impl Preprocessor {
+    /// 条件を作る。グロブを変換できない場合は理由を返す
+    pub fn new(command: &str, globs: &[String]) -> Result<Preprocessor, String> {
+        let globs = globs
+            .iter()
+            .map(|glob| Glob::new(glob, "pre-glob"))
+            .collect::<Result<_, _>>()?;
+        Ok(Preprocessor {
+            command: command.to_string(),
+            globs,
+        })
+    }
+
+    /// `path` を変換するか
+    pub fn applies(&self, path: &Path) -> bool {
+        self.globs.is_empty() || matches_any(&self.globs, path)
+    }
+
+    /// コマンドを実行し、標準出力を返す。実行できない場合と失敗した場合はエラーを返す
+    pub fn run(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let output = Command::new(&self.command)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| io::Error::new(e.kind(), format!("preprocessor {}: {e}", self.command)))?;
+        if !output.status.success() {
+            let message = format!("preprocessor {} failed: {}", self.command, output.status);
+            return Err(io::Error::other(message));
+        }
+        Ok(output.stdout)
+    }
+}
@@ -0,0 +1,238 @@
+//! パターンを設定し、入力した文字列ごとにマッチを確かめる対話的なモード
+//!
+//! `:` で始まる行はコマンドとして、それ以外の行は検索する文字列として読む。
+//! `:` で始まる文字列を検索する場合は、先頭の `:` を重ねて書く
+use super::{
+    EXIT_ERROR, EXIT_MATCH,
+    args::{ENGINES, Options, engine_name},
+    render_error,
+};
+use regexp::{Engine, Error, Regex, RegexBuilder};
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    time::Instant,
+};
+
+/// コマンドの説明
+const HELP: &str = "\
+commands:
+  :pattern PATTERN   set the pattern (also :p)
+  :ascii             toggle ASCII-only \\d, \\w, \\s and \\b
+  :multi-line        toggle ^ and $ matching at each line
+  :lenient           toggle treating invalid escapes as literals
+  :engine NAME       use auto, literal, backtrack, pikevm or dfa
+  :show              print the pattern, the engine and the flags
+  :help              print this help
+  :quit              exit (also :q, or end of input)
+any other line is searched; write :: to search a line starting with :";
+
+/// パターンをコンパイルする設定
+#[derive(Debug, Clone, Default)]
+struct Settings {
+    pattern: Option<String>, // 設定したパターン
+    ascii: bool,             // \d などを ASCII 文字に限るか
+    multi_line: bool,        // 複数行モードか
+    lenient: bool,           // 寛容なモードでパースするか
+    engine: Option<Engine>,  // 利用するエンジン。None は自動的に選ぶ
+}
+
+/// 対話的なモードの状態
+struct Repl {
+    settings: Settings,
+    regex: Option<Regex>, // 設定をコンパイルしたもの。パターンがない場合は None
+}
+
+/// 標準入力から1行ずつ読んで処理し、終了コードを返す
+///
+/// `options` のパターンと、寛容なモードとエンジンの設定から始める。
+/// 入力が端末の場合は、各行の前にプロンプトを表示する
+pub fn run(options: &Options) -> i32 {
+    let mut repl = Repl {
+        settings: Settings {
+            lenient: options.lenient,
+            engine: options.engine,
+            ..Settings::default()
+        },
+        regex: None,
+    };
+    let result = repl.read_loop(options.patterns.first(), &mut io::stdout().lock());
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("regexp: {e}");
+            EXIT_ERROR
+        }
+        _ => EXIT_MATCH,
+    }
+}
+
+impl Repl {
+    /// `pattern` がある場合はそれを設定してから、入力が終わるか `:quit` まで1行ずつ処理する
+    fn read_loop(&mut self, pattern: Option<&String>, out: &mut impl Write) -> io::Result<()> {
+        if let Some(pattern) = pattern {
+            let settings = Settings {
+                pattern: Some(pattern.clone()),
+                ..self.settings.clone()
+            };
+            self.apply(settings, out)?;
+        }
+        let stdin = io::stdin();
+        let prompt = stdin.is_terminal();
+        let mut line = String::new();
+        loop {
+            if prompt {
+                write!(out, "> ")?;
+            }
+            out.flush()?;
+            line.clear();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            match line.strip_prefix(':') {
+                Some(text) if text.starts_with(':') => self.search(text, out)?,
+                Some(command) => {
+                    if !self.command(command, out)? {
+                        return Ok(());
+                    }
+                }
+                None => self.search(line, out)?,
+            }
+        }
+    }
+
+    /// `:` を除いたコマンドを実行する。終了する場合は false を返す
+    fn command(&mut self, command: &str, out: &mut impl Write) -> io::Result<bool> {
+        let (name, arg) = command.split_once(' ').unwrap_or((command, ""));
+        let mut settings = self.settings.clone();
+        match name {
+            "pattern" | "p" => settings.pattern = Some(arg.to_string()),
+            "ascii" => settings.ascii = !settings.ascii,
+            "multi-line" => settings.multi_line = !settings.multi_line,
+            "lenient" => settings.lenient = !settings.lenient,
+            "engine" => {
+                settings.engine = match ENGINES.iter().find(|(n, _)| *n == arg) {
+                    Some(&(_, engine)) => Some(engine),
+                    None if arg == "auto" => None,
+                    None => {
+                        writeln!(out, "error: unknown engine: {arg}")?;
+                        return Ok(true);
+                    }
+                }
+            }
+            "show" => {
+                self.show(out)?;
+                return Ok(true);
+            }
+            "help" => {
+                writeln!(out, "{HELP}")?;
+                return Ok(true);
+            }
+            "quit" | "q" => return Ok(false),
+            _ => {
+                writeln!(out, "error: unknown command :{name} (try :help)")?;
+                return Ok(true);
+            }
+        }
+        self.apply(settings, out)?;
+        Ok(true)
+    }
+
+    /// 設定を変更してコンパイルし直す。コンパイルできない場合は、元の設定のままにする
+    fn apply(&mut self, settings: Settings, out: &mut impl Write) -> io::Result<()> {
+        match compile(&settings) {
+            Ok(regex) => {
+                self.settings = settings;
+                self.regex = regex;
+                self.show(out)
+            }
+            Err(e) => {
+                let pattern = settings.pattern.as_deref().unwrap_or_default();
+                writeln!(out, "error: {}", render_error(&e, pattern))
+            }
+        }
+    }
+
+    /// 現在のパターンと、選ばれたエンジンと、有効なフラグを1行で表示する
+    fn show(&self, out: &mut impl Write) -> io::Result<()> {
+        let Settings {
+            pattern,
+            ascii,
+            multi_line,
+            lenient,
+            engine,
+        } = &self.settings;
+        match (pattern, &self.regex) {
+            (Some(pattern), Some(regex)) => {
+                let name = engine_name(regex.engine());
+                let auto = if engine.is_none() { " (auto)" } else { "" };
+                write!(out, "pattern {pattern:?}, engine {name}{auto}")?;
+            }
+            _ => write!(out, "no pattern")?,
+        }
+        let flags = [
+            (*ascii, "ascii"),
+            (*multi_line, "multi-line"),
+            (*lenient, "lenient"),
+        ];
+        let flags: Vec<&str> = flags
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, n)| *n)
+            .collect();
+        if !flags.is_empty() {
+            write!(out, ", flags {}", flags.join(" "))?;
+        }
+        writeln!(out)
+    }
+
+    /// `text` 中のすべてのマッチの範囲と、各グループの範囲と、検索にかかった時間を表示する
+    ///
+    /// 範囲は `text` のバイトオフセットで表す
+    fn search(&self, text: &str, out: &mut impl Write) -> io::Result<()> {
+        let Some(regex) = &self.regex else {
+            return writeln!(out, "no pattern (set one with :pattern PATTERN)");
+        };
+        let start = Instant::now();
+        let matches: Vec<_> = regex.captures_iter(text).collect();
+        let elapsed = start.elapsed();
+        if matches.is_empty() {
+            writeln!(out, "no match")?;
+        }
+        for caps in &matches {
+            for (i, group) in caps.iter().enumerate() {
+                let indent = if i == 0 { "" } else { "  " };
+                match group {
+                    Some(m) if i == 0 => {
+                        writeln!(out, "{}..{} {:?}", m.start(), m.end(), m.as_str())?
+                    }
+                    Some(m) => writeln!(
+                        out,
+                        "{indent}{i}: {}..{} {:?}",
+                        m.start(),
+                        m.end(),
+                        m.as_str()
+                    )?,
+                    None => writeln!(out, "{indent}{i}: -")?,
+                }
+            }
+        }
+        writeln!(out, "time {elapsed:?}")
+    }
+}
+
+/// 設定のパターンをコンパイルする。パターンがない場合は `None` を返す
+fn compile(settings: &Settings) -> Result<Option<Regex>, Error> {
+    let Some(pattern) = &settings.pattern else {
+        return Ok(None);
+    };
+    let mut builder = RegexBuilder::new(pattern);
+    builder
+        .ascii(settings.ascii)
+        .multi_line(settings.multi_line)
+        .lenient(settings.lenient);
+    if let Some(engine) = settings.engine {
+        builder.engine(engine);
+    }
+    builder.build().map(Some)
+}
@@ -0,0 +1,507 @@
+//! 検索対象の文字列から、パターンにマッチする行を探す
+use regexp::{
+    Cache, CaptureMatches, Engine, Error, FindMatches, Regex, RegexSetBuilder, SearchStats,
+    TokenKind, tokenize,
+};
+use std::{collections::VecDeque, iter::Peekable, sync::Mutex};
+
+/// 行ごとにパターンを検索する
+///
+/// マッチする行を選ぶか、マッチしない行を選ぶかを切り替えられる。
+/// マッチしない行を選ぶ場合も、マッチする行を同じ方法で探し、その間の行を返す
+///
+/// 複数行モードでコンパイルしたパターンで文字列全体を検索してマッチを含む行を見つけ、
+/// その行だけを改めて検索して確かめる。行をまたぐマッチは、このとき取り除かれる。
+/// `(?-m)^` のように複数行モードを無効にするパターンは、文字列全体の検索では
+/// 行の先頭と末尾にマッチしないため、1行ずつ検索する
+///
+/// 行をまたぐマッチを探す設定の場合は、文字列全体の検索で見つけたマッチを確かめずに、
+/// マッチにかかる行をまとめて1つの選ぶ行とする
+///
+/// 行の区切りは改行のほか、NUL などの ASCII の文字に変えられる。改行でない区切りの場合は、
+/// 複数行モードの `^` と `$` が区切りの前後にマッチしないため、1行ずつ検索する。
+/// このとき `^` と `$` は、行の中の改行の前後にもマッチする
+#[derive(Debug)]
+pub struct Searcher {
+    regex: Regex,
+    per_line: bool,            // 1行ずつ検索するか
+    invert: bool,              // マッチしない行を選ぶか
+    max_count: Option<usize>,  // 1つの入力で選ぶ行の数の上限
+    multiline: bool,           // 行をまたぐマッチを探すか
+    terminator: char,          // 行の区切りの文字
+    stats: Mutex<SearchStats>, // 行を選ぶための検索で、評価器が行った処理の回数の合計
+}
+
+impl Searcher {
+    /// `builder` のパターンを1つの命令列にまとめてコンパイルする
+    ///
+    /// いずれかのパターンにマッチする行を選ぶ。`builder` の複数行モードは有効にする
+    pub fn new(patterns: &[String], builder: &mut RegexSetBuilder) -> Result<Searcher, Error> {
+        builder.options().multi_line(true);
+        let regex = builder.build()?.regex().clone();
+        let per_line = patterns.iter().any(|pattern| {
+            tokenize(pattern).iter().any(|token| {
+                let text = &pattern[token.span.byte_start..token.span.byte_end];
+                matches!(token.kind, TokenKind::Flags | TokenKind::GroupOpen)
+                    && text
+                        .split_once('-')
+                        .is_some_and(|(_, disabled)| disabled.contains('m'))
+            })
+        });
+        Ok(Searcher {
+            regex,
+            per_line,
+            invert: false,
+            max_count: None,
+            multiline: false,
+            terminator: '\n',
+            stats: Mutex::new(SearchStats::default()),
+        })
+    }
+
+    /// マッチしない行を選ぶかを設定する
+    pub fn invert(&mut self, yes: bool) -> &mut Searcher {
+        self.invert = yes;
+        self
+    }
+
+    /// 1つの入力で選ぶ行の数の上限を設定する。None の場合は上限を設けない
+    ///
+    /// 上限に達すると、残りの行は調べない
+    pub fn max_count(&mut self, limit: Option<usize>) -> &mut Searcher {
+        self.max_count = limit;
+        self
+    }
+
+    /// 行をまたぐマッチを探すかを設定する
+    ///
+    /// 有効にすると、マッチにかかる連続した行と、その最後の行で始まるマッチにかかる行を
+    /// まとめて1つの選ぶ行とする。その [`Line::text`] は間の改行を含む
+    pub fn multiline(&mut self, yes: bool) -> &mut Searcher {
+        self.multiline = yes;
+        self
+    }
+
+    /// 行の区切りの文字を設定する。既定は改行
+    pub fn terminator(&mut self, terminator: char) -> &mut Searcher {
+        self.terminator = terminator;
+        self
+    }
+
+    /// 検索に利用するエンジン
+    pub fn engine(&self) -> Engine {
+        self.regex.engine()
+    }
+
+    /// これまでに行を選ぶための検索で、評価器が行った処理の回数の合計
+    ///
+    /// イテレータが返す行を最後まで受け取らなかった検索は、イテレータを破棄した時点で加える
+    pub fn stats(&self) -> SearchStats {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// `haystack` 中の選ぶ行を先頭から順に返すイテレータ
+    pub fn lines<'s, 'h>(&'s self, haystack: &'h str) -> SelectedLines<'s, 'h> {
+        let matching = MatchingLines {
+            searcher: self,
+            haystack,
+            cache: Cache::new(),
+            pos: 0,
+            number: 1,
+        };
+        SelectedLines {
+            matching: matching.peekable(),
+            remaining: self.max_count.unwrap_or(usize::MAX),
+            all: self.invert.then(|| self.all_lines(haystack)),
+        }
+    }
+
+    /// 選ぶ行と、その前の `before` 行と後ろの `after` 行を先頭から順に返すイテレータ
+    pub fn context_lines<'s, 'h>(
+        &'s self,
+        haystack: &'h str,
+        before: usize,
+        after: usize,
+    ) -> ContextLines<'s, 'h> {
+        ContextLines {
+            selected: self.lines(haystack).peekable(),
+            all: self.all_lines(haystack),
+            before,
+            after,
+            recent: VecDeque::with_capacity(before),
+            queue: VecDeque::new(),
+            remaining: 0,
+            last: None,
+        }
+    }
+
+    /// すべての行を先頭から順に、選ぶ行は [`Event::Selected`] として、
+    /// それ以外の行は [`Event::Context`] として返すイテレータ
+    ///
+    /// 選ぶ行の数が上限に達した後の行は、すべて [`Event::Context`] として返す
+    pub fn passthru<'s, 'h>(&'s self, haystack: &'h str) -> PassthruLines<'s, 'h> {
+        PassthruLines {
+            selected: self.lines(haystack).peekable(),
+            all: self.all_lines(haystack),
+        }
+    }
+
+    /// `line` 中のマッチを左から順に返す
+    pub fn matches<'h>(&self, line: &'h str) -> FindMatches<'_, 'h> {
+        self.regex.find_iter(line)
+    }
+
+    /// `line` 中のマッチと捕獲グループの範囲を左から順に返す
+    pub fn captures<'h>(&self, line: &'h str) -> CaptureMatches<'_, 'h> {
+        self.regex.captures_iter(line)
+    }
+
+    /// `line` 中のマッチを、`template` を展開した文字列に置き換えた文字列を返す
+    pub fn replace(&self, line: &str, template: &str) -> String {
+        self.regex.replace_all_template(line, template)
+    }
+
+    /// `haystack` 中のすべての行を先頭から順に返すイテレータ
+    fn all_lines<'h>(&self, haystack: &'h str) -> AllLines<'h> {
+        AllLines {
+            haystack,
+            terminator: self.terminator,
+            pos: 0,
+            number: 1,
+        }
+    }
+
+    /// `line` にマッチするかを判定する
+    fn is_match(&self, cache: &mut Cache, line: &str) -> bool {
+        let found = self.regex.try_find_at(cache, line, 0);
+        found.expect("no step limit is set").is_some()
+    }
+}
+
+/// 選んだ行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'h> {
+    pub number: usize, // 1から始まる行番号
+    pub offset: usize, // 行の先頭の、入力の先頭からのバイト位置
+    pub text: &'h str, // 末尾の区切りを含まない行の内容。複数行の場合は間の改行を含む
+    pub last: usize,   // 最後の行の行番号。複数行にわたらない場合は number と同じ
+}
+
+impl Line<'_> {
+    /// 行の中の位置 `pos` がある行の行番号と、その行の先頭の位置
+    pub fn locate(&self, pos: usize) -> (usize, usize) {
+        if self.last == self.number {
+            return (self.number, 0);
+        }
+        let before = &self.text[..pos];
+        let number = self.number + before.bytes().filter(|&b| b == b'\n').count();
+        (number, before.rfind('\n').map_or(0, |i| i + 1))
+    }
+}
+
+/// [`Searcher::lines`] が返すイテレータ
+#[derive(Debug)]
+pub struct SelectedLines<'s, 'h> {
+    matching: Peekable<MatchingLines<'s, 'h>>,
+    remaining: usize,          // まだ選べる行の数
+    all: Option<AllLines<'h>>, // マッチしない行を選ぶ場合の、すべての行
+}
+
+impl<'h> Iterator for SelectedLines<'_, 'h> {
+    type Item = Line<'h>;
+
+    fn next(&mut self) -> Option<Line<'h>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let line = match &mut self.all {
+            None => self.matching.next()?,
+            // 次にマッチする行に達するまでの行を返す。複数行のマッチにかかる行は読み飛ばす
+            Some(all) => loop {
+                let line = all.next()?;
+                match self.matching.next_if(|m| m.number == line.number) {
+                    Some(m) => {
+                        for _ in m.number..m.last {
+                            all.next();
+                        }
+                    }
+                    None => break line,
+                }
+            },
+        };
+        self.remaining -= 1;
+        Some(line)
+    }
+}
+
+/// [`Searcher::context_lines`] が返す行と区切り
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'h> {
+    Selected(Line<'h>), // 選んだ行
+    Context(Line<'h>),  // 選んだ行の前後の行
+    Break,              // 連続しない行の間の区切り
+}
+
+/// [`Searcher::context_lines`] が返すイテレータ
+///
+/// 選ばなかった直近の行を `before` 行まで保持し、次に選ぶ行の前に返す
+#[derive(Debug)]
+pub struct ContextLines<'s, 'h> {
+    selected: Peekable<SelectedLines<'s, 'h>>,
+    all: AllLines<'h>,
+    before: usize,
+    after: usize,
+    recent: VecDeque<Line<'h>>, // 選ばなかった直近の行
+    queue: VecDeque<Event<'h>>, // 返す順に並べた、まだ返していないもの
+    remaining: usize,           // 選んだ行の後ろに続けて返す行の数
+    last: Option<usize>,        // 最後に返した行の行番号
+}
+
+impl<'h> Iterator for ContextLines<'_, 'h> {
+    type Item = Event<'h>;
+
+    fn next(&mut self) -> Option<Event<'h>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Some(event);
+            }
+            if self.remaining == 0 && self.selected.peek().is_none() {
+                return None;
+            }
+            let line = self.all.next()?;
+            if let Some(line) = self.selected.next_if(|s| s.number == line.number) {
+                let first = self.recent.front().map_or(line.number, |l| l.number);
+                if self.last.is_some_and(|last| first > last + 1) {
+                    self.queue.push_back(Event::Break);
+                }
+                self.queue.extend(self.recent.drain(..).map(Event::Context));
+                self.queue.push_back(Event::Selected(line));
+                // 複数行の場合は、含まれる行を読み飛ばす
+                for _ in line.number..line.last {
+                    self.all.next();
+                }
+                self.remaining = self.after;
+                self.last = Some(line.last);
+            } else if self.remaining > 0 {
+                self.remaining -= 1;
+                self.last = Some(line.number);
+                return Some(Event::Context(line));
+            } else if self.before > 0 {
+                if self.recent.len() == self.before {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back(line);
+            }
+        }
+    }
+}
+
+/// [`Searcher::passthru`] が返すイテレータ
+#[derive(Debug)]
+pub struct PassthruLines<'s, 'h> {
+    selected: Peekable<SelectedLines<'s, 'h>>,
+    all: AllLines<'h>,
+}
+
+impl<'h> Iterator for PassthruLines<'_, 'h> {
+    type Item = Event<'h>;
+
+    fn next(&mut self) -> Option<Event<'h>> {
+        let line = self.all.next()?;
+        match self.selected.next_if(|s| s.number == line.number) {
+            Some(selected) => {
+                for _ in selected.number..selected.last {
+                    self.all.next();
+                }
+                Some(Event::Selected(selected))
+            }
+            None => Some(Event::Context(line)),
+        }
+    }
+}
+
+/// マッチする行を先頭から順に返すイテレータ
+///
+/// 行番号は、読み飛ばした範囲の改行だけを数えて進める
+#[derive(Debug)]
+struct MatchingLines<'s, 'h> {
+    searcher: &'s Searcher,
+    haystack: &'h str,
+    cache: Cache,
+    pos: usize,    // 次に調べる行の先頭
+    number: usize, // pos から始まる行の行番号
+}
+
+impl<'h> Iterator for MatchingLines<'_, 'h> {
+    type Item = Line<'h>;
+
+    fn next(&mut self) -> Option<Line<'h>> {
+        if self.searcher.multiline {
+            return self.next_block();
+        }
+        let haystack = self.haystack;
+        let terminator = self.searcher.terminator;
+        while self.pos < haystack.len() {
+            // マッチを含む行から調べる
+            let start = if self.searcher.per_line || terminator != '\n' {
+                self.pos
+            } else {
+                let found = self
+                    .searcher
+                    .regex
+                    .try_find_at(&mut self.cache, haystack, self.pos);
+                let Some(m) = found.expect("no step limit is set") else {
+                    break;
+                };
+                let before = &haystack[self.pos..m.start()];
+                self.pos + before.rfind(terminator).map_or(0, |i| i + 1)
+            };
+            // 末尾の改行の後ろのマッチは、行に含まれない
+            if start == haystack.len() {
+                break;
+            }
+            let end = haystack[start..]
+                .find(terminator)
+                .map_or(haystack.len(), |i| start + i);
+            let skipped = haystack[self.pos..start].matches(terminator);
+            let number = self.number + skipped.count();
+            self.pos = end + 1;
+            self.number = number + 1;
+            let text = &haystack[start..end];
+            if self.searcher.is_match(&mut self.cache, text) {
+                return Some(Line {
+                    number,
+                    offset: start,
+                    text,
+                    last: number,
+                });
+            }
+        }
+        self.pos = haystack.len();
+        None
+    }
+}
+
+impl<'h> MatchingLines<'_, 'h> {
+    /// 次のマッチにかかる行をまとめて返す。行をまたぐマッチを探す場合に利用する
+    ///
+    /// まとめた最後の行で始まるマッチがある場合は、そのマッチにかかる行もまとめる
+    fn next_block(&mut self) -> Option<Line<'h>> {
+        let haystack = self.haystack;
+        let m = self.find(self.pos)?;
+        let start = self.pos + haystack[self.pos..m.0].rfind('\n').map_or(0, |i| i + 1);
+        // 末尾の改行の後ろのマッチは、行に含まれない
+        if start == haystack.len() {
+            self.pos = haystack.len();
+            return None;
+        }
+        let mut end = self.block_end(m);
+        let mut next = self.advance(m);
+        while next <= end {
+            match self.find(next) {
+                Some(m) if m.0 <= end => {
+                    end = end.max(self.block_end(m));
+                    next = self.advance(m);
+                }
+                _ => break,
+            }
+        }
+        let skipped = haystack[self.pos..start].bytes().filter(|&b| b == b'\n');
+        let number = self.number + skipped.count();
+        let text = &haystack[start..end];
+        let line = Line {
+            number,
+            offset: start,
+            text,
+            last: number + text.bytes().filter(|&b| b == b'\n').count(),
+        };
+        self.pos = end + 1;
+        self.number = line.last + 1;
+        Some(line)
+    }
+
+    /// `from` 以降で最も左にあるマッチの範囲
+    fn find(&mut self, from: usize) -> Option<(usize, usize)> {
+        if from > self.haystack.len() {
+            return None;
+        }
+        let found = self
+            .searcher
+            .regex
+            .try_find_at(&mut self.cache, self.haystack, from);
+        found
+            .expect("no step limit is set")
+            .map(|m| (m.start(), m.end()))
+    }
+
+    /// マッチの最後の文字がある行の、末尾の改行の位置。最後の行の場合は文字列の長さ
+    ///
+    /// 空のマッチは、その位置の文字がある行とする
+    fn block_end(&self, (start, end): (usize, usize)) -> usize {
+        let last = if end > start { end - 1 } else { start };
+        let haystack = self.haystack;
+        haystack[last..]
+            .find('\n')
+            .map_or(haystack.len(), |i| last + i)
+    }
+
+    /// マッチの次に検索を始める位置。空のマッチの場合は1文字進める
+    fn advance(&self, (start, end): (usize, usize)) -> usize {
+        if end > start {
+            return end;
+        }
+        end + self.haystack[end..]
+            .chars()
+            .next()
+            .map_or(1, char::len_utf8)
+    }
+}
+
+impl Drop for MatchingLines<'_, '_> {
+    /// 検索で評価器が行った処理の回数を、[`Searcher`] の合計に加える
+    fn drop(&mut self) {
+        let stats = self.cache.take_stats();
+        let mut total = self
+            .searcher
+            .stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        total.steps += stats.steps;
+        total.backtracks += stats.backtracks;
+        total.peak_threads = total.peak_threads.max(stats.peak_threads);
+        total.prefilter_hits += stats.prefilter_hits;
+    }
+}
+
+/// すべての行を先頭から順に返すイテレータ
+#[derive(Debug)]
+struct AllLines<'h> {
+    haystack: &'h str,
+    terminator: char, // 行の区切りの文字
+    pos: usize,       // 次の行の先頭
+    number: usize,    // 次の行の行番号
+}
+
+impl<'h> Iterator for AllLines<'h> {
+    type Item = Line<'h>;
+
+    fn next(&mut self) -> Option<Line<'h>> {
+        let haystack = self.haystack;
+        if self.pos >= haystack.len() {
+            return None;
+        }
+        let start = self.pos;
+        let end = haystack[start..]
+            .find(self.terminator)
+            .map_or(haystack.len(), |i| start + i);
+        let line = Line {
+            number: self.number,
+            offset: start,
+            text: &haystack[start..end],
+            last: self.number,
+        };
+        self.pos = end + 1;
+        self.number += 1;
+        Some(line)
+    }
+}
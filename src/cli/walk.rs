@@ -0,0 +1,188 @@
+//! ディレクトリをたどり、中のファイルを列挙する
+use super::ignore::Ignore;
+use regexp::{Regex, glob_to_regex};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// ディレクトリ以下のファイルを名前の順に返すイテレータ
+///
+/// サブディレクトリの中は、そのディレクトリの位置で続けて返す。
+/// 途中で見つけたシンボリックリンクは、ループを避けるためたどらない。
+/// 途中で見つけたパスのうち、[`Filter`] の条件で除くものはたどらない
+#[derive(Debug)]
+pub struct Walk<'f> {
+    root: PathBuf,       // たどり始めたパス
+    stack: Vec<Pending>, // まだ調べていないパス。末尾が先頭
+    filter: &'f Filter,  // たどるパスを選ぶ条件
+}
+
+/// まだ調べていないパス
+#[derive(Debug)]
+struct Pending {
+    path: PathBuf,
+    root: bool,                  // たどり始めたパスか
+    ignore: Option<Arc<Ignore>>, // 親のディレクトリまでの規則
+}
+
+impl Walk<'_> {
+    /// `root` 以下をたどる。`root` がファイルの場合は、そのファイルだけを返す
+    ///
+    /// `root` 自体は `filter` の条件によらずたどる
+    pub fn new<'f>(root: &Path, filter: &'f Filter) -> Walk<'f> {
+        Walk {
+            root: root.to_path_buf(),
+            stack: vec![Pending {
+                path: root.to_path_buf(),
+                root: true,
+                ignore: None,
+            }],
+            filter,
+        }
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = Result<PathBuf, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Pending { path, root, ignore } = self.stack.pop()?;
+            // たどり始めたパスがシンボリックリンクの場合は、リンク先を調べる
+            let metadata = if root {
+                fs::metadata(&path)
+            } else {
+                fs::symlink_metadata(&path)
+            };
+            let file_type = match metadata {
+                Ok(metadata) => metadata.file_type(),
+                Err(error) => return Some(Err(WalkError { path, error })),
+            };
+            if file_type.is_file() {
+                return Some(Ok(path));
+            }
+            if !file_type.is_dir() {
+                continue;
+            }
+            let entries = fs::read_dir(&path).and_then(|entries| {
+                entries
+                    .map(|entry| entry.and_then(|e| Ok((e.path(), e.file_type()?.is_dir()))))
+                    .collect()
+            });
+            let mut entries: Vec<(PathBuf, bool)> = match entries {
+                Ok(entries) => entries,
+                Err(error) => return Some(Err(WalkError { path, error })),
+            };
+            let ignore = if self.filter.ignore {
+                Ignore::load(&path, ignore)
+            } else {
+                None
+            };
+            entries.retain(|(path, is_dir)| {
+                let relative = path.strip_prefix(&self.root).unwrap_or(path);
+                !(self.filter.excludes(relative, *is_dir)
+                    || ignore.as_ref().is_some_and(|i| i.is_ignored(path, *is_dir)))
+            });
+            entries.sort_unstable_by(|a, b| b.cmp(a));
+            self.stack
+                .extend(entries.into_iter().map(|(path, _)| Pending {
+                    path,
+                    root: false,
+                    ignore: ignore.clone(),
+                }));
+        }
+    }
+}
+
+/// たどるパスを選ぶ条件
+///
+/// グロブは [`glob_to_regex`] で変換する。`/` を含まないグロブはファイル名に、
+/// 含むグロブはたどり始めたパスからの相対パスにマッチする
+#[derive(Debug)]
+pub struct Filter {
+    ignore: bool,           // .gitignore などの規則に従うか
+    include: Vec<Glob>,     // 検索するファイル。空の場合はすべて
+    exclude: Vec<Glob>,     // 検索しないファイル
+    exclude_dir: Vec<Glob>, // たどらないディレクトリ
+}
+
+/// 1つのグロブ
+///
+/// `/` を含まないグロブはファイル名に、含むグロブはパス全体にマッチする
+#[derive(Debug)]
+pub struct Glob {
+    regex: Regex, // グロブを変換したパターン
+    path: bool,   // 相対パス全体と比べるか
+}
+
+impl Glob {
+    /// グロブを変換する。変換できない場合は、オプション `--{name}` の誤りとして理由を返す
+    pub fn new(glob: &str, name: &str) -> Result<Glob, String> {
+        let regex = Regex::new(&glob_to_regex(glob))
+            .map_err(|e| format!("invalid glob for --{name}: {glob}: {e}"))?;
+        let path = glob.contains('/');
+        Ok(Glob { regex, path })
+    }
+}
+
+impl Filter {
+    /// 条件を作る。グロブを変換できない場合は理由を返す
+    ///
+    /// `ignore` が true の場合は、`.gitignore` と `.ignore` で無視するパスを除く。
+    /// たどり始めたパスより上のディレクトリの規則は読まない。
+    /// `.git` ディレクトリは `ignore` によらず除き、たどり始めたパスに指定した場合だけたどる
+    pub fn new(
+        ignore: bool,
+        include: &[String],
+        exclude: &[String],
+        exclude_dir: &[String],
+    ) -> Result<Filter, String> {
+        let globs = |globs: &[String], name: &str| {
+            globs
+                .iter()
+                .map(|glob| Glob::new(glob, name))
+                .collect::<Result<Vec<_>, String>>()
+        };
+        Ok(Filter {
+            ignore,
+            include: globs(include, "include")?,
+            exclude: globs(exclude, "exclude")?,
+            exclude_dir: globs(exclude_dir, "exclude-dir")?,
+        })
+    }
+
+    /// 相対パス `relative` を除くか。`is_dir` は `relative` がディレクトリか
+    fn excludes(&self, relative: &Path, is_dir: bool) -> bool {
+        if is_dir {
+            return relative.file_name() == Some(".git".as_ref())
+                || matches_any(&self.exclude_dir, relative);
+        }
+        !self.include.is_empty() && !matches_any(&self.include, relative)
+            || matches_any(&self.exclude, relative)
+    }
+}
+
+/// `relative` がいずれかのグロブにマッチするか
+pub fn matches_any(globs: &[Glob], relative: &Path) -> bool {
+    let name = relative.file_name().unwrap_or_default().to_string_lossy();
+    let path = relative.to_string_lossy();
+    globs.iter().any(|glob| {
+        let text = if glob.path { &path } else { &name };
+        glob.regex.is_match(text)
+    })
+}
+
+/// たどれなかったパスと、その理由
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
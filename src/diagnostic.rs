@@ -0,0 +1,144 @@
+//! miette の [`Diagnostic`] トレイトの実装。`miette` フィーチャを有効にすると使える
+//!
+//! エラーは元のパターンを持たないため、そのままでは範囲のラベルだけを報告する。
+//! [`Error::with_pattern`] でパターンと組にすると、パターン中の範囲に説明のラベルを付け、
+//! 修正の提案を添えて表示できる
+//!
+//! ```text
+//! let pattern = "ab)c";
+//! let regex = Regex::new(pattern).map_err(|e| e.with_pattern(pattern))?;
+//! ```
+use crate::{
+    Error,
+    engine::{
+        binary::DecodeError,
+        codegen::CodeGenError,
+        evaluator::EvalError,
+        message,
+        parser::{ParserError, ParserErrors},
+    },
+};
+use miette::{Diagnostic, LabeledSpan, SourceCode};
+use std::{
+    error,
+    fmt::{self, Display},
+};
+
+/// パースエラーの範囲に、エラーの説明のラベルを付ける
+fn label(e: &ParserError) -> LabeledSpan {
+    let span = e.span();
+    LabeledSpan::new_with_span(
+        Some(message::parser_error(e.kind()).to_string()),
+        span.byte_start..span.byte_end,
+    )
+}
+
+impl Diagnostic for ParserError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(self.code()))
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(std::iter::once(label(self))))
+    }
+}
+
+impl Diagnostic for ParserErrors {
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(self.0.iter().map(label)))
+    }
+}
+
+impl Diagnostic for CodeGenError {}
+
+impl Diagnostic for EvalError {}
+
+impl Diagnostic for DecodeError {}
+
+impl Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.inner().code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.inner().labels()
+    }
+}
+
+impl Error {
+    /// 元のエラー
+    fn inner(&self) -> &dyn Diagnostic {
+        match self {
+            Error::Parse(e) => e,
+            Error::ParseAll(e) => e,
+            Error::CodeGen(e) => e,
+            Error::Eval(e) => e,
+            Error::Decode(e) => e,
+            Error::Set(_, e) => e.inner(),
+        }
+    }
+
+    /// エラーをパース元のパターンと組にする
+    ///
+    /// 返したエラーは、パターン中の範囲のラベルと修正の提案を報告する
+    pub fn with_pattern(self, pattern: &str) -> PatternError {
+        PatternError {
+            error: self,
+            pattern: pattern.to_string(),
+        }
+    }
+}
+
+/// パース元のパターンと組にしたエラー。[`Error::with_pattern`] で生成する
+///
+/// 表示は元のエラーと同じ
+#[derive(Debug)]
+pub struct PatternError {
+    error: Error,    // 元のエラー
+    pattern: String, // パース元のパターン
+}
+
+impl PatternError {
+    /// 元のエラー
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    /// パース元のパターン
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl error::Error for PatternError {} // エラー用に Error トレイトを実装
+
+impl Diagnostic for PatternError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.error.code()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        // 提案は、エラーが1つだけの場合に添える
+        let e = match &self.error {
+            Error::Parse(e) => e,
+            Error::ParseAll(ParserErrors(errors)) if errors.len() == 1 => &errors[0],
+            _ => return None,
+        };
+        let suggestion = e.suggestion(&self.pattern)?;
+        Some(Box::new(suggestion.message))
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.pattern)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.error.labels()
+    }
+}
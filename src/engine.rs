@@ -1,25 +1,138 @@
 use std::fmt::Display;
 
-mod codegen;
-mod parser;
+pub mod analysis;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+pub mod binary;
+pub mod class;
+pub mod closure;
+pub mod codegen;
+pub mod dfa;
+pub mod evaluator;
+pub mod glob;
+pub mod graph;
+pub mod length;
+pub mod lint;
+pub mod literal;
+pub mod look;
+pub mod message;
+pub mod meta;
+pub mod normalize;
+pub mod optimizer;
+pub mod parser;
+pub mod prefilter;
+pub mod simplify;
+pub mod stats;
+pub mod syntax;
+pub mod token;
+pub mod tree;
+pub mod unparse;
+pub mod utf8;
+pub mod visit;
 
 /// アセンブリのニーモニックを表す列挙型
 /// オペランドをフィールドに持つ
-#[derive(Debug)]
+///
+/// アドレスは u32 で表し、可変長のオペランドは [`Program`] の表に分けて格納するため、
+/// 1命令は 12 バイトに収まる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     Char(char),
+    Class(u32), // Program::classes のインデックス
     Match,
-    Jump(usize),
-    Split(usize, usize),
+    Jump(u32),
+    Split(u32, u32),
+    RepeatStart(u32),    // Program::repeats のインデックスの回数を 0 にする
+    Repeat(u32, u32),    // 回数に応じて直後の本体か、脱出先のアドレスへ進む
+    RepeatEnd(u32, u32), // 回数を 1 増やし、ループの先頭のアドレスへ戻る
+    Mark(u32),           // ループに入った位置を Program::marks 個の場所の1つに記録する
+    Progress(u32),       // 2回目以降の繰り返しで、記録した位置から進んでいなければ失敗する
+    Again(u32),          // 記録した位置から進んでいなければ失敗し、進んでいれば現在の位置を記録する
+    Look(look::Look),    // 位置の前後の文字が判定を満たさなければ失敗する
+    Save(u32),           // 現在の位置を Program::slots 個の場所の1つに捕獲する
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Instruction::Char(c) => write!(f, "char {c}"),
+            Instruction::Class(idx) => write!(f, "class #{idx}"),
             Instruction::Match => write!(f, "match"),
             Instruction::Jump(addr) => write!(f, "jump {:>04}", addr),
             Instruction::Split(addr1, addr2) => write!(f, "split {:>04}, {:>04}", addr1, addr2),
+            Instruction::RepeatStart(idx) => write!(f, "repeat_start #{idx}"),
+            Instruction::Repeat(idx, addr) => write!(f, "repeat #{idx}, {:>04}", addr),
+            Instruction::RepeatEnd(idx, addr) => write!(f, "repeat_end #{idx}, {:>04}", addr),
+            Instruction::Mark(slot) => write!(f, "mark #{slot}"),
+            Instruction::Progress(slot) => write!(f, "progress #{slot}"),
+            Instruction::Again(slot) => write!(f, "again #{slot}"),
+            Instruction::Look(look) => write!(f, "look {look}"),
+            Instruction::Save(slot) => write!(f, "save #{slot}"),
         }
     }
 }
+
+/// 回数を数える繰り返しの、回数の下限と上限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Repeat {
+    pub min: u32, // 下限
+    pub max: u32, // 上限
+}
+
+/// 命令列と、命令から参照される文字クラスと繰り返しの表
+///
+/// 繰り返しの回数を数える命令はバックトラック型の評価器でのみ実行できる
+#[derive(Debug, Default)]
+pub struct Program {
+    pub insts: Vec<Instruction>,
+    pub classes: Vec<class::CharClass>,
+    pub repeats: Vec<Repeat>,
+    pub marks: usize,                        // 位置を記録する場所の数
+    pub slots: usize, // 捕獲した位置を記録する場所の数。捕獲しない命令列では 0
+    pub closures: Option<closure::Closures>, // 各命令から jump と split をたどって到達する命令
+}
+
+impl Program {
+    /// 文字クラスの範囲に文字が含まれるかを判定する
+    pub fn class_contains(&self, idx: u32, c: char) -> bool {
+        self.classes[idx as usize].contains(c)
+    }
+
+    /// 回数を数える繰り返しの命令を含むかを判定する
+    pub fn has_repeats(&self) -> bool {
+        !self.repeats.is_empty()
+    }
+
+    /// 位置の判定の命令を含むかを判定する
+    pub fn has_looks(&self) -> bool {
+        self.insts
+            .iter()
+            .any(|inst| matches!(inst, Instruction::Look(_)))
+    }
+}
+
+impl Display for Program {
+    /// アドレスを付けて命令を1行ずつ出力する。文字クラスは範囲を展開して表示する
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (pc, inst) in self.insts.iter().enumerate() {
+            write!(f, "{pc:>04}: {inst}")?;
+            if let Instruction::Class(idx) = inst {
+                write!(f, " [")?;
+                for (lo, hi) in self.classes[*idx as usize].ranges() {
+                    if lo == hi {
+                        write!(f, "{lo}")?;
+                    } else {
+                        write!(f, "{lo}-{hi}")?;
+                    }
+                }
+                write!(f, "]")?;
+            }
+            if let Instruction::Repeat(idx, _) = inst {
+                let Repeat { min, max } = self.repeats[*idx as usize];
+                write!(f, " {{{min},{max}}}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
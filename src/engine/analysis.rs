@@ -0,0 +1,274 @@
+//! バックトラックの回数が入力の長さに対して急激に増えうる構造を、パターンから探す
+//!
+//! 利用者が入力したパターンを受け付けるサービスで、コンパイルする前に危険なパターンを
+//! 見分けるために利用する。エンジンを指定しない場合、このクレートは入れ子になった繰り返しと
+//! 繰り返しの中の選択に Pike VM を選び、展開した命令列が命令数の上限を超える場合は
+//! コンパイルをエラーにする。そのため検索の時間は入力の長さに比例するが、
+//! エンジンを指定する場合や、他のエンジンに渡すパターンの検査にも利用できる。
+//! 隣り合う繰り返しはバックトラック型の評価器で検索するため、多項式の時間がかかりうる
+//!
+//! 繰り返しは `*`、`+` と、上限が 2 以上の `{n,m}` を指す。次の構造を問題として報告する
+//!
+//! - 繰り返しの本体が、繰り返しだけからなる：`(a+)+`、`(\w+\s?)*`、`(a{1,2})*`
+//! - 繰り返しの本体の選択肢が、同じ文字から始まりうる：`(a|ab)*`、`(\w|\d)+`、`(a|aa){0,40}`
+//! - 上限のある繰り返しの本体が、空文字列にマッチしうる：`(a?){0,1000}`
+//! - 繰り返しの中に、繰り返しがある：`(a+b)*`
+//! - 同じ文字にマッチしうる上限のない繰り返しが隣り合っている：`\d+\d*`
+use super::{
+    class::normalize,
+    length, message,
+    parser::{self, AST, AstNode, Flags, NodeId, ParserError},
+};
+use std::{
+    fmt::{self, Display},
+    ops::Range,
+};
+
+/// 問題の深刻さ。後ろのものほど深刻
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Polynomial,  // マッチに失敗するまでの時間が、入力の長さの多項式で増える
+    Exponential, // マッチに失敗するまでの時間が、入力の長さの指数で増える
+}
+
+/// 問題とみなした構造
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reason {
+    NestedQuantifier,       // 繰り返しの中に、繰り返しか空文字列にマッチしうる式がある
+    OverlappingAlternation, // 繰り返しの中の選択肢が、同じ文字から始まりうる
+    AdjacentQuantifiers,    // 同じ文字にマッチしうる上限のない繰り返しが隣り合っている
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", message::reason(*self))
+    }
+}
+
+/// 見つかった問題
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Finding {
+    pub severity: Severity,
+    pub reason: Reason,
+    pub span: Range<usize>, // 問題の部分パターンの範囲（バイト）
+}
+
+/// パターンの解析結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub findings: Vec<Finding>, // 見つかった問題。部分パターンの開始位置の順
+}
+
+impl Report {
+    /// 最も深刻な問題の深刻さ。問題がない場合は `None`
+    pub fn severity(&self) -> Option<Severity> {
+        self.findings.iter().map(|finding| finding.severity).max()
+    }
+}
+
+/// パターンをパースし、バックトラックの回数が急激に増えうる構造を探す
+///
+/// パースに失敗した場合はエラーを返す
+pub fn analyze(pattern: &str) -> Result<Report, ParserError> {
+    let ast = parser::parse(pattern, Flags::default())?;
+    let mut findings = Vec::new();
+    visit(&ast, ast.root(), None, &mut findings);
+    findings.sort_by_key(|finding| (finding.span.start, finding.span.end));
+    findings.dedup();
+    Ok(Report { findings })
+}
+
+/// `id` 以下の部分木を調べ、見つかった問題を `out` に追加する
+///
+/// `outer` は `id` を囲む最も内側の繰り返し。
+/// 指数的に増える繰り返しは、その中の問題を重ねて報告しない
+fn visit(ast: &AST, id: NodeId, outer: Option<NodeId>, out: &mut Vec<Finding>) {
+    if let Some(e) = loop_body(ast, id) {
+        // 上限のある繰り返しは、空文字列にマッチする本体も上限まで繰り返す
+        let bounded = matches!(ast[id], AstNode::Repeat(_, _, Some(_)));
+        let reason = if bounded && length::nullable(ast, e) {
+            Some(Reason::NestedQuantifier)
+        } else {
+            ambiguous(ast, e)
+        };
+        if let Some(reason) = reason {
+            out.push(Finding {
+                severity: Severity::Exponential,
+                reason,
+                span: ast.span(id),
+            });
+            return;
+        }
+        // 入れ子になった繰り返しは、外側の繰り返しの範囲で報告する
+        if let Some(outer) = outer {
+            out.push(Finding {
+                severity: Severity::Polynomial,
+                reason: Reason::NestedQuantifier,
+                span: ast.span(outer),
+            });
+        }
+        visit(ast, e, Some(id), out);
+        return;
+    }
+    match &ast[id] {
+        AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => (),
+        AstNode::Plus(e)
+        | AstNode::Star(e)
+        | AstNode::Question(e)
+        | AstNode::Group(e, _)
+        | AstNode::Repeat(e, _, _) => {
+            visit(ast, *e, outer, out);
+        }
+        AstNode::Or(..) => {
+            for e in ast.branches(id) {
+                visit(ast, e, outer, out);
+            }
+        }
+        AstNode::Seq(v) => {
+            for e in v {
+                visit(ast, *e, outer, out);
+            }
+            if let Some((e1, e2)) = adjacent(ast, v) {
+                out.push(Finding {
+                    severity: Severity::Polynomial,
+                    reason: Reason::AdjacentQuantifiers,
+                    span: ast.span(e1).start..ast.span(e2).end,
+                });
+            }
+        }
+    }
+}
+
+/// 繰り返しの本体が、同じ文字列に複数の方法でマッチしうるかを判定する
+fn ambiguous(ast: &AST, id: NodeId) -> Option<Reason> {
+    let id = strip(ast, id);
+    if loop_body(ast, id).is_some() {
+        return Some(Reason::NestedQuantifier);
+    }
+    match &ast[id] {
+        // 繰り返し以外が空文字列にマッチしうる場合は、繰り返しだけが残る
+        AstNode::Seq(v) => {
+            let loops = v.iter().filter(|&&e| is_loop(ast, e)).count();
+            let rest_nullable = v
+                .iter()
+                .all(|&e| is_loop(ast, e) || length::nullable(ast, e));
+            if (loops > 0 && rest_nullable) || adjacent(ast, v).is_some() {
+                Some(Reason::NestedQuantifier)
+            } else {
+                None
+            }
+        }
+        AstNode::Or(..) => {
+            let mut branches = Vec::new();
+            collect_or(ast, id, &mut branches);
+            let firsts: Vec<_> = branches.iter().map(|&e| firsts(ast, e)).collect();
+            for (i, a) in firsts.iter().enumerate() {
+                if firsts[i + 1..].iter().any(|b| overlaps(a, b)) {
+                    return Some(Reason::OverlappingAlternation);
+                }
+            }
+            branches.iter().find_map(|&e| ambiguous(ast, e))
+        }
+        _ => None,
+    }
+}
+
+/// 連接の中で隣り合う、同じ文字にマッチしうる1文字の上限のない繰り返しの組を返す
+fn adjacent(ast: &AST, v: &[NodeId]) -> Option<(NodeId, NodeId)> {
+    let chars = |e: NodeId| match &ast[strip(ast, e)] {
+        AstNode::Plus(body) | AstNode::Star(body) | AstNode::Repeat(body, _, None) => {
+            match &ast[strip(ast, *body)] {
+                AstNode::Char(c) => Some(vec![(*c, *c)]),
+                AstNode::Class(class) => Some(class.ranges().to_vec()),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    v.windows(2).find_map(|pair| {
+        let (a, b) = (chars(pair[0])?, chars(pair[1])?);
+        overlaps(&a, &b).then_some((pair[0], pair[1]))
+    })
+}
+
+/// 捕獲グループを取り除いた中の式
+fn strip(ast: &AST, mut id: NodeId) -> NodeId {
+    while let AstNode::Group(e, _) = ast[id] {
+        id = e;
+    }
+    id
+}
+
+/// 2回以上繰り返しうる式であれば、その本体を返す。`?` と `{n,1}` は繰り返しとみなさない
+fn loop_body(ast: &AST, id: NodeId) -> Option<NodeId> {
+    match ast[id] {
+        AstNode::Plus(e) | AstNode::Star(e) | AstNode::Repeat(e, _, None) => Some(e),
+        AstNode::Repeat(e, _, Some(max)) if max > 1 => Some(e),
+        _ => None,
+    }
+}
+
+/// 捕獲グループを取り除いた中の式が、繰り返しであるかを判定する
+fn is_loop(ast: &AST, id: NodeId) -> bool {
+    loop_body(ast, strip(ast, id)).is_some()
+}
+
+/// 入れ子になった OR の選択肢を集める
+fn collect_or(ast: &AST, id: NodeId, out: &mut Vec<NodeId>) {
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+        match ast[strip(ast, id)] {
+            AstNode::Or(e1, e2) => {
+                stack.push(e2);
+                stack.push(e1);
+            }
+            _ => out.push(id),
+        }
+    }
+}
+
+/// マッチする文字列の先頭になりうる文字の範囲
+fn firsts(ast: &AST, id: NodeId) -> Vec<(char, char)> {
+    let ranges = match &ast[id] {
+        AstNode::Char(c) => vec![(*c, *c)],
+        AstNode::Class(class) => class.ranges().to_vec(),
+        AstNode::Look(_) => Vec::new(),
+        AstNode::Plus(e)
+        | AstNode::Star(e)
+        | AstNode::Question(e)
+        | AstNode::Repeat(e, _, _)
+        | AstNode::Group(e, _) => firsts(ast, *e),
+        AstNode::Or(..) => ast
+            .branches(id)
+            .iter()
+            .flat_map(|&e| firsts(ast, e))
+            .collect(),
+        // 空文字列にマッチしうる要素の次の要素も、先頭になりうる
+        AstNode::Seq(v) => {
+            let mut ranges = Vec::new();
+            for &e in v {
+                ranges.extend(firsts(ast, e));
+                if !length::nullable(ast, e) {
+                    break;
+                }
+            }
+            ranges
+        }
+    };
+    normalize(ranges)
+}
+
+/// 昇順に並んだ2つの文字の範囲の列が、共通の文字を含むかを判定する
+fn overlaps(a: &[(char, char)], b: &[(char, char)]) -> bool {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].1 < b[j].0 {
+            i += 1;
+        } else if b[j].1 < a[i].0 {
+            j += 1;
+        } else {
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,105 @@
+//! ファジング用に、構造の正しい AST を任意のバイト列から生成する
+//!
+//! 子ノードを親ノードより前に格納し、捕獲グループには開き括弧の順に 1 から番号を付ける。
+//! パターンの文字列を持たないため、パターン中の範囲はすべて空にする
+use super::{
+    class::CharClass,
+    look::Look,
+    parser::{AST, AstNode, NodeId},
+};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+/// 生成する AST の入れ子の深さの上限
+const MAX_DEPTH: u32 = 8;
+
+/// 連接の要素数と、文字クラスの範囲の数の上限
+const MAX_LEN: usize = 4;
+
+/// 繰り返しの回数の上限。大きすぎると命令数の上限を超えるだけになる
+const MAX_REPEAT: u32 = 5;
+
+/// 生成する位置の判定
+const LOOKS: [Look; 8] = [
+    Look::Start,
+    Look::End,
+    Look::StartLine,
+    Look::EndLine,
+    Look::WordBoundary(false),
+    Look::WordBoundary(true),
+    Look::NotWordBoundary(false),
+    Look::NotWordBoundary(true),
+];
+
+impl<'a> Arbitrary<'a> for AST {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<AST> {
+        let mut ast = AST::new();
+        let mut groups = 0;
+        let root = node(u, &mut ast, &mut groups, MAX_DEPTH)?;
+        ast.set_root(root);
+        ast.set_groups(groups);
+        Ok(ast)
+    }
+}
+
+/// ノードを1つ生成し、子ノードとあわせて `ast` に追加する
+fn node(u: &mut Unstructured, ast: &mut AST, groups: &mut u32, depth: u32) -> Result<NodeId> {
+    // 深さの上限では子ノードを持たないノードに限る
+    let kind = if depth == 0 || u.is_empty() {
+        u.int_in_range(0..=2)?
+    } else {
+        u.int_in_range(0..=9)?
+    };
+    let depth = depth.saturating_sub(1);
+    let node = match kind {
+        0 => AstNode::Char(any_char(u)?),
+        1 => {
+            let mut ranges = Vec::new();
+            for _ in 0..u.int_in_range(0..=MAX_LEN)? {
+                let (lo, hi) = (any_char(u)?, any_char(u)?);
+                ranges.push((lo.min(hi), lo.max(hi)));
+            }
+            AstNode::Class(CharClass::new(ranges))
+        }
+        2 => AstNode::Look(*u.choose(&LOOKS)?),
+        3 => AstNode::Plus(node(u, ast, groups, depth)?),
+        4 => AstNode::Star(node(u, ast, groups, depth)?),
+        5 => AstNode::Question(node(u, ast, groups, depth)?),
+        6 => {
+            let e = node(u, ast, groups, depth)?;
+            let min = u.int_in_range(0..=MAX_REPEAT)?;
+            let max = match u.arbitrary::<bool>()? {
+                true => Some(u.int_in_range(min..=MAX_REPEAT)?),
+                false => None,
+            };
+            AstNode::Repeat(e, min, max)
+        }
+        7 => {
+            // 子ノードのグループより先に番号を付ける
+            *groups += 1;
+            let idx = *groups;
+            AstNode::Group(node(u, ast, groups, depth)?, idx)
+        }
+        8 => {
+            let e1 = node(u, ast, groups, depth)?;
+            let e2 = node(u, ast, groups, depth)?;
+            AstNode::Or(e1, e2)
+        }
+        _ => {
+            let mut v = Vec::new();
+            for _ in 0..u.int_in_range(0..=MAX_LEN)? {
+                v.push(node(u, ast, groups, depth)?);
+            }
+            AstNode::Seq(v)
+        }
+    };
+    Ok(ast.push_span(node, 0..0))
+}
+
+/// 文字を生成する。検索対象の文字列と重なりやすいよう、多くは ASCII の印字可能文字にする
+fn any_char(u: &mut Unstructured) -> Result<char> {
+    if u.ratio(3, 4)? {
+        Ok(char::from(u.int_in_range(b' '..=b'~')?))
+    } else {
+        u.arbitrary()
+    }
+}
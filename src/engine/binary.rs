@@ -0,0 +1,441 @@
+//! 命令列をバイト列に書き出し、読み込むための形式
+//!
+//! 整数はリトルエンディアンで書き、可変長の列は要素数を先に書く。
+//! 読み込んだ命令列は、アドレスや表のインデックスが範囲内にあることと、
+//! 文字を消費せずに戻ってくる分岐がないことを確かめる。
+//! 確かめた命令列は評価してもパニックや無限ループを起こさないが、
+//! 元のパターンと同じ意味であるかまでは確かめない
+use super::{Instruction, Program, Repeat, class::CharClass, closure, look::Look, message};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// バイト列の読み込みに失敗したことを表すエラー
+///
+/// 今後のバージョンで種類が増える可能性がある
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    BadMagic,                // 先頭がこのクレートの形式を表すバイト列でない
+    UnsupportedVersion(u32), // 読み込めない版の形式
+    Truncated,               // 途中でバイト列が終わっている
+    Checksum,                // チェックサムが一致しない
+    Invalid,                 // 命令列や表の内容が矛盾している
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = message::decode_error(self);
+        match self {
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "DecodeError: {message}: version = {version}")
+            }
+            _ => write!(f, "DecodeError: {message}"),
+        }
+    }
+}
+
+impl Error for DecodeError {} // エラー用に Error トレイトを実装
+
+/// バイト列を書き出す
+#[derive(Debug, Default)]
+pub struct Writer {
+    pub bytes: Vec<u8>, // 書き出したバイト列
+}
+
+impl Writer {
+    /// 1バイトを書く
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    /// 4バイトの整数を書く
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// 8バイトの整数を書く
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// 要素数や位置を u32 で書く
+    pub fn len(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+
+    /// 真偽値を 0 か 1 の1バイトで書く
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    /// 値がない場合は 0 を、ある場合は 1 に続けて値を書く
+    pub fn option<T>(&mut self, value: Option<T>, f: impl FnOnce(&mut Writer, T)) {
+        match value {
+            None => self.u8(0),
+            Some(value) => {
+                self.u8(1);
+                f(self, value);
+            }
+        }
+    }
+
+    /// 文字列を、バイト数に続けて UTF-8 で書く
+    pub fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+}
+
+/// バイト列を先頭から読み込む
+#[derive(Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// `bytes` の先頭から読み込む
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    /// 読み残したバイト数
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// `n` バイトを読む
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    /// 1バイトを読む
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// 4バイトの整数を読む
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// 8バイトの整数を読む
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// 要素数を読む。1要素あたり `min_size` バイト以上の列が、残りのバイト列に収まることを確かめる
+    pub fn len(&mut self, min_size: usize) -> Result<usize, DecodeError> {
+        let len = self.u32()? as usize;
+        if len.saturating_mul(min_size) > self.bytes.len() {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(len)
+    }
+
+    /// 真偽値を読む。0 と 1 以外はエラー
+    pub fn bool(&mut self) -> Result<bool, DecodeError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::Invalid),
+        }
+    }
+
+    /// [`Writer::option`] で書いた値を読む
+    pub fn option<T>(
+        &mut self,
+        f: impl FnOnce(&mut Reader<'a>) -> Result<T, DecodeError>,
+    ) -> Result<Option<T>, DecodeError> {
+        match self.bool()? {
+            false => Ok(None),
+            true => f(self).map(Some),
+        }
+    }
+
+    /// [`Writer::str`] で書いた文字列を読む
+    pub fn str(&mut self) -> Result<&'a str, DecodeError> {
+        let len = self.len(1)?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| DecodeError::Invalid)
+    }
+
+    /// 4バイトの整数を文字として読む
+    fn char(&mut self) -> Result<char, DecodeError> {
+        char::from_u32(self.u32()?).ok_or(DecodeError::Invalid)
+    }
+}
+
+/// 64 ビットの FNV-1a ハッシュ。書き出したバイト列の破損を見つけるために使う
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// 命令列を書き出す。jump と split でたどる命令の並びは、読み込む際に求め直す
+pub fn write_program(w: &mut Writer, prog: &Program) {
+    w.len(prog.insts.len());
+    for inst in &prog.insts {
+        match *inst {
+            Instruction::Char(c) => {
+                w.u8(0);
+                w.u32(c as u32);
+            }
+            Instruction::Class(idx) => {
+                w.u8(1);
+                w.u32(idx);
+            }
+            Instruction::Match => w.u8(2),
+            Instruction::Jump(addr) => {
+                w.u8(3);
+                w.u32(addr);
+            }
+            Instruction::Split(addr1, addr2) => {
+                w.u8(4);
+                w.u32(addr1);
+                w.u32(addr2);
+            }
+            Instruction::RepeatStart(idx) => {
+                w.u8(5);
+                w.u32(idx);
+            }
+            Instruction::Repeat(idx, addr) => {
+                w.u8(6);
+                w.u32(idx);
+                w.u32(addr);
+            }
+            Instruction::RepeatEnd(idx, addr) => {
+                w.u8(7);
+                w.u32(idx);
+                w.u32(addr);
+            }
+            Instruction::Mark(slot) => {
+                w.u8(8);
+                w.u32(slot);
+            }
+            Instruction::Progress(slot) => {
+                w.u8(9);
+                w.u32(slot);
+            }
+            Instruction::Again(slot) => {
+                w.u8(10);
+                w.u32(slot);
+            }
+            Instruction::Look(look) => {
+                w.u8(11);
+                let (tag, ascii) = match look {
+                    Look::Start => (0, false),
+                    Look::End => (1, false),
+                    Look::StartLine => (2, false),
+                    Look::EndLine => (3, false),
+                    Look::WordBoundary(ascii) => (4, ascii),
+                    Look::NotWordBoundary(ascii) => (5, ascii),
+                };
+                w.u8(tag);
+                w.bool(ascii);
+            }
+            Instruction::Save(slot) => {
+                w.u8(12);
+                w.u32(slot);
+            }
+        }
+    }
+    w.len(prog.classes.len());
+    for class in &prog.classes {
+        w.len(class.ranges().len());
+        for &(lo, hi) in class.ranges() {
+            w.u32(lo as u32);
+            w.u32(hi as u32);
+        }
+    }
+    w.len(prog.repeats.len());
+    for repeat in &prog.repeats {
+        w.u32(repeat.min);
+        w.u32(repeat.max);
+    }
+    w.len(prog.marks);
+    w.len(prog.slots);
+}
+
+/// [`write_program`] で書き出した命令列を読み込み、内容を確かめる
+pub fn read_program(r: &mut Reader) -> Result<Program, DecodeError> {
+    let len = r.len(1)?;
+    let mut insts = Vec::with_capacity(len);
+    for _ in 0..len {
+        let inst = match r.u8()? {
+            0 => Instruction::Char(r.char()?),
+            1 => Instruction::Class(r.u32()?),
+            2 => Instruction::Match,
+            3 => Instruction::Jump(r.u32()?),
+            4 => Instruction::Split(r.u32()?, r.u32()?),
+            5 => Instruction::RepeatStart(r.u32()?),
+            6 => Instruction::Repeat(r.u32()?, r.u32()?),
+            7 => Instruction::RepeatEnd(r.u32()?, r.u32()?),
+            8 => Instruction::Mark(r.u32()?),
+            9 => Instruction::Progress(r.u32()?),
+            10 => Instruction::Again(r.u32()?),
+            11 => {
+                let tag = r.u8()?;
+                let ascii = r.bool()?;
+                Instruction::Look(match tag {
+                    0 => Look::Start,
+                    1 => Look::End,
+                    2 => Look::StartLine,
+                    3 => Look::EndLine,
+                    4 => Look::WordBoundary(ascii),
+                    5 => Look::NotWordBoundary(ascii),
+                    _ => return Err(DecodeError::Invalid),
+                })
+            }
+            12 => Instruction::Save(r.u32()?),
+            _ => return Err(DecodeError::Invalid),
+        };
+        insts.push(inst);
+    }
+
+    let len = r.len(4)?;
+    let mut classes = Vec::with_capacity(len);
+    for _ in 0..len {
+        let len = r.len(8)?;
+        let mut ranges = Vec::with_capacity(len);
+        for _ in 0..len {
+            let (lo, hi) = (r.char()?, r.char()?);
+            if lo > hi {
+                return Err(DecodeError::Invalid);
+            }
+            ranges.push((lo, hi));
+        }
+        classes.push(CharClass::new(ranges));
+    }
+
+    let len = r.len(8)?;
+    let mut repeats = Vec::with_capacity(len);
+    for _ in 0..len {
+        let (min, max) = (r.u32()?, r.u32()?);
+        if min > max {
+            return Err(DecodeError::Invalid);
+        }
+        repeats.push(Repeat { min, max });
+    }
+
+    let mut prog = Program {
+        insts,
+        classes,
+        repeats,
+        marks: r.u32()? as usize,
+        slots: r.u32()? as usize,
+        closures: None,
+    };
+    validate(&prog)?;
+    // 検索時に確保する表の大きさは、命令が使う番号から求め直す。
+    // 書き換えたデータの大きな数で、大量のメモリを確保しないようにする
+    (prog.marks, prog.slots) = used_slots(&prog.insts);
+    prog.closures = closure::compute(&prog);
+    Ok(prog)
+}
+
+/// 命令が使う、ループで位置を記録する場所の数と、位置を捕獲する場所の数
+fn used_slots(insts: &[Instruction]) -> (usize, usize) {
+    let (mut marks, mut slots) = (0, 0);
+    for inst in insts {
+        match *inst {
+            Instruction::Mark(slot) | Instruction::Progress(slot) | Instruction::Again(slot) => {
+                marks = marks.max(slot as usize + 1);
+            }
+            Instruction::Save(slot) => slots = slots.max(slot as usize + 1),
+            _ => (),
+        }
+    }
+    (marks, slots)
+}
+
+/// 命令のアドレスと表のインデックスが範囲内にあり、
+/// 文字を消費せずに同じ命令へ戻る分岐がないことを確かめる
+///
+/// progress と again は位置が進んでいなければ失敗し、repeat_end は回数の上限で止まるため、
+/// これらを通る分岐は戻ってきてもよい
+fn validate(prog: &Program) -> Result<(), DecodeError> {
+    let len = prog.insts.len() as u64;
+    let addr = |addr: u32| (addr as u64) < len;
+    let next = |pc: usize| (pc as u64 + 1) < len;
+    for (pc, inst) in prog.insts.iter().enumerate() {
+        let valid = match *inst {
+            Instruction::Match => true,
+            Instruction::Jump(a) => addr(a),
+            Instruction::Split(a1, a2) => addr(a1) && addr(a2),
+            Instruction::Char(_) | Instruction::Look(_) => next(pc),
+            Instruction::Class(idx) => (idx as usize) < prog.classes.len() && next(pc),
+            Instruction::RepeatStart(idx) => (idx as usize) < prog.repeats.len() && next(pc),
+            Instruction::Repeat(idx, a) | Instruction::RepeatEnd(idx, a) => {
+                (idx as usize) < prog.repeats.len() && addr(a) && next(pc)
+            }
+            Instruction::Mark(slot) | Instruction::Progress(slot) | Instruction::Again(slot) => {
+                (slot as usize) < prog.marks && next(pc)
+            }
+            Instruction::Save(slot) => (slot as usize) < prog.slots && next(pc),
+        };
+        if !valid {
+            return Err(DecodeError::Invalid);
+        }
+    }
+    if prog.insts.is_empty() {
+        return Err(DecodeError::Invalid);
+    }
+
+    // 文字を消費しない分岐だけをたどる深さ優先探索で、たどっている途中の命令に戻ったら循環とする
+    // 0: 未訪問、1: たどっている途中、2: たどり終えた
+    let mut color = vec![0u8; prog.insts.len()];
+    for root in 0..prog.insts.len() {
+        if color[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0)]; // (命令, 次に調べる分岐先の番号)
+        color[root] = 1;
+        while let Some((pc, i)) = stack.last_mut() {
+            let pc = *pc;
+            let succ = epsilon_succ(prog, pc);
+            if let Some(&to) = succ.get(*i) {
+                *i += 1;
+                match color[to] {
+                    0 => {
+                        color[to] = 1;
+                        stack.push((to, 0));
+                    }
+                    1 => return Err(DecodeError::Invalid),
+                    _ => (),
+                }
+            } else {
+                color[pc] = 2;
+                stack.pop();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 文字を消費せず、循環を止める仕組みも持たずに進む分岐先
+fn epsilon_succ(prog: &Program, pc: usize) -> Vec<usize> {
+    match prog.insts[pc] {
+        Instruction::Jump(addr) => vec![addr as usize],
+        Instruction::Split(addr1, addr2) => vec![addr1 as usize, addr2 as usize],
+        Instruction::Repeat(_, addr) => vec![pc + 1, addr as usize],
+        Instruction::RepeatStart(_)
+        | Instruction::Mark(_)
+        | Instruction::Look(_)
+        | Instruction::Save(_) => vec![pc + 1],
+        Instruction::Char(_)
+        | Instruction::Class(_)
+        | Instruction::Match
+        | Instruction::RepeatEnd(..)
+        | Instruction::Progress(_)
+        | Instruction::Again(_) => Vec::new(),
+    }
+}
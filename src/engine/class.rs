@@ -0,0 +1,173 @@
+//! 文字クラスを表す型
+//!
+//! 文字の範囲の一覧に加えて、ASCII 文字が含まれるかどうかを 128 ビットのビットマップで保持する。
+//! ASCII 文字の判定はビットマップを1回引くだけで済み、それ以外の文字は範囲を二分探索する
+use std::sync::OnceLock;
+
+/// 文字クラス
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CharClass {
+    ranges: Vec<(char, char)>, // 文字の範囲。昇順に並び、重なり合わない
+    ascii: u128,               // ASCII 文字のビットマップ
+}
+
+impl CharClass {
+    /// 文字の範囲から文字クラスを生成する。範囲は並べ替えて結合する
+    pub fn new(ranges: Vec<(char, char)>) -> CharClass {
+        let ranges = normalize(ranges);
+        let mut ascii = 0;
+        for &(lo, hi) in &ranges {
+            for c in lo as u32..=(hi as u32).min(127) {
+                ascii |= 1 << c;
+            }
+        }
+        CharClass { ranges, ascii }
+    }
+
+    /// 含まれない文字からなる文字クラスを返す
+    pub fn negate(&self) -> CharClass {
+        let mut ranges = Vec::new();
+        let mut next = Some('\0');
+        for &(lo, hi) in &self.ranges {
+            if let Some(start) = next
+                && start < lo
+            {
+                // lo は '\0' より大きいため、直前の文字が存在する
+                ranges.push((start, char_before(lo)));
+            }
+            next = char_after(hi);
+        }
+        if let Some(start) = next {
+            ranges.push((start, char::MAX));
+        }
+        CharClass::new(ranges)
+    }
+
+    /// 文字の範囲。昇順に並ぶ
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    /// 文字クラスに文字が含まれるかを判定する
+    pub fn contains(&self, c: char) -> bool {
+        if c.is_ascii() {
+            return self.ascii & (1 << c as u32) != 0;
+        }
+        let idx = self.ranges.partition_point(|&(_, hi)| hi < c);
+        self.ranges.get(idx).is_some_and(|&(lo, _)| lo <= c)
+    }
+}
+
+/// 文字の範囲の一覧としてシリアライズする
+#[cfg(feature = "serde")]
+impl serde::Serialize for CharClass {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.ranges.serialize(serializer)
+    }
+}
+
+/// 文字の範囲の一覧からデシリアライズする。範囲は並べ替えて結合する
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CharClass {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<CharClass, D::Error> {
+        let ranges = Vec::<(char, char)>::deserialize(deserializer)?;
+        if ranges.iter().any(|&(lo, hi)| lo > hi) {
+            return Err(serde::de::Error::custom("range start is greater than end"));
+        }
+        Ok(CharClass::new(ranges))
+    }
+}
+
+/// `\d`、`\w`、`\s` の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Perl {
+    Digit, // \d
+    Word,  // \w
+    Space, // \s
+}
+
+impl CharClass {
+    /// `\d`、`\w`、`\s` が表す文字クラスを返す
+    ///
+    /// `ascii` が真の場合は ASCII 文字に限る。
+    /// 偽の場合は標準ライブラリの `char::is_numeric`、[`is_word`]、`char::is_whitespace` を
+    /// 満たす文字からなり、その表は初めて利用する際に1度だけ作る
+    pub fn perl(kind: Perl, ascii: bool) -> CharClass {
+        if ascii {
+            let ranges = match kind {
+                Perl::Digit => vec![('0', '9')],
+                Perl::Word => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+                Perl::Space => vec![('\t', '\r'), (' ', ' ')],
+            };
+            return CharClass::new(ranges);
+        }
+
+        static TABLES: OnceLock<[CharClass; 3]> = OnceLock::new();
+        let tables = TABLES.get_or_init(|| {
+            [
+                from_fn(char::is_numeric),
+                from_fn(|c| is_word(c, false)),
+                from_fn(char::is_whitespace),
+            ]
+        });
+        tables[kind as usize].clone()
+    }
+}
+
+/// 単語を構成する文字であるかを判定する。`ascii` が真の場合は ASCII 文字に限る
+///
+/// 英数字と `_` を単語を構成する文字とする
+pub fn is_word(c: char, ascii: bool) -> bool {
+    if ascii {
+        c.is_ascii_alphanumeric() || c == '_'
+    } else {
+        c.is_alphanumeric() || c == '_'
+    }
+}
+
+/// 条件を満たす文字をすべて調べ、文字クラスにする
+fn from_fn(f: impl Fn(char) -> bool) -> CharClass {
+    let mut ranges: Vec<(char, char)> = Vec::new();
+    let mut run: Option<(char, char)> = None;
+    for c in '\0'..=char::MAX {
+        if f(c) {
+            run = Some(run.map_or((c, c), |(lo, _)| (lo, c)));
+        } else if let Some(r) = run.take() {
+            ranges.push(r);
+        }
+    }
+    ranges.extend(run);
+    CharClass::new(ranges)
+}
+
+/// 文字の範囲を昇順に並べ、重なり合う範囲や隣接する範囲を結合する
+pub fn normalize(mut ranges: Vec<(char, char)>) -> Vec<(char, char)> {
+    ranges.sort_unstable();
+    let mut result: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+    for (lo, hi) in ranges {
+        if let Some((_, last_hi)) = result.last_mut()
+            && (lo as u32 <= *last_hi as u32 + 1 || char_after(*last_hi) == Some(lo))
+        {
+            *last_hi = (*last_hi).max(hi);
+        } else {
+            result.push((lo, hi));
+        }
+    }
+    result
+}
+
+/// 直後の文字。サロゲートの範囲は飛ばす
+fn char_after(c: char) -> Option<char> {
+    match c {
+        '\u{d7ff}' => Some('\u{e000}'),
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// 直前の文字。サロゲートの範囲は飛ばす
+fn char_before(c: char) -> char {
+    match c {
+        '\u{e000}' => '\u{d7ff}',
+        _ => char::from_u32(c as u32 - 1).unwrap_or('\0'),
+    }
+}
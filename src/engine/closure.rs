@@ -0,0 +1,92 @@
+//! 状態の集合を進める評価器のために、jump や split でたどれる命令をあらかじめ求める
+//!
+//! 文字を読んだ後に到達しうる命令ごとに、jump、split、mark、progress、again だけで
+//! たどれる文字を消費する命令と match 命令を、たどる順に並べて保持する。
+//! 評価時は1文字ごとに同じ分岐をたどり直す代わりに、この並びを読むだけで状態集合に追加できる
+use super::{Instruction, Program};
+
+/// 並べる命令の総数の、命令1つあたりの上限。これを超える場合は求めない
+const MAX_LEN_PER_INST: usize = 16;
+
+/// 各命令から jump と split をたどって到達する命令の並び
+#[derive(Debug, Default, Clone)]
+pub struct Closures {
+    ranges: Vec<(u32, u32)>, // 命令ごとの pcs の範囲。到達しうる命令でなければ空
+    pcs: Vec<u32>,           // 各命令からたどる順に並べた、到達する命令のアドレス
+}
+
+impl Closures {
+    /// `pc` から jump と split をたどって到達する命令の並びの、[`Closures::pc`] で読む範囲
+    pub fn range(&self, pc: u32) -> (u32, u32) {
+        self.ranges[pc as usize]
+    }
+
+    /// 並べた命令のうち `i` 番目のもののアドレス
+    pub fn pc(&self, i: u32) -> u32 {
+        self.pcs[i as usize]
+    }
+}
+
+/// 先頭の命令と、文字を消費する命令の次の命令について、到達する命令の並びを求める
+///
+/// 回数を数える命令や位置を捕獲する命令を含む命令列は、状態の集合を進める評価器で
+/// そのまま実行しないため求めない。
+/// 位置の判定の命令を含む場合、判定を満たした先をたどる順は、それまでに同じ評価の中で
+/// たどった分岐に左右されるため求めない。並びが長くなりすぎる場合も `None` を返す
+pub fn compute(prog: &Program) -> Option<Closures> {
+    if prog.has_repeats() || prog.slots > 0 || prog.has_looks() {
+        return None;
+    }
+    let len = prog.insts.len();
+    let max = len.saturating_mul(MAX_LEN_PER_INST).max(256);
+
+    let mut closures = Closures {
+        ranges: vec![(0, 0); len],
+        pcs: Vec::new(),
+    };
+    // たどった命令は visited に記録し、次の命令から求める前にその分だけ戻す
+    let mut seen = vec![false; len];
+    let mut visited = Vec::new();
+    let mut stack = Vec::new();
+    let mut entries = vec![false; len];
+    entries[0] = true;
+    for (pc, inst) in prog.insts.iter().enumerate() {
+        if matches!(inst, Instruction::Char(_) | Instruction::Class(_)) && pc + 1 < len {
+            entries[pc + 1] = true;
+        }
+    }
+
+    for (entry, &is_entry) in entries.iter().enumerate() {
+        if !is_entry {
+            continue;
+        }
+        let start = closures.pcs.len();
+        for pc in visited.drain(..) {
+            seen[pc as usize] = false;
+        }
+        stack.push(entry as u32);
+        while let Some(pc) = stack.pop() {
+            if seen[pc as usize] {
+                continue;
+            }
+            seen[pc as usize] = true;
+            visited.push(pc);
+            match prog.insts[pc as usize] {
+                Instruction::Jump(addr) => stack.push(addr),
+                Instruction::Split(addr1, addr2) => {
+                    stack.push(addr2);
+                    stack.push(addr1);
+                }
+                Instruction::Mark(_) | Instruction::Progress(_) | Instruction::Again(_) => {
+                    stack.push(pc + 1)
+                }
+                _ => closures.pcs.push(pc),
+            }
+        }
+        if closures.pcs.len() > max {
+            return None;
+        }
+        closures.ranges[entry] = (start as u32, closures.pcs.len() as u32);
+    }
+    Some(closures)
+}
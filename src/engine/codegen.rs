@@ -0,0 +1,563 @@
+//! 抽象構文木(AST)から命令列を生成する
+use super::{
+    Instruction, Program, Repeat,
+    class::CharClass,
+    length,
+    look::Look,
+    message,
+    parser::{AST, AstNode, NodeId},
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// コード生成エラーを表すための型
+#[derive(Debug)]
+pub enum CodeGenError {
+    PCOverFlow,     // プログラムカウンタのオーバーフロー
+    FailPlus,       // + のコード生成に失敗
+    FailStar,       // * のコード生成に失敗
+    FailOr,         // | のコード生成に失敗
+    FailQuestion,   // ? のコード生成に失敗
+    FailRepeat,     // {n,m} のコード生成に失敗
+    CompiledTooBig, // 命令数が上限を超える
+}
+
+impl Display for CodeGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CodeGenError: {}", message::codegen_error(self))
+    }
+}
+
+impl Error for CodeGenError {} // エラー用に Error トレイトを実装
+
+/// コード生成器
+///
+/// `reverse` が真の場合は、連接を逆順に並べた逆向きのプログラムを生成する
+#[derive(Debug)]
+struct Generator {
+    pc: u32,
+    insts: Vec<Instruction>,
+    classes: Vec<CharClass>,
+    repeats: Vec<Repeat>,
+    marks: usize, // 位置を記録する場所の数
+    reverse: bool,
+    unroll_limit: u32, // 繰り返しの回数がこれを超える場合は、展開せずに回数を数える
+    slots: usize,      // 捕獲する位置の数。0 の場合は捕獲グループの中の式だけを生成する
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Generator {
+            pc: 0,
+            insts: Vec::new(),
+            classes: Vec::new(),
+            repeats: Vec::new(),
+            marks: 0,
+            reverse: false,
+            unroll_limit: u32::MAX,
+            slots: 0,
+        }
+    }
+}
+
+/// AST から命令列を生成する
+///
+/// `{n,m}` は `unroll_limit` 回までは式を並べて展開し、
+/// それより多く繰り返す場合は回数を数える命令を生成する
+pub fn get_code(ast: &AST, unroll_limit: u32) -> Result<Program, CodeGenError> {
+    let mut generator = Generator {
+        unroll_limit,
+        ..Default::default()
+    };
+    generator.gen_code(ast)?;
+    Ok(generator.into_program())
+}
+
+/// AST から逆向きの命令列を生成する
+///
+/// 生成されたプログラムは、入力を末尾から先頭に向かって読むことで
+/// マッチの終了位置から開始位置を求めるために利用する。
+/// 状態の集合を進める評価器で実行するため、`{n,m}` は常に展開する
+pub fn get_code_reverse(ast: &AST) -> Result<Program, CodeGenError> {
+    let mut generator = Generator {
+        reverse: true,
+        ..Default::default()
+    };
+    generator.gen_code(ast)?;
+    Ok(generator.into_program())
+}
+
+/// AST から捕獲グループの位置を捕獲する命令列を生成する
+///
+/// グループ `i` の開始位置と終了位置は、それぞれ `2 * i` と `2 * i + 1` 番目の場所に捕獲する。
+/// マッチ全体を表す 0 番目の場所には何も捕獲しない。
+/// `unroll_limit` は [`get_code`] と同じ。回数を数える命令を含む命令列は、
+/// バックトラック型の評価器で実行する
+pub fn get_code_captures(ast: &AST, unroll_limit: u32) -> Result<Program, CodeGenError> {
+    let mut generator = Generator {
+        unroll_limit,
+        slots: 2 * (ast.groups() as usize + 1),
+        ..Default::default()
+    };
+    generator.gen_code(ast)?;
+    Ok(generator.into_program())
+}
+
+/// 生成する命令列の命令数を、命令列を生成せずに求める
+///
+/// `unroll_limit` は [`get_code`] と同じ。
+/// `captures` が真の場合は、[`get_code_captures`] と同様に位置を捕獲する命令も数える。
+/// 命令数が u64 に収まらない場合は u64::MAX を返す
+pub fn code_len(ast: &AST, unroll_limit: u32, captures: bool) -> u64 {
+    expr_len(ast, ast.root(), unroll_limit, captures).saturating_add(1) // match の分
+}
+
+/// `id` 以下の部分木から生成する命令数。各ノードの命令数は、対応するコード生成の関数と同じ
+fn expr_len(ast: &AST, id: NodeId, unroll_limit: u32, captures: bool) -> u64 {
+    let len = |e: NodeId| expr_len(ast, e, unroll_limit, captures);
+    // e+ の命令数。空文字列にマッチしうる場合は mark、progress、split、again、jump が加わる
+    let plus = |e: NodeId| {
+        let extra = if length::nullable(ast, e) { 5 } else { 1 };
+        len(e).saturating_add(extra)
+    };
+    // e* の命令数。空文字列にマッチしうる場合は split の後ろに e+ を生成する
+    let star = |e: NodeId| {
+        if length::nullable(ast, e) {
+            plus(e).saturating_add(1)
+        } else {
+            len(e).saturating_add(2)
+        }
+    };
+    // e{n,m} の命令数。回数を数える場合は repeat_start、repeat、repeat_end が加わる
+    let bounded = |e: NodeId, min: u32, max: u32| {
+        if max > unroll_limit {
+            len(e).saturating_add(3)
+        } else {
+            let body = len(e);
+            body.saturating_mul(min as u64)
+                .saturating_add(body.saturating_add(1).saturating_mul((max - min) as u64))
+        }
+    };
+    match &ast[id] {
+        AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => 1,
+        AstNode::Group(e, _) => len(*e).saturating_add(if captures { 2 } else { 0 }),
+        AstNode::Seq(v) => v.iter().map(|&e| len(e)).fold(0, u64::saturating_add),
+        // 選択肢の間ごとに split と jmp が加わる
+        AstNode::Or(..) => {
+            let branches = ast.branches(id);
+            let extra = 2 * (branches.len() as u64 - 1);
+            branches
+                .iter()
+                .map(|&e| len(e))
+                .fold(extra, u64::saturating_add)
+        }
+        AstNode::Question(e) => len(*e).saturating_add(1),
+        AstNode::Plus(e) => plus(*e),
+        AstNode::Star(e) => star(*e),
+        AstNode::Repeat(e, 0, None) => star(*e),
+        AstNode::Repeat(e, min, None) => bounded(*e, *min - 1, *min - 1).saturating_add(plus(*e)),
+        AstNode::Repeat(e, min, Some(max)) => bounded(*e, *min, *max),
+    }
+}
+
+impl Generator {
+    /// 生成した命令列をプログラムに変換する
+    ///
+    /// jump と split でたどれる命令は、最適化で命令を並べ直した後に求める
+    fn into_program(self) -> Program {
+        Program {
+            insts: self.insts,
+            classes: self.classes,
+            repeats: self.repeats,
+            marks: self.marks,
+            slots: self.slots,
+            closures: None,
+        }
+    }
+
+    /// コード生成を行う関数の入り口
+    fn gen_code(&mut self, ast: &AST) -> Result<(), CodeGenError> {
+        self.gen_expr(ast, ast.root())?;
+        self.inc_pc()?;
+        self.insts.push(Instruction::Match);
+        Ok(())
+    }
+
+    /// AST をパターン分けしてコード生成を行う
+    fn gen_expr(&mut self, ast: &AST, id: NodeId) -> Result<(), CodeGenError> {
+        match &ast[id] {
+            AstNode::Char(c) => self.gen_char(*c)?,
+            AstNode::Class(class) => self.gen_class(class)?,
+            AstNode::Look(look) => self.gen_look(*look)?,
+            AstNode::Group(e, idx) => self.gen_group(ast, *e, *idx)?,
+            AstNode::Or(..) => self.gen_or(ast, id)?,
+            AstNode::Plus(e) => self.gen_plus(ast, *e)?,
+            AstNode::Star(e) => self.gen_star(ast, *e)?,
+            AstNode::Question(e) => self.gen_question(ast, *e)?,
+            AstNode::Repeat(e, min, max) => self.gen_repeat(ast, *e, *min, *max)?,
+            AstNode::Seq(v) => self.gen_seq(ast, v)?,
+        }
+        Ok(())
+    }
+
+    /// プログラムカウンタをインクリメント
+    fn inc_pc(&mut self) -> Result<(), CodeGenError> {
+        self.pc = self.pc.checked_add(1).ok_or(CodeGenError::PCOverFlow)?;
+        Ok(())
+    }
+
+    /// char 命令を生成
+    fn gen_char(&mut self, c: char) -> Result<(), CodeGenError> {
+        let inst = Instruction::Char(c);
+        self.insts.push(inst);
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// 文字クラスの命令を生成
+    ///
+    /// 文字クラスは表に追加し、命令からはそのインデックスで参照する
+    fn gen_class(&mut self, class: &CharClass) -> Result<(), CodeGenError> {
+        let idx = self.classes.len() as u32;
+        self.classes.push(class.clone());
+        self.insts.push(Instruction::Class(idx));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// 位置の判定の命令を生成
+    fn gen_look(&mut self, look: Look) -> Result<(), CodeGenError> {
+        self.insts.push(Instruction::Look(look));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// 捕獲グループのコード生成
+    ///
+    /// ```text
+    ///     save #2i
+    ///     e のコード
+    ///     save #2i+1
+    /// ```
+    ///
+    /// 捕獲しない場合は e のコードだけを生成する
+    fn gen_group(&mut self, ast: &AST, e: NodeId, idx: u32) -> Result<(), CodeGenError> {
+        if self.slots == 0 {
+            return self.gen_expr(ast, e);
+        }
+        self.insts.push(Instruction::Save(2 * idx));
+        self.inc_pc()?;
+        self.gen_expr(ast, e)?;
+        self.insts.push(Instruction::Save(2 * idx + 1));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// 連接のコード生成
+    ///
+    /// 逆向きのプログラムを生成する場合は、要素を末尾から順に生成する
+    fn gen_seq(&mut self, ast: &AST, exprs: &[NodeId]) -> Result<(), CodeGenError> {
+        if self.reverse {
+            for e in exprs.iter().rev() {
+                self.gen_expr(ast, *e)?;
+            }
+        } else {
+            for e in exprs {
+                self.gen_expr(ast, *e)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// OR 演算子のコード生成
+    ///
+    /// ```text
+    ///     split L1, L2
+    /// L1: e1 のコード
+    ///     jmp L3
+    /// L2: e2 のコード
+    /// L3:
+    /// ```
+    ///
+    /// 選択肢の多い OR は深い入れ子になるため、入れ子の OR を平坦化して選択肢を順に生成する。
+    /// 最後の選択肢以外の前に split を置き、各選択肢の後ろの jmp はすべて OR の終わりに飛ぶ
+    fn gen_or(&mut self, ast: &AST, id: NodeId) -> Result<(), CodeGenError> {
+        let branches = ast.branches(id);
+        let (last, rest) = branches.split_last().unwrap();
+        let mut jumps = Vec::with_capacity(rest.len());
+        for &e in rest {
+            // split L1, L2
+            let split_addr = self.pc;
+            self.inc_pc()?;
+            let split = Instruction::Split(self.pc, 0); // L1 = self.pc。L2 は後で埋める
+            self.insts.push(split);
+
+            // L1: e のコード
+            self.gen_expr(ast, e)?;
+
+            // jmp L3
+            jumps.push(self.pc);
+            self.insts.push(Instruction::Jump(0)); // L3 は後で埋める
+            self.inc_pc()?;
+
+            // L2 の値を設定
+            if let Some(Instruction::Split(_, l2)) = self.insts.get_mut(split_addr as usize) {
+                *l2 = self.pc;
+            } else {
+                return Err(CodeGenError::FailOr);
+            }
+        }
+
+        // L2: 最後の選択肢のコード
+        self.gen_expr(ast, *last)?;
+
+        // L3 の値を設定
+        for jmp_addr in jumps {
+            if let Some(Instruction::Jump(l3)) = self.insts.get_mut(jmp_addr as usize) {
+                *l3 = self.pc;
+            } else {
+                return Err(CodeGenError::FailOr);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// ? 限量子のコード生成
+    ///
+    /// ```text
+    ///     split L1, L2
+    /// L1: e のコード
+    /// L2:
+    /// ```
+    fn gen_question(&mut self, ast: &AST, e: NodeId) -> Result<(), CodeGenError> {
+        // split L1, L2
+        let split_addr = self.pc;
+        self.inc_pc()?;
+        let split = Instruction::Split(self.pc, 0); // L1 = self.pc。L2 は後で埋める
+        self.insts.push(split);
+
+        // L1: e のコード
+        self.gen_expr(ast, e)?;
+
+        // L2 の値を設定
+        if let Some(Instruction::Split(_, l2)) = self.insts.get_mut(split_addr as usize) {
+            *l2 = self.pc;
+            Ok(())
+        } else {
+            Err(CodeGenError::FailQuestion)
+        }
+    }
+
+    /// + 限量子のコード生成
+    ///
+    /// ```text
+    /// L1: e のコード
+    ///     split L1, L2
+    /// L2:
+    /// ```
+    ///
+    /// e が空文字列にマッチしうる場合は、位置が進まないまま繰り返して停止しなくなるのを防ぐ。
+    /// 1回目の繰り返しで進まなければ脱出だけを試し、2回目以降で進まなければ失敗させる
+    ///
+    /// ```text
+    ///     mark #i
+    /// L1: e のコード
+    ///     progress #i
+    ///     split L2, L3
+    /// L2: again #i
+    ///     jump L1
+    /// L3:
+    /// ```
+    fn gen_plus(&mut self, ast: &AST, e: NodeId) -> Result<(), CodeGenError> {
+        if !length::nullable(ast, e) {
+            // L1: e のコード
+            let l1 = self.pc;
+            self.gen_expr(ast, e)?;
+
+            // split L1, L2
+            self.inc_pc()?;
+            let split = Instruction::Split(l1, self.pc); // self.pc が L2
+            self.insts.push(split);
+            return Ok(());
+        }
+
+        // mark #i
+        let slot = self.marks as u32;
+        self.marks += 1;
+        self.inc_pc()?;
+        self.insts.push(Instruction::Mark(slot));
+
+        // L1: e のコード
+        let l1 = self.pc;
+        self.gen_expr(ast, e)?;
+
+        // progress #i
+        self.inc_pc()?;
+        self.insts.push(Instruction::Progress(slot));
+
+        // split L2, L3
+        let split_addr = self.pc;
+        self.inc_pc()?;
+        self.insts.push(Instruction::Split(self.pc, 0)); // L2 = self.pc。L3 は後で埋める
+
+        // L2: again #i
+        self.inc_pc()?;
+        self.insts.push(Instruction::Again(slot));
+
+        // jump L1
+        self.inc_pc()?;
+        self.insts.push(Instruction::Jump(l1));
+
+        // L3 の値を設定
+        if let Some(Instruction::Split(_, l3)) = self.insts.get_mut(split_addr as usize) {
+            *l3 = self.pc;
+            Ok(())
+        } else {
+            Err(CodeGenError::FailPlus)
+        }
+    }
+
+    /// * 限量子のコード生成
+    ///
+    /// ```text
+    /// L1: split L2, L3
+    /// L2: e のコード
+    ///     jump L1
+    /// L3:
+    /// ```
+    ///
+    /// e が空文字列にマッチしうる場合は、(e+)? として生成する
+    fn gen_star(&mut self, ast: &AST, e: NodeId) -> Result<(), CodeGenError> {
+        // L1: split L2, L3
+        let l1 = self.pc;
+        self.inc_pc()?;
+        let split = Instruction::Split(self.pc, 0); // L2 = self.pc。L3 は後で埋める
+        self.insts.push(split);
+
+        if length::nullable(ast, e) {
+            // L2: e+ のコード
+            self.gen_plus(ast, e)?;
+        } else {
+            // L2: e のコード
+            self.gen_expr(ast, e)?;
+
+            // jump L1
+            self.inc_pc()?;
+            self.insts.push(Instruction::Jump(l1));
+        }
+
+        // L3 の値を設定
+        if let Some(Instruction::Split(_, l3)) = self.insts.get_mut(l1 as usize) {
+            *l3 = self.pc;
+            Ok(())
+        } else {
+            Err(CodeGenError::FailStar)
+        }
+    }
+
+    /// {n,m} 限量子のコード生成
+    ///
+    /// 上限がない場合は e を n - 1 回並べた後ろに e+ を生成する。
+    /// 上限がある場合は e を n 回並べた後ろに、入れ子にした e? を m - n 回並べる。
+    /// 回数が `unroll_limit` を超える場合は、並べる代わりに回数を数える
+    ///
+    /// ```text
+    ///     e のコード（n 回）
+    ///     split L1, L3
+    /// L1: e のコード
+    ///     split L2, L3
+    /// L2: e のコード
+    ///     ...
+    /// L3:
+    /// ```
+    fn gen_repeat(
+        &mut self,
+        ast: &AST,
+        e: NodeId,
+        min: u32,
+        max: Option<u32>,
+    ) -> Result<(), CodeGenError> {
+        let Some(max) = max else {
+            if min == 0 {
+                return self.gen_star(ast, e);
+            }
+            self.gen_repeat(ast, e, min - 1, Some(min - 1))?;
+            return self.gen_plus(ast, e);
+        };
+        if max > self.unroll_limit {
+            return self.gen_counted(ast, e, min, max);
+        }
+
+        for _ in 0..min {
+            self.gen_expr(ast, e)?;
+        }
+
+        let mut splits = Vec::with_capacity((max - min) as usize);
+        for _ in min..max {
+            // split L1, L3
+            splits.push(self.pc);
+            self.inc_pc()?;
+            self.insts.push(Instruction::Split(self.pc, 0)); // L1 = self.pc。L3 は後で埋める
+
+            // L1: e のコード
+            self.gen_expr(ast, e)?;
+        }
+
+        // L3 の値を設定
+        for addr in splits {
+            if let Some(Instruction::Split(_, l3)) = self.insts.get_mut(addr as usize) {
+                *l3 = self.pc;
+            } else {
+                return Err(CodeGenError::FailRepeat);
+            }
+        }
+        Ok(())
+    }
+
+    /// 回数を数える {n,m} 限量子のコード生成
+    ///
+    /// ```text
+    ///     repeat_start #i
+    /// L1: repeat #i, L2
+    ///     e のコード
+    ///     repeat_end #i, L1
+    /// L2:
+    /// ```
+    fn gen_counted(
+        &mut self,
+        ast: &AST,
+        e: NodeId,
+        min: u32,
+        max: u32,
+    ) -> Result<(), CodeGenError> {
+        let idx = self.repeats.len() as u32;
+        self.repeats.push(Repeat { min, max });
+
+        // repeat_start #i
+        self.inc_pc()?;
+        self.insts.push(Instruction::RepeatStart(idx));
+
+        // L1: repeat #i, L2
+        let l1 = self.pc;
+        self.inc_pc()?;
+        self.insts.push(Instruction::Repeat(idx, 0)); // L2 は後で埋める
+
+        // e のコード
+        self.gen_expr(ast, e)?;
+
+        // repeat_end #i, L1
+        self.inc_pc()?;
+        self.insts.push(Instruction::RepeatEnd(idx, l1));
+
+        // L2 の値を設定
+        if let Some(Instruction::Repeat(_, l2)) = self.insts.get_mut(l1 as usize) {
+            *l2 = self.pc;
+            Ok(())
+        } else {
+            Err(CodeGenError::FailRepeat)
+        }
+    }
+}
@@ -0,0 +1,764 @@
+//! 命令列を決定性有限オートマトン(DFA)に変換する
+//!
+//! 文字と文字クラスの判定を UTF-8 のバイトの範囲の列に置き換えたバイト単位の NFA を作り、
+//! その状態の集合を1つの状態とみなして決定化したうえで、
+//! Hopcroft のアルゴリズムで状態数を最小化する
+use super::{
+    Instruction, Program,
+    binary::{DecodeError, Reader, Writer},
+    utf8,
+};
+use std::{collections::HashMap, fmt::Write};
+
+/// DFA の状態数の上限。超える場合は DFA を構築しない
+const MAX_STATES: usize = 4096;
+
+/// 遷移表の要素数の上限
+const MAX_TRANSITIONS: usize = 1 << 18;
+
+/// 状態集合を求める際にたどる命令の数の合計の上限
+///
+/// 状態数が上限に達する前でも、文字クラスの多いパターンでは状態集合が大きくなり、
+/// 状態を1つ求めるたびに多くの命令をたどる。超える場合は DFA を構築しない
+const MAX_WORK: usize = 1 << 24;
+
+/// これ以上進んでもマッチしないことを表す状態
+const DEAD: u32 = 0;
+
+/// 決定性有限オートマトン
+///
+/// 入力はバイトごとに読み、同じ遷移をするバイトに同じ番号（アルファベット）を割り当てる。
+/// 遷移表は `状態 × アルファベット` の大きさを持つ
+#[derive(Debug)]
+pub struct Dfa {
+    alphabet: [u8; 256], // バイトごとのアルファベット
+    stride: usize,       // アルファベットの数
+    trans: Vec<u32>,     // 遷移表。状態 s のアルファベット a による遷移先は trans[s * stride + a]
+    accept: Vec<bool>,   // 直前の位置でマッチが終わる状態
+    start: u32,
+}
+
+/// バイト単位の NFA の命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteInst {
+    Range(u8, u8, usize), // バイトが範囲に含まれたら後続へ進む
+    Jump(usize),
+    Split(usize, usize),
+    Match,
+}
+
+/// 決定化の途中の状態
+///
+/// NFA の命令のアドレスを優先度の高い順に並べたものと、次の位置から新たにマッチを始めるかどうかの組
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct StateKey {
+    pcs: Vec<usize>,
+    restart: bool,
+}
+
+impl Dfa {
+    /// 命令列から DFA を構築する。状態数か状態集合を求める手間が上限を超える場合や、
+    /// 繰り返しの回数を数える命令を含む場合は `None` を返す
+    ///
+    /// 構築した DFA は、検索を始めた位置以降のいずれかの位置から始まるマッチを探し、
+    /// 評価器と同じく左から最初に見つかったマッチのうち優先度の高いものの終了位置を求める。
+    /// マッチが見つかった時点で、それより優先度の低いスレッドと新たにマッチを始めるスレッドを捨てる
+    pub fn new(prog: &Program) -> Option<Dfa> {
+        if !Dfa::can_build(prog) {
+            return None;
+        }
+        let nfa = to_bytes(prog);
+        let (alphabet, stride) = partition(&nfa);
+
+        let mut threads = Threads::new(&nfa);
+
+        // 状態 0 はマッチしない状態とする
+        let mut keys = vec![StateKey {
+            pcs: Vec::new(),
+            restart: false,
+        }];
+        let mut ids: HashMap<StateKey, u32> = HashMap::from([(keys[0].clone(), DEAD)]);
+
+        let start = truncate(
+            &nfa,
+            StateKey {
+                pcs: threads.restart.clone(),
+                restart: true,
+            },
+        );
+        ids.insert(start.clone(), 1);
+        keys.push(start);
+
+        let mut trans = Vec::new();
+        let mut targets = vec![Vec::new(); stride];
+        let mut s = 0;
+        while s < keys.len() {
+            // アルファベットごとに、範囲に含まれた判定の後続のアドレスを優先度の高い順に集める
+            targets.iter_mut().for_each(Vec::clear);
+            for &pc in &keys[s].pcs {
+                if let ByteInst::Range(lo, hi, next) = nfa[pc]
+                    && lo <= hi
+                {
+                    for a in alphabet[lo as usize]..=alphabet[hi as usize] {
+                        targets[a as usize].push(next);
+                    }
+                }
+            }
+            let restart = keys[s].restart;
+            for targets in &targets {
+                let next = threads.step(&nfa, targets, restart);
+                if threads.work > MAX_WORK {
+                    return None;
+                }
+                let id = match ids.get(&next) {
+                    Some(&id) => id,
+                    None => {
+                        if keys.len() >= MAX_STATES || (keys.len() + 1) * stride > MAX_TRANSITIONS {
+                            return None;
+                        }
+                        let id = keys.len() as u32;
+                        ids.insert(next.clone(), id);
+                        keys.push(next);
+                        id
+                    }
+                };
+                trans.push(id);
+            }
+            s += 1;
+        }
+
+        let accept = keys
+            .iter()
+            .map(|key| key.pcs.iter().any(|&pc| nfa[pc] == ByteInst::Match))
+            .collect();
+        let mut dfa = Dfa {
+            alphabet,
+            stride,
+            trans,
+            accept,
+            start: 1,
+        };
+        dfa.minimize();
+        dfa.merge_alphabet();
+        Some(dfa)
+    }
+
+    /// 命令列から DFA を構築できる見込みがあるかを、構築せずに判定する
+    ///
+    /// 繰り返しの回数と、前後の文字による位置の判定は状態に含められない。
+    /// 真の場合も、状態数や手間が上限を超えて [`Dfa::new`] が `None` を返す場合がある
+    pub fn can_build(prog: &Program) -> bool {
+        !prog.has_repeats() && !prog.has_looks()
+    }
+
+    /// `input` の `start` 以降から始まるマッチを探し、その終了位置を返す
+    ///
+    /// 入力をバイトごとに読むため、文字に復号する必要がない。
+    /// マッチする状態には文字の符号化の途中で到達しないため、終了位置は文字の境界になる
+    pub fn find(&self, input: &str, start: usize) -> Option<usize> {
+        let mut state = self.start;
+        let mut end = self.accept[state as usize].then_some(start);
+        for (i, &b) in input.as_bytes()[start..].iter().enumerate() {
+            let a = self.alphabet[b as usize] as usize;
+            state = self.trans[state as usize * self.stride + a];
+            if state == DEAD {
+                break;
+            }
+            if self.accept[state as usize] {
+                end = Some(start + i + 1);
+            }
+        }
+        end
+    }
+
+    /// 遷移表を書き出す
+    pub fn write(&self, w: &mut Writer) {
+        w.bytes.extend_from_slice(&self.alphabet);
+        w.len(self.stride);
+        w.len(self.accept.len());
+        for &next in &self.trans {
+            w.u32(next);
+        }
+        for &accept in &self.accept {
+            w.bool(accept);
+        }
+        w.u32(self.start);
+    }
+
+    /// [`Dfa::write`] で書き出した遷移表を読み込み、内容を確かめる
+    ///
+    /// 状態数と遷移表の大きさが構築時の上限以下で、遷移先が範囲内にあることに加えて、
+    /// 文字の符号化の途中でマッチする状態に到達しないことを確かめる
+    pub fn read(r: &mut Reader) -> Result<Dfa, DecodeError> {
+        let alphabet: [u8; 256] = r.take(256)?.try_into().unwrap();
+        let stride = r.u32()? as usize;
+        let states = r.u32()? as usize;
+        if !(1..=256).contains(&stride)
+            || alphabet.iter().any(|&a| a as usize >= stride)
+            || states == 0
+            || states > MAX_STATES
+            || states * stride > MAX_TRANSITIONS
+        {
+            return Err(DecodeError::Invalid);
+        }
+        let mut trans = Vec::with_capacity(states * stride);
+        for _ in 0..states * stride {
+            let next = r.u32()?;
+            if next as usize >= states {
+                return Err(DecodeError::Invalid);
+            }
+            trans.push(next);
+        }
+        let accept = (0..states).map(|_| r.bool()).collect::<Result<_, _>>()?;
+        let start = r.u32()?;
+        if start as usize >= states {
+            return Err(DecodeError::Invalid);
+        }
+        let dfa = Dfa {
+            alphabet,
+            stride,
+            trans,
+            accept,
+            start,
+        };
+        if !dfa.ends_on_boundary() {
+            return Err(DecodeError::Invalid);
+        }
+        Ok(dfa)
+    }
+
+    /// UTF-8 の文字列を読む限り、文字の符号化の途中でマッチする状態に到達しないかを判定する
+    ///
+    /// 状態と、読みかけの文字の残りのバイト数の組をたどる
+    fn ends_on_boundary(&self) -> bool {
+        let mut seen = vec![[false; 4]; self.accept.len()];
+        let mut stack = vec![(self.start, 0)];
+        seen[self.start as usize][0] = true;
+        while let Some((state, pending)) = stack.pop() {
+            if pending > 0 && self.accept[state as usize] {
+                return false;
+            }
+            if state == DEAD {
+                continue;
+            }
+            // 先頭のバイトの範囲と、読んだ後の残りのバイト数
+            let ranges: &[(u8, u8, usize)] = if pending > 0 {
+                &[(0x80, 0xbf, pending - 1)]
+            } else {
+                &[
+                    (0x00, 0x7f, 0),
+                    (0xc2, 0xdf, 1),
+                    (0xe0, 0xef, 2),
+                    (0xf0, 0xf4, 3),
+                ]
+            };
+            for &(lo, hi, rest) in ranges {
+                for b in lo..=hi {
+                    let a = self.alphabet[b as usize] as usize;
+                    let next = self.trans[state as usize * self.stride + a];
+                    if !seen[next as usize][rest] {
+                        seen[next as usize][rest] = true;
+                        stack.push((next, rest));
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// 遷移表と、それを読んでマッチを探す関数を定義する Rust のモジュールのソースを返す
+    ///
+    /// 生成したモジュールは標準ライブラリ以外に依存せず、`name` をモジュール名とする。
+    /// `pattern` は説明のためのコメントにだけ利用する
+    pub fn to_rust(&self, name: &str, pattern: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "// regexp で生成したパターン {pattern:?} の DFA");
+        let _ = writeln!(out, "#[allow(dead_code)]\npub mod {name} {{");
+        let _ = writeln!(out, "    const STRIDE: usize = {};", self.stride);
+        let _ = writeln!(out, "    const START: u16 = {};", self.start);
+        write_table(&mut out, "ALPHABET", "u8", &self.alphabet);
+        write_table(&mut out, "TRANS", "u16", &self.trans);
+        write_table(&mut out, "ACCEPT", "bool", &self.accept);
+        out.push_str(
+            r#"
+    /// `haystack` の `start` 以降から始まるマッチを探し、その終了位置を返す
+    ///
+    /// `haystack` は UTF-8 のバイト列とし、不正なバイト列はどの文字にもマッチしない
+    pub fn find_end(haystack: &[u8], start: usize) -> Option<usize> {
+        let mut state = START as usize;
+        let mut end = ACCEPT[state].then_some(start);
+        for (i, &b) in haystack[start..].iter().enumerate() {
+            state = TRANS[state * STRIDE + ALPHABET[b as usize] as usize] as usize;
+            if state == 0 {
+                break;
+            }
+            if ACCEPT[state] {
+                end = Some(start + i + 1);
+            }
+        }
+        end
+    }
+
+    /// `haystack` がマッチする部分を含むかを判定する
+    pub fn is_match(haystack: &[u8]) -> bool {
+        let mut state = START as usize;
+        for &b in haystack {
+            if ACCEPT[state] {
+                return true;
+            }
+            state = TRANS[state * STRIDE + ALPHABET[b as usize] as usize] as usize;
+            if state == 0 {
+                return false;
+            }
+        }
+        ACCEPT[state]
+    }
+}
+"#,
+        );
+        out
+    }
+
+    /// Hopcroft のアルゴリズムで、区別できない状態をまとめる
+    ///
+    /// マッチする状態としない状態に分けた分割から始め、
+    /// ある区画に遷移するかどうかで区別できる区画を分割し続ける
+    fn minimize(&mut self) {
+        let n = self.accept.len();
+
+        // 逆向きの遷移。(遷移先, アルファベット) ごとの遷移元の一覧
+        let mut inverse: Vec<Vec<u32>> = vec![Vec::new(); n * self.stride];
+        for s in 0..n {
+            for a in 0..self.stride {
+                let t = self.trans[s * self.stride + a] as usize;
+                inverse[t * self.stride + a].push(s as u32);
+            }
+        }
+
+        let (acc, rej): (Vec<u32>, Vec<u32>) =
+            (0..n as u32).partition(|&s| self.accept[s as usize]);
+        let mut blocks: Vec<Vec<u32>> = [acc, rej].into_iter().filter(|b| !b.is_empty()).collect();
+        let mut block_of = vec![0; n];
+        for (b, states) in blocks.iter().enumerate() {
+            for &s in states {
+                block_of[s as usize] = b;
+            }
+        }
+        let mut work: Vec<usize> = (0..blocks.len()).collect();
+
+        let mut marked = vec![false; n];
+        while let Some(splitter) = work.pop() {
+            let splitter = blocks[splitter].clone();
+            for a in 0..self.stride {
+                // splitter に遷移する状態に印を付ける
+                let mut touched = Vec::new();
+                for &t in &splitter {
+                    for &s in &inverse[t as usize * self.stride + a] {
+                        if !marked[s as usize] {
+                            marked[s as usize] = true;
+                            touched.push(s);
+                        }
+                    }
+                }
+
+                let mut candidates: Vec<usize> =
+                    touched.iter().map(|&s| block_of[s as usize]).collect();
+                candidates.sort_unstable();
+                candidates.dedup();
+                for b in candidates {
+                    let (inside, outside): (Vec<u32>, Vec<u32>) =
+                        blocks[b].iter().copied().partition(|&s| marked[s as usize]);
+                    if outside.is_empty() {
+                        continue;
+                    }
+
+                    // 小さい方を新しい区画にする
+                    let (keep, split) = if inside.len() <= outside.len() {
+                        (outside, inside)
+                    } else {
+                        (inside, outside)
+                    };
+                    let new = blocks.len();
+                    for &s in &split {
+                        block_of[s as usize] = new;
+                    }
+                    blocks[b] = keep;
+                    blocks.push(split);
+                    // b が未処理であれば両方が、処理済みであれば小さい方が未処理の区画になる
+                    work.push(new);
+                }
+
+                for s in touched {
+                    marked[s as usize] = false;
+                }
+            }
+        }
+
+        // マッチしない状態を 0、開始状態を 1 とし、残りは元の順に番号を振り直す
+        let mut order = vec![block_of[DEAD as usize], block_of[self.start as usize]];
+        for &b in &block_of {
+            if !order.contains(&b) {
+                order.push(b);
+            }
+        }
+        order.dedup();
+        let mut renumber = vec![0u32; blocks.len()];
+        for (id, &b) in order.iter().enumerate() {
+            renumber[b] = id as u32;
+        }
+
+        let mut trans = vec![DEAD; order.len() * self.stride];
+        let mut accept = vec![false; order.len()];
+        for (id, &b) in order.iter().enumerate() {
+            let s = blocks[b][0] as usize;
+            accept[id] = self.accept[s];
+            for a in 0..self.stride {
+                let t = self.trans[s * self.stride + a] as usize;
+                trans[id * self.stride + a] = renumber[block_of[t]];
+            }
+        }
+        self.start = renumber[block_of[self.start as usize]];
+        self.trans = trans;
+        self.accept = accept;
+    }
+
+    /// すべての状態で同じ遷移をするアルファベットを1つにまとめ、遷移表の列を減らす
+    fn merge_alphabet(&mut self) {
+        let n = self.accept.len();
+        let column =
+            |a: usize| -> Vec<u32> { (0..n).map(|s| self.trans[s * self.stride + a]).collect() };
+
+        let mut columns: HashMap<Vec<u32>, u8> = HashMap::new();
+        let mut remap = Vec::with_capacity(self.stride);
+        let mut order = Vec::new();
+        for a in 0..self.stride {
+            let col = column(a);
+            let next = columns.len() as u8;
+            let id = *columns.entry(col).or_insert_with(|| {
+                order.push(a);
+                next
+            });
+            remap.push(id);
+        }
+
+        let stride = order.len();
+        let mut trans = vec![DEAD; n * stride];
+        for s in 0..n {
+            for (id, &a) in order.iter().enumerate() {
+                trans[s * stride + id] = self.trans[s * self.stride + a];
+            }
+        }
+
+        self.stride = stride;
+        self.trans = trans;
+        for a in &mut self.alphabet {
+            *a = remap[*a as usize];
+        }
+    }
+}
+
+/// 要素を1行に16個ずつ並べた定数の配列を出力する
+fn write_table<T: std::fmt::Display>(out: &mut String, name: &str, ty: &str, table: &[T]) {
+    let _ = writeln!(out, "    const {name}: [{ty}; {}] = [", table.len());
+    for row in table.chunks(16) {
+        out.push_str("       ");
+        for x in row {
+            let _ = write!(out, " {x},");
+        }
+        out.push('\n');
+    }
+    out.push_str("    ];\n");
+}
+
+/// 命令列をバイト単位の NFA に変換する
+///
+/// アドレス `pc` の命令は NFA の同じアドレスに置き、文字と文字クラスの判定は
+/// UTF-8 のバイトの範囲の列を [`Trie`] でまとめた判定に置き換えて末尾に追加する
+fn to_bytes(prog: &Program) -> Vec<ByteInst> {
+    let mut nfa: Vec<ByteInst> = prog
+        .insts
+        .iter()
+        .enumerate()
+        .map(|(pc, inst)| match *inst {
+            Instruction::Jump(addr) => ByteInst::Jump(addr as usize),
+            // 同じ状態に到達したスレッドは1つにまとめるため、位置が進んだかを確かめる必要はない。
+            // 位置の捕獲はマッチの範囲に影響しない
+            Instruction::Mark(_)
+            | Instruction::Progress(_)
+            | Instruction::Again(_)
+            | Instruction::Save(_) => ByteInst::Jump(pc + 1),
+            Instruction::Split(addr1, addr2) => ByteInst::Split(addr1 as usize, addr2 as usize),
+            Instruction::Char(_)
+            | Instruction::Class(_)
+            | Instruction::Match
+            | Instruction::RepeatStart(_)
+            | Instruction::Repeat(..)
+            | Instruction::RepeatEnd(..)
+            | Instruction::Look(_) => ByteInst::Match,
+        })
+        .collect();
+
+    for (pc, inst) in prog.insts.iter().enumerate() {
+        let ranges = match *inst {
+            Instruction::Char(c) => vec![(c, c)],
+            Instruction::Class(idx) => prog.classes[idx as usize].ranges().to_vec(),
+            _ => continue,
+        };
+        let mut trie = Trie::new(pc + 1);
+        for &(lo, hi) in &ranges {
+            for seq in utf8::sequences(lo, hi) {
+                trie.add(&mut nfa, &seq);
+            }
+        }
+        nfa[pc] = ByteInst::Jump(trie.finish(&mut nfa));
+    }
+    nfa
+}
+
+/// UTF-8 のバイトの範囲の列を、共通の接頭辞と接尾辞をまとめた NFA の命令にする
+///
+/// 列は符号位置の順に追加する。直前の列と共通の接頭辞を持つ間は同じノードをたどり、
+/// 共通でなくなった位置より深いノードはそれ以上遷移が増えないため、命令にして確定する。
+/// 確定したノードは遷移の一覧ごとに使い回すため、`[80-bf]` のような共通の接尾辞は1つになる
+struct Trie {
+    target: usize,                                  // 列を読み終えた後に進むアドレス
+    stack: Vec<Node>,                               // 直前の列をたどる確定していないノード
+    compiled: HashMap<Vec<(u8, u8, usize)>, usize>, // 確定したノードの遷移から、そのアドレス
+}
+
+/// 確定していないノード
+#[derive(Debug, Default)]
+struct Node {
+    trans: Vec<(u8, u8, usize)>, // 確定した遷移。範囲と遷移先のアドレス
+    last: Option<(u8, u8)>,      // 遷移先がまだ確定していない、最後に追加した遷移の範囲
+}
+
+impl Trie {
+    /// 列を読み終えた後に `target` へ進む、空のトライ
+    fn new(target: usize) -> Trie {
+        Trie {
+            target,
+            stack: vec![Node::default()],
+            compiled: HashMap::new(),
+        }
+    }
+
+    /// 列を追加する。列は直前に追加した列より後ろの符号位置を表すものとする
+    fn add(&mut self, nfa: &mut Vec<ByteInst>, seq: &[(u8, u8)]) {
+        let prefix = seq
+            .iter()
+            .zip(&self.stack)
+            .take_while(|&(&range, node)| node.last == Some(range))
+            .count();
+        if prefix == seq.len() {
+            return;
+        }
+        self.freeze(nfa, prefix.min(self.stack.len() - 1) + 1);
+        for (i, &range) in seq.iter().enumerate().skip(prefix) {
+            if i > prefix {
+                self.stack.push(Node::default());
+            }
+            self.stack.last_mut().unwrap().last = Some(range);
+        }
+    }
+
+    /// すべてのノードを確定し、先頭のノードのアドレスを返す
+    fn finish(mut self, nfa: &mut Vec<ByteInst>) -> usize {
+        self.freeze(nfa, 1);
+        let root = self.stack.pop().unwrap();
+        self.compile(nfa, root.trans)
+    }
+
+    /// 深さ `depth` より深いノードを確定し、深さ `depth` のノードまでを残す
+    fn freeze(&mut self, nfa: &mut Vec<ByteInst>, depth: usize) {
+        let mut next = self.target;
+        while self.stack.len() > depth {
+            let mut node = self.stack.pop().unwrap();
+            if let Some((lo, hi)) = node.last.take() {
+                node.trans.push((lo, hi, next));
+            }
+            next = self.compile(nfa, node.trans);
+        }
+        let node = &mut self.stack[depth - 1];
+        if let Some((lo, hi)) = node.last.take() {
+            node.trans.push((lo, hi, next));
+        }
+    }
+
+    /// ノードの遷移を、範囲の判定と、それを選ぶ split の連鎖にする
+    ///
+    /// 隣り合う範囲で遷移先が同じものは1つの範囲にまとめる。
+    /// 範囲の表すバイトは交わらないため、優先度は影響しない
+    fn compile(&mut self, nfa: &mut Vec<ByteInst>, mut trans: Vec<(u8, u8, usize)>) -> usize {
+        trans.dedup_by(|next, prev| {
+            let adjacent = prev.1.checked_add(1) == Some(next.0) && prev.2 == next.2;
+            if adjacent {
+                prev.1 = next.1;
+            }
+            adjacent
+        });
+        if let Some(&id) = self.compiled.get(&trans) {
+            return id;
+        }
+        let id = match trans[..] {
+            [] => {
+                // 空の文字クラスはどのバイトにもマッチしない
+                nfa.push(ByteInst::Range(1, 0, self.target));
+                nfa.len() - 1
+            }
+            _ => {
+                let mut heads: Vec<usize> = trans
+                    .iter()
+                    .map(|&(lo, hi, next)| {
+                        nfa.push(ByteInst::Range(lo, hi, next));
+                        nfa.len() - 1
+                    })
+                    .collect();
+                let mut id = heads.pop().unwrap();
+                while let Some(head) = heads.pop() {
+                    nfa.push(ByteInst::Split(head, id));
+                    id = nfa.len() - 1;
+                }
+                id
+            }
+        };
+        self.compiled.insert(trans, id);
+        id
+    }
+}
+
+/// NFA 中のバイトの範囲の境界でバイトの全体を区間に分け、
+/// バイトごとの区間の番号と、区間の数を返す
+fn partition(nfa: &[ByteInst]) -> ([u8; 256], usize) {
+    let mut bounds = [false; 257];
+    bounds[0] = true;
+    for inst in nfa {
+        if let ByteInst::Range(lo, hi, _) = *inst
+            && lo <= hi
+        {
+            bounds[lo as usize] = true;
+            bounds[hi as usize + 1] = true;
+        }
+    }
+
+    let mut alphabet = [0; 256];
+    let mut count = 0;
+    for b in 0..256 {
+        if bounds[b] {
+            count += 1;
+        }
+        alphabet[b] = (count - 1) as u8;
+    }
+    (alphabet, count)
+}
+
+/// 状態集合を求めるための作業領域
+struct Threads {
+    seen: Vec<u32>,      // 命令ごとに、最後にたどった際の世代
+    generation: u32,     // 状態集合を求めるたびに増やす世代
+    stack: Vec<usize>,   // たどる途中の命令のアドレス
+    restart: Vec<usize>, // 先頭の命令から新たにマッチを始めるスレッドの状態集合
+    work: usize,         // これまでにたどった命令の数
+}
+
+impl Threads {
+    fn new(nfa: &[ByteInst]) -> Threads {
+        let mut threads = Threads {
+            seen: vec![0; nfa.len()],
+            generation: 0,
+            stack: Vec::new(),
+            restart: Vec::new(),
+            work: 0,
+        };
+        let mut restart = Vec::new();
+        threads.clear();
+        threads.add(nfa, &mut restart, 0);
+        threads.restart = restart;
+        threads
+    }
+
+    /// たどった命令の記録を消す
+    fn clear(&mut self) {
+        if self.generation == u32::MAX {
+            self.seen.fill(0);
+            self.generation = 0;
+        }
+        self.generation += 1;
+    }
+
+    /// jump と split をたどり、バイトの範囲の判定と match 命令を状態集合に追加する
+    fn add(&mut self, nfa: &[ByteInst], list: &mut Vec<usize>, pc: usize) {
+        self.stack.push(pc);
+        while let Some(pc) = self.stack.pop() {
+            self.work += 1;
+            if self.seen[pc] == self.generation {
+                continue;
+            }
+            self.seen[pc] = self.generation;
+
+            match nfa[pc] {
+                ByteInst::Jump(addr) => self.stack.push(addr),
+                ByteInst::Split(addr1, addr2) => {
+                    // addr1 を先にたどるため、addr2 から積む
+                    self.stack.push(addr2);
+                    self.stack.push(addr1);
+                }
+                ByteInst::Range(..) | ByteInst::Match => list.push(pc),
+            }
+        }
+    }
+
+    /// バイトを読んで `targets` の命令へ進んだ後の状態を返す
+    ///
+    /// `restart` の場合は、新たにマッチを始めるスレッドを加える
+    fn step(&mut self, nfa: &[ByteInst], targets: &[usize], restart: bool) -> StateKey {
+        self.clear();
+        let mut pcs = Vec::new();
+        for &pc in targets {
+            self.add(nfa, &mut pcs, pc);
+        }
+
+        // 新たにマッチを始めるスレッドは、すでにあるスレッドより優先度が低い。
+        // jump と split をたどった命令の後続もたどっているため、
+        // たどっていない判定を先頭の命令からの順に加えれば、先頭の命令からたどった場合と同じになる
+        if restart {
+            self.work += self.restart.len();
+            for &pc in &self.restart {
+                if self.seen[pc] != self.generation {
+                    self.seen[pc] = self.generation;
+                    pcs.push(pc);
+                }
+            }
+        }
+        truncate(nfa, StateKey { pcs, restart })
+    }
+}
+
+/// match 命令より優先度の低いスレッドを捨てる
+fn truncate(nfa: &[ByteInst], mut key: StateKey) -> StateKey {
+    if let Some(i) = key.pcs.iter().position(|&pc| nfa[pc] == ByteInst::Match) {
+        key.pcs.truncate(i + 1);
+        key.restart = false;
+    }
+    key
+}
+
+/// 識別子に使えない Rust のキーワード。将来のために予約されたものも含める
+const KEYWORDS: &[&str] = &[
+    "Self", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if",
+    "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// `name` が Rust の識別子として使える名前かを判定する。キーワードと `_` は使えない
+///
+/// 生成するソースのモジュール名や静的変数の名前を検査するために利用する
+pub fn is_rust_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c == '_' || c.is_alphabetic())
+        && chars.all(|c| c == '_' || c.is_alphanumeric())
+        && name != "_"
+        && !KEYWORDS.contains(&name)
+}
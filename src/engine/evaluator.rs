@@ -0,0 +1,756 @@
+//! 命令列と入力文字列を受け取り、マッチングを行う
+use super::{Instruction, Program, Repeat, closure::Closures, look::Context, message};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 評価エラーを表すための型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    StepLimitExceeded, // 実行した命令数が上限を超えた
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EvalError: {}", message::eval_error(self))
+    }
+}
+
+impl Error for EvalError {} // エラー用に Error トレイトを実装
+
+/// 検索中に評価器が行った処理の回数
+///
+/// パターンが特定の入力に対して遅い原因を調べるために利用する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub steps: u64,          // 評価器が実行した命令の数。DFA の遷移は含まない
+    pub backtracks: u64,     // バックトラック型の評価器で失敗した経路の数
+    pub peak_threads: usize, // 状態の集合を進める評価器で、同時に保持したスレッドの最大数
+    pub prefilter_hits: u64, // 前処理が返した開始位置の候補の数
+}
+
+/// 評価器が実行した命令を受け取る
+///
+/// どちらのメソッドも、既定では何もしない
+pub trait Tracer {
+    /// 新しい経路を始める。最初の経路と、スタックに積んだ分岐からやり直すたびに呼ばれる
+    fn thread(&mut self) {}
+
+    /// 入力の位置 `sp` で、アドレス `pc` の命令を実行する
+    fn step(&mut self, _pc: u32, _sp: usize) {}
+}
+
+/// 何も受け取らない。通常の評価で利用する
+impl Tracer for () {}
+
+/// 捕獲した位置を持つスレッドを追加する際の、作業用スタックの要素
+#[derive(Debug, Clone, Copy)]
+enum Explore {
+    Pc(u32),                    // たどる命令のアドレス
+    Unsave(u32, Option<usize>), // 戻る際に元に戻す捕獲した位置 (インデックス, 位置)
+}
+
+/// バックトラック用のスタックの要素
+#[derive(Debug, Clone, Copy)]
+enum Frame {
+    Branch(u32, usize),         // split で後回しにした分岐 (pc, sp)
+    Restore(u32, u32),          // 戻る際に元に戻す繰り返しの回数 (インデックス, 回数)
+    Remark(u32, (usize, bool)), // 戻る際に元に戻す記録した位置 (インデックス, 位置)
+    Unsave(u32, Option<usize>), // 戻る際に元に戻す捕獲した位置 (インデックス, 位置)
+}
+
+/// 評価の途中で利用する作業用のメモリ
+///
+/// 同じ Cache を複数回の評価で使い回すことで、検索のたびにメモリを確保し直さずに済む
+#[derive(Debug, Default, Clone)]
+pub struct Cache {
+    stack: Vec<Frame>,          // バックトラック先のスタック
+    counters: Vec<u32>,         // 各繰り返しの現在の回数
+    marks: Vec<(usize, bool)>,  // ループで記録した位置と、1回目の繰り返しの途中か
+    seen: Vec<bool>,            // 状態集合に追加済みの命令
+    clist: Vec<u32>,            // 現在の状態集合
+    nlist: Vec<u32>,            // 次の状態集合
+    starts: Vec<usize>,         // clist の各スレッドのマッチの開始位置
+    nstarts: Vec<usize>,        // nlist の各スレッドのマッチの開始位置
+    closure: Vec<u32>,          // jump と split をたどるための作業用スタック
+    slots: Vec<Option<usize>>,  // clist の各スレッドの捕獲した位置を並べたもの
+    nslots: Vec<Option<usize>>, // nlist の各スレッドの捕獲した位置を並べたもの
+    saved: Vec<Option<usize>>,  // 追加中のスレッドか、バックトラックで探索中の経路の捕獲した位置
+    explore: Vec<Explore>,      // 捕獲した位置を持つスレッドを追加するための作業用スタック
+    stats: SearchStats,         // 統計情報
+}
+
+impl Cache {
+    /// 空の Cache を生成する
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// これまでの検索で集めた統計情報を取り出し、0 に戻す
+    pub fn take_stats(&mut self) -> SearchStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// 統計情報を更新する
+    pub(crate) fn stats_mut(&mut self) -> &mut SearchStats {
+        &mut self.stats
+    }
+}
+
+/// `sp` の位置から始まるマッチを深さ優先で探索し、マッチした場合は終了位置を返す
+///
+/// 位置はすべて `input` のバイトオフセットで表す
+///
+/// split で後回しにした分岐はヒープ上のスタックに積んで管理するため、
+/// パターンが深く入れ子になっていたり入力が長かったりしてもコールスタックを消費しない
+///
+/// `fuel` は実行できる残りの命令数で、1命令ごとに減らす。
+/// 使い切った場合は [`EvalError::StepLimitExceeded`] を返す
+///
+/// 繰り返しの回数とループで記録した位置は、書き換えるたびに元の値をスタックに積み、
+/// 分岐までさかのぼる際に書き換える前の値に戻す。
+/// progress と again は繰り返しの始めから位置が進んでいない分岐を失敗させるため、
+/// 空文字列にマッチしうる式の繰り返しでも停止する
+pub fn eval(
+    prog: &Program,
+    input: &str,
+    sp: usize,
+    fuel: &mut u64,
+    cache: &mut Cache,
+) -> Result<Option<usize>, EvalError> {
+    eval_traced(prog, input, sp, fuel, cache, &mut ())
+}
+
+/// [`eval`] と同じく評価し、実行した命令を順に `tracer` に渡す
+pub fn eval_traced(
+    prog: &Program,
+    input: &str,
+    sp: usize,
+    fuel: &mut u64,
+    cache: &mut Cache,
+    tracer: &mut impl Tracer,
+) -> Result<Option<usize>, EvalError> {
+    cache.saved.clear();
+    backtrack(prog, input, sp, None, fuel, cache, tracer)
+}
+
+/// `start` の位置から始まり `end` の位置で終わるマッチのうち、最も優先度の高いものを
+/// [`eval`] と同じく深さ優先で探し、その捕獲グループの位置を返す
+///
+/// [`eval_captures`] と同じ位置を返すが、回数を数える命令を含む命令列も実行できる。
+/// 捕獲した位置は、繰り返しの回数と同じく分岐までさかのぼる際に元に戻す
+pub fn eval_captures_backtrack(
+    prog: &Program,
+    input: &str,
+    start: usize,
+    end: usize,
+    fuel: &mut u64,
+    cache: &mut Cache,
+) -> Result<Option<Vec<Option<usize>>>, EvalError> {
+    cache.saved.clear();
+    cache.saved.resize(prog.slots, None);
+    let found = backtrack(prog, input, start, Some(end), fuel, cache, &mut ())?;
+    Ok(found.map(|_| cache.saved.clone()))
+}
+
+/// [`eval_traced`] と [`eval_captures_backtrack`] の本体
+///
+/// `end` を指定した場合は、その位置で到達した match 命令だけをマッチとする。
+/// save は `cache.saved` に位置を捕獲する。`saved` が空の場合は何もせずに次の命令へ進む
+fn backtrack(
+    prog: &Program,
+    input: &str,
+    sp: usize,
+    end: Option<usize>,
+    fuel: &mut u64,
+    cache: &mut Cache,
+    tracer: &mut impl Tracer,
+) -> Result<Option<usize>, EvalError> {
+    let Cache {
+        stack,
+        counters,
+        marks,
+        saved,
+        stats,
+        ..
+    } = cache;
+    stack.clear();
+    stack.push(Frame::Branch(0, sp));
+    counters.clear();
+    counters.resize(prog.repeats.len(), 0);
+    marks.clear();
+    marks.resize(prog.marks, (0, true));
+
+    while let Some(frame) = stack.pop() {
+        let (mut pc, mut sp) = match frame {
+            Frame::Branch(pc, sp) => (pc, sp),
+            Frame::Restore(idx, count) => {
+                counters[idx as usize] = count;
+                continue;
+            }
+            Frame::Remark(slot, pos) => {
+                marks[slot as usize] = pos;
+                continue;
+            }
+            Frame::Unsave(slot, pos) => {
+                saved[slot as usize] = pos;
+                continue;
+            }
+        };
+        tracer.thread();
+        loop {
+            if *fuel == 0 {
+                return Err(EvalError::StepLimitExceeded);
+            }
+            *fuel -= 1;
+            tracer.step(pc, sp);
+
+            let next = match prog.insts[pc as usize] {
+                Instruction::Char(c) => input[sp..].chars().next().filter(|&next| next == c),
+                Instruction::Class(idx) => input[sp..]
+                    .chars()
+                    .next()
+                    .filter(|&next| prog.class_contains(idx, next)),
+                Instruction::Match if end.is_none_or(|end| end == sp) => return Ok(Some(sp)),
+                Instruction::Match => break,
+                Instruction::Jump(addr) => {
+                    pc = addr;
+                    continue;
+                }
+                Instruction::Split(addr1, addr2) => {
+                    stack.push(Frame::Branch(addr2, sp));
+                    pc = addr1;
+                    continue;
+                }
+                Instruction::RepeatStart(idx) => {
+                    let count = &mut counters[idx as usize];
+                    stack.push(Frame::Restore(idx, *count));
+                    *count = 0;
+                    pc += 1;
+                    continue;
+                }
+                Instruction::Repeat(idx, addr) => {
+                    // 下限に満たなければ本体へ、上限に達したら脱出先へ進み、
+                    // その間は本体を優先して脱出先を後回しにする
+                    let Repeat { min, max } = prog.repeats[idx as usize];
+                    let count = counters[idx as usize];
+                    if count < min {
+                        pc += 1;
+                    } else if count >= max {
+                        pc = addr;
+                    } else {
+                        stack.push(Frame::Branch(addr, sp));
+                        pc += 1;
+                    }
+                    continue;
+                }
+                Instruction::RepeatEnd(idx, addr) => {
+                    let count = &mut counters[idx as usize];
+                    stack.push(Frame::Restore(idx, *count));
+                    *count = count.saturating_add(1);
+                    pc = addr;
+                    continue;
+                }
+                Instruction::Mark(slot) => {
+                    let mark = &mut marks[slot as usize];
+                    stack.push(Frame::Remark(slot, *mark));
+                    *mark = (sp, true);
+                    pc += 1;
+                    continue;
+                }
+                Instruction::Progress(slot) => {
+                    if marks[slot as usize] == (sp, false) {
+                        break;
+                    }
+                    pc += 1;
+                    continue;
+                }
+                Instruction::Again(slot) => {
+                    let mark = &mut marks[slot as usize];
+                    if mark.0 == sp {
+                        break;
+                    }
+                    stack.push(Frame::Remark(slot, *mark));
+                    *mark = (sp, false);
+                    pc += 1;
+                    continue;
+                }
+                Instruction::Look(look) => {
+                    if !look.matches(Context::at(input, sp)) {
+                        break;
+                    }
+                    pc += 1;
+                    continue;
+                }
+                Instruction::Save(slot) => {
+                    if let Some(pos) = saved.get_mut(slot as usize) {
+                        stack.push(Frame::Unsave(slot, *pos));
+                        *pos = Some(sp);
+                    }
+                    pc += 1;
+                    continue;
+                }
+            };
+
+            // 文字にマッチしなかった場合は、スタックに積んだ分岐からやり直す
+            let Some(next) = next else {
+                break;
+            };
+            pc += 1;
+            sp += next.len_utf8();
+        }
+        stats.backtracks += 1;
+    }
+
+    Ok(None)
+}
+
+/// 開始位置が `start` 以上 `end` 未満のマッチのうち、最も左にあるものを
+/// 取りうる状態の集合を1文字ずつ進めて探し、その範囲を返す
+///
+/// 状態集合は優先度の高い順に並べ、同じ命令に到達したスレッドは優先度の高いものだけを残す。
+/// match 命令に到達した時点で、それより優先度の低いスレッドを捨てるため、
+/// [`eval`] で左から順に試した場合と同じマッチが見つかる。
+/// 入力の長さと命令数の積に比例する時間で必ず終了する
+///
+/// `fuel` は [`eval`] と同じく、スレッドが命令を実行するたびに減らす
+pub fn eval_pike(
+    prog: &Program,
+    input: &str,
+    start: usize,
+    end: usize,
+    fuel: &mut u64,
+    cache: &mut Cache,
+) -> Result<Option<(usize, usize)>, EvalError> {
+    let Cache {
+        seen,
+        clist,
+        nlist,
+        starts,
+        nstarts,
+        closure,
+        stats,
+        ..
+    } = cache;
+    seen.clear();
+    seen.resize(prog.insts.len(), false);
+    clist.clear();
+    starts.clear();
+
+    let mut found = None;
+    let mut sp = start;
+    loop {
+        // まだマッチが見つかっていなければ、現在の位置から始まるスレッドを最も低い優先度で追加する
+        if found.is_none() && sp < end {
+            add_thread(prog, clist, seen, closure, Some(Context::at(input, sp)), 0);
+            starts.resize(clist.len(), sp);
+        }
+        stats.peak_threads = stats.peak_threads.max(clist.len());
+        if let Some(i) = clist
+            .iter()
+            .position(|&pc| prog.insts[pc as usize] == Instruction::Match)
+        {
+            found = Some((starts[i], sp));
+            clist.truncate(i);
+            starts.truncate(i);
+        }
+        if clist.is_empty() && (found.is_some() || sp >= end) {
+            break;
+        }
+        let Some(c) = input[sp..].chars().next() else {
+            break;
+        };
+
+        seen.fill(false);
+        nlist.clear();
+        nstarts.clear();
+        let ctx = Context::at(input, sp + c.len_utf8());
+        for (&pc, &st) in clist.iter().zip(starts.iter()) {
+            if *fuel == 0 {
+                return Err(EvalError::StepLimitExceeded);
+            }
+            *fuel -= 1;
+
+            let accept = match prog.insts[pc as usize] {
+                Instruction::Char(x) => x == c,
+                Instruction::Class(idx) => prog.class_contains(idx, c),
+                _ => false,
+            };
+            if accept {
+                add_thread(prog, nlist, seen, closure, Some(ctx), pc + 1);
+                nstarts.resize(nlist.len(), st);
+            }
+        }
+        std::mem::swap(clist, nlist);
+        std::mem::swap(starts, nstarts);
+        sp += c.len_utf8();
+    }
+
+    Ok(found)
+}
+
+/// 入力のすべての位置から始まるスレッドを同時に進め、到達した match 命令のアドレスを返す
+///
+/// [`eval_pike`] と異なり、マッチが見つかってもその後の位置から始まるスレッドを捨てずに進め、
+/// 到達しうる match 命令をすべて集める。命令列が match 命令を複数持つ場合に、
+/// 入力を1回走査するだけで、それぞれの match 命令に到達するマッチがあるかを判定できる。
+/// すべての match 命令に到達した時点で走査を終える。返すアドレスの順は定めない
+///
+/// `fuel` は [`eval`] と同じく、スレッドが命令を実行するたびに減らす
+pub fn eval_pike_all(
+    prog: &Program,
+    input: &str,
+    fuel: &mut u64,
+    cache: &mut Cache,
+) -> Result<Vec<u32>, EvalError> {
+    let Cache {
+        seen,
+        clist,
+        nlist,
+        closure,
+        stats,
+        ..
+    } = cache;
+    let total = prog
+        .insts
+        .iter()
+        .filter(|&&inst| inst == Instruction::Match)
+        .count();
+    seen.clear();
+    seen.resize(prog.insts.len(), false);
+    clist.clear();
+
+    let mut found = Vec::new();
+    let mut sp = 0;
+    loop {
+        add_thread(prog, clist, seen, closure, Some(Context::at(input, sp)), 0);
+        stats.peak_threads = stats.peak_threads.max(clist.len());
+        for &pc in clist.iter() {
+            if prog.insts[pc as usize] == Instruction::Match && !found.contains(&pc) {
+                found.push(pc);
+            }
+        }
+        if found.len() == total {
+            break;
+        }
+        let Some(c) = input[sp..].chars().next() else {
+            break;
+        };
+
+        seen.fill(false);
+        nlist.clear();
+        let ctx = Context::at(input, sp + c.len_utf8());
+        for &pc in clist.iter() {
+            if *fuel == 0 {
+                return Err(EvalError::StepLimitExceeded);
+            }
+            *fuel -= 1;
+
+            let accept = match prog.insts[pc as usize] {
+                Instruction::Char(x) => x == c,
+                Instruction::Class(idx) => prog.class_contains(idx, c),
+                _ => false,
+            };
+            if accept {
+                add_thread(prog, nlist, seen, closure, Some(ctx), pc + 1);
+            }
+        }
+        std::mem::swap(clist, nlist);
+        sp += c.len_utf8();
+    }
+
+    Ok(found)
+}
+
+/// 逆向きのプログラムを `end` の位置から先頭に向かって実行し、マッチの開始位置を返す
+///
+/// 取りうる状態の集合を1文字ずつ進めるため、開始位置の候補が複数ある場合は
+/// もっとも先頭に近い位置を返す
+pub fn eval_rev(prog: &Program, input: &str, end: usize, cache: &mut Cache) -> Option<usize> {
+    let Cache {
+        seen,
+        clist,
+        nlist,
+        closure,
+        stats,
+        ..
+    } = cache;
+    seen.clear();
+    seen.resize(prog.insts.len(), false);
+    clist.clear();
+    add_thread(prog, clist, seen, closure, Some(Context::at(input, end)), 0);
+
+    let mut start = None;
+    let mut sp = end;
+    let mut chars = input[..end].chars();
+    loop {
+        stats.peak_threads = stats.peak_threads.max(clist.len());
+        if clist
+            .iter()
+            .any(|&pc| prog.insts[pc as usize] == Instruction::Match)
+        {
+            start = Some(sp);
+        }
+        if clist.is_empty() {
+            break;
+        }
+        let Some(c) = chars.next_back() else {
+            break;
+        };
+        sp -= c.len_utf8();
+
+        // 現在の文字を受理するスレッドのみを次の状態集合に進める
+        seen.fill(false);
+        nlist.clear();
+        let ctx = Context::at(input, sp);
+        for &pc in clist.iter() {
+            let accept = match prog.insts[pc as usize] {
+                Instruction::Char(x) => x == c,
+                Instruction::Class(idx) => prog.class_contains(idx, c),
+                _ => false,
+            };
+            if accept {
+                add_thread(prog, nlist, seen, closure, Some(ctx), pc + 1);
+            }
+        }
+        std::mem::swap(clist, nlist);
+    }
+
+    start
+}
+
+/// `start` の位置から始まり `end` の位置で終わるマッチのうち、最も優先度の高いものを探し、
+/// その捕獲グループの位置を返す
+///
+/// [`eval_pike`] と同じく状態の集合を1文字ずつ進めるが、各スレッドは捕獲した位置を持つ。
+/// 優先度の高いスレッドの到達した命令には低いスレッドが追加されないため、
+/// `end` で最初に match 命令に到達したスレッドが、先頭から試した場合に選ばれる経路と一致する。
+/// マッチの範囲を別の評価器で求めておき、その範囲だけを読むことで、
+/// 各スレッドの位置を複製する負荷をマッチした部分に限る
+///
+/// 返す値の `2 * i` と `2 * i + 1` 番目の要素が、グループ `i` の開始位置と終了位置を表す。
+/// マッチ全体を表す 0 番目のグループの位置は設定しない
+pub fn eval_captures(
+    prog: &Program,
+    input: &str,
+    start: usize,
+    end: usize,
+    fuel: &mut u64,
+    cache: &mut Cache,
+) -> Result<Option<Vec<Option<usize>>>, EvalError> {
+    let Cache {
+        seen,
+        clist,
+        nlist,
+        slots,
+        nslots,
+        saved,
+        explore,
+        stats,
+        ..
+    } = cache;
+    let n = prog.slots;
+    seen.clear();
+    seen.resize(prog.insts.len(), false);
+    clist.clear();
+    slots.clear();
+    saved.clear();
+    saved.resize(n, None);
+    let mut threads = Threads { list: clist, slots };
+    let mut work = Explorer {
+        seen,
+        stack: explore,
+        saved,
+    };
+    add_thread_slots(prog, &mut threads, &mut work, input, start, 0);
+
+    let mut sp = start;
+    loop {
+        stats.peak_threads = stats.peak_threads.max(threads.list.len());
+        if sp == end {
+            let found = threads
+                .list
+                .iter()
+                .position(|&pc| prog.insts[pc as usize] == Instruction::Match);
+            return Ok(found.map(|i| threads.slots[i * n..(i + 1) * n].to_vec()));
+        }
+        let Some(c) = input[sp..].chars().next() else {
+            return Ok(None);
+        };
+        if threads.list.is_empty() {
+            return Ok(None);
+        }
+        sp += c.len_utf8();
+
+        work.seen.fill(false);
+        nlist.clear();
+        nslots.clear();
+        let mut next = Threads {
+            list: nlist,
+            slots: nslots,
+        };
+        for (i, &pc) in threads.list.iter().enumerate() {
+            if *fuel == 0 {
+                return Err(EvalError::StepLimitExceeded);
+            }
+            *fuel -= 1;
+
+            let accept = match prog.insts[pc as usize] {
+                Instruction::Char(x) => x == c,
+                Instruction::Class(idx) => prog.class_contains(idx, c),
+                _ => false,
+            };
+            if accept {
+                work.saved
+                    .copy_from_slice(&threads.slots[i * n..(i + 1) * n]);
+                add_thread_slots(prog, &mut next, &mut work, input, sp, pc + 1);
+            }
+        }
+        std::mem::swap(threads.list, next.list);
+        std::mem::swap(threads.slots, next.slots);
+    }
+}
+
+/// 捕獲した位置を持つスレッドの集合
+struct Threads<'a> {
+    list: &'a mut Vec<u32>,            // 各スレッドの命令のアドレス
+    slots: &'a mut Vec<Option<usize>>, // 各スレッドの捕獲した位置を並べたもの
+}
+
+/// スレッドを追加する際に命令をたどるための作業用のメモリ
+struct Explorer<'a> {
+    seen: &'a mut Vec<bool>,           // 状態集合に追加済みの命令
+    stack: &'a mut Vec<Explore>,       // たどる命令と、元に戻す捕獲した位置のスタック
+    saved: &'a mut Vec<Option<usize>>, // 追加するスレッドのそれまでに捕獲した位置
+}
+
+/// [`add_thread`] と同じく命令をたどり、save で位置を捕獲しながらスレッドを追加する
+///
+/// 捕獲した位置は分岐をたどるたびに書き換え、戻る際に元に戻す。`sp` は現在の位置
+fn add_thread_slots(
+    prog: &Program,
+    threads: &mut Threads<'_>,
+    work: &mut Explorer<'_>,
+    input: &str,
+    sp: usize,
+    pc: u32,
+) {
+    let Explorer { seen, stack, saved } = work;
+    let ctx = Context::at(input, sp);
+    stack.clear();
+    stack.push(Explore::Pc(pc));
+    while let Some(frame) = stack.pop() {
+        let pc = match frame {
+            Explore::Pc(pc) => pc,
+            Explore::Unsave(slot, pos) => {
+                saved[slot as usize] = pos;
+                continue;
+            }
+        };
+        if seen[pc as usize] {
+            continue;
+        }
+        seen[pc as usize] = true;
+
+        match prog.insts[pc as usize] {
+            Instruction::Jump(addr) => stack.push(Explore::Pc(addr)),
+            Instruction::Split(addr1, addr2) => {
+                stack.push(Explore::Pc(addr2));
+                stack.push(Explore::Pc(addr1));
+            }
+            Instruction::Mark(_) | Instruction::Progress(_) | Instruction::Again(_) => {
+                stack.push(Explore::Pc(pc + 1))
+            }
+            Instruction::Save(slot) => {
+                let pos = &mut saved[slot as usize];
+                stack.push(Explore::Unsave(slot, *pos));
+                *pos = Some(sp);
+                stack.push(Explore::Pc(pc + 1));
+            }
+            Instruction::Look(look) => {
+                if look.matches(ctx) {
+                    stack.push(Explore::Pc(pc + 1));
+                }
+            }
+            Instruction::Char(_) | Instruction::Class(_) | Instruction::Match => {
+                threads.list.push(pc);
+                threads.slots.extend_from_slice(saved);
+            }
+            Instruction::RepeatStart(_) | Instruction::Repeat(..) | Instruction::RepeatEnd(..) => {
+                unreachable!("counted repetition in a state-set program")
+            }
+        }
+    }
+}
+
+/// jump と split をたどり、文字を消費する命令と match 命令を状態集合に追加する
+///
+/// 状態の集合は繰り返しの回数を持たないため、回数を数える命令を含まない命令列に限る。
+/// 同じ位置で同じ命令に到達したスレッドは1つにまとめるため、mark、progress、again は
+/// 何もせずに次の命令へ進むものとして扱っても、位置が進まないまま繰り返すことはない。
+/// save も何もせずに次の命令へ進み、位置の捕獲は [`eval_captures`] で行う
+///
+/// 位置の判定は `ctx` の前後の文字で行う。
+/// 次の文字がまだ分からない場合は `ctx` に `None` を渡し、判定の命令をそのまま状態集合に追加する
+///
+/// 到達する命令の並びをコンパイル時に求めてある場合は、分岐をたどらずにその並びを読む
+pub fn add_thread(
+    prog: &Program,
+    list: &mut Vec<u32>,
+    seen: &mut [bool],
+    stack: &mut Vec<u32>,
+    ctx: Option<Context>,
+    pc: u32,
+) {
+    if let Some(closures) = &prog.closures {
+        add_closure(closures, list, seen, pc);
+        return;
+    }
+
+    stack.clear();
+    stack.push(pc);
+    while let Some(pc) = stack.pop() {
+        if seen[pc as usize] {
+            continue;
+        }
+        seen[pc as usize] = true;
+
+        match prog.insts[pc as usize] {
+            Instruction::Jump(addr) => stack.push(addr),
+            Instruction::Split(addr1, addr2) => {
+                // addr1 を先にたどるため、addr2 から積む
+                stack.push(addr2);
+                stack.push(addr1);
+            }
+            Instruction::Mark(_)
+            | Instruction::Progress(_)
+            | Instruction::Again(_)
+            | Instruction::Save(_) => stack.push(pc + 1),
+            Instruction::Look(look) => match ctx {
+                Some(ctx) if look.matches(ctx) => stack.push(pc + 1),
+                Some(_) => (),
+                None => list.push(pc),
+            },
+            Instruction::Char(_) | Instruction::Class(_) | Instruction::Match => list.push(pc),
+            Instruction::RepeatStart(_) | Instruction::Repeat(..) | Instruction::RepeatEnd(..) => {
+                unreachable!("counted repetition in a state-set program")
+            }
+        }
+    }
+}
+
+/// あらかじめ求めた到達する命令の並びを読み、[`add_thread`] と同じ順に状態集合に追加する
+///
+/// 分岐をたどる際に飛ばす、同じ評価の中ですでにたどった命令から到達する命令は、
+/// すべて状態集合に追加済みなので、追加済みの命令を飛ばすだけで分岐をたどった場合と同じ順になる
+fn add_closure(closures: &Closures, list: &mut Vec<u32>, seen: &mut [bool], pc: u32) {
+    let (start, end) = closures.range(pc);
+    for i in start..end {
+        let pc = closures.pc(i);
+        if !seen[pc as usize] {
+            seen[pc as usize] = true;
+            list.push(pc);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! ファイル名のグロブをパターンに変換する
+
+/// グロブを、パス全体にマッチするパターンの文字列に変換する
+///
+/// パスの区切りは `/` とし、`*` と `?` と文字クラスは `/` にマッチしない。
+///
+/// - `*` は任意の長さの文字列、`?` は任意の1文字にマッチする
+/// - `[abc]` と `[a-z]` は列挙した文字に、`[!abc]` と `[^abc]` はそれ以外の文字にマッチする
+/// - 先頭の `**/` と途中の `/**/` は 0 個以上のディレクトリに、末尾の `/**` はその下のすべてに
+///   マッチする。それ以外の `**` は `*` と同じ
+/// - `\` は直後の文字をそのまま表す。閉じていない `[` は `[` という文字として扱う
+///
+/// ```text
+/// *.rs        →  ^[^/]*.rs$
+/// src/**/*.rs →  src/a.rs や src/a/b.rs にマッチし、src.rs や src/a/b にはマッチしない
+/// ```
+pub fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut pattern = String::from("^");
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let whole = i == 0 || chars[i - 1] == '/';
+                if whole && chars.get(i + 2) == Some(&'/') {
+                    pattern.push_str("(?:[^/]*/)*");
+                    i += 3;
+                    continue;
+                }
+                if whole && i + 2 == chars.len() {
+                    pattern.push_str(r"[\s\S]*");
+                    break;
+                }
+                while chars.get(i + 1) == Some(&'*') {
+                    i += 1;
+                }
+                pattern.push_str("[^/]*");
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            '[' => match class(&chars, i) {
+                Some((class, end)) => {
+                    pattern.push_str(&class);
+                    i = end;
+                }
+                None => push_char(&mut pattern, '['),
+            },
+            '\\' => {
+                i += 1;
+                push_char(&mut pattern, chars.get(i).copied().unwrap_or('\\'));
+            }
+            c => push_char(&mut pattern, c),
+        }
+        i += 1;
+    }
+    pattern.push('$');
+    pattern
+}
+
+/// `start` の `[` から始まる文字クラスを変換し、パターンと `]` の位置を返す。
+/// 閉じていない場合は `None`
+///
+/// `-` は前後に文字がある場合だけ範囲を表し、それ以外は文字として扱う
+fn class(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let first = i;
+    let mut body = String::new();
+    loop {
+        let c = *chars.get(i)?;
+        match c {
+            ']' if i > first => break,
+            '-' if i > first && !matches!(chars.get(i + 1), Some(']') | None) => body.push('-'),
+            '\\' => {
+                i += 1;
+                push_class_char(&mut body, *chars.get(i)?);
+            }
+            c => push_class_char(&mut body, c),
+        }
+        i += 1;
+    }
+    let class = if negated {
+        format!("[^/{body}]")
+    } else {
+        format!("[{body}]")
+    };
+    Some((class, i))
+}
+
+/// 文字をパターンに追加する。特殊文字はエスケープする
+fn push_char(pattern: &mut String, c: char) {
+    if matches!(
+        c,
+        '\\' | '(' | ')' | '|' | '+' | '*' | '?' | '[' | ']' | '{' | '}' | '^' | '$'
+    ) {
+        pattern.push('\\');
+    }
+    pattern.push(c);
+}
+
+/// 文字を文字クラスの中に追加する。特殊文字はエスケープする
+fn push_class_char(class: &mut String, c: char) {
+    if matches!(c, '\\' | '[' | ']' | '-' | '^') {
+        class.push('\\');
+    }
+    class.push(c);
+}
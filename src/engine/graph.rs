@@ -0,0 +1,196 @@
+//! 命令列を、状態と遷移からなるグラフとして出力する
+//!
+//! 命令を1つの状態とし、文字を読む命令からは次の命令へ文字の遷移を、
+//! 分岐や位置の判定などの命令からは入力を読まない遷移（ε 遷移）を引く。
+//! 状態の番号は命令のアドレスと同じ
+use super::{Instruction, Program, Repeat, class::CharClass};
+use std::fmt::Write;
+
+/// 文字クラスのラベルに表示する範囲の数の上限。超えた分は `…` で省略する
+const CLASS_RANGES: usize = 8;
+
+/// 状態の間の1つの遷移
+#[derive(Debug)]
+struct Edge {
+    from: usize,
+    to: usize,
+    label: String, // 読む文字か、遷移の条件。条件のない ε 遷移は空
+    epsilon: bool, // 入力を読まない遷移か
+}
+
+/// 命令列を Graphviz の DOT 形式の有向グラフにする。`title` はグラフの見出しになる
+///
+/// 文字の遷移は実線で、ε 遷移は破線で表し、match 命令の状態は二重丸にする
+///
+/// ```text
+/// digraph regex {
+///     ...
+///     0 -> 1 [label="a"];
+///     1 -> 3 [label="ε", style=dashed];
+/// }
+/// ```
+pub fn dot(prog: &Program, title: &str) -> String {
+    let mut dot = String::new();
+    let _ = writeln!(dot, "digraph regex {{");
+    let title: String = title.chars().map(show).collect();
+    let _ = writeln!(dot, "    label=\"{}\";", dot_escape(&title));
+    let _ = writeln!(dot, "    labelloc=t;");
+    let _ = writeln!(dot, "    rankdir=LR;");
+    let _ = writeln!(dot, "    node [shape=circle];");
+    let _ = writeln!(dot, "    start [shape=point];");
+    for (pc, inst) in prog.insts.iter().enumerate() {
+        if *inst == Instruction::Match {
+            let _ = writeln!(dot, "    {pc} [shape=doublecircle];");
+        }
+    }
+    let _ = writeln!(dot, "    start -> 0;");
+    for edge in edges(prog) {
+        let Edge {
+            from,
+            to,
+            label,
+            epsilon,
+        } = edge;
+        if epsilon {
+            let label = if label.is_empty() { "ε" } else { &label };
+            let label = dot_escape(label);
+            let _ = writeln!(dot, "    {from} -> {to} [label=\"{label}\", style=dashed];");
+        } else {
+            let label = dot_escape(&label);
+            let _ = writeln!(dot, "    {from} -> {to} [label=\"{label}\"];");
+        }
+    }
+    let _ = writeln!(dot, "}}");
+    dot
+}
+
+/// 命令列を Mermaid の状態遷移図にする
+///
+/// 状態は `s` に続けてアドレスを書いた名前で、アドレスを表示する。
+/// 線の種類を変えられないため、条件のない ε 遷移には `ε` というラベルを付ける
+///
+/// ```text
+/// stateDiagram-v2
+///     ...
+///     [*] --> s0
+///     s0 --> s1: a
+///     s1 --> [*]
+/// ```
+pub fn mermaid(prog: &Program) -> String {
+    let mut mermaid = String::new();
+    let _ = writeln!(mermaid, "stateDiagram-v2");
+    let _ = writeln!(mermaid, "    direction LR");
+    for pc in 0..prog.insts.len() {
+        let _ = writeln!(mermaid, "    state \"{pc}\" as s{pc}");
+    }
+    let _ = writeln!(mermaid, "    [*] --> s0");
+    for edge in edges(prog) {
+        let Edge {
+            from,
+            to,
+            label,
+            epsilon,
+        } = edge;
+        let label = if epsilon && label.is_empty() {
+            "ε"
+        } else {
+            &label
+        };
+        let label = mermaid_escape(label);
+        let _ = writeln!(mermaid, "    s{from} --> s{to}: {label}");
+    }
+    for (pc, inst) in prog.insts.iter().enumerate() {
+        if *inst == Instruction::Match {
+            let _ = writeln!(mermaid, "    s{pc} --> [*]");
+        }
+    }
+    mermaid
+}
+
+/// 命令列のすべての遷移を、元の命令のアドレスの順に返す
+///
+/// 回数を数える繰り返しの遷移には、回数の条件と更新をラベルとして付ける
+fn edges(prog: &Program) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    for (pc, inst) in prog.insts.iter().enumerate() {
+        let mut push = |to: usize, label: String, epsilon: bool| {
+            edges.push(Edge {
+                from: pc,
+                to,
+                label,
+                epsilon,
+            });
+        };
+        match *inst {
+            Instruction::Char(c) => push(pc + 1, show(c), false),
+            Instruction::Class(idx) => {
+                push(pc + 1, class_label(&prog.classes[idx as usize]), false)
+            }
+            Instruction::Match => {}
+            Instruction::Jump(addr) => push(addr as usize, String::new(), true),
+            Instruction::Split(addr1, addr2) => {
+                push(addr1 as usize, String::new(), true);
+                push(addr2 as usize, String::new(), true);
+            }
+            Instruction::RepeatStart(idx) => push(pc + 1, format!("#{idx} = 0"), true),
+            Instruction::Repeat(idx, addr) => {
+                let Repeat { min, max } = prog.repeats[idx as usize];
+                push(pc + 1, format!("#{idx} < {max}"), true);
+                push(addr as usize, format!("#{idx} >= {min}"), true);
+            }
+            Instruction::RepeatEnd(idx, addr) => push(addr as usize, format!("#{idx} += 1"), true),
+            Instruction::Look(look) => push(pc + 1, look.to_string(), true),
+            Instruction::Mark(_)
+            | Instruction::Progress(_)
+            | Instruction::Again(_)
+            | Instruction::Save(_) => push(pc + 1, String::new(), true),
+        }
+    }
+    edges
+}
+
+/// 文字クラスを `[a-z0-9]` の形で表す
+fn class_label(class: &CharClass) -> String {
+    let mut label = String::from("[");
+    for &(lo, hi) in class.ranges().iter().take(CLASS_RANGES) {
+        label.push_str(&show(lo));
+        if lo != hi {
+            label.push('-');
+            label.push_str(&show(hi));
+        }
+    }
+    if class.ranges().len() > CLASS_RANGES {
+        label.push('…');
+    }
+    label.push(']');
+    label
+}
+
+/// ラベルに表示する文字。制御文字などの表示できない文字はエスケープする
+fn show(c: char) -> String {
+    match c {
+        '"' | '\'' | '\\' => c.to_string(),
+        _ => c.escape_debug().to_string(),
+    }
+}
+
+/// Mermaid のラベルの中で特別な意味を持ちうる記号を、`#35;` の形の文字参照にする
+fn mermaid_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '"' | '#' | '%' | ':' | ';' | '<' | '>' | '[' | ']' | '{' | '}' | '|'
+        ) {
+            let _ = write!(escaped, "#{};", u32::from(c));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// DOT の文字列の中で特別な意味を持つ `"` と `\` をエスケープする
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,68 @@
+//! パターンにマッチする文字列の長さを解析する
+use super::parser::{AST, AstNode, NodeId};
+
+/// マッチする文字列の最大の長さをバイト単位で返す
+///
+/// `*` や `+` のように上限なく繰り返す場合は `None` を返す
+pub fn max_len(ast: &AST) -> Option<usize> {
+    max_len_node(ast, ast.root())
+}
+
+/// マッチする文字列の最小の長さをバイト単位で返す
+pub fn min_len(ast: &AST) -> usize {
+    min_len_node(ast, ast.root())
+}
+
+/// `id` 以下の部分木が空文字列にマッチしうるかを判定する
+pub fn nullable(ast: &AST, id: NodeId) -> bool {
+    min_len_node(ast, id) == 0
+}
+
+/// `id` 以下の部分木が空文字列にしかマッチしないかを判定する
+pub fn empty_only(ast: &AST, id: NodeId) -> bool {
+    max_len_node(ast, id) == Some(0)
+}
+
+fn min_len_node(ast: &AST, id: NodeId) -> usize {
+    match &ast[id] {
+        AstNode::Char(c) => c.len_utf8(),
+        AstNode::Class(class) => class.ranges().first().map_or(0, |(lo, _)| lo.len_utf8()),
+        AstNode::Question(_) | AstNode::Star(_) | AstNode::Look(_) => 0,
+        AstNode::Plus(e) | AstNode::Group(e, _) => min_len_node(ast, *e),
+        AstNode::Repeat(e, min, _) => min_len_node(ast, *e).saturating_mul(*min as usize),
+        AstNode::Or(..) => ast
+            .branches(id)
+            .iter()
+            .map(|&e| min_len_node(ast, e))
+            .min()
+            .unwrap_or(0),
+        AstNode::Seq(v) => v
+            .iter()
+            .fold(0usize, |sum, e| sum.saturating_add(min_len_node(ast, *e))),
+    }
+}
+
+fn max_len_node(ast: &AST, id: NodeId) -> Option<usize> {
+    match &ast[id] {
+        AstNode::Char(c) => Some(c.len_utf8()),
+        AstNode::Class(class) => Some(class.ranges().last().map_or(0, |(_, hi)| hi.len_utf8())),
+        AstNode::Look(_) => Some(0),
+        AstNode::Question(e) | AstNode::Group(e, _) => max_len_node(ast, *e),
+        AstNode::Plus(e) | AstNode::Star(e) => match max_len_node(ast, *e)? {
+            0 => Some(0),
+            _ => None,
+        },
+        AstNode::Repeat(e, _, Some(max)) => max_len_node(ast, *e)?.checked_mul(*max as usize),
+        AstNode::Repeat(e, _, None) => match max_len_node(ast, *e)? {
+            0 => Some(0),
+            _ => None,
+        },
+        AstNode::Or(..) => ast
+            .branches(id)
+            .iter()
+            .try_fold(0usize, |len, &e| Some(len.max(max_len_node(ast, e)?))),
+        AstNode::Seq(v) => v
+            .iter()
+            .try_fold(0usize, |sum, e| sum.checked_add(max_len_node(ast, *e)?)),
+    }
+}
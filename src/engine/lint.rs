@@ -0,0 +1,136 @@
+//! コンパイルはできるが、誤りの可能性が高い書き方をパターンから探す
+//!
+//! 次の書き方を警告として報告する。警告があってもパターンはそのままコンパイルする
+//!
+//! - 前に現れた選択肢と同じ選択肢：`a|b|a`
+//! - 文字を消費する式の後ろの `^` や、前の `$`：`a^b`、`a$b`
+//! - 意味が重なる入れ子の限量子：`(a+)*`、`(a?)?`
+//! - 空文字列にしかマッチしない捕獲グループ：`()`、`(\b)`
+use super::{
+    length,
+    look::Look,
+    message,
+    parser::{AST, AstNode, NodeId, Span},
+    simplify,
+    visit::{Visitor, walk},
+};
+use std::fmt::{self, Display};
+
+/// 警告の種類
+///
+/// 今後のバージョンで種類が増える可能性がある
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    DuplicateBranch,  // 前に現れた選択肢と同じで、マッチに影響しない選択肢
+    MisplacedAnchor,  // 前後に必ず文字があるため、決して満たされない ^ や $
+    NestedQuantifier, // 1つにまとめられる入れ子の限量子
+    EmptyGroup,       // 空文字列にしかマッチしない捕獲グループ
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", message::warning(*self))
+    }
+}
+
+/// 見つかった警告
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub span: Span, // 警告の対象となる部分パターンの範囲
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Warning: {}: pos = {}", self.kind, self.span.char_start)
+    }
+}
+
+/// パースした AST を調べ、警告を部分パターンの開始位置の順に返す
+///
+/// `pattern` は `ast` のパース元のパターン
+pub fn lint(pattern: &str, ast: &AST) -> Vec<Warning> {
+    let mut linter = Linter {
+        in_or: false,
+        found: Vec::new(),
+    };
+    linter.visit(ast, ast.root());
+    let mut warnings: Vec<_> = linter
+        .found
+        .into_iter()
+        .map(|(kind, id)| Warning {
+            kind,
+            span: Span::from_bytes(pattern, ast.span(id)),
+        })
+        .collect();
+    warnings.sort_by_key(|warning| (warning.span.byte_start, warning.span.byte_end));
+    warnings
+}
+
+/// 部分木を調べて警告を集める走査
+struct Linter {
+    in_or: bool,                       // 訪れているノードの親が OR であるか
+    found: Vec<(WarningKind, NodeId)>, // 見つかった警告と対象のノード
+}
+
+impl Visitor for Linter {
+    fn visit(&mut self, ast: &AST, id: NodeId) {
+        match &ast[id] {
+            AstNode::Plus(e) | AstNode::Star(e) | AstNode::Question(e)
+                if quantifier(&ast[strip(ast, *e)]) =>
+            {
+                self.found.push((WarningKind::NestedQuantifier, id));
+            }
+            AstNode::Group(e, _) if length::empty_only(ast, *e) => {
+                self.found.push((WarningKind::EmptyGroup, id));
+            }
+            // 入れ子になった OR は、最も外側の OR でまとめて調べる
+            AstNode::Or(..) if !self.in_or => {
+                let mut seen = simplify::Seen::default();
+                for b in ast.branches(id) {
+                    if !seen.insert(ast, b) {
+                        self.found.push((WarningKind::DuplicateBranch, b));
+                    }
+                }
+            }
+            AstNode::Seq(v) => {
+                let consumes =
+                    |others: &[NodeId]| others.iter().any(|&o| !length::nullable(ast, o));
+                for (i, &e) in v.iter().enumerate() {
+                    let misplaced = match ast[e] {
+                        AstNode::Look(Look::Start) => consumes(&v[..i]),
+                        AstNode::Look(Look::End) => consumes(&v[i + 1..]),
+                        _ => false,
+                    };
+                    if misplaced {
+                        self.found.push((WarningKind::MisplacedAnchor, e));
+                    }
+                }
+            }
+            _ => (),
+        }
+        let in_or = std::mem::replace(&mut self.in_or, matches!(ast[id], AstNode::Or(..)));
+        walk(self, ast, id);
+        self.in_or = in_or;
+    }
+}
+
+/// `+`、`*`、`?` のいずれかであるかを判定する
+fn quantifier(node: &AstNode) -> bool {
+    matches!(
+        node,
+        AstNode::Plus(_) | AstNode::Star(_) | AstNode::Question(_)
+    )
+}
+
+/// 捕獲グループと要素が1つの連接を取り除いた中の式
+fn strip(ast: &AST, mut id: NodeId) -> NodeId {
+    loop {
+        match &ast[id] {
+            AstNode::Group(e, _) => id = *e,
+            AstNode::Seq(v) if v.len() == 1 => id = v[0],
+            _ => return id,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! パターンにマッチする文字列の先頭に現れるリテラルを抽出する
+use super::{
+    look::Look,
+    parser::{AST, AstNode, NodeId},
+};
+
+/// 抽出するリテラルの最大の長さ（バイト）
+const MAX_LEN: usize = 3;
+
+/// 抽出するリテラルの最大の個数
+const MAX_COUNT: usize = 32;
+
+/// リテラルとして展開する文字クラスの最大の文字数
+const MAX_CLASS_CHARS: usize = 8;
+
+/// 抽出途中のリテラル
+///
+/// `complete` が偽の場合は、その後ろに続く文字が分からないため、それ以上伸ばさない
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Literal {
+    bytes: Vec<u8>,
+    complete: bool,
+}
+
+/// マッチする文字列がいずれかで始まるような、空でないリテラルの集合を返す
+///
+/// 空文字列にマッチしうる場合や、リテラルの数が多すぎる場合は `None` を返す
+pub fn prefixes(ast: &AST) -> Option<Vec<Vec<u8>>> {
+    let lits = prefixes_node(ast, ast.root())?;
+    if lits.iter().any(|lit| lit.bytes.is_empty()) {
+        return None;
+    }
+    let mut lits: Vec<Vec<u8>> = lits.into_iter().map(|lit| lit.bytes).collect();
+    lits.sort();
+    lits.dedup();
+    Some(lits)
+}
+
+/// パターンが文字を連接しただけのリテラルである場合、その文字列を返す
+pub fn literal(ast: &AST) -> Option<String> {
+    match &ast[ast.root()] {
+        AstNode::Char(c) => Some(c.to_string()),
+        AstNode::Seq(v) => v
+            .iter()
+            .map(|e| match ast[*e] {
+                AstNode::Char(c) => Some(c),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// `^` で始まるパターンの、`^` に続くリテラル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anchored {
+    pub multi_line: bool, // 行の先頭でもマッチする場合は true
+    pub prefix: String,   // `^` の直後に続く文字列。空の場合もある
+    pub exact: bool,      // パターンが `^` とリテラルだけからなる場合は true
+}
+
+/// パターンが `^` で始まる場合、その直後に続くリテラルを返す
+pub fn anchored(ast: &AST) -> Option<Anchored> {
+    let root = ast.root();
+    let nodes = match &ast[root] {
+        AstNode::Seq(v) => &v[..],
+        _ => std::slice::from_ref(&root),
+    };
+    let multi_line = match ast[*nodes.first()?] {
+        AstNode::Look(Look::Start) => false,
+        AstNode::Look(Look::StartLine) => true,
+        _ => return None,
+    };
+    let rest = &nodes[1..];
+    let prefix: String = rest
+        .iter()
+        .map_while(|e| match ast[*e] {
+            AstNode::Char(c) => Some(c),
+            _ => None,
+        })
+        .collect();
+    Some(Anchored {
+        multi_line,
+        exact: prefix.chars().count() == rest.len(),
+        prefix,
+    })
+}
+
+fn prefixes_node(ast: &AST, id: NodeId) -> Option<Vec<Literal>> {
+    let lits = match &ast[id] {
+        AstNode::Char(c) => vec![char_literal(*c)],
+        AstNode::Class(class) => {
+            // 含まれる文字が少ない場合に限り、それぞれの文字をリテラルとする
+            let mut chars = class.ranges().iter().flat_map(|&(lo, hi)| lo..=hi);
+            let lits: Vec<Literal> = chars
+                .by_ref()
+                .take(MAX_CLASS_CHARS)
+                .map(char_literal)
+                .collect();
+            if lits.is_empty() || chars.next().is_some() {
+                return None;
+            }
+            lits
+        }
+        // 位置の判定は文字を消費しないため、空文字列として扱う
+        AstNode::Look(_) => vec![empty()],
+        AstNode::Group(e, _) => prefixes_node(ast, *e)?,
+        AstNode::Question(e) => {
+            let mut lits = prefixes_node(ast, *e)?;
+            lits.push(empty());
+            lits
+        }
+        AstNode::Star(e) => {
+            let mut lits = incomplete(prefixes_node(ast, *e)?);
+            lits.push(empty());
+            lits
+        }
+        AstNode::Plus(e) | AstNode::Repeat(e, 1.., _) => incomplete(prefixes_node(ast, *e)?),
+        AstNode::Repeat(e, 0, _) => {
+            let mut lits = incomplete(prefixes_node(ast, *e)?);
+            lits.push(empty());
+            lits
+        }
+        AstNode::Or(..) => {
+            let mut lits = Vec::new();
+            for e in ast.branches(id) {
+                lits.extend(prefixes_node(ast, e)?);
+                if lits.len() > MAX_COUNT {
+                    return None;
+                }
+            }
+            lits
+        }
+        AstNode::Seq(v) => {
+            let mut lits = vec![empty()];
+            for e in v {
+                if lits.iter().all(|lit| !lit.complete) {
+                    break;
+                }
+                lits = concat(lits, &prefixes_node(ast, *e)?);
+                if lits.len() > MAX_COUNT {
+                    return None;
+                }
+            }
+            lits
+        }
+    };
+
+    if lits.len() > MAX_COUNT {
+        None
+    } else {
+        Some(lits)
+    }
+}
+
+/// 1文字からなるリテラル
+fn char_literal(c: char) -> Literal {
+    let mut buf = [0; 4];
+    truncate(Literal {
+        bytes: c.encode_utf8(&mut buf).as_bytes().to_vec(),
+        complete: true,
+    })
+}
+
+/// 空文字列を表すリテラル
+fn empty() -> Literal {
+    Literal {
+        bytes: Vec::new(),
+        complete: true,
+    }
+}
+
+/// すべてのリテラルをそれ以上伸ばさないようにする
+fn incomplete(mut lits: Vec<Literal>) -> Vec<Literal> {
+    for lit in &mut lits {
+        lit.complete = false;
+    }
+    lits
+}
+
+/// 伸ばせるリテラルの後ろに `tails` のリテラルをそれぞれ連結する
+fn concat(heads: Vec<Literal>, tails: &[Literal]) -> Vec<Literal> {
+    let mut lits = Vec::new();
+    for head in heads {
+        if !head.complete {
+            lits.push(head);
+            continue;
+        }
+        for tail in tails {
+            let mut bytes = head.bytes.clone();
+            bytes.extend_from_slice(&tail.bytes);
+            lits.push(truncate(Literal {
+                bytes,
+                complete: tail.complete,
+            }));
+        }
+    }
+    lits.dedup();
+    lits
+}
+
+/// 最大の長さを超えるリテラルを切り詰める
+fn truncate(mut lit: Literal) -> Literal {
+    if lit.bytes.len() > MAX_LEN {
+        lit.bytes.truncate(MAX_LEN);
+        lit.complete = false;
+    }
+    lit
+}
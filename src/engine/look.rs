@@ -0,0 +1,69 @@
+//! 文字を消費せずに、位置の前後の文字を調べる判定
+use super::class;
+use std::fmt::{self, Display};
+
+/// 位置の前後の文字に対する判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Look {
+    Start,                 // ^。文字列の先頭
+    End,                   // $。文字列の末尾
+    StartLine,             // 複数行モードの ^。文字列か行の先頭
+    EndLine,               // 複数行モードの $。文字列か行の末尾
+    WordBoundary(bool),    // \b。ASCII の単語構成文字に限る場合は true
+    NotWordBoundary(bool), // \B。ASCII の単語構成文字に限る場合は true
+}
+
+/// 判定する位置の直前と直後の文字。文字列の先頭や末尾では `None`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    pub prev: Option<char>,
+    pub next: Option<char>,
+}
+
+impl Context {
+    /// `input` の `at` の位置の前後の文字
+    pub fn at(input: &str, at: usize) -> Context {
+        Context {
+            prev: input[..at].chars().next_back(),
+            next: input[at..].chars().next(),
+        }
+    }
+}
+
+impl Look {
+    /// 前後の文字が判定を満たすかを返す
+    pub fn matches(self, ctx: Context) -> bool {
+        match self {
+            Look::Start => ctx.prev.is_none(),
+            Look::End => ctx.next.is_none(),
+            Look::StartLine => ctx.prev.is_none_or(|c| c == '\n'),
+            Look::EndLine => ctx.next.is_none_or(|c| c == '\n'),
+            Look::WordBoundary(ascii) => is_word(ctx.prev, ascii) != is_word(ctx.next, ascii),
+            Look::NotWordBoundary(ascii) => is_word(ctx.prev, ascii) == is_word(ctx.next, ascii),
+        }
+    }
+}
+
+impl Display for Look {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, ascii) = match self {
+            Look::Start => return write!(f, "^"),
+            Look::End => return write!(f, "$"),
+            Look::StartLine => return write!(f, "(?m)^"),
+            Look::EndLine => return write!(f, "(?m)$"),
+            Look::WordBoundary(ascii) => ("\\b", ascii),
+            Look::NotWordBoundary(ascii) => ("\\B", ascii),
+        };
+        if *ascii {
+            write!(f, "(?a){name}")
+        } else {
+            write!(f, "{name}")
+        }
+    }
+}
+
+/// 文字が存在し、単語を構成する文字であるかを判定する
+fn is_word(c: Option<char>, ascii: bool) -> bool {
+    c.is_some_and(|c| class::is_word(c, ascii))
+}
@@ -0,0 +1,207 @@
+//! エラーや警告のメッセージの文言
+//!
+//! メッセージは英語と日本語で表示できる。既定は英語で、`ja` フィーチャを有効にすると日本語になる。
+//! 実行時には [`set_language`] で切り替えられる。
+//! エラーの種類やコードは言語によらない
+use super::{
+    analysis::Reason, binary::DecodeError, codegen::CodeGenError, evaluator::EvalError,
+    lint::WarningKind, parser::ParserErrorKind, syntax::Syntax,
+};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// メッセージの言語
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    Japanese,
+}
+
+/// 現在の言語。0 が英語、1 が日本語
+static LANGUAGE: AtomicU8 = AtomicU8::new(if cfg!(feature = "ja") { 1 } else { 0 });
+
+/// メッセージの言語を設定する。プロセス全体に適用される
+pub fn set_language(language: Language) {
+    let value = match language {
+        Language::English => 0,
+        Language::Japanese => 1,
+    };
+    LANGUAGE.store(value, Ordering::Relaxed);
+}
+
+/// 現在のメッセージの言語
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        0 => Language::English,
+        _ => Language::Japanese,
+    }
+}
+
+/// 現在の言語に応じて、英語か日本語の文言を選ぶ
+fn pick<T>(en: T, ja: T) -> T {
+    match language() {
+        Language::English => en,
+        Language::Japanese => ja,
+    }
+}
+
+/// パースエラーの説明
+pub fn parser_error(kind: ParserErrorKind) -> &'static str {
+    match kind {
+        ParserErrorKind::InvalidEscape(_) => pick("invalid escape", "誤ったエスケープシーケンス"),
+        ParserErrorKind::InvalidRightParen => pick(
+            "invalid right parenthesis",
+            "対応する開き括弧のない閉じ括弧",
+        ),
+        ParserErrorKind::NoPrev => pick("no previous expression", "前に式がない"),
+        ParserErrorKind::NoRightParen => pick("no right parenthesis", "閉じ括弧がない"),
+        ParserErrorKind::NoRightBracket => pick("no right bracket", "文字クラスの閉じ角括弧がない"),
+        ParserErrorKind::NoRightBrace => pick("no right brace", "繰り返し回数の閉じ波括弧がない"),
+        ParserErrorKind::InvalidRepeat => pick("invalid repetition count", "誤った繰り返し回数"),
+        ParserErrorKind::InvalidFlag => pick("invalid flag", "誤ったフラグ"),
+        ParserErrorKind::InvalidRange => pick(
+            "invalid character class range",
+            "文字クラスの範囲の始まりが終わりより大きい",
+        ),
+        ParserErrorKind::Empty => pick("empty expression", "空のパターン"),
+        ParserErrorKind::NestingTooDeep => pick("nesting too deep", "入れ子が深すぎる"),
+        ParserErrorKind::PatternTooLong => pick("pattern too long", "パターンが長すぎる"),
+        ParserErrorKind::Disabled(_) => pick("disabled syntax", "無効にした構文"),
+        ParserErrorKind::ClassTooLarge => pick(
+            "character class too large",
+            "文字クラスの文字数が上限を超える",
+        ),
+    }
+}
+
+/// 構文の名前
+pub fn syntax(syntax: Syntax) -> &'static str {
+    match syntax {
+        Syntax::Alternation => pick("alternation", "選択"),
+        Syntax::Group => pick("capture group", "捕獲グループ"),
+        Syntax::CountedRepetition => pick("counted repetition", "回数を指定した繰り返し"),
+        Syntax::NestedRepetition => pick("nested repetition", "繰り返しの入れ子"),
+        Syntax::UnboundedRepetition => pick("unbounded repetition", "上限のない繰り返し"),
+        Syntax::Look => pick("anchor or word boundary", "位置の判定"),
+    }
+}
+
+/// コード生成エラーの説明
+pub fn codegen_error(e: &CodeGenError) -> &'static str {
+    match e {
+        CodeGenError::PCOverFlow => pick(
+            "program counter overflow",
+            "プログラムカウンタのオーバーフロー",
+        ),
+        CodeGenError::FailPlus => pick("failed to generate +", "+ のコード生成に失敗"),
+        CodeGenError::FailStar => pick("failed to generate *", "* のコード生成に失敗"),
+        CodeGenError::FailOr => pick("failed to generate |", "| のコード生成に失敗"),
+        CodeGenError::FailQuestion => pick("failed to generate ?", "? のコード生成に失敗"),
+        CodeGenError::FailRepeat => pick("failed to generate {n,m}", "{n,m} のコード生成に失敗"),
+        CodeGenError::CompiledTooBig => pick("compiled program too big", "命令数が上限を超える"),
+    }
+}
+
+/// 評価エラーの説明
+pub fn eval_error(e: &EvalError) -> &'static str {
+    match e {
+        EvalError::StepLimitExceeded => pick("step limit exceeded", "実行した命令数が上限を超えた"),
+    }
+}
+
+/// 読み込みエラーの説明
+pub fn decode_error(e: &DecodeError) -> &'static str {
+    match e {
+        DecodeError::BadMagic => pick(
+            "not a serialized regex",
+            "正規表現を書き出したバイト列でない",
+        ),
+        DecodeError::UnsupportedVersion(_) => {
+            pick("unsupported format version", "読み込めない版の形式")
+        }
+        DecodeError::Truncated => pick("unexpected end of data", "バイト列が途中で終わっている"),
+        DecodeError::Checksum => pick("checksum mismatch", "チェックサムが一致しない"),
+        DecodeError::Invalid => pick(
+            "inconsistent program or tables",
+            "命令列や表の内容が矛盾している",
+        ),
+    }
+}
+
+/// 警告の説明
+pub fn warning(kind: WarningKind) -> &'static str {
+    match kind {
+        WarningKind::DuplicateBranch => pick("duplicate alternation branch", "重複した選択肢"),
+        WarningKind::MisplacedAnchor => pick(
+            "anchor can never match here",
+            "決して満たされない位置の判定",
+        ),
+        WarningKind::NestedQuantifier => {
+            pick("redundant nested quantifier", "まとめられる入れ子の限量子")
+        }
+        WarningKind::EmptyGroup => pick(
+            "group always matches the empty string",
+            "空文字列にしかマッチしないグループ",
+        ),
+    }
+}
+
+/// バックトラックの回数が急激に増えうる構造の説明
+pub fn reason(reason: Reason) -> &'static str {
+    match reason {
+        Reason::NestedQuantifier => pick("nested quantifier", "繰り返しの入れ子"),
+        Reason::OverlappingAlternation => pick(
+            "overlapping alternation under a quantifier",
+            "繰り返しの中の、同じ文字から始まりうる選択肢",
+        ),
+        Reason::AdjacentQuantifiers => pick(
+            "adjacent overlapping quantifiers",
+            "同じ文字にマッチしうる隣り合った繰り返し",
+        ),
+    }
+}
+
+/// パースエラーを修正する方法の提案
+#[derive(Debug, Clone, Copy)]
+pub enum Help<'a> {
+    Escape(&'a str),      // 特殊文字をエスケープする
+    UseStart,             // \A の代わりに ^ を使う
+    UseEnd,               // \z の代わりに $ を使う
+    WriteChar(char),      // \n などの代わりに文字そのものを書く
+    NeedlessEscape(char), // エスケープが不要な文字のバックスラッシュを取り除く
+    SwapBounds(&'a str),  // 文字クラスの範囲の両端を入れ替える
+    Close(&'a str),       // 閉じ括弧を補う
+}
+
+/// 提案の説明
+pub fn help(help: Help) -> String {
+    match (language(), help) {
+        (Language::English, Help::Escape(s)) => format!("escape it as `{s}`"),
+        (Language::English, Help::UseStart) => "use `^` for the start of the text".to_string(),
+        (Language::English, Help::UseEnd) => "use `$` for the end of the text".to_string(),
+        (Language::English, Help::WriteChar(c)) => match c {
+            '\t' => "write a literal tab".to_string(),
+            '\n' => "write a literal newline".to_string(),
+            '\r' => "write a literal carriage return".to_string(),
+            c => format!("write a literal `{c}`"),
+        },
+        (Language::English, Help::NeedlessEscape(c)) => {
+            format!("`{c}` needs no escape; remove the backslash")
+        }
+        (Language::English, Help::SwapBounds(s)) => format!("swap the bounds as `{s}`"),
+        (Language::English, Help::Close(s)) => format!("close it with `{s}`"),
+        (Language::Japanese, Help::Escape(s)) => format!("`{s}` とエスケープする"),
+        (Language::Japanese, Help::UseStart) => "文字列の先頭には `^` を使う".to_string(),
+        (Language::Japanese, Help::UseEnd) => "文字列の末尾には `$` を使う".to_string(),
+        (Language::Japanese, Help::WriteChar(c)) => match c {
+            '\t' => "タブ文字をそのまま書く".to_string(),
+            '\n' => "改行文字をそのまま書く".to_string(),
+            '\r' => "復帰文字をそのまま書く".to_string(),
+            c => format!("`{c}` をそのまま書く"),
+        },
+        (Language::Japanese, Help::NeedlessEscape(c)) => {
+            format!("`{c}` はエスケープ不要なので、バックスラッシュを取り除く")
+        }
+        (Language::Japanese, Help::SwapBounds(s)) => format!("`{s}` と範囲の両端を入れ替える"),
+        (Language::Japanese, Help::Close(s)) => format!("`{s}` で閉じる"),
+    }
+}
@@ -0,0 +1,75 @@
+//! パターンに合わせて検索に利用するエンジンを選ぶ
+use super::{
+    Program,
+    dfa::Dfa,
+    length, literal,
+    parser::{AST, AstNode, NodeId},
+};
+
+/// 検索に利用するエンジン
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Engine {
+    Literal,   // 部分文字列の検索
+    Backtrack, // 深さ優先で分岐を試す評価器
+    PikeVm,    // 状態の集合を1文字ずつ進める評価器
+    Dfa,       // 決定性有限オートマトン
+}
+
+/// パターンを調べて、検索に利用するエンジンを選ぶ
+///
+/// - パターンがリテラルの場合は、命令列を評価せずに部分文字列を検索する
+/// - DFA を構築できる見込みがある場合は DFA を利用する。DFA はまだ構築しないため、
+///   構築に失敗した場合は、呼び出し側で [`evaluator`] を使って選び直す
+/// - それ以外の場合は [`evaluator`] で選ぶ
+pub fn select(ast: &AST, prog: &Program) -> Engine {
+    if literal::literal(ast).is_some() {
+        Engine::Literal
+    } else if Dfa::can_build(prog) {
+        Engine::Dfa
+    } else {
+        evaluator(ast)
+    }
+}
+
+/// 命令列を評価するエンジンを選ぶ
+///
+/// - 2回以上繰り返す式の中に、さらに2回以上繰り返す式や選択がある場合と、
+///   上限のある繰り返しの本体が空文字列にマッチしうる場合は、
+///   バックトラックの回数が指数的に増えるおそれがあるため Pike VM を利用する。
+///   命令列が繰り返しの回数を数える場合も、Pike VM は繰り返しを展開した命令列を実行する
+/// - それ以外の場合は、定数倍の速いバックトラック型の評価器を利用する。
+///   回数を数える命令を実行できるのはバックトラック型の評価器だけである
+pub fn evaluator(ast: &AST) -> Engine {
+    if nested_repetition(ast, ast.root(), false) {
+        Engine::PikeVm
+    } else {
+        Engine::Backtrack
+    }
+}
+
+/// 2回以上繰り返す式の中に、2回以上繰り返す式か選択があるかを判定する
+///
+/// 上限のある繰り返しは、空文字列にマッチしうる本体も回数の上限まで繰り返すため、
+/// 本体が空文字列にマッチしうる場合も真とする
+fn nested_repetition(ast: &AST, id: NodeId, in_loop: bool) -> bool {
+    match &ast[id] {
+        AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => false,
+        AstNode::Plus(e) | AstNode::Star(e) | AstNode::Repeat(e, _, None) => {
+            in_loop || nested_repetition(ast, *e, true)
+        }
+        AstNode::Repeat(e, _, Some(max)) if *max > 1 => {
+            in_loop || length::nullable(ast, *e) || nested_repetition(ast, *e, true)
+        }
+        AstNode::Question(e) | AstNode::Group(e, _) | AstNode::Repeat(e, _, Some(_)) => {
+            nested_repetition(ast, *e, in_loop)
+        }
+        AstNode::Or(..) => {
+            in_loop
+                || ast
+                    .branches(id)
+                    .iter()
+                    .any(|&e| nested_repetition(ast, e, in_loop))
+        }
+        AstNode::Seq(v) => v.iter().any(|e| nested_repetition(ast, *e, in_loop)),
+    }
+}
@@ -0,0 +1,22 @@
+//! パターンを単純化した等価なパターンに書き直す
+use super::{
+    parser::{self, Flags, ParserError},
+    simplify,
+};
+
+/// パターンをパースして単純化し、等価なパターンの文字列に書き直す
+///
+/// 不要な `(?:...)` や `{1}` を取り除き、入れ子の限量子をまとめ、同一の選択肢を1つにする。
+/// 捕獲グループは番号も含めて残すため、書き直したパターンでも捕獲グループの位置は変わらない。
+/// 書き直したパターンは既定のフラグで解釈され、`flags` やパターン中のフラグの指定は、
+/// 必要な箇所だけに `(?m)` や `(?a)` を付けるか、文字クラスに展開して表す。
+/// 同じパターンを同じ文字列に書き直すため、多数のパターンから重複を取り除くのに利用できる
+///
+/// ```text
+/// (?:ab){1}|(?:ab)  →  ab
+/// (a+)+\d{0,}       →  (a+)+\d*
+/// ```
+pub fn normalize(pattern: &str, flags: Flags) -> Result<String, ParserError> {
+    let ast = parser::parse(pattern, flags)?;
+    Ok(simplify::simplify(&ast, true).to_string())
+}
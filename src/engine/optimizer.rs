@@ -0,0 +1,276 @@
+//! コード生成後の命令列に対するのぞき穴最適化
+//!
+//! 命令列をいったん後続ノードを明示したグラフに変換し、次の変換を不動点に達するまで繰り返す
+//!
+//! - 同じ後続を持つ文字テストどうしの split を、1つの文字クラスのテストにまとめる
+//! - 構造が同一の部分プログラムを1つにまとめる
+//! - 両方の分岐先が同じ split を取り除く
+//!
+//! 最後に先頭から到達可能なノードだけを並べ直すため、jump の連鎖や
+//! 到達不能な命令もあわせて取り除かれる。
+//! 並べ直した命令列に対して、jump と split でたどれる命令をあらかじめ求めておく
+use super::{
+    Instruction, Program,
+    class::{CharClass, normalize},
+    closure,
+    look::Look,
+};
+use std::collections::HashMap;
+
+/// 後続ノードを明示した命令
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Test(Vec<(char, char)>, usize), // 文字の範囲のいずれかにマッチしたら後続へ進む
+    Split(usize, usize),
+    RepeatStart(u32, usize),   // 繰り返しの回数を 0 にして後続へ進む
+    Repeat(u32, usize, usize), // 回数に応じて本体か脱出先へ進む
+    RepeatEnd(u32, usize),     // 回数を 1 増やしてループの先頭へ戻る
+    Mark(u32, usize),          // ループに入った位置を記録して後続へ進む
+    Progress(u32, usize),      // 繰り返しで位置が進んでいれば後続へ進む
+    Again(u32, usize),         // 位置が進んでいれば、位置を記録して後続へ進む
+    Look(Look, usize),         // 位置の判定を満たしたら後続へ進む
+    Save(u32, usize),          // 現在の位置を捕獲して後続へ進む
+    Match,
+}
+
+/// 命令列を最適化する
+pub fn optimize(prog: &Program) -> Program {
+    let (mut nodes, root) = to_graph(prog);
+
+    // 各ノードの代表元。まとめられたノードは代表元を指す
+    let mut rep: Vec<usize> = (0..nodes.len()).collect();
+    loop {
+        let merged = merge_tests(&mut nodes, &mut rep);
+        let deduped = dedup(&nodes, &mut rep);
+        if !merged && !deduped {
+            break;
+        }
+    }
+
+    let mut prog = emit(&nodes, &mut rep, root, prog);
+    prog.closures = closure::compute(&prog);
+    prog
+}
+
+/// jump をたどり、最初の jump 以外の命令のアドレスを返す
+fn resolve(insts: &[Instruction], mut pc: usize) -> usize {
+    // jump のみで構成された無限ループに備えて、命令数を上限にたどる
+    for _ in 0..insts.len() {
+        match insts[pc] {
+            Instruction::Jump(addr) => pc = addr as usize,
+            _ => break,
+        }
+    }
+    pc
+}
+
+/// 命令列をグラフに変換する
+///
+/// ノードの番号は元の命令のアドレスと一致させる。
+/// jump 命令のノードはどこからも参照されないため、仮に Match として扱う
+fn to_graph(prog: &Program) -> (Vec<Node>, usize) {
+    let insts = &prog.insts;
+    let nodes = insts
+        .iter()
+        .enumerate()
+        .map(|(pc, inst)| match *inst {
+            Instruction::Char(c) => Node::Test(vec![(c, c)], resolve(insts, pc + 1)),
+            Instruction::Class(idx) => Node::Test(
+                prog.classes[idx as usize].ranges().to_vec(),
+                resolve(insts, pc + 1),
+            ),
+            Instruction::Split(addr1, addr2) => Node::Split(
+                resolve(insts, addr1 as usize),
+                resolve(insts, addr2 as usize),
+            ),
+            Instruction::RepeatStart(idx) => Node::RepeatStart(idx, resolve(insts, pc + 1)),
+            Instruction::Repeat(idx, addr) => {
+                Node::Repeat(idx, resolve(insts, pc + 1), resolve(insts, addr as usize))
+            }
+            Instruction::RepeatEnd(idx, addr) => {
+                Node::RepeatEnd(idx, resolve(insts, addr as usize))
+            }
+            Instruction::Mark(slot) => Node::Mark(slot, resolve(insts, pc + 1)),
+            Instruction::Progress(slot) => Node::Progress(slot, resolve(insts, pc + 1)),
+            Instruction::Again(slot) => Node::Again(slot, resolve(insts, pc + 1)),
+            Instruction::Look(look) => Node::Look(look, resolve(insts, pc + 1)),
+            Instruction::Save(slot) => Node::Save(slot, resolve(insts, pc + 1)),
+            Instruction::Jump(_) | Instruction::Match => Node::Match,
+        })
+        .collect();
+    (nodes, resolve(insts, 0))
+}
+
+/// 代表元をたどる
+fn find(rep: &mut [usize], mut id: usize) -> usize {
+    while rep[id] != id {
+        rep[id] = rep[rep[id]];
+        id = rep[id];
+    }
+    id
+}
+
+/// 後続が同一の文字テストを分岐する split を、文字クラスのテストに置き換える
+///
+/// 両方の分岐先が同一の split は、分岐先そのものにまとめる
+fn merge_tests(nodes: &mut [Node], rep: &mut [usize]) -> bool {
+    let mut changed = false;
+    for id in 0..nodes.len() {
+        if find(rep, id) != id {
+            continue;
+        }
+        let Node::Split(addr1, addr2) = nodes[id] else {
+            continue;
+        };
+        let (addr1, addr2) = (find(rep, addr1), find(rep, addr2));
+        if addr1 == addr2 && addr1 != id {
+            rep[id] = addr1;
+            changed = true;
+            continue;
+        }
+        if let (Node::Test(ranges1, next1), Node::Test(ranges2, next2)) =
+            (&nodes[addr1], &nodes[addr2])
+            && find(rep, *next1) == find(rep, *next2)
+        {
+            let mut ranges = ranges1.clone();
+            ranges.extend_from_slice(ranges2);
+            let test = Node::Test(normalize(ranges), *next1);
+            nodes[id] = test;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// 構造が同一のノードを1つにまとめる
+fn dedup(nodes: &[Node], rep: &mut [usize]) -> bool {
+    let mut changed = false;
+    let mut seen = HashMap::new();
+    for id in 0..nodes.len() {
+        if find(rep, id) != id {
+            continue;
+        }
+        let key = match &nodes[id] {
+            Node::Test(ranges, next) => Node::Test(ranges.clone(), find(rep, *next)),
+            Node::Split(addr1, addr2) => Node::Split(find(rep, *addr1), find(rep, *addr2)),
+            Node::RepeatStart(idx, next) => Node::RepeatStart(*idx, find(rep, *next)),
+            Node::Repeat(idx, body, exit) => Node::Repeat(*idx, find(rep, *body), find(rep, *exit)),
+            Node::RepeatEnd(idx, head) => Node::RepeatEnd(*idx, find(rep, *head)),
+            Node::Mark(slot, next) => Node::Mark(*slot, find(rep, *next)),
+            Node::Progress(slot, next) => Node::Progress(*slot, find(rep, *next)),
+            Node::Again(slot, next) => Node::Again(*slot, find(rep, *next)),
+            Node::Look(look, next) => Node::Look(*look, find(rep, *next)),
+            Node::Save(slot, next) => Node::Save(*slot, find(rep, *next)),
+            Node::Match => Node::Match,
+        };
+        if let Some(&other) = seen.get(&key) {
+            rep[id] = other;
+            changed = true;
+        } else {
+            seen.insert(key, id);
+        }
+    }
+    changed
+}
+
+/// 先頭から到達可能なノードを並べ、プログラムに戻す
+///
+/// 文字テストの後続と split の1つ目の分岐先は、可能な限り直後に配置する。
+/// 繰り返しの本体のように命令の直後に置く必要がある後続は、配置済みの場合は jump でつなぐ。
+/// 同一の文字クラスは表の同じ要素を共有し、繰り返しの表と記録する場所の数は `orig` から引き継ぐ
+fn emit(nodes: &[Node], rep: &mut [usize], root: usize, orig: &Program) -> Program {
+    // 配置順。2つ目の要素が真の場合は、配置済みのノードへの jump を表す
+    let mut order: Vec<(usize, bool)> = Vec::new();
+    let mut placed = vec![false; nodes.len()];
+    let mut stack = vec![find(rep, root)];
+
+    while let Some(id) = stack.pop() {
+        if placed[id] {
+            continue;
+        }
+        let mut cur = id;
+        loop {
+            if placed[cur] {
+                // 直前がテストなどの場合は、配置済みの後続へ jump する
+                order.push((cur, true));
+                break;
+            }
+            placed[cur] = true;
+            order.push((cur, false));
+            match &nodes[cur] {
+                Node::Test(_, next)
+                | Node::RepeatStart(_, next)
+                | Node::Mark(_, next)
+                | Node::Progress(_, next)
+                | Node::Again(_, next)
+                | Node::Look(_, next)
+                | Node::Save(_, next) => cur = find(rep, *next),
+                Node::Repeat(_, body, exit) => {
+                    stack.push(find(rep, *exit));
+                    cur = find(rep, *body);
+                }
+                Node::RepeatEnd(_, head) => {
+                    stack.push(find(rep, *head));
+                    break;
+                }
+                Node::Split(addr1, addr2) => {
+                    stack.push(find(rep, *addr2));
+                    let addr1 = find(rep, *addr1);
+                    if placed[addr1] {
+                        break;
+                    }
+                    cur = addr1;
+                }
+                Node::Match => break,
+            }
+        }
+    }
+
+    // 各ノードの新しいアドレスを求める
+    let mut addrs = vec![0; nodes.len()];
+    for (pc, &(id, is_jump)) in order.iter().enumerate() {
+        if !is_jump {
+            addrs[id] = pc as u32;
+        }
+    }
+
+    let mut prog = Program {
+        repeats: orig.repeats.clone(),
+        marks: orig.marks,
+        slots: orig.slots,
+        ..Default::default()
+    };
+    let mut class_ids = HashMap::new();
+    for &(id, is_jump) in &order {
+        let inst = if is_jump {
+            Instruction::Jump(addrs[id])
+        } else {
+            match &nodes[id] {
+                Node::Test(ranges, _) => match ranges[..] {
+                    [(lo, hi)] if lo == hi => Instruction::Char(lo),
+                    _ => {
+                        let idx = *class_ids.entry(ranges.clone()).or_insert_with(|| {
+                            prog.classes.push(CharClass::new(ranges.clone()));
+                            prog.classes.len() as u32 - 1
+                        });
+                        Instruction::Class(idx)
+                    }
+                },
+                Node::Split(addr1, addr2) => {
+                    Instruction::Split(addrs[find(rep, *addr1)], addrs[find(rep, *addr2)])
+                }
+                Node::RepeatStart(idx, _) => Instruction::RepeatStart(*idx),
+                Node::Repeat(idx, _, exit) => Instruction::Repeat(*idx, addrs[find(rep, *exit)]),
+                Node::RepeatEnd(idx, head) => Instruction::RepeatEnd(*idx, addrs[find(rep, *head)]),
+                Node::Mark(slot, _) => Instruction::Mark(*slot),
+                Node::Progress(slot, _) => Instruction::Progress(*slot),
+                Node::Again(slot, _) => Instruction::Again(*slot),
+                Node::Look(look, _) => Instruction::Look(*look),
+                Node::Save(slot, _) => Instruction::Save(*slot),
+                Node::Match => Instruction::Match,
+            }
+        };
+        prog.insts.push(inst);
+    }
+    prog
+}
@@ -1,187 +1,1250 @@
 //! 正規表現をパースし、抽象構文木(AST)に変換する
+use super::{
+    class::{CharClass, Perl},
+    look::Look,
+    message::{self, Help},
+    syntax::Syntax,
+    token::{TokenKind, tokenize},
+};
 use std::{
     error::Error,
     fmt::{self, Display},
-    mem::take,
+    hash::{Hash, Hasher},
+    mem::{self, take},
+    ops::{Index, Range},
 };
 
-/// 抽象構文木を表現するための型
-#[derive(Debug)]
-pub enum AST {
+/// AST のノードを指すインデックス
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(usize);
+
+/// AST のノード
+///
+/// 子ノードはボックスではなく、同じ AST に格納されたノードのインデックスで参照する。
+/// 今後のバージョンで種類が増える可能性がある
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AstNode {
     Char(char),
-    Plus(Box<AST>),
-    Star(Box<AST>),
-    Question(Box<AST>),
-    Or(Box<AST>, Box<AST>),
-    Seq(Vec<AST>),
+    Class(CharClass),
+    Plus(NodeId),
+    Star(NodeId),
+    Question(NodeId),
+    Repeat(NodeId, u32, Option<u32>), // {n,m}。上限がない場合は None
+    Look(Look),                       // 文字を消費しない位置の判定
+    Group(NodeId, u32),               // 捕獲グループと、1 から始まるその番号
+    Or(NodeId, NodeId),
+    Seq(Vec<NodeId>),
 }
 
-/// parse_plus_star_question 関数で利用するための列挙型
-enum PSQ {
-    Plus,
-    Star,
-    Question,
+/// 抽象構文木を表現するための型
+///
+/// すべてのノードを1つのベクタ（アリーナ）に格納するため、
+/// パース時の確保回数が少なく、深い木でも再帰的な Drop が発生しない。
+/// 比較とハッシュは根のノードからたどれる木の構造と捕獲グループの数で行い、
+/// パターン中の範囲やアリーナ中のノードの並び、たどれないノードは区別しない。
+/// そのため `[]a]` と `[\]a]` のように同じ木になる書き方の AST は等しい
+///
+/// `serde` フィーチャを有効にすると、シリアライズできる。
+/// デシリアライズでは、子ノードが親ノードより前に格納されていること、
+/// グループの番号が捕獲グループの数以下であることと、繰り返しの下限が上限以下であることを確かめる
+///
+/// `arbitrary` フィーチャを有効にすると、ファジング用に任意のバイト列から生成できる
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AST {
+    nodes: Vec<AstNode>,
+    spans: Vec<Range<usize>>, // 各ノードに対応するパターン中の範囲（バイト）
+    root: NodeId,
+    groups: u32, // 捕獲グループの数
+}
+
+impl AST {
+    /// 空の AST を生成する
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// パターン中の範囲を指定してノードを追加し、そのインデックスを返す
+    pub fn push_span(&mut self, node: AstNode, span: Range<usize>) -> NodeId {
+        self.nodes.push(node);
+        self.spans.push(span);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// ノードに対応するパターン中の範囲（バイト）
+    pub fn span(&self, id: NodeId) -> Range<usize> {
+        self.spans[id.0].clone()
+    }
+
+    /// ノードに対応するパターン中の範囲を設定する
+    fn set_span(&mut self, id: NodeId, span: Range<usize>) {
+        self.spans[id.0] = span;
+    }
+
+    /// 根のノード
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// 根のノードを設定する
+    pub fn set_root(&mut self, id: NodeId) {
+        self.root = id;
+    }
+
+    /// ノードの数
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// 捕獲グループの数。マッチ全体は含まない
+    pub fn groups(&self) -> u32 {
+        self.groups
+    }
+
+    /// 捕獲グループの数を設定する
+    pub fn set_groups(&mut self, groups: u32) {
+        self.groups = groups;
+    }
+
+    /// `id` から OR で結合された選択肢を、入れ子の OR を平坦化して左から順に返す
+    ///
+    /// 選択肢の多いパターンは深い OR の入れ子になるため、再帰せずにたどる。
+    /// `id` が OR でない場合は `id` だけを返す
+    pub fn branches(&self, id: NodeId) -> Vec<NodeId> {
+        let mut branches = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            match self[id] {
+                AstNode::Or(e1, e2) => {
+                    stack.push(e2);
+                    stack.push(e1);
+                }
+                _ => branches.push(id),
+            }
+        }
+        branches
+    }
+}
+
+impl PartialEq for AST {
+    fn eq(&self, other: &AST) -> bool {
+        if self.groups != other.groups {
+            return false;
+        }
+        if self.nodes.is_empty() || other.nodes.is_empty() {
+            return self.nodes.is_empty() == other.nodes.is_empty();
+        }
+        // 深い木でもスタックがあふれないよう、再帰せずに2つの木を同時にたどる
+        let mut stack = vec![(self.root, other.root)];
+        while let Some((a, b)) = stack.pop() {
+            match (&self[a], &other[b]) {
+                (AstNode::Char(c1), AstNode::Char(c2)) if c1 == c2 => {}
+                (AstNode::Class(c1), AstNode::Class(c2)) if c1 == c2 => {}
+                (AstNode::Look(l1), AstNode::Look(l2)) if l1 == l2 => {}
+                (AstNode::Plus(e1), AstNode::Plus(e2))
+                | (AstNode::Star(e1), AstNode::Star(e2))
+                | (AstNode::Question(e1), AstNode::Question(e2)) => stack.push((*e1, *e2)),
+                (AstNode::Repeat(e1, min1, max1), AstNode::Repeat(e2, min2, max2))
+                    if min1 == min2 && max1 == max2 =>
+                {
+                    stack.push((*e1, *e2));
+                }
+                (AstNode::Group(e1, idx1), AstNode::Group(e2, idx2)) if idx1 == idx2 => {
+                    stack.push((*e1, *e2));
+                }
+                (AstNode::Or(l1, r1), AstNode::Or(l2, r2)) => {
+                    stack.push((*r1, *r2));
+                    stack.push((*l1, *l2));
+                }
+                (AstNode::Seq(v1), AstNode::Seq(v2)) if v1.len() == v2.len() => {
+                    stack.extend(v1.iter().copied().zip(v2.iter().copied()).rev());
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Eq for AST {}
+
+impl Hash for AST {
+    /// 比較と同じく、根のノードからたどれる木の構造だけをハッシュする
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.groups.hash(state);
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            let node = &self[id];
+            mem::discriminant(node).hash(state);
+            match node {
+                AstNode::Char(c) => c.hash(state),
+                AstNode::Class(class) => class.hash(state),
+                AstNode::Look(look) => look.hash(state),
+                AstNode::Plus(e) | AstNode::Star(e) | AstNode::Question(e) => stack.push(*e),
+                AstNode::Repeat(e, min, max) => {
+                    min.hash(state);
+                    max.hash(state);
+                    stack.push(*e);
+                }
+                AstNode::Group(e, idx) => {
+                    idx.hash(state);
+                    stack.push(*e);
+                }
+                AstNode::Or(e1, e2) => stack.extend([*e2, *e1]),
+                AstNode::Seq(v) => {
+                    v.len().hash(state);
+                    stack.extend(v.iter().rev());
+                }
+            }
+        }
+    }
+}
+
+impl Index<NodeId> for AST {
+    type Output = AstNode;
+
+    fn index(&self, id: NodeId) -> &AstNode {
+        &self.nodes[id.0]
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AST {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<AST, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        struct Raw {
+            nodes: Vec<AstNode>,
+            spans: Vec<Range<usize>>,
+            root: NodeId,
+            groups: u32,
+        }
+
+        let Raw {
+            nodes,
+            spans,
+            root,
+            groups,
+        } = Raw::deserialize(deserializer)?;
+        if nodes.len() != spans.len() {
+            return Err(D::Error::custom("the number of spans differs from nodes"));
+        }
+        if root.0 >= nodes.len() {
+            return Err(D::Error::custom("root is out of range"));
+        }
+        // 子ノードが親ノードより前にあれば、インデックスは範囲内で循環もしない
+        for (i, node) in nodes.iter().enumerate() {
+            let valid = match node {
+                AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => true,
+                AstNode::Plus(e) | AstNode::Star(e) | AstNode::Question(e) => e.0 < i,
+                // パーサーと同じく、下限が上限より大きい繰り返しは受け付けない
+                AstNode::Repeat(e, min, max) => e.0 < i && max.is_none_or(|max| *min <= max),
+                AstNode::Group(e, idx) => e.0 < i && (1..=groups).contains(idx),
+                AstNode::Or(e1, e2) => e1.0 < i && e2.0 < i,
+                AstNode::Seq(v) => v.iter().all(|e| e.0 < i),
+            };
+            if !valid {
+                return Err(D::Error::custom(format_args!("invalid node at {i}")));
+            }
+        }
+        Ok(AST {
+            nodes,
+            spans,
+            root,
+            groups,
+        })
+    }
+}
+
+/// パターン中の範囲
+///
+/// パターンの文字列をスライスする場合はバイトオフセットを利用する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub byte_start: usize, // 開始位置（バイト）
+    pub byte_end: usize,   // 終了位置（バイト）
+    pub char_start: usize, // 開始位置（文字数）
+}
+
+impl Span {
+    /// `start` 番目の文字から `end` 番目の文字の直前までの範囲
+    ///
+    /// `offsets` は文字の位置からバイトオフセットへの対応で、末尾にパターンの長さを含む
+    fn new(offsets: &[usize], start: usize, end: usize) -> Span {
+        Span {
+            byte_start: offsets[start],
+            byte_end: offsets[end],
+            char_start: start,
+        }
+    }
+
+    /// `pattern` 中のバイトオフセットの範囲
+    pub fn from_bytes(pattern: &str, bytes: Range<usize>) -> Span {
+        Span {
+            byte_start: bytes.start,
+            byte_end: bytes.end,
+            char_start: pattern[..bytes.start].chars().count(),
+        }
+    }
+}
+
+/// パースエラーを修正する方法の提案
+///
+/// パターンの `span` の範囲を `replacement` で置き換えると修正できる。
+/// 範囲が空の場合は、その位置に挿入することを表す
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Suggestion {
+    pub message: String,     // 提案の説明
+    pub span: Span,          // 置き換える範囲
+    pub replacement: String, // 置き換える文字列
+}
+
+impl Suggestion {
+    /// 提案をパターンに適用した文字列を返す
+    pub fn apply(&self, pattern: &str) -> String {
+        let mut fixed = pattern[..self.span.byte_start].to_string();
+        fixed.push_str(&self.replacement);
+        fixed.push_str(&pattern[self.span.byte_end..]);
+        fixed
+    }
+}
+
+/// パースエラーの種類
+///
+/// 今後のバージョンで種類が増える可能性がある
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParserErrorKind {
+    InvalidEscape(char), // 誤ったエスケープシーケンス
+    InvalidRightParen,   //開き括弧なし
+    NoPrev,              // +, *, ?, {n,m} の前に式がない
+    NoRightParen,        // 閉じ括弧なし
+    NoRightBracket,      // 文字クラスの閉じ角括弧なし
+    NoRightBrace,        // 繰り返し回数の閉じ波括弧なし
+    InvalidRepeat,       // 繰り返し回数の指定が誤っている
+    InvalidFlag,         // 誤ったフラグの指定
+    InvalidRange,        // 文字クラスの範囲の始まりが終わりより大きい
+    Empty,               // 空のパターン
+    NestingTooDeep,      // グループや繰り返しの入れ子が深すぎる
+    PatternTooLong,      // パターンが長すぎる
+    Disabled(Syntax),    // ビルダーで無効にした構文
+    ClassTooLarge,       // 文字クラスの文字数が上限を超える
+}
+
+impl ParserErrorKind {
+    /// エラーの種類を表す短いコード。バージョンをまたいで変わらない
+    pub fn code(self) -> &'static str {
+        match self {
+            ParserErrorKind::InvalidEscape(_) => "E0001",
+            ParserErrorKind::InvalidRightParen => "E0002",
+            ParserErrorKind::NoPrev => "E0003",
+            ParserErrorKind::NoRightParen => "E0004",
+            ParserErrorKind::NoRightBracket => "E0005",
+            ParserErrorKind::NoRightBrace => "E0006",
+            ParserErrorKind::InvalidRepeat => "E0007",
+            ParserErrorKind::InvalidFlag => "E0008",
+            ParserErrorKind::InvalidRange => "E0009",
+            ParserErrorKind::Empty => "E0010",
+            ParserErrorKind::NestingTooDeep => "E0011",
+            ParserErrorKind::PatternTooLong => "E0012",
+            ParserErrorKind::Disabled(_) => "E0013",
+            ParserErrorKind::ClassTooLarge => "E0014",
+        }
+    }
 }
 
 /// パースエラーを表すための型
-#[derive(Debug)]
-pub enum ParserError {
-    InvalidEscape(usize, char), // 誤ったエスケープシーケンス
-    InvalidRightParen(usize),   //開き括弧なし
-    NoPrev(usize),              // +, |, *, ? の前に式がない
-    NoRightParen,               // 閉じ括弧なし
-    Empty,                      // 空のパターン
+///
+/// 閉じ括弧がないエラーの範囲は、開き括弧からパターンの末尾まで
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParserError {
+    kind: ParserErrorKind, // エラーの種類
+    span: Span,            // エラーの原因となったパターン中の範囲
+}
+
+impl ParserError {
+    pub(crate) fn new(kind: ParserErrorKind, span: Span) -> ParserError {
+        ParserError { kind, span }
+    }
+
+    /// エラーの種類
+    pub fn kind(&self) -> ParserErrorKind {
+        self.kind
+    }
+
+    /// エラーの種類を表す短いコード。[`ParserErrorKind::code`] と同じ
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    /// エラーの原因となったパターン中の範囲
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// よくある誤りに対して、修正する方法を提案する
+    ///
+    /// `pattern` はエラーとなったパターン。提案がない場合は `None`
+    pub fn suggestion(&self, pattern: &str) -> Option<Suggestion> {
+        let span = self.span();
+        let text = pattern.get(span.byte_start..span.byte_end)?;
+        let (message, replacement) = match self.kind {
+            // 特殊文字を文字として使うつもりだったとみなす
+            ParserErrorKind::InvalidRightParen
+            | ParserErrorKind::NoPrev
+            | ParserErrorKind::InvalidRepeat => {
+                let escaped = escape(text);
+                (message::help(Help::Escape(&escaped)), escaped)
+            }
+            ParserErrorKind::InvalidEscape(c) => match c {
+                'A' => (message::help(Help::UseStart), "^".to_string()),
+                'z' | 'Z' => (message::help(Help::UseEnd), "$".to_string()),
+                't' | 'n' | 'r' => {
+                    let c = match c {
+                        't' => '\t',
+                        'n' => '\n',
+                        _ => '\r',
+                    };
+                    (message::help(Help::WriteChar(c)), c.to_string())
+                }
+                c if !c.is_alphanumeric() => {
+                    (message::help(Help::NeedlessEscape(c)), c.to_string())
+                }
+                _ => return None,
+            },
+            ParserErrorKind::InvalidRange => {
+                // 入れ替えると意味が変わりうるため、英数字どうしの範囲に限る
+                let mut chars = text.chars();
+                let (Some(lo), Some('-'), Some(hi), None) =
+                    (chars.next(), chars.next(), chars.next(), chars.next())
+                else {
+                    return None;
+                };
+                if !lo.is_alphanumeric() || !hi.is_alphanumeric() {
+                    return None;
+                }
+                let swapped = format!("{hi}-{lo}");
+                (message::help(Help::SwapBounds(&swapped)), swapped)
+            }
+            ParserErrorKind::NoRightParen
+            | ParserErrorKind::NoRightBracket
+            | ParserErrorKind::NoRightBrace => {
+                let close = match self.kind {
+                    ParserErrorKind::NoRightParen => ")",
+                    ParserErrorKind::NoRightBracket => "]",
+                    _ => "}",
+                };
+                let end = Span::from_bytes(pattern, pattern.len()..pattern.len());
+                return Some(Suggestion {
+                    message: message::help(Help::Close(close)),
+                    span: end,
+                    replacement: close.to_string(),
+                });
+            }
+            ParserErrorKind::InvalidFlag
+            | ParserErrorKind::Empty
+            | ParserErrorKind::NestingTooDeep
+            | ParserErrorKind::PatternTooLong
+            | ParserErrorKind::Disabled(_)
+            | ParserErrorKind::ClassTooLarge => return None,
+        };
+        Some(Suggestion {
+            message,
+            span,
+            replacement,
+        })
+    }
+
+    /// パターンと、その下にエラーの範囲を示す印を付けて表示する
+    ///
+    /// 修正する方法の提案がある場合は、その説明も表示する
+    ///
+    /// ```text
+    /// ParseError: invalid right parenthesis: pos = 2
+    ///   |
+    ///   | ab)
+    ///   |   ^
+    ///   = help: escape it as `\)`
+    /// ```
+    ///
+    /// 全角の文字は2文字分の幅として印の位置をそろえる
+    pub fn at_pattern<'a>(&'a self, pattern: &'a str) -> impl Display + 'a {
+        AtPattern {
+            error: self,
+            pattern,
+        }
+    }
+}
+
+/// [`ParserError::at_pattern`] で表示するための型
+struct AtPattern<'a> {
+    error: &'a ParserError,
+    pattern: &'a str,
+}
+
+impl Display for AtPattern<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.error.span();
+        let columns = |s: &str| s.chars().map(width).sum::<usize>();
+        let before = self.pattern.get(..span.byte_start).map_or(0, columns);
+        let marked = self
+            .pattern
+            .get(span.byte_start..span.byte_end)
+            .map_or(0, columns);
+        writeln!(f, "{}", self.error)?;
+        writeln!(f, "  |")?;
+        writeln!(f, "  | {}", self.pattern)?;
+        write!(
+            f,
+            "  | {}^{}",
+            " ".repeat(before),
+            "~".repeat(marked.saturating_sub(1))
+        )?;
+        if let Some(suggestion) = self.error.suggestion(self.pattern) {
+            write!(f, "\n  = help: {}", suggestion.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// 特殊文字の前にバックスラッシュを付ける
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '(' | ')' | '|' | '+' | '*' | '?' | '[' | ']' | '{' | '}' | '^' | '$'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// 端末に表示した際の文字の幅。全角の文字は 2 とする
+fn width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
 }
 
 impl Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ParserError::InvalidEscape(pos, c) => {
-                write!(f, "ParseError: invalid escape: pos = {pos}, char = '{c}'")
-            }
-            ParserError::InvalidRightParen(pos) => {
-                write!(f, "ParseError: invalid right parenthesis: pos = {pos}")
+        let pos = self.span().char_start;
+        let message = message::parser_error(self.kind);
+        match self.kind {
+            ParserErrorKind::InvalidEscape(c) => {
+                write!(f, "ParseError: {message}: pos = {pos}, char = '{c}'")
             }
-            ParserError::NoPrev(pos) => {
-                write!(f, "ParseError: no previous expression: pos = {pos}")
+            ParserErrorKind::Disabled(syntax) => {
+                let syntax = message::syntax(syntax);
+                write!(f, "ParseError: {message}: pos = {pos}, syntax = {syntax}")
             }
-            ParserError::NoRightParen => write!(f, "ParseError: no right parenthesis"),
-            ParserError::Empty => write!(f, "ParseError: empty expression"),
+            // 閉じ括弧がない場合と空のパターンは、位置を表示しない
+            ParserErrorKind::NoRightParen
+            | ParserErrorKind::NoRightBracket
+            | ParserErrorKind::NoRightBrace
+            | ParserErrorKind::Empty => write!(f, "ParseError: {message}"),
+            _ => write!(f, "ParseError: {message}: pos = {pos}"),
         }
     }
 }
 
 impl Error for ParserError {} // エラー用に Error トレイトを実装
 
+/// パターンの解釈を変えるフラグ
+///
+/// パターン中では `(?a)` のように指定し、指定したグループの終わりまで有効になる。
+/// `(?a:...)` のように書くと、そのグループの中だけで有効になる
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Flags {
+    pub ascii: bool,      // a: \d、\w、\s、\b を ASCII 文字に限る
+    pub multi_line: bool, // m: ^ と $ を各行の先頭と末尾にもマッチさせる
+}
+
+/// 複数のパースエラーをまとめたもの
+///
+/// 互いに独立したエラーを、パターン中に現れた順に並べる
+#[derive(Debug)]
+pub struct ParserErrors(pub Vec<ParserError>);
+
+impl Display for ParserErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, e) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{e}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParserErrors {} // エラー用に Error トレイトを実装
+
+/// パターンの長さが `limit` バイト以下であることを確かめる
+///
+/// 上限を超えた場合は、上限を超えた最初の文字からパターンの末尾までの範囲をエラーとする
+pub fn check_length(pattern: &str, limit: usize) -> Result<(), ParserError> {
+    if pattern.len() <= limit {
+        return Ok(());
+    }
+    // 上限の位置を含む文字の先頭
+    let byte_start = (0..=limit)
+        .rev()
+        .find(|&i| pattern.is_char_boundary(i))
+        .unwrap_or(0);
+    Err(ParserError::new(
+        ParserErrorKind::PatternTooLong,
+        Span::from_bytes(pattern, byte_start..pattern.len()),
+    ))
+}
+
+/// AST の入れ子の深さの既定の上限
+pub const DEFAULT_NEST_LIMIT: u32 = 250;
+
+/// AST の入れ子の深さが `limit` 以下であることを確かめる
+///
+/// AST をたどる処理の多くは再帰呼び出しで実装しているため、
+/// 深すぎる AST を渡すとスタックがあふれる。
+/// 括弧や繰り返しの入れ子の深さを数えるよう、グループと OR の子は親と同じ段として数える。
+/// 連続した選択肢は OR の入れ子になるが、AST をたどる処理は OR の入れ子を再帰せずにたどるため、全体で1段となる。
+/// 上限を超えた場合は、上限を超えた最も外側のノードの範囲をエラーとする
+pub fn check_nesting(pattern: &str, ast: &AST, limit: u32) -> Result<(), ParserError> {
+    // 再帰呼び出しを避けるため、明示的なスタックでたどる
+    // 子を持たないノードと、全体を表すノードは数えない
+    let mut stack = vec![(ast.root(), 0, true)]; // (ノード, 親の深さ, 親と同じ段か)
+    while let Some((id, depth, same_level)) = stack.pop() {
+        let node = &ast[id];
+        if let AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) = node {
+            continue;
+        }
+        let depth = if same_level { depth } else { depth + 1 };
+        if depth > limit {
+            return Err(ParserError::new(
+                ParserErrorKind::NestingTooDeep,
+                Span::from_bytes(pattern, ast.span(id)),
+            ));
+        }
+        match node {
+            AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => (),
+            AstNode::Plus(e)
+            | AstNode::Star(e)
+            | AstNode::Question(e)
+            | AstNode::Repeat(e, _, _) => stack.push((*e, depth, false)),
+            AstNode::Group(e, _) => stack.push((*e, depth, true)),
+            AstNode::Or(e1, e2) => {
+                stack.push((*e2, depth, true));
+                stack.push((*e1, depth, true));
+            }
+            AstNode::Seq(v) => stack.extend(v.iter().rev().map(|&e| (e, depth, false))),
+        }
+    }
+    Ok(())
+}
+
 /// 正規表現を抽象構文木に変換する
-pub fn parse(expr: &str) -> Result<AST, ParserError> {
-    // 内部状態を表現するための型
-    // Char 状態：文字列処理中
-    // Escape 状態：エスケープシーケンス処理中
-    enum ParseState {
-        Char,
-        Escape,
-    }
-
-    let mut seq = Vec::new();
-    let mut seq_or = Vec::new();
-    let mut stack = Vec::new();
-    let mut state = ParseState::Char;
-
-    for (i, c) in expr.chars().enumerate() {
-        match &state {
-            ParseState::Char => match c {
-                '+' => parse_plus_star_question(&mut seq, PSQ::Plus, i)?,
-                '*' => parse_plus_star_question(&mut seq, PSQ::Star, i)?,
-                '?' => parse_plus_star_question(&mut seq, PSQ::Question, i)?,
-                '(' => {
-                    // 現在のコンテキストをスタックに保存し、
-                    // 現在のコンテキストを空の状態にする
-                    let prev = take(&mut seq);
-                    let prev_or = take(&mut seq_or);
-                    stack.push((prev, prev_or));
-                }
-                ')' => {
-                    // 現在のコンテキストをスタックからポップ
-                    if let Some((mut prev, prev_or)) = stack.pop() {
-                        // "()" のように式が空の場合は push しない
-                        if !seq.is_empty() {
-                            seq_or.push(AST::Seq(seq));
-                        }
+///
+/// `flags` はパターンの先頭でのフラグの値。エラーがある場合は最初のエラーを返す
+pub fn parse(expr: &str, flags: Flags) -> Result<AST, ParserError> {
+    parse_all(expr, flags).map_err(|mut errors| errors.swap_remove(0))
+}
 
-                        // OR を生成
-                        if let Some(ast) = fold_or(seq_or) {
-                            prev.push(ast);
-                        }
-                        // 以前のコンテキストを現在のコンテキストにする
-                        seq = prev;
-                        seq_or = prev_or;
-                    } else {
-                        // "abc)" のように、開き括弧がないのに閉じ括弧がある場合はエラー
-                        return Err(ParserError::InvalidRightParen(i));
-                    }
+/// 正規表現を抽象構文木に変換し、エラーがある場合はすべてのエラーを返す
+///
+/// [`tokenize`] で分けた字句を先頭から順に解釈する。文字や文字クラスなどの式は連接に加え、
+/// 後置演算子は直前の式に適用する。中置演算子は結合の強さに従い、
+/// それより強く結ばれた式をまとめてから前後の式を結ぶ。
+/// 入れ子の深いパターンでもスタックがあふれないよう、グループは明示的なスタックで扱う
+///
+/// エラーを見つけてもパースを続ける。誤ったエスケープシーケンスは文字として扱い、
+/// 対応する開き括弧のない閉じ括弧や、前に式のない `+`、`{n,m}` などは読み飛ばす。
+/// 括弧が閉じられていないことによる空のパターンのように、他のエラーから生じるエラーは含めない
+///
+/// 得られた AST をたどる処理でスタックがあふれないよう、入れ子の深さが
+/// [`DEFAULT_NEST_LIMIT`] を超える場合は [`ParserErrorKind::NestingTooDeep`] のエラーとする
+pub fn parse_all(expr: &str, flags: Flags) -> Result<AST, Vec<ParserError>> {
+    parse_with(expr, flags, false, DEFAULT_NEST_LIMIT)
+}
+
+/// [`parse_all`] と同様に、寛容なモードで正規表現を抽象構文木に変換する
+///
+/// grep のように、誤ったエスケープシーケンスをエラーにせず、バックスラッシュを除いた文字として扱う。
+/// パターンやグループ、選択肢の先頭にあり、繰り返す式のない `+`、`*`、`?` も文字として扱う
+pub fn parse_all_lenient(expr: &str, flags: Flags) -> Result<AST, Vec<ParserError>> {
+    parse_with(expr, flags, true, DEFAULT_NEST_LIMIT)
+}
+
+/// 寛容なモードか否かと入れ子の深さの上限を指定して、正規表現を抽象構文木に変換する
+pub(crate) fn parse_with(
+    expr: &str,
+    mut flags: Flags,
+    lenient: bool,
+    nest_limit: u32,
+) -> Result<AST, Vec<ParserError>> {
+    let mut parser = Parser::new(expr, lenient);
+    let mut ast = AST::new();
+    // 最も外側の要素は、パターン全体を表す
+    let mut stack = vec![Frame::new(flags, None, 0)];
+    // これまでに現れた捕獲グループの数
+    let mut groups = 0;
+
+    for token in tokenize(expr) {
+        let text = &expr[token.span.byte_start..token.span.byte_end];
+        let start = token.span.char_start;
+        let end = start + text.chars().count();
+        let frame = stack.last_mut().unwrap();
+        match parser.item(token.kind, text, start, end, flags) {
+            Item::Atom(node) => {
+                let id = ast.push_span(node, parser.span(start, end));
+                frame.operands(Precedence::Concatenation).push(id);
+            }
+            Item::Postfix(op) => match frame.operands(Precedence::Concatenation).pop() {
+                Some(prev) => {
+                    let span = ast.span(prev).start..parser.offsets[end];
+                    let id = ast.push_span(op.apply(prev), span);
+                    frame.operands(Precedence::Concatenation).push(id);
                 }
-                '|' => {
-                    if seq.is_empty() {
-                        return Err(ParserError::NoPrev(i));
-                    } else {
-                        let prev = take(&mut seq);
-                        seq_or.push(AST::Seq(prev));
-                    }
+                // 寛容なモードでは、繰り返す式のない +、*、? を文字として扱う
+                None if parser.lenient && !matches!(op, Postfix::Repeat(..)) => {
+                    let c = text.chars().next().unwrap();
+                    let id = ast.push_span(AstNode::Char(c), parser.span(start, end));
+                    frame.operands(Precedence::Concatenation).push(id);
                 }
-                '\\' => state = ParseState::Escape,
-                _ => seq.push(AST::Char(c)),
+                None => parser.error(ParserErrorKind::NoPrev, start, end),
             },
-            ParseState::Escape => {
-                let ast = parse_escape(i, c)?;
-                seq.push(ast);
-                state = ParseState::Char;
+            // "|a" のように前に式がない場合は、空の式を選択肢とする
+            Item::Infix(precedence) => {
+                frame.reduce(&mut ast, precedence, parser.span(start, start))
             }
+            Item::Open { capture, inner } => {
+                // 捕獲グループには開き括弧の順に番号を付ける
+                let group = capture.then(|| {
+                    groups += 1;
+                    groups
+                });
+                stack.push(Frame::new(flags, group, start));
+                flags = inner;
+            }
+            // "abc)" のように、開き括弧がないのに閉じ括弧がある場合はエラー
+            Item::Close if stack.len() == 1 => {
+                parser.error(ParserErrorKind::InvalidRightParen, start, end);
+            }
+            Item::Close => {
+                let mut frame = stack.pop().unwrap();
+                // 式が空の場合は空の連接とする。
+                // 捕獲しないグループは、括弧を含めた範囲を中の式の範囲とする
+                let e = frame
+                    .finish(&mut ast, parser.span(start, start))
+                    .unwrap_or_else(|| {
+                        ast.push_span(AstNode::Seq(Vec::new()), parser.span(start, start))
+                    });
+                let outer = stack.last_mut().unwrap();
+                let id = match frame.group {
+                    Some(group) => {
+                        ast.push_span(AstNode::Group(e, group), parser.span(frame.open, end))
+                    }
+                    None => {
+                        ast.set_span(e, parser.span(frame.open, end));
+                        e
+                    }
+                };
+                outer.operands(Precedence::Concatenation).push(id);
+                flags = frame.flags;
+            }
+            Item::Flags(new) => flags = new,
+            Item::Skip => (),
         }
     }
 
-    // 閉じ括弧が足りない場合はエラー
-    if !stack.is_empty() {
-        return Err(ParserError::NoRightParen);
+    // 閉じ括弧が足りない場合はエラー。内側の開き括弧から順に報告する
+    let len = parser.offsets.len() - 1;
+    for frame in stack[1..].iter().rev() {
+        parser.error(ParserErrorKind::NoRightParen, frame.open, len);
+    }
+    // 文字クラスや繰り返し回数が閉じられていない場合は、最後に報告する
+    parser.errors.extend(parser.unclosed.take());
+    if !parser.errors.is_empty() {
+        return Err(parser.errors);
     }
 
-    // "()" のように式が空の場合は push しない
-    if !seq.is_empty() {
-        seq_or.push(AST::Seq(seq));
+    // 式をまとめ、空でない場合はそれを根として返す
+    match stack.pop().unwrap().finish(&mut ast, parser.span(len, len)) {
+        Some(root) => {
+            ast.set_root(root);
+            ast.set_groups(groups);
+            check_nesting(expr, &ast, nest_limit).map_err(|e| vec![e])?;
+            Ok(ast)
+        }
+        None => Err(vec![ParserError::new(
+            ParserErrorKind::Empty,
+            parser.at(0, len),
+        )]),
     }
+}
 
-    // OR を生成し、成功した場合はそれを返す
-    if let Some(ast) = fold_or(seq_or) {
-        Ok(ast)
-    } else {
-        Err(ParserError::Empty)
+/// 中置演算子と連接の結合の強さ。後に書いたものほど強く結合する
+///
+/// 同じ強さで結ばれた式は、より弱い中置演算子か、グループやパターンの終わりに達した時点で
+/// [`Precedence::reduce`] で1つのノードにまとめる。
+/// 後置演算子は常に最も強く結合するため、ここには含めない
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Alternation,   // a|b
+    Concatenation, // ab。演算子を書かずに式を並べる
+}
+
+impl Precedence {
+    /// 弱いものから順に並べた、すべての結合の強さ
+    const ALL: [Precedence; 2] = [Precedence::Alternation, Precedence::Concatenation];
+
+    /// この強さで結ばれた空でない式の並びを、1つのノードにまとめる
+    fn reduce(self, ast: &mut AST, operands: Vec<NodeId>) -> NodeId {
+        match self {
+            Precedence::Alternation => fold_or(ast, operands),
+            Precedence::Concatenation => push_seq(ast, operands),
+        }
+    }
+}
+
+/// 直前の式に適用する後置演算子
+#[derive(Debug, Clone, Copy)]
+enum Postfix {
+    Plus,
+    Star,
+    Question,
+    Repeat(u32, Option<u32>), // {n,m}。上限がない場合は None
+}
+
+impl Postfix {
+    /// 式 `e` に適用したノード
+    fn apply(self, e: NodeId) -> AstNode {
+        match self {
+            Postfix::Plus => AstNode::Plus(e),
+            Postfix::Star => AstNode::Star(e),
+            Postfix::Question => AstNode::Question(e),
+            Postfix::Repeat(min, max) => AstNode::Repeat(e, min, max),
+        }
+    }
+}
+
+/// 字句を解釈した結果
+#[derive(Debug)]
+enum Item {
+    Atom(AstNode),     // 文字、文字クラス、位置の判定のように、それだけで式になるもの
+    Postfix(Postfix),  // 直前の式に適用する後置演算子
+    Infix(Precedence), // 前後の式を結ぶ中置演算子
+    Open { capture: bool, inner: Flags }, // グループの始まりと、グループの中で有効なフラグ
+    Close,             // グループの終わり
+    Flags(Flags),      // 現在のグループの残りで有効なフラグ
+    Skip,              // エラーとなった字句など、読み飛ばすもの
+}
+
+/// グループごとの、パース中の式
+#[derive(Debug)]
+struct Frame {
+    operands: [Vec<NodeId>; Precedence::ALL.len()], // 結合の強さごとの、まだまとめていない式
+    flags: Flags,       // グループの外で有効なフラグ。グループを閉じる際に戻す
+    group: Option<u32>, // 捕獲グループの番号。捕獲しないグループは None
+    open: usize,        // 開き括弧の位置（文字数）
+}
+
+impl Frame {
+    fn new(flags: Flags, group: Option<u32>, open: usize) -> Frame {
+        Frame {
+            operands: Default::default(),
+            flags,
+            group,
+            open,
+        }
+    }
+
+    /// `precedence` の強さで結ばれる式の並び
+    fn operands(&mut self, precedence: Precedence) -> &mut Vec<NodeId> {
+        &mut self.operands[precedence as usize]
+    }
+
+    /// 直前の `precedence` の演算子の後に、式がないかを判定する
+    fn is_empty(&self, precedence: Precedence) -> bool {
+        self.operands[precedence as usize + 1..]
+            .iter()
+            .all(Vec::is_empty)
+    }
+
+    /// `precedence` より強く結ばれた式をまとめ、`precedence` の式の並びに加える
+    ///
+    /// `a||b` や `a|` のように式がない場合は、`at` の範囲の空の連接を加える
+    fn reduce(&mut self, ast: &mut AST, precedence: Precedence, at: Range<usize>) {
+        if self.is_empty(precedence) {
+            let id = ast.push_span(AstNode::Seq(Vec::new()), at);
+            self.operands(precedence).push(id);
+            return;
+        }
+        for &stronger in Precedence::ALL[precedence as usize + 1..].iter().rev() {
+            let operands = take(self.operands(stronger));
+            if !operands.is_empty() {
+                let id = stronger.reduce(ast, operands);
+                self.operands[stronger as usize - 1].push(id);
+            }
+        }
+    }
+
+    /// すべての式を1つのノードにまとめる。式がない場合は None
+    ///
+    /// `at` はグループやパターンの終わりの位置で、最後の選択肢が空の場合はその範囲とする
+    fn finish(&mut self, ast: &mut AST, at: Range<usize>) -> Option<NodeId> {
+        if self.operands.iter().all(Vec::is_empty) {
+            return None;
+        }
+        let weakest = Precedence::ALL[0];
+        self.reduce(ast, weakest, at);
+        let operands = take(self.operands(weakest));
+        (!operands.is_empty()).then(|| weakest.reduce(ast, operands))
+    }
+}
+
+/// 字句の解釈に使う、パターン全体に関する情報と見つかったエラー
+struct Parser {
+    offsets: Vec<usize>, // 文字の位置からバイトオフセットへの対応。末尾にパターンの長さを含む
+    errors: Vec<ParserError>, // 見つかったエラー
+    unclosed: Option<ParserError>, // パターンの末尾まで閉じられていない文字クラスか繰り返し回数の指定
+    lenient: bool, // 誤ったエスケープシーケンスと繰り返す式のない +、*、? を文字として扱うか
+}
+
+impl Parser {
+    fn new(expr: &str, lenient: bool) -> Parser {
+        Parser {
+            offsets: expr
+                .char_indices()
+                .map(|(b, _)| b)
+                .chain([expr.len()])
+                .collect(),
+            errors: Vec::new(),
+            unclosed: None,
+            lenient,
+        }
+    }
+
+    /// `start` 番目の文字から `end` 番目の文字の直前までの、パターン中の範囲
+    fn at(&self, start: usize, end: usize) -> Span {
+        Span::new(&self.offsets, start, end)
+    }
+
+    /// `start` 番目の文字から `end` 番目の文字の直前までの、バイトオフセットの範囲
+    fn span(&self, start: usize, end: usize) -> Range<usize> {
+        self.offsets[start]..self.offsets[end]
+    }
+
+    /// `start` 番目の文字から `end` 番目の文字の直前までの範囲のエラーを記録する
+    fn error(&mut self, kind: ParserErrorKind, start: usize, end: usize) {
+        self.errors
+            .push(ParserError::new(kind, self.at(start, end)));
+    }
+
+    /// `start` 番目の文字から始まる字句を解釈する
+    ///
+    /// `text` は字句の文字列で、`end` は字句の直後の文字の位置。
+    /// `flags` は字句の位置で有効なフラグ
+    fn item(
+        &mut self,
+        kind: TokenKind,
+        text: &str,
+        start: usize,
+        end: usize,
+        flags: Flags,
+    ) -> Item {
+        let mut chars = text.chars();
+        let first = chars.next().unwrap();
+        match kind {
+            TokenKind::Literal => Item::Atom(AstNode::Char(first)),
+            TokenKind::Escape => match chars.next() {
+                Some(c) => Item::Atom(parse_escape(self.at(start, end), c, flags).unwrap_or_else(
+                    |e| {
+                        if !self.lenient {
+                            self.errors.push(e);
+                        }
+                        AstNode::Char(c)
+                    },
+                )),
+                // パターン末尾の \ は読み飛ばす
+                None => Item::Skip,
+            },
+            TokenKind::Metachar => match (first, flags.multi_line) {
+                ('|', _) => Item::Infix(Precedence::Alternation),
+                ('^', false) => Item::Atom(AstNode::Look(Look::Start)),
+                ('^', true) => Item::Atom(AstNode::Look(Look::StartLine)),
+                (_, false) => Item::Atom(AstNode::Look(Look::End)),
+                (_, true) => Item::Atom(AstNode::Look(Look::EndLine)),
+            },
+            TokenKind::Quantifier => match first {
+                '+' => Item::Postfix(Postfix::Plus),
+                '*' => Item::Postfix(Postfix::Star),
+                '?' => Item::Postfix(Postfix::Question),
+                _ => match text[1..].strip_suffix('}') {
+                    Some(spec) => match parse_repeat(spec, self.at(start, end)) {
+                        Ok((min, max)) => Item::Postfix(Postfix::Repeat(min, max)),
+                        Err(e) => {
+                            self.errors.push(e);
+                            Item::Skip
+                        }
+                    },
+                    None => {
+                        let len = self.offsets.len() - 1;
+                        self.unclosed = Some(ParserError::new(
+                            ParserErrorKind::NoRightBrace,
+                            self.at(start, len),
+                        ));
+                        Item::Skip
+                    }
+                },
+            },
+            TokenKind::Class => self.class(text, start, flags),
+            // (?a:...) は捕獲しないグループを始め、その中だけに適用する。
+            // (?:...) はフラグを変えずに、捕獲しないグループを始める
+            TokenKind::GroupOpen => match text.strip_prefix("(?") {
+                Some(spec) => Item::Open {
+                    capture: false,
+                    inner: match &spec[..spec.len() - 1] {
+                        "" => flags,
+                        spec => self.flags(spec, start, end, flags),
+                    },
+                },
+                None => Item::Open {
+                    capture: true,
+                    inner: flags,
+                },
+            },
+            TokenKind::GroupClose => Item::Close,
+            // (?a) は現在のグループの残りに適用する
+            TokenKind::Flags => match text[2..].strip_suffix(')') {
+                Some(spec) => Item::Flags(self.flags(spec, start, end, flags)),
+                None => {
+                    let len = self.offsets.len() - 1;
+                    self.error(ParserErrorKind::NoRightParen, start, len);
+                    Item::Skip
+                }
+            },
+        }
+    }
+
+    /// フラグの指定を現在のフラグに適用する。誤っている場合はエラーを記録し、フラグを変えない
+    fn flags(&mut self, spec: &str, start: usize, end: usize, flags: Flags) -> Flags {
+        parse_flags(spec, self.at(start, end), flags).unwrap_or_else(|e| {
+            self.errors.push(e);
+            flags
+        })
+    }
+
+    /// `start` 番目の文字から始まる文字クラスの字句を解釈する
+    ///
+    /// 先頭の `^` は否定を表し、その直後の `]` は文字として扱う
+    fn class(&mut self, text: &str, start: usize, flags: Flags) -> Item {
+        // class の要素は (位置, 文字, エスケープされているか)。
+        // \d などで指定された範囲は class_ranges に分けて集める
+        let mut class = Vec::new();
+        let mut class_ranges = Vec::new();
+        let mut negated = false;
+        let mut chars = text.chars().enumerate().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '^' if i == 1 => negated = true,
+                ']' if !class.is_empty() || !class_ranges.is_empty() => {
+                    let mut ranges = parse_class(&class, &self.offsets).unwrap_or_else(|e| {
+                        self.errors.push(e);
+                        Vec::new()
+                    });
+                    ranges.append(&mut class_ranges);
+                    let class = CharClass::new(ranges);
+                    return Item::Atom(AstNode::Class(if negated {
+                        class.negate()
+                    } else {
+                        class
+                    }));
+                }
+                '\\' => match chars.next() {
+                    Some((i, c @ ('\\' | '[' | ']' | '-' | '^'))) => {
+                        class.push((start + i, c, true))
+                    }
+                    Some((i, c)) => match parse_perl(c, flags) {
+                        Some(perl) => class_ranges.extend_from_slice(perl.ranges()),
+                        None => {
+                            if !self.lenient {
+                                self.error(
+                                    ParserErrorKind::InvalidEscape(c),
+                                    start + i - 1,
+                                    start + i + 1,
+                                );
+                            }
+                            class.push((start + i, c, true));
+                        }
+                    },
+                    None => break,
+                },
+                _ => class.push((start + i, c, false)),
+            }
+        }
+        let len = self.offsets.len() - 1;
+        self.unclosed = Some(ParserError::new(
+            ParserErrorKind::NoRightBracket,
+            self.at(start, len),
+        ));
+        Item::Skip
     }
 }
 
 /// 特殊文字のエスケープ処理を行う
-fn parse_escape(pos: usize, c: char) -> Result<AST, ParserError> {
+///
+/// `\d`、`\w`、`\s` とその否定は文字クラスに、`\b` と `\B` は位置の判定に変換する
+///
+/// `span` はバックスラッシュを含むエスケープシーケンスの範囲
+fn parse_escape(span: Span, c: char, flags: Flags) -> Result<AstNode, ParserError> {
     match c {
-        '\\' | '(' | ')' | '|' | '+' | '*' | '?' => Ok(AST::Char(c)),
-        _ => {
-            let err = ParserError::InvalidEscape(pos, c);
-            Err(err)
+        '\\' | '(' | ')' | '|' | '+' | '*' | '?' | '[' | ']' | '{' | '}' | '^' | '$' => {
+            Ok(AstNode::Char(c))
         }
+        'b' => Ok(AstNode::Look(Look::WordBoundary(flags.ascii))),
+        'B' => Ok(AstNode::Look(Look::NotWordBoundary(flags.ascii))),
+        _ => match parse_perl(c, flags) {
+            Some(class) => Ok(AstNode::Class(class)),
+            None => Err(ParserError::new(ParserErrorKind::InvalidEscape(c), span)),
+        },
     }
 }
 
-/// +, *, ? を AST に変換する
-///
-/// 後置記法で +, *, ? の前にパターンがない場合はエラー
-fn parse_plus_star_question(
-    seq: &mut Vec<AST>,
-    ast_type: PSQ,
-    pos: usize,
-) -> Result<(), ParserError> {
-    if let Some(prev) = seq.pop() {
-        let ast = match ast_type {
-            PSQ::Plus => AST::Plus(Box::new(prev)),
-            PSQ::Star => AST::Star(Box::new(prev)),
-            PSQ::Question => AST::Question(Box::new(prev)),
-        };
-        seq.push(ast);
-        Ok(())
+/// `\d`、`\w`、`\s` とその否定の文字クラスを返す
+fn parse_perl(c: char, flags: Flags) -> Option<CharClass> {
+    let kind = match c.to_ascii_lowercase() {
+        'd' => Perl::Digit,
+        'w' => Perl::Word,
+        's' => Perl::Space,
+        _ => return None,
+    };
+    let class = CharClass::perl(kind, flags.ascii);
+    if c.is_ascii_uppercase() {
+        Some(class.negate())
     } else {
-        Err(ParserError::NoPrev(pos))
+        Some(class)
     }
 }
 
-/// OR で結合された複数の式を AST に変換する
-fn fold_or(mut seq_or: Vec<AST>) -> Option<AST> {
-    if seq_or.len() > 1 {
-        // seq_or の要素が複数ある場合は、OR で式を結合
-        let mut ast = seq_or.pop().unwrap();
-        seq_or.reverse();
-        for s in seq_or {
-            ast = AST::Or(Box::new(s), Box::new(ast));
+/// `(?` と `)` または `:` の間のフラグの指定を、現在のフラグに適用する
+///
+/// `a` や `m` はフラグを有効にし、`-` の後ろに書いたフラグは無効にする。
+/// `span` は開き括弧から、閉じ括弧か `:` までの範囲
+fn parse_flags(spec: &str, span: Span, mut flags: Flags) -> Result<Flags, ParserError> {
+    let mut enable = true;
+    let mut any = false;
+    for c in spec.chars() {
+        match c {
+            '-' if enable => enable = false,
+            'a' => {
+                flags.ascii = enable;
+                any = true;
+            }
+            'm' => {
+                flags.multi_line = enable;
+                any = true;
+            }
+            _ => return Err(ParserError::new(ParserErrorKind::InvalidFlag, span)),
         }
-        Some(ast)
-    } else {
-        // seq_or の要素が1つのみの場合は、最初の式を返す
-        seq_or.pop()
     }
+    // "(?)" や "(?-)" のようにフラグがない場合はエラー
+    if !any {
+        return Err(ParserError::new(ParserErrorKind::InvalidFlag, span));
+    }
+    Ok(flags)
+}
+
+/// 文字クラスの要素を文字の範囲に変換する
+///
+/// エスケープされていない - の前後に文字がある場合は範囲を表し、
+/// 先頭や末尾の - は文字として扱う。
+/// `offsets` は文字の位置からバイトオフセットへの対応
+fn parse_class(
+    class: &[(usize, char, bool)],
+    offsets: &[usize],
+) -> Result<Vec<(char, char)>, ParserError> {
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < class.len() {
+        let (pos, lo, _) = class[i];
+        if let (Some((_, '-', false)), Some(&(end, hi, _))) = (class.get(i + 1), class.get(i + 2)) {
+            if lo > hi {
+                return Err(ParserError::new(
+                    ParserErrorKind::InvalidRange,
+                    Span::new(offsets, pos, end + 1),
+                ));
+            }
+            ranges.push((lo, hi));
+            i += 3;
+        } else {
+            ranges.push((lo, lo));
+            i += 1;
+        }
+    }
+    Ok(ranges)
+}
+
+/// `{n}`、`{n,}`、`{n,m}` の波括弧の間の文字列 `spec` を、回数の下限と上限に変換する
+///
+/// `span` は波括弧を含む範囲。回数が数値でない場合や下限が上限より大きい場合はエラー
+fn parse_repeat(spec: &str, span: Span) -> Result<(u32, Option<u32>), ParserError> {
+    let count = |s: &str| {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParserError::new(ParserErrorKind::InvalidRepeat, span));
+        }
+        s.parse::<u32>()
+            .map_err(|_| ParserError::new(ParserErrorKind::InvalidRepeat, span))
+    };
+    let (min, max) = match spec.split_once(',') {
+        None => {
+            let n = count(spec)?;
+            (n, Some(n))
+        }
+        Some((min, "")) => (count(min)?, None),
+        Some((min, max)) => (count(min)?, Some(count(max)?)),
+    };
+    if max.is_some_and(|max| min > max) {
+        return Err(ParserError::new(ParserErrorKind::InvalidRepeat, span));
+    }
+    Ok((min, max))
+}
+
+/// 空でない連接を AST に追加する。範囲は最初の要素の先頭から最後の要素の末尾まで
+fn push_seq(ast: &mut AST, seq: Vec<NodeId>) -> NodeId {
+    let span = ast.span(seq[0]).start..ast.span(seq[seq.len() - 1]).end;
+    ast.push_span(AstNode::Seq(seq), span)
+}
+
+/// OR で結合された空でない式の並びを AST に変換する。要素が1つの場合はその式を返す
+fn fold_or(ast: &mut AST, mut seq_or: Vec<NodeId>) -> NodeId {
+    // 後ろの式から順に、右結合の OR で結合する
+    let mut id = seq_or.pop().unwrap();
+    for s in seq_or.into_iter().rev() {
+        let span = ast.span(s).start..ast.span(id).end;
+        id = ast.push_span(AstNode::Or(s, id), span);
+    }
+    id
 }
@@ -0,0 +1,180 @@
+//! マッチの開始位置の候補を高速に探すための前処理
+//!
+//! パターンから抽出した短いリテラルの集合を Teddy と呼ばれる方法で探し、
+//! いずれかのリテラルが現れる位置だけを評価器に渡す。
+//! パターン全体が1つのリテラルの場合は、memchr の部分文字列検索で探す。
+//! `simd` フィーチャを有効にすると、実行時に CPU が SSSE3 に対応しているかを調べ、
+//! 対応している場合は 16 バイトずつまとめて調べる
+//!
+//! 前処理は [`Prefilter`] トレイトとして表し、利用者が独自の前処理を与えることもできる
+use memchr::memmem::Finder;
+use std::{fmt::Debug, panic::RefUnwindSafe};
+
+/// マッチの開始位置の候補を探す前処理
+///
+/// 候補でない位置を返すことは許されるが、マッチが始まる位置を飛ばしてはならない。
+/// 返す位置が文字の境界でない場合は、次の文字の境界から評価する
+///
+/// 前処理を持つ [`Regex`](crate::Regex) を複数のスレッドで共有できるよう、`Send` と `Sync` を要求する
+pub trait Prefilter: Debug + Send + Sync + RefUnwindSafe {
+    /// `haystack` の `at` 以降で、マッチが始まりうる最も左の位置を返す。候補がない場合は `None`
+    fn find(&self, haystack: &[u8], at: usize) -> Option<usize>;
+}
+
+/// 1つのリテラルが現れる位置を探す
+///
+/// [`Regex`](crate::Regex) はパターンがリテラルの場合にこれを利用し、見つけた位置をそのままマッチとする。
+/// [`RegexBuilder::prefilter`](crate::RegexBuilder::prefilter) に渡し、
+/// マッチが必ず含む先頭のリテラルを探す前処理として利用することもできる
+#[derive(Debug, Clone)]
+pub struct Memmem {
+    finder: Finder<'static>,
+}
+
+impl Memmem {
+    /// リテラルを探す前処理を構築する。リテラルが空の場合は `None` を返す
+    pub fn new(literal: &str) -> Option<Memmem> {
+        (!literal.is_empty()).then(|| Memmem {
+            finder: Finder::new(literal.as_bytes()).into_owned(),
+        })
+    }
+
+    /// 探すリテラル
+    pub fn needle(&self) -> &[u8] {
+        self.finder.needle()
+    }
+}
+
+impl Prefilter for Memmem {
+    /// `haystack` の `at` 以降で、リテラルが現れる最も左の位置を返す
+    fn find(&self, haystack: &[u8], at: usize) -> Option<usize> {
+        self.finder.find(haystack.get(at..)?).map(|i| at + i)
+    }
+}
+
+/// リテラルを振り分けるバケットの数
+const BUCKETS: usize = 8;
+
+/// 複数のリテラルのいずれかが現れる位置を探す
+///
+/// 各リテラルの先頭の `len` バイトについて、バイトの上位と下位の4ビットごとに
+/// そのバイトを先頭から j 番目に持つリテラルのバケットの集合を表に記録しておく。
+/// 文字列の各位置で表を引いて論理積を取り、残ったバケットのリテラルだけを比較する
+#[derive(Debug)]
+pub struct Teddy {
+    lits: Vec<Vec<u8>>,
+    buckets: [Vec<usize>; BUCKETS], // バケットごとのリテラルのインデックス
+    len: usize,                     // 表を引くバイト数。リテラルの最小長と 3 の小さい方
+    lo: [[u8; 16]; 3],              // 下位4ビットからバケットの集合を引く表
+    hi: [[u8; 16]; 3],              // 上位4ビットからバケットの集合を引く表
+}
+
+impl Teddy {
+    /// リテラルの集合から Teddy を構築する。空のリテラルを含む場合は `None` を返す
+    pub fn new(lits: Vec<Vec<u8>>) -> Option<Teddy> {
+        let len = lits.iter().map(|lit| lit.len()).min()?.min(3);
+        if len == 0 {
+            return None;
+        }
+
+        let mut teddy = Teddy {
+            lits,
+            buckets: Default::default(),
+            len,
+            lo: [[0; 16]; 3],
+            hi: [[0; 16]; 3],
+        };
+        for (i, lit) in teddy.lits.iter().enumerate() {
+            // 先頭のバイトが同じリテラルは同じバケットに入れ、候補となるバケットを減らす
+            let bucket = lit[0] as usize % BUCKETS;
+            teddy.buckets[bucket].push(i);
+            for (j, &b) in lit[..len].iter().enumerate() {
+                teddy.lo[j][(b & 0x0f) as usize] |= 1 << bucket;
+                teddy.hi[j][(b >> 4) as usize] |= 1 << bucket;
+            }
+        }
+        Some(teddy)
+    }
+
+    /// 1バイトずつ表を引いて探す
+    fn find_scalar(&self, haystack: &[u8], at: usize) -> Option<usize> {
+        let last = haystack.len().checked_sub(self.len)?;
+        (at..=last).find(|&i| {
+            let mut mask = 0xff;
+            for j in 0..self.len {
+                let b = haystack[i + j];
+                mask &= self.lo[j][(b & 0x0f) as usize] & self.hi[j][(b >> 4) as usize];
+            }
+            mask != 0 && self.verify(haystack, i, mask)
+        })
+    }
+
+    /// `mask` のバケットに含まれるリテラルのいずれかが `i` の位置に現れるかを判定する
+    fn verify(&self, haystack: &[u8], i: usize, mask: u8) -> bool {
+        (0..BUCKETS)
+            .filter(|bucket| mask & (1 << bucket) != 0)
+            .flat_map(|bucket| &self.buckets[bucket])
+            .any(|&idx| haystack[i..].starts_with(&self.lits[idx]))
+    }
+
+    /// 16 バイトずつ pshufb 命令で表を引いて探す。末尾の 16 バイトに満たない部分は1バイトずつ探す
+    ///
+    /// # Safety
+    ///
+    /// 実行中の CPU が SSSE3 に対応している必要がある
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    #[target_feature(enable = "ssse3")]
+    unsafe fn find_ssse3(&self, haystack: &[u8], at: usize) -> Option<usize> {
+        use std::arch::x86_64::*;
+
+        // SAFETY: 表はそれぞれ 16 バイトの配列
+        let lo: [__m128i; 3] =
+            std::array::from_fn(|j| unsafe { _mm_loadu_si128(self.lo[j].as_ptr().cast()) });
+        let hi: [__m128i; 3] =
+            std::array::from_fn(|j| unsafe { _mm_loadu_si128(self.hi[j].as_ptr().cast()) });
+        let nibble = _mm_set1_epi8(0x0f);
+        let zero = _mm_setzero_si128();
+
+        let mut i = at;
+        while i + 16 + self.len - 1 <= haystack.len() {
+            let mut res = _mm_set1_epi8(-1);
+            for j in 0..self.len {
+                // SAFETY: i + j + 16 <= haystack.len() をループの条件で保証している
+                let v = unsafe { _mm_loadu_si128(haystack.as_ptr().add(i + j).cast()) };
+                let l = _mm_shuffle_epi8(lo[j], _mm_and_si128(v, nibble));
+                let h = _mm_shuffle_epi8(hi[j], _mm_and_si128(_mm_srli_epi16(v, 4), nibble));
+                res = _mm_and_si128(res, _mm_and_si128(l, h));
+            }
+
+            // バケットが残った位置のビットを立てる
+            let mut candidates = !(_mm_movemask_epi8(_mm_cmpeq_epi8(res, zero)) as u32) & 0xffff;
+            if candidates != 0 {
+                let mut masks = [0u8; 16];
+                // SAFETY: masks は 16 バイトの配列
+                unsafe { _mm_storeu_si128(masks.as_mut_ptr().cast(), res) };
+                while candidates != 0 {
+                    let k = candidates.trailing_zeros() as usize;
+                    if self.verify(haystack, i + k, masks[k]) {
+                        return Some(i + k);
+                    }
+                    candidates &= candidates - 1;
+                }
+            }
+            i += 16;
+        }
+
+        self.find_scalar(haystack, i)
+    }
+}
+
+impl Prefilter for Teddy {
+    /// `haystack` の `at` 以降で、いずれかのリテラルが現れる最も左の位置を返す
+    fn find(&self, haystack: &[u8], at: usize) -> Option<usize> {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("ssse3") {
+            // SAFETY: 実行中の CPU が SSSE3 に対応していることを確認済み
+            return unsafe { self.find_ssse3(haystack, at) };
+        }
+        self.find_scalar(haystack, at)
+    }
+}
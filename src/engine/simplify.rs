@@ -0,0 +1,239 @@
+//! パース後の AST を等価でより単純な AST に書き換える
+//!
+//! コード生成の前に適用することで、生成される命令列を小さくするとともに、
+//! `(a*)*` のように空文字列に繰り返しマッチする入れ子の限量子を、位置が進んだかを
+//! 確かめる命令が不要な形にまとめる
+//!
+//! 書き換えるのは1つの式で表せる限量子の組に限るため、`(a{1,2})*` や `(a?){0,1000}` のように
+//! バックトラックの回数が指数的に増えうる構造は単純化しても残る。
+//! そのような構造には、検索に利用するエンジンを選ぶ際に Pike VM を選ぶ
+use super::{
+    parser::{AST, AstNode, NodeId},
+    visit::{Fold, fold_children},
+};
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    mem,
+    ops::Range,
+};
+
+/// AST を単純化した新しい AST を返す
+///
+/// - 入れ子になった連接を平坦化し、要素が1つの連接をその要素に置き換える
+/// - `(a*)*` や `(a+)?` のように入れ子になった限量子を1つにまとめる
+/// - `a{0,1}` や `a{1,}` のように他の限量子で表せる繰り返しを置き換える
+/// - OR で結合された同一の選択肢のうち、2つ目以降を取り除く
+///
+/// `groups` が偽の場合は捕獲グループを取り除き、その中の式に置き換える。
+/// マッチの範囲だけを求める場合は、グループをまたいで限量子をまとめられる
+///
+/// 書き換えたノードは、元のノードのパターン中の範囲を引き継ぐ
+pub fn simplify(ast: &AST, groups: bool) -> AST {
+    let mut out = AST::new();
+    let root = Simplifier { groups }.fold(ast, ast.root(), &mut out);
+    out.set_root(root);
+    if groups {
+        out.set_groups(ast.groups());
+    }
+    out
+}
+
+/// 子ノードから順に単純化する書き換え
+struct Simplifier {
+    groups: bool, // 捕獲グループを残すか
+}
+
+impl Fold for Simplifier {
+    fn fold(&mut self, ast: &AST, id: NodeId, out: &mut AST) -> NodeId {
+        match ast[id] {
+            AstNode::Or(..) => simplify_or(self, ast, id, out),
+            _ => fold_children(self, ast, id, out),
+        }
+    }
+
+    fn build(&mut self, out: &mut AST, node: AstNode, span: Range<usize>) -> NodeId {
+        match node {
+            AstNode::Plus(e) => simplify_plus(out, e, span),
+            AstNode::Star(e) => simplify_star(out, e, span),
+            AstNode::Question(e) => simplify_question(out, e, span),
+            AstNode::Repeat(e, min, max) => simplify_repeat(out, e, min, max, span),
+            AstNode::Group(e, _) if !self.groups => e,
+            AstNode::Seq(v) => simplify_seq(out, v, span),
+            node => out.push_span(node, span),
+        }
+    }
+}
+
+/// 連接を平坦化する
+fn simplify_seq(out: &mut AST, v: Vec<NodeId>, span: Range<usize>) -> NodeId {
+    let mut seq = Vec::with_capacity(v.len());
+    for e in v {
+        match &out[e] {
+            AstNode::Seq(inner) => seq.extend_from_slice(inner),
+            _ => seq.push(e),
+        }
+    }
+
+    if seq.len() == 1 {
+        seq[0]
+    } else {
+        out.push_span(AstNode::Seq(seq), span)
+    }
+}
+
+/// `(e*)+` → `e*`、`(e+)+` → `e+`、`(e?)+` → `e*`
+fn simplify_plus(out: &mut AST, e: NodeId, span: Range<usize>) -> NodeId {
+    match out[e] {
+        AstNode::Star(inner) | AstNode::Question(inner) => {
+            out.push_span(AstNode::Star(inner), span)
+        }
+        AstNode::Plus(_) => e,
+        _ => out.push_span(AstNode::Plus(e), span),
+    }
+}
+
+/// `(e*)*`、`(e+)*`、`(e?)*` → `e*`
+fn simplify_star(out: &mut AST, e: NodeId, span: Range<usize>) -> NodeId {
+    match out[e] {
+        AstNode::Star(_) => e,
+        AstNode::Plus(inner) | AstNode::Question(inner) => {
+            out.push_span(AstNode::Star(inner), span)
+        }
+        _ => out.push_span(AstNode::Star(e), span),
+    }
+}
+
+/// `(e*)?`、`(e+)?` → `e*`、`(e?)?` → `e?`
+fn simplify_question(out: &mut AST, e: NodeId, span: Range<usize>) -> NodeId {
+    match out[e] {
+        AstNode::Star(_) | AstNode::Question(_) => e,
+        AstNode::Plus(inner) => out.push_span(AstNode::Star(inner), span),
+        _ => out.push_span(AstNode::Question(e), span),
+    }
+}
+
+/// `e{1}` → `e`、`e{0,1}` → `e?`、`e{0,}` → `e*`、`e{1,}` → `e+`
+fn simplify_repeat(
+    out: &mut AST,
+    e: NodeId,
+    min: u32,
+    max: Option<u32>,
+    span: Range<usize>,
+) -> NodeId {
+    match (min, max) {
+        (1, Some(1)) => e,
+        (0, Some(1)) => simplify_question(out, e, span),
+        (0, None) => simplify_star(out, e, span),
+        (1, None) => simplify_plus(out, e, span),
+        _ => out.push_span(AstNode::Repeat(e, min, max), span),
+    }
+}
+
+/// OR の選択肢を左から並べ、すでに現れた選択肢と同一のものを取り除く
+///
+/// 左の選択肢が優先してマッチするため、後ろに現れる同一の選択肢が結果に影響することはない。
+/// 入れ子になった OR は最も外側の OR で一度に平坦化し、選択肢の数に比例する時間で組み立て直す。
+/// 組み立て直した OR の範囲は、最も左の選択肢の先頭から `span` の末尾まで
+fn simplify_or(folder: &mut Simplifier, ast: &AST, id: NodeId, out: &mut AST) -> NodeId {
+    let span = ast.span(id);
+    let mut seen = Seen::default();
+    let mut uniq = Vec::new();
+    for e in ast.branches(id) {
+        // 捕獲グループを取り除くと、中の OR が選択肢として現れる
+        let e = folder.fold(ast, e, out);
+        for b in out.branches(e) {
+            if seen.insert(out, b) {
+                uniq.push(b);
+            }
+        }
+    }
+
+    // 右結合の OR として組み立て直す
+    let mut id = uniq.pop().unwrap();
+    while let Some(e) = uniq.pop() {
+        let start = if uniq.is_empty() {
+            span.start
+        } else {
+            out.span(e).start
+        };
+        id = out.push_span(AstNode::Or(e, id), start..span.end);
+    }
+    id
+}
+
+/// 構造的に等しい部分木が、すでに現れたかを調べるための集合
+///
+/// 部分木の構造から求めたハッシュ値で候補を絞り、同じハッシュ値のものだけを [`same`] で比べる
+#[derive(Debug, Default)]
+pub struct Seen {
+    buckets: HashMap<u64, Vec<NodeId>>, // 構造のハッシュ値ごとの、現れた部分木
+}
+
+impl Seen {
+    /// `id` の部分木を加える。構造的に等しい部分木がすでに現れていた場合は偽を返す
+    pub fn insert(&mut self, ast: &AST, id: NodeId) -> bool {
+        let mut hasher = DefaultHasher::new();
+        hash(ast, id, &mut hasher);
+        let bucket = self.buckets.entry(hasher.finish()).or_default();
+        if bucket.iter().any(|&b| same(ast, b, id)) {
+            return false;
+        }
+        bucket.push(id);
+        true
+    }
+}
+
+/// 部分木の構造をハッシュ値に加える。[`same`] で等しい部分木は同じハッシュ値になる
+fn hash(ast: &AST, id: NodeId, state: &mut impl Hasher) {
+    let node = &ast[id];
+    mem::discriminant(node).hash(state);
+    match node {
+        AstNode::Char(c) => c.hash(state),
+        AstNode::Class(class) => class.hash(state),
+        AstNode::Look(look) => look.hash(state),
+        AstNode::Plus(e) | AstNode::Star(e) | AstNode::Question(e) => hash(ast, *e, state),
+        AstNode::Group(e, idx) => {
+            idx.hash(state);
+            hash(ast, *e, state);
+        }
+        AstNode::Repeat(e, min, max) => {
+            (min, max).hash(state);
+            hash(ast, *e, state);
+        }
+        AstNode::Or(..) => {
+            let branches = ast.branches(id);
+            branches.len().hash(state);
+            branches.iter().for_each(|e| hash(ast, *e, state));
+        }
+        AstNode::Seq(v) => {
+            v.len().hash(state);
+            v.iter().for_each(|e| hash(ast, *e, state));
+        }
+    }
+}
+
+/// 2つの部分木が構造的に等しいかを判定する
+pub fn same(ast: &AST, a: NodeId, b: NodeId) -> bool {
+    match (&ast[a], &ast[b]) {
+        (AstNode::Char(c1), AstNode::Char(c2)) => c1 == c2,
+        (AstNode::Class(c1), AstNode::Class(c2)) => c1 == c2,
+        (AstNode::Look(l1), AstNode::Look(l2)) => l1 == l2,
+        (AstNode::Plus(e1), AstNode::Plus(e2))
+        | (AstNode::Star(e1), AstNode::Star(e2))
+        | (AstNode::Question(e1), AstNode::Question(e2)) => same(ast, *e1, *e2),
+        (AstNode::Group(e1, idx1), AstNode::Group(e2, idx2)) => idx1 == idx2 && same(ast, *e1, *e2),
+        (AstNode::Repeat(e1, min1, max1), AstNode::Repeat(e2, min2, max2)) => {
+            min1 == min2 && max1 == max2 && same(ast, *e1, *e2)
+        }
+        // 選択肢の多い OR は深い入れ子になるため、平坦化した選択肢を比べる
+        (AstNode::Or(..), AstNode::Or(..)) => {
+            let (b1, b2) = (ast.branches(a), ast.branches(b));
+            b1.len() == b2.len() && b1.iter().zip(&b2).all(|(e1, e2)| same(ast, *e1, *e2))
+        }
+        (AstNode::Seq(v1), AstNode::Seq(v2)) => {
+            v1.len() == v2.len() && v1.iter().zip(v2).all(|(e1, e2)| same(ast, *e1, *e2))
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,82 @@
+//! AST の大きさと、構文ごとの出現回数を数える
+use super::parser::{AST, AstNode};
+
+/// AST の深さ、ノード数と、構文ごとのノードの数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AstStats {
+    pub depth: u32,      // 入れ子の深さ。check_nesting と同じ数え方
+    pub nodes: usize,    // 根からたどれるノードの数
+    pub chars: usize,    // 文字
+    pub classes: usize,  // 文字クラス
+    pub looks: usize,    // 位置の判定
+    pub plus: usize,     // +
+    pub star: usize,     // *
+    pub question: usize, // ?
+    pub repeats: usize,  // {n,m}
+    pub groups: usize,   // 捕獲グループ
+    pub ors: usize,      // OR。n 個の選択肢は n - 1 個の OR になる
+    pub seqs: usize,     // 連接
+}
+
+impl AstStats {
+    /// 限量子の数。`+`、`*`、`?`、`{n,m}` の合計
+    pub fn quantifiers(&self) -> usize {
+        self.plus + self.star + self.question + self.repeats
+    }
+}
+
+impl AST {
+    /// 根からたどれるノードを数える
+    ///
+    /// 利用者の入力した深い AST にも使えるよう、再帰呼び出しを使わずにたどる
+    pub fn stats(&self) -> AstStats {
+        let mut stats = AstStats::default();
+        // 深さは check_nesting と同様に、子を持たないノードと全体を表すノードを数えない
+        let mut stack = vec![(self.root(), 0, true)]; // (ノード, 親の深さ, 親と同じ段か)
+        while let Some((id, depth, same_level)) = stack.pop() {
+            stats.nodes += 1;
+            let node = &self[id];
+            let depth = match node {
+                AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => depth,
+                _ if same_level => depth,
+                _ => depth + 1,
+            };
+            stats.depth = stats.depth.max(depth);
+            match node {
+                AstNode::Char(_) => stats.chars += 1,
+                AstNode::Class(_) => stats.classes += 1,
+                AstNode::Look(_) => stats.looks += 1,
+                AstNode::Plus(e) => {
+                    stats.plus += 1;
+                    stack.push((*e, depth, false));
+                }
+                AstNode::Star(e) => {
+                    stats.star += 1;
+                    stack.push((*e, depth, false));
+                }
+                AstNode::Question(e) => {
+                    stats.question += 1;
+                    stack.push((*e, depth, false));
+                }
+                AstNode::Repeat(e, _, _) => {
+                    stats.repeats += 1;
+                    stack.push((*e, depth, false));
+                }
+                AstNode::Group(e, _) => {
+                    stats.groups += 1;
+                    stack.push((*e, depth, true));
+                }
+                AstNode::Or(e1, e2) => {
+                    stats.ors += 1;
+                    stack.push((*e2, depth, true));
+                    stack.push((*e1, depth, true));
+                }
+                AstNode::Seq(v) => {
+                    stats.seqs += 1;
+                    stack.extend(v.iter().map(|&e| (e, depth, false)));
+                }
+            }
+        }
+        stats
+    }
+}
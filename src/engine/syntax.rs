@@ -0,0 +1,114 @@
+//! 利用者が入力したパターンに許す構文を制限する
+//!
+//! パースした AST をたどり、無効にした構文や大きすぎる文字クラスを見つけた場合はエラーとする
+use super::{
+    parser::{AST, AstNode, NodeId, ParserError, ParserErrorKind, Span},
+    visit::{Visitor, walk},
+};
+
+/// 無効にできる構文
+///
+/// 今後のバージョンで種類が増える可能性がある
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Syntax {
+    Alternation,         // |
+    Group,               // 捕獲グループ
+    CountedRepetition,   // {n,m}
+    NestedRepetition,    // (a+)* のような、繰り返しの中の繰り返し
+    UnboundedRepetition, // +、* と {n,}
+    Look,                // ^、$、\b と \B
+}
+
+/// AST が制限に従うことを確かめる
+///
+/// `disabled` は無効にした構文、`class_limit` は文字クラスに含められる文字数の上限。
+/// `\d` や `[^a]` のような文字クラスも、含む文字の数で数える。
+/// 制限に従わない場合は、パターン中で最も左にあるノードの範囲をエラーとする
+pub fn check_syntax(
+    pattern: &str,
+    ast: &AST,
+    disabled: &[Syntax],
+    class_limit: Option<usize>,
+) -> Result<(), ParserError> {
+    let mut checker = Checker {
+        disabled,
+        class_limit,
+        depth: 0,
+        found: None,
+    };
+    checker.visit(ast, ast.root());
+    match checker.found {
+        Some((kind, id)) => Err(ParserError::new(
+            kind,
+            Span::from_bytes(pattern, ast.span(id)),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// 制限に従わない最初のノードを探す走査
+struct Checker<'a> {
+    disabled: &'a [Syntax],
+    class_limit: Option<usize>,
+    depth: u32,                               // 訪れているノードを囲む繰り返しの数
+    found: Option<(ParserErrorKind, NodeId)>, // 見つかったエラーと対象のノード
+}
+
+impl Visitor for Checker<'_> {
+    fn visit(&mut self, ast: &AST, id: NodeId) {
+        if self.found.is_some() {
+            return;
+        }
+        let node = &ast[id];
+        let repetition = matches!(
+            node,
+            AstNode::Plus(_) | AstNode::Star(_) | AstNode::Question(_) | AstNode::Repeat(..)
+        );
+        // ノードが使う構文。(a{2,})* の {2,} のように、1つのノードが複数の構文を使う場合がある
+        let syntaxes = [
+            (Syntax::NestedRepetition, repetition && self.depth > 0),
+            (
+                Syntax::UnboundedRepetition,
+                matches!(
+                    node,
+                    AstNode::Plus(_) | AstNode::Star(_) | AstNode::Repeat(_, _, None)
+                ),
+            ),
+            (
+                Syntax::CountedRepetition,
+                matches!(node, AstNode::Repeat(..)),
+            ),
+            (Syntax::Alternation, matches!(node, AstNode::Or(..))),
+            (Syntax::Group, matches!(node, AstNode::Group(..))),
+            (Syntax::Look, matches!(node, AstNode::Look(_))),
+        ];
+        if let Some(&(syntax, _)) = syntaxes
+            .iter()
+            .find(|&&(syntax, used)| used && self.disabled.contains(&syntax))
+        {
+            self.found = Some((ParserErrorKind::Disabled(syntax), id));
+            return;
+        }
+        if let (AstNode::Class(class), Some(limit)) = (node, self.class_limit) {
+            let size: usize = class.ranges().iter().map(|&(lo, hi)| count(lo, hi)).sum();
+            if size > limit {
+                self.found = Some((ParserErrorKind::ClassTooLarge, id));
+                return;
+            }
+        }
+        self.depth += u32::from(repetition);
+        walk(self, ast, id);
+        self.depth -= u32::from(repetition);
+    }
+}
+
+/// `lo` 以上 `hi` 以下の文字の数。サロゲートの範囲は文字でないため数えない
+fn count(lo: char, hi: char) -> usize {
+    let surrogates = if (lo as u32) < 0xd800 && (hi as u32) > 0xdfff {
+        0x800
+    } else {
+        0
+    };
+    hi as usize - lo as usize + 1 - surrogates
+}
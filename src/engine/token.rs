@@ -0,0 +1,106 @@
+//! パターンを種類ごとの字句に分ける
+//!
+//! パーサーはこの字句を順に解釈して AST を組み立てるため、区切り方はパースの結果と一致する。
+//! エディタでパターンを色分けする用途にも利用できる。
+//! 誤ったパターンでも最後まで字句に分け、閉じられていない文字クラスや繰り返し回数の指定は
+//! パターンの末尾までを1つの字句とする
+//!
+//! ```text
+//! a(?:b|\d)+[x-z]{2}
+//! ^ Literal
+//!  ^^^ GroupOpen
+//!     ^ Literal
+//!      ^ Metachar
+//!       ^^ Escape
+//!         ^ GroupClose
+//!          ^ Quantifier
+//!           ^^^^^ Class
+//!                ^^^ Quantifier
+//! ```
+use super::parser::Span;
+
+/// 字句の種類
+///
+/// 今後のバージョンで種類が増える可能性がある
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Literal,    // 文字そのもの
+    Escape,     // \+ や \d、\b などのエスケープシーケンス
+    Metachar,   // | と、位置の判定の ^ と $
+    Class,      // [...]。角括弧を含む文字クラス全体
+    Quantifier, // +、*、? と {n,m}
+    GroupOpen,  // (、(?: と (?a:
+    GroupClose, // )
+    Flags,      // (?a) のように、グループを始めないフラグの指定
+}
+
+/// パターン中の字句
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span, // 字句の範囲
+}
+
+/// パターンを先頭から字句に分ける
+///
+/// 文字は1文字ずつ [`TokenKind::Literal`] の字句にする。返す字句は重ならず、
+/// すべての字句の範囲をつなげるとパターン全体になる
+pub fn tokenize(pattern: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.char_indices().peekable();
+    let mut char_start = 0;
+    while let Some((start, c)) = chars.next() {
+        // 字句の残りの文字を読み、字句の種類を決める
+        let kind = match c {
+            '\\' => {
+                chars.next();
+                TokenKind::Escape
+            }
+            '|' | '^' | '$' => TokenKind::Metachar,
+            '+' | '*' | '?' => TokenKind::Quantifier,
+            '{' => {
+                while !matches!(chars.next(), Some((_, '}')) | None) {}
+                TokenKind::Quantifier
+            }
+            '(' if chars.next_if(|&(_, c)| c == '?').is_some() => loop {
+                match chars.next() {
+                    Some((_, ':')) => break TokenKind::GroupOpen,
+                    Some((_, ')')) | None => break TokenKind::Flags,
+                    _ => {}
+                }
+            },
+            '(' => TokenKind::GroupOpen,
+            ')' => TokenKind::GroupClose,
+            '[' => {
+                // 先頭の ^ は否定を表し、その直後の ] は文字として扱う
+                chars.next_if(|&(_, c)| c == '^');
+                let mut empty = true;
+                loop {
+                    match chars.next() {
+                        Some((_, ']')) if !empty => break,
+                        Some((_, '\\')) => {
+                            chars.next();
+                        }
+                        None => break,
+                        _ => {}
+                    }
+                    empty = false;
+                }
+                TokenKind::Class
+            }
+            _ => TokenKind::Literal,
+        };
+        let end = chars.peek().map_or(pattern.len(), |&(i, _)| i);
+        tokens.push(Token {
+            kind,
+            span: Span {
+                byte_start: start,
+                byte_end: end,
+                char_start,
+            },
+        });
+        char_start += pattern[start..end].chars().count();
+    }
+    tokens
+}
@@ -0,0 +1,89 @@
+//! AST を字下げした木の形の文字列にする
+//!
+//! ```text
+//! Seq
+//! ├─ Char 'a'
+//! └─ Star
+//!    └─ Class [0-9a-z]
+//! ```
+//!
+//! `a|b|c` のように連なった選択は、入れ子にせず1つの `Or` の子ノードとして並べる。
+//! そのため字下げは選択肢の数によらず、出力の長さはノードの数と木の深さの積で収まる
+use super::parser::{AST, AstNode, NodeId};
+use std::fmt::Write;
+
+impl AST {
+    /// 根のノードから1行に1ノードずつ、子ノードを字下げした木の形の文字列を返す
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        // (ノード, 字下げの文字列, 兄弟の最後のノードか, 根のノードか)
+        let mut stack = vec![(self.root(), String::new(), true, true)];
+        while let Some((id, prefix, last, root)) = stack.pop() {
+            let (branch, indent) = match (root, last) {
+                (true, _) => ("", ""),
+                (false, true) => ("└─ ", "   "),
+                (false, false) => ("├─ ", "│  "),
+            };
+            let _ = writeln!(out, "{prefix}{branch}{}", label(self, id));
+
+            let children = children(self, id);
+            let prefix = format!("{prefix}{indent}");
+            for (i, &e) in children.iter().enumerate().rev() {
+                stack.push((e, prefix.clone(), i + 1 == children.len(), false));
+            }
+        }
+        out
+    }
+}
+
+/// ノードの子ノード。連なった選択は、すべての選択肢を左から順に返す
+fn children(ast: &AST, id: NodeId) -> Vec<NodeId> {
+    match &ast[id] {
+        AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => Vec::new(),
+        AstNode::Plus(e)
+        | AstNode::Star(e)
+        | AstNode::Question(e)
+        | AstNode::Repeat(e, _, _)
+        | AstNode::Group(e, _) => vec![*e],
+        AstNode::Or(e1, e2) => {
+            let mut alternatives = Vec::new();
+            let mut stack = vec![*e2, *e1];
+            while let Some(id) = stack.pop() {
+                match &ast[id] {
+                    AstNode::Or(e1, e2) => stack.extend([*e2, *e1]),
+                    _ => alternatives.push(id),
+                }
+            }
+            alternatives
+        }
+        AstNode::Seq(v) => v.clone(),
+    }
+}
+
+/// ノードの種類と値を表す1行の文字列
+fn label(ast: &AST, id: NodeId) -> String {
+    match &ast[id] {
+        AstNode::Char(c) => format!("Char {c:?}"),
+        AstNode::Class(class) => {
+            let mut s = String::from("Class [");
+            for &(lo, hi) in class.ranges() {
+                if lo == hi {
+                    let _ = write!(s, "{}", lo.escape_debug());
+                } else {
+                    let _ = write!(s, "{}-{}", lo.escape_debug(), hi.escape_debug());
+                }
+            }
+            s.push(']');
+            s
+        }
+        AstNode::Look(look) => format!("Look {look}"),
+        AstNode::Plus(_) => "Plus".to_string(),
+        AstNode::Star(_) => "Star".to_string(),
+        AstNode::Question(_) => "Question".to_string(),
+        AstNode::Repeat(_, min, Some(max)) => format!("Repeat {{{min},{max}}}"),
+        AstNode::Repeat(_, min, None) => format!("Repeat {{{min},}}"),
+        AstNode::Group(_, idx) => format!("Group #{idx}"),
+        AstNode::Or(_, _) => "Or".to_string(),
+        AstNode::Seq(_) => "Seq".to_string(),
+    }
+}
@@ -0,0 +1,169 @@
+//! AST をパターンの文字列に戻す
+//!
+//! 出力したパターンを既定のフラグでパースし直すと、元と等しい（同じ構造の）AST になる。
+//! 特殊文字はエスケープし、連接や OR を限量子の対象にする場合は `(?:...)` で囲む。
+//! 捕獲グループの番号は出力しないため、パースし直すと開き括弧の順に番号を付け直す
+use super::{
+    class::{CharClass, Perl},
+    look::Look,
+    parser::{AST, AstNode, NodeId},
+};
+use std::fmt::{self, Display, Write};
+
+impl Display for AST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 空のパターンはパースできないため、空の式は `(?:)` と書く
+        if let AstNode::Seq(v) = &self[self.root()]
+            && v.is_empty()
+        {
+            return f.write_str("(?:)");
+        }
+        // 深い AST でもスタックがあふれないよう、これから書く部分を明示的なスタックで扱う
+        let mut stack = vec![Part::Alt(self.root())];
+        while let Some(part) = stack.pop() {
+            match part {
+                Part::Alt(id) => push_alt(self, id, &mut stack),
+                Part::Elem(id) => write_elem(self, id, f, &mut stack)?,
+                Part::Suffix(id) => write_suffix(&self[id], f)?,
+                Part::Str(s) => f.write_str(s)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// これから書く部分
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    Alt(NodeId),       // 根やグループの中の式
+    Elem(NodeId),      // 連接の要素や限量子の対象になる式
+    Suffix(NodeId),    // 限量子の記号
+    Str(&'static str), // そのまま書く文字列
+}
+
+/// 根やグループの中の式を、先に書く部分が上になるよう積む
+///
+/// OR の選択肢は `|` で区切る。空の選択肢は何も書かない
+fn push_alt(ast: &AST, id: NodeId, stack: &mut Vec<Part>) {
+    match &ast[id] {
+        AstNode::Or(..) => {
+            for (i, e) in ast.branches(id).into_iter().enumerate().rev() {
+                stack.push(Part::Alt(e));
+                if i > 0 {
+                    stack.push(Part::Str("|"));
+                }
+            }
+        }
+        AstNode::Seq(v) => stack.extend(v.iter().rev().map(|&e| Part::Elem(e))),
+        _ => stack.push(Part::Elem(id)),
+    }
+}
+
+/// 連接の要素や限量子の対象になる式を書く。子を持つ式は、残りの部分を積む
+fn write_elem(
+    ast: &AST,
+    id: NodeId,
+    f: &mut fmt::Formatter<'_>,
+    stack: &mut Vec<Part>,
+) -> fmt::Result {
+    match &ast[id] {
+        AstNode::Char(c) => write_char(*c, f),
+        AstNode::Class(class) => write_class(class, f),
+        AstNode::Look(look) => write_look(*look, f),
+        AstNode::Plus(e) | AstNode::Star(e) | AstNode::Question(e) | AstNode::Repeat(e, _, _) => {
+            stack.extend([Part::Suffix(id), Part::Elem(*e)]);
+            Ok(())
+        }
+        AstNode::Group(e, _) => {
+            stack.extend([Part::Str(")"), Part::Alt(*e)]);
+            f.write_char('(')
+        }
+        AstNode::Or(..) | AstNode::Seq(_) => {
+            stack.extend([Part::Str(")"), Part::Alt(id)]);
+            f.write_str("(?:")
+        }
+    }
+}
+
+/// 限量子の記号を書く
+fn write_suffix(node: &AstNode, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match node {
+        AstNode::Plus(_) => f.write_char('+'),
+        AstNode::Star(_) => f.write_char('*'),
+        AstNode::Question(_) => f.write_char('?'),
+        AstNode::Repeat(_, min, max) => match max {
+            Some(max) if max == min => write!(f, "{{{min}}}"),
+            Some(max) => write!(f, "{{{min},{max}}}"),
+            None => write!(f, "{{{min},}}"),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// 文字を書く。特殊文字はエスケープする
+fn write_char(c: char, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if matches!(
+        c,
+        '\\' | '(' | ')' | '|' | '+' | '*' | '?' | '[' | ']' | '{' | '}' | '^' | '$'
+    ) {
+        f.write_char('\\')?;
+    }
+    f.write_char(c)
+}
+
+/// 位置の判定を書く。フラグが必要な判定は、直後にフラグを元に戻す
+fn write_look(look: Look, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match look {
+        Look::Start => f.write_char('^'),
+        Look::End => f.write_char('$'),
+        Look::StartLine => f.write_str("(?m)^(?-m)"),
+        Look::EndLine => f.write_str("(?m)$(?-m)"),
+        Look::WordBoundary(false) => f.write_str("\\b"),
+        Look::WordBoundary(true) => f.write_str("(?a)\\b(?-a)"),
+        Look::NotWordBoundary(false) => f.write_str("\\B"),
+        Look::NotWordBoundary(true) => f.write_str("(?a)\\B(?-a)"),
+    }
+}
+
+/// 文字クラスを書く
+///
+/// `\d`、`\w`、`\s` とその否定に等しい場合はエスケープシーケンスで書き、
+/// 否定したほうが範囲が少ない場合は `[^...]` で書く
+fn write_class(class: &CharClass, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (kind, name) in [(Perl::Digit, 'd'), (Perl::Word, 'w'), (Perl::Space, 's')] {
+        let perl = CharClass::perl(kind, false);
+        if *class == perl {
+            return write!(f, "\\{name}");
+        }
+        if *class == perl.negate() {
+            return write!(f, "\\{}", name.to_ascii_uppercase());
+        }
+    }
+
+    // 空の文字クラスは [] と書けないため、否定で書く。すべての文字を含む場合は否定で書けない
+    let negated = class.negate();
+    let (ranges, caret) = if !negated.ranges().is_empty()
+        && (class.ranges().is_empty() || negated.ranges().len() < class.ranges().len())
+    {
+        (negated.ranges(), "^")
+    } else {
+        (class.ranges(), "")
+    };
+    write!(f, "[{caret}")?;
+    for &(lo, hi) in ranges {
+        write_class_char(lo, f)?;
+        if lo != hi {
+            f.write_char('-')?;
+            write_class_char(hi, f)?;
+        }
+    }
+    f.write_char(']')
+}
+
+/// 文字クラスの中の文字を書く。特殊文字はエスケープする
+fn write_class_char(c: char, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if matches!(c, '\\' | '[' | ']' | '-' | '^') {
+        f.write_char('\\')?;
+    }
+    f.write_char(c)
+}
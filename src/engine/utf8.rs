@@ -0,0 +1,77 @@
+//! 文字の範囲を UTF-8 のバイトの範囲の列に変換する
+//!
+//! 例えば `[\u{80}-\u{10ffff}]` に含まれる文字は、次のバイト列のいずれかとして符号化される
+//!
+//! ```text
+//! [c2-df][80-bf]
+//! [e0][a0-bf][80-bf]
+//! [e1-ec][80-bf][80-bf]
+//! ...
+//! ```
+//!
+//! 変換した列を利用すると、文字に復号せずにバイトを1つずつ読んでマッチを判定できる
+
+/// UTF-8 のバイトの範囲の列。i 番目のバイトが i 番目の範囲に含まれる場合にマッチする
+pub type Sequence = Vec<(u8, u8)>;
+
+/// `lo` から `hi` までの文字の符号化に一致するバイトの範囲の列を返す
+///
+/// 列は表す文字の昇順に並び、得られる列が表すバイト列の集合は互いに交わらない
+pub fn sequences(lo: char, hi: char) -> Vec<Sequence> {
+    let mut result = Vec::new();
+    let mut stack = vec![(lo as u32, hi as u32)];
+    'outer: while let Some((lo, hi)) = stack.pop() {
+        // サロゲートの範囲を取り除く
+        if lo <= 0xdfff && 0xd800 <= hi {
+            if 0xe000 <= hi {
+                stack.push((0xe000, hi));
+            }
+            if lo < 0xd800 {
+                stack.push((lo, 0xd7ff));
+            }
+            continue;
+        }
+
+        // 符号化したバイト数が同じになるように分割する
+        for max in [0x7f, 0x7ff, 0xffff] {
+            if lo <= max && max < hi {
+                stack.push((max + 1, hi));
+                stack.push((lo, max));
+                continue 'outer;
+            }
+        }
+
+        if hi <= 0x7f {
+            result.push(vec![(lo as u8, hi as u8)]);
+            continue;
+        }
+
+        // 後続のバイトの範囲が、上位のバイトによらず同じになるように分割する
+        for i in 1..4 {
+            let mask = (1u32 << (6 * i)) - 1;
+            if lo & !mask != hi & !mask {
+                if lo & mask != 0 {
+                    stack.push(((lo | mask) + 1, hi));
+                    stack.push((lo, lo | mask));
+                    continue 'outer;
+                }
+                if hi & mask != mask {
+                    stack.push((hi & !mask, hi));
+                    stack.push((lo, (hi & !mask) - 1));
+                    continue 'outer;
+                }
+            }
+        }
+
+        let (mut lo_buf, mut hi_buf) = ([0; 4], [0; 4]);
+        let lo = encode(lo, &mut lo_buf);
+        let hi = encode(hi, &mut hi_buf);
+        result.push(lo.iter().zip(hi).map(|(&l, &h)| (l, h)).collect());
+    }
+    result
+}
+
+/// サロゲートを含まない範囲の文字を UTF-8 に符号化する
+fn encode(c: u32, buf: &mut [u8; 4]) -> &[u8] {
+    char::from_u32(c).map_or(&[], |c| c.encode_utf8(buf).as_bytes())
+}
@@ -0,0 +1,94 @@
+//! AST をたどるためのトレイト
+//!
+//! [`Visitor`] は AST を読むだけの走査に、[`Fold`] は AST から新しい AST を組み立てる書き換えに使う。
+//! どちらも子ノードをたどる処理を既定の実装として持つため、実装する側は関心のある
+//! ノードの処理だけを書けばよい
+use super::parser::{AST, AstNode, NodeId};
+use std::ops::Range;
+
+/// AST を読むだけの走査
+pub trait Visitor {
+    /// `id` のノードを訪れる。既定では子ノードを左から順に訪れる
+    fn visit(&mut self, ast: &AST, id: NodeId) {
+        walk(self, ast, id);
+    }
+}
+
+/// `id` の子ノードを左から順に [`Visitor::visit`] で訪れる
+///
+/// OR は入れ子の OR を平坦化し、再帰せずに各選択肢を訪れる。入れ子の内側の OR は訪れない
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, ast: &AST, id: NodeId) {
+    match &ast[id] {
+        AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => (),
+        AstNode::Plus(e)
+        | AstNode::Star(e)
+        | AstNode::Question(e)
+        | AstNode::Repeat(e, _, _)
+        | AstNode::Group(e, _) => visitor.visit(ast, *e),
+        AstNode::Or(..) => {
+            for e in ast.branches(id) {
+                visitor.visit(ast, e);
+            }
+        }
+        AstNode::Seq(v) => {
+            for e in v {
+                visitor.visit(ast, *e);
+            }
+        }
+    }
+}
+
+/// AST から新しい AST を組み立てる書き換え
+pub trait Fold {
+    /// `ast` の `id` 以下の部分木を書き換えて `out` に追加し、そのインデックスを返す。
+    /// 既定では [`fold_children`] で子ノードを書き換えてから、[`Fold::build`] でノードを組み立てる
+    fn fold(&mut self, ast: &AST, id: NodeId, out: &mut AST) -> NodeId {
+        fold_children(self, ast, id, out)
+    }
+
+    /// 子ノードを書き換え済みのノードを `out` に追加する。既定ではそのまま追加する
+    ///
+    /// `span` は元のノードのパターン中の範囲
+    fn build(&mut self, out: &mut AST, node: AstNode, span: Range<usize>) -> NodeId {
+        out.push_span(node, span)
+    }
+}
+
+/// `id` の子ノードを [`Fold::fold`] で書き換え、[`Fold::build`] でノードを組み立てる
+///
+/// 右結合の OR の連なりは再帰せずにたどり、右の OR から順に組み立てる
+pub fn fold_children<F: Fold + ?Sized>(
+    folder: &mut F,
+    ast: &AST,
+    id: NodeId,
+    out: &mut AST,
+) -> NodeId {
+    let node = match &ast[id] {
+        AstNode::Char(c) => AstNode::Char(*c),
+        AstNode::Class(class) => AstNode::Class(class.clone()),
+        AstNode::Look(look) => AstNode::Look(*look),
+        AstNode::Plus(e) => AstNode::Plus(folder.fold(ast, *e, out)),
+        AstNode::Star(e) => AstNode::Star(folder.fold(ast, *e, out)),
+        AstNode::Question(e) => AstNode::Question(folder.fold(ast, *e, out)),
+        AstNode::Repeat(e, min, max) => AstNode::Repeat(folder.fold(ast, *e, out), *min, *max),
+        AstNode::Group(e, idx) => AstNode::Group(folder.fold(ast, *e, out), *idx),
+        AstNode::Or(..) => return fold_or(folder, ast, id, out),
+        AstNode::Seq(v) => AstNode::Seq(v.iter().map(|e| folder.fold(ast, *e, out)).collect()),
+    };
+    folder.build(out, node, ast.span(id))
+}
+
+/// 右結合の OR の連なりを書き換える。左の選択肢は [`Fold::fold`] で書き換える
+fn fold_or<F: Fold + ?Sized>(folder: &mut F, ast: &AST, id: NodeId, out: &mut AST) -> NodeId {
+    let mut spine = Vec::new(); // 連なりの OR のノードと、書き換えた左の選択肢
+    let mut rest = id;
+    while let AstNode::Or(e1, e2) = ast[rest] {
+        spine.push((rest, folder.fold(ast, e1, out)));
+        rest = e2;
+    }
+    let mut e2 = folder.fold(ast, rest, out);
+    for (or, e1) in spine.into_iter().rev() {
+        e2 = folder.build(out, AstNode::Or(e1, e2), ast.span(or));
+    }
+    e2
+}
@@ -0,0 +1,86 @@
+//! コンパイルと検索で発生するエラーをまとめた型
+//!
+//! [`Regex::new`](crate::Regex::new) などのコンパイルは [`Error`] を返す。
+//! 検索は [`EvalError`] を返すが、`?` で [`Error`] に変換できる
+use crate::engine::{
+    binary::DecodeError,
+    codegen::CodeGenError,
+    evaluator::EvalError,
+    parser::{ParserError, ParserErrors},
+};
+use std::{
+    error,
+    fmt::{self, Display},
+};
+
+/// このクレートで発生するエラー
+///
+/// 表示は元のエラーと同じで、元のエラーは [`source`](error::Error::source) で取り出せる。
+/// [`Error::Set`] の表示には、パターンの番号を加える。
+/// 今後のバージョンで種類が増える可能性がある
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParserError),     // パターンのパースに失敗した
+    ParseAll(ParserErrors), // ParserErrors を集める設定で、パースに失敗した
+    CodeGen(CodeGenError),  // 命令列の生成に失敗した
+    Eval(EvalError),        // 検索中に評価に失敗した
+    Decode(DecodeError),    // 書き出したコンパイル結果の読み込みに失敗した
+    Set(usize, Box<Error>), // RegexSet の、0 から数えた番号のパターンのコンパイルに失敗した
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(e) => write!(f, "{e}"),
+            Error::ParseAll(e) => write!(f, "{e}"),
+            Error::CodeGen(e) => write!(f, "{e}"),
+            Error::Eval(e) => write!(f, "{e}"),
+            Error::Decode(e) => write!(f, "{e}"),
+            Error::Set(i, e) => write!(f, "pattern {i}: {e}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Parse(e) => Some(e),
+            Error::ParseAll(e) => Some(e),
+            Error::CodeGen(e) => Some(e),
+            Error::Eval(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            Error::Set(_, e) => Some(&**e),
+        }
+    }
+}
+
+impl From<ParserError> for Error {
+    fn from(e: ParserError) -> Error {
+        Error::Parse(e)
+    }
+}
+
+impl From<ParserErrors> for Error {
+    fn from(e: ParserErrors) -> Error {
+        Error::ParseAll(e)
+    }
+}
+
+impl From<CodeGenError> for Error {
+    fn from(e: CodeGenError) -> Error {
+        Error::CodeGen(e)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(e: EvalError) -> Error {
+        Error::Eval(e)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Error {
+        Error::Decode(e)
+    }
+}
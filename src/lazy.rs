@@ -0,0 +1,68 @@
+//! 初回の利用時にコンパイルする正規表現
+use crate::regex::Regex;
+use std::{ops::Deref, sync::OnceLock};
+
+/// 初回の利用時にパターンをコンパイルする正規表現
+///
+/// `const` な関数で生成できるため、外部のクレートを使わずに
+/// `static GREETING: LazyRegex = LazyRegex::new("hello|こんにちは");` のように宣言できる。
+/// [`Deref`] を実装しているので、[`Regex`] のメソッドをそのまま呼び出せる
+#[derive(Debug)]
+pub struct LazyRegex {
+    source: Source,
+    regex: OnceLock<Regex>,
+}
+
+/// 初回の利用時に正規表現を作る元
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    Pattern(&'static str), // コンパイルするパターン
+    Bytes(&'static [u8]),  // Regex::to_bytes で書き出したコンパイル結果
+}
+
+impl LazyRegex {
+    /// パターンを保持した LazyRegex を生成する。コンパイルはまだ行わない
+    pub const fn new(pattern: &'static str) -> LazyRegex {
+        LazyRegex {
+            source: Source::Pattern(pattern),
+            regex: OnceLock::new(),
+        }
+    }
+
+    /// [`Regex::to_bytes`] で書き出したコンパイル結果を保持した LazyRegex を生成する
+    ///
+    /// 初回の利用時に [`Regex::from_bytes`] で読み込むため、命令列と DFA を生成し直さずに済む。
+    /// ビルドスクリプトで書き出したコンパイル結果を埋め込む場合に使う
+    pub const fn from_bytes(bytes: &'static [u8]) -> LazyRegex {
+        LazyRegex {
+            source: Source::Bytes(bytes),
+            regex: OnceLock::new(),
+        }
+    }
+
+    /// コンパイル済みの正規表現を返す。初回の呼び出し時にコンパイルする
+    ///
+    /// # Panics
+    ///
+    /// パターンが不正な場合と、コンパイル結果を読み込めない場合はパニックする
+    pub fn get(&self) -> &Regex {
+        self.regex.get_or_init(|| match self.source {
+            Source::Pattern(pattern) => match Regex::new(pattern) {
+                Ok(regex) => regex,
+                Err(e) => panic!("invalid pattern {pattern:?}: {e}"),
+            },
+            Source::Bytes(bytes) => match Regex::from_bytes(bytes) {
+                Ok(regex) => regex,
+                Err(e) => panic!("invalid compiled regex: {e}"),
+            },
+        })
+    }
+}
+
+impl Deref for LazyRegex {
+    type Target = Regex;
+
+    fn deref(&self) -> &Regex {
+        self.get()
+    }
+}
@@ -1 +1,45 @@
+//! 正規表現エンジン
+//!
+//! パターンを抽象構文木(AST)にパースし、命令列を生成したうえで評価する
+#![allow(clippy::upper_case_acronyms)]
 
+mod cache;
+#[cfg(feature = "miette")]
+mod diagnostic;
+mod engine;
+mod error;
+mod lazy;
+mod regex;
+
+pub use cache::{CacheStats, cache_stats, clear_cache, find, is_match, set_cache_capacity};
+#[cfg(feature = "miette")]
+pub use diagnostic::PatternError;
+pub use engine::{
+    analysis::{Finding, Reason, Report, Severity, analyze},
+    binary::DecodeError,
+    class::CharClass,
+    codegen::CodeGenError,
+    dfa::is_rust_ident,
+    evaluator::{Cache, EvalError, SearchStats},
+    glob::glob_to_regex,
+    lint::{Warning, WarningKind},
+    look::Look,
+    message::{Language, language, set_language},
+    meta::Engine,
+    normalize::normalize,
+    parser::{
+        AST, AstNode, Flags, NodeId, ParserError, ParserErrorKind, ParserErrors, Span, Suggestion,
+        parse, parse_all, parse_all_lenient,
+    },
+    prefilter::{Memmem, Prefilter},
+    stats::AstStats,
+    syntax::Syntax,
+    token::{Token, TokenKind, tokenize},
+    visit::{Fold, Visitor, fold_children, walk},
+};
+pub use error::Error;
+pub use lazy::LazyRegex;
+pub use regex::{
+    CaptureMatches, Captures, CompileMetrics, FindMatches, Match, Regex, RegexBuilder, RegexSet,
+    RegexSetBuilder, StreamMatch, StreamState, Trace, TraceStep,
+};
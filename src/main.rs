@@ -1,5 +1,10 @@
-mod engine;
+//! パターンにマッチする行を表示する、grep のようなコマンド
+//!
+//! ```text
+//! regexp [OPTIONS] PATTERN [FILE...]
+//! ```
+mod cli;
 
 fn main() {
-    println!("Hello, world!");
+    std::process::exit(cli::run());
 }
@@ -0,0 +1,1052 @@
+//! コンパイル済みの正規表現
+use crate::{
+    engine::{
+        Program,
+        codegen::{self, CodeGenError},
+        dfa::{Dfa, is_rust_ident},
+        evaluator::{self, Cache, EvalError, SearchStats},
+        graph, length,
+        lint::{self, Warning},
+        literal::{self, Anchored},
+        meta::{self, Engine},
+        optimizer,
+        parser::{self, AST, Flags, ParserError, ParserErrors},
+        prefilter::{Memmem, Prefilter, Teddy},
+        simplify,
+        syntax::{self, Syntax},
+    },
+    error::Error,
+};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+mod captures;
+mod parallel;
+mod serialize;
+mod set;
+mod stream;
+mod trace;
+
+pub use captures::{CaptureMatches, Captures};
+pub use set::{RegexSet, RegexSetBuilder};
+pub use stream::{StreamMatch, StreamState};
+pub use trace::{Trace, TraceStep};
+
+/// マッチした範囲を表す型
+///
+/// 位置はすべて検索対象文字列のバイトオフセットで表す
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    /// マッチの開始位置
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// マッチの終了位置
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// マッチした部分文字列
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.start..self.end]
+    }
+}
+
+/// `{n,m}` を展開する回数の上限の既定値
+const DEFAULT_UNROLL_LIMIT: u32 = 32;
+
+/// 命令数の上限の既定値
+const DEFAULT_SIZE_LIMIT: usize = 1 << 20;
+
+/// コンパイル時に指定する設定
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Config {
+    step_limit: Option<u64>,            // 1回の検索で実行できる命令数の上限
+    unroll_limit: Option<u32>,          // {n,m} を展開する回数の上限。None の場合は既定値
+    nest_limit: Option<u32>,            // AST の入れ子の深さの上限。None の場合は既定値
+    size_limit: Option<usize>,          // 生成する命令数の上限。None の場合は既定値
+    pattern_limit: Option<usize>,       // パターンの長さ（バイト）の上限。None の場合は上限なし
+    ascii: bool,                        // \d、\w、\s、\b を ASCII 文字に限るか
+    multi_line: bool,                   // ^ と $ を各行の先頭と末尾にもマッチさせるか
+    collect_errors: bool,               // パースエラーをすべて集めて返すか
+    prefilter: Option<CustomPrefilter>, // 組み込みの前処理の代わりに利用する前処理
+    engine: Option<Engine>,             // 検索に利用するエンジン。None の場合は自動的に選ぶ
+    disabled: Vec<Syntax>,              // 無効にした構文
+    class_limit: Option<usize>,         // 文字クラスの文字数の上限。None の場合は上限なし
+    lenient: bool,                      // 誤ったエスケープシーケンスなどを文字として扱うか
+}
+
+/// 利用者が指定した前処理
+///
+/// 設定をキャッシュのキーとして比較できるよう、同じオブジェクトを指す場合に限り等しいものとする
+#[derive(Clone)]
+pub(crate) struct CustomPrefilter(Arc<dyn Prefilter>);
+
+impl fmt::Debug for CustomPrefilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl PartialEq for CustomPrefilter {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(Arc::as_ptr(&self.0), Arc::as_ptr(&other.0))
+    }
+}
+
+impl Eq for CustomPrefilter {}
+
+impl Hash for CustomPrefilter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<()>().hash(state);
+    }
+}
+
+/// 設定を指定して [`Regex`] を構築するビルダー
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pub(crate) pattern: String,
+    pub(crate) config: Config,
+}
+
+impl RegexBuilder {
+    /// パターンを指定してビルダーを生成する
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            config: Config::default(),
+        }
+    }
+
+    /// 1回の検索で評価器が実行できる命令数の上限を設定する
+    ///
+    /// 上限を超えた場合、`try_` で始まる検索メソッドは
+    /// [`EvalError::StepLimitExceeded`] を返す。
+    /// パターンや検索対象が信頼できない場合に、評価にかかる時間を抑えるために利用する
+    pub fn step_limit(&mut self, limit: u64) -> &mut RegexBuilder {
+        self.config.step_limit = Some(limit);
+        self
+    }
+
+    /// `{n,m}` を展開して命令列を生成する回数の上限を設定する。既定値は 32
+    ///
+    /// 回数がこれを超える繰り返しは展開せずに回数を数える命令で表し、命令列が大きくなるのを防ぐ。
+    /// そのようなパターンの検索には、回数を数える命令を実行できるバックトラック型の評価器を利用する。
+    /// ただし、本体が空文字列にマッチしうる繰り返しのようにバックトラックの回数が指数的に増えうる場合は、
+    /// 繰り返しを展開して Pike VM で検索する
+    pub fn unroll_limit(&mut self, limit: u32) -> &mut RegexBuilder {
+        self.config.unroll_limit = Some(limit);
+        self
+    }
+
+    /// パターンの長さ（バイト）の上限を設定する。既定では上限はない
+    ///
+    /// 利用者が入力したパターンを受け付ける場合に、コンパイルに使うメモリと時間を抑えるために利用する。
+    /// 長さはパースの前に確かめ、上限を超えるパターンは
+    /// [`ParserErrorKind::PatternTooLong`](crate::ParserErrorKind::PatternTooLong) のエラーとする
+    pub fn pattern_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.config.pattern_limit = Some(limit);
+        self
+    }
+
+    /// グループや繰り返しの入れ子の深さの上限を設定する。既定値は 250
+    ///
+    /// 入れ子が深すぎるパターンの処理でスタックがあふれないよう、
+    /// 上限を超えるパターンは [`ParserErrorKind::NestingTooDeep`](crate::ParserErrorKind::NestingTooDeep)
+    /// のエラーとする
+    pub fn nest_limit(&mut self, limit: u32) -> &mut RegexBuilder {
+        self.config.nest_limit = Some(limit);
+        self
+    }
+
+    /// 生成する命令列の命令数の上限を設定する。既定値は 1 << 20
+    ///
+    /// 上限は、回数を数える命令で繰り返しを表した命令列に適用する。
+    /// Pike VM で検索する場合は、初めて利用する際に生成する繰り返しを展開した命令列にも適用する。
+    /// 命令数は命令列を生成する前に求め、上限を超える場合は
+    /// [`CodeGenError::CompiledTooBig`] のエラーとする
+    pub fn size_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.config.size_limit = Some(limit);
+        self
+    }
+
+    /// `\d`、`\w`、`\s`、`\b` とその否定を ASCII 文字に限るかを設定する。既定値は false
+    ///
+    /// パターン中で `(?a)` と指定した場合と同じになる。
+    /// 有効にしても、パターン中の `(?-a)` で部分的に無効にできる
+    pub fn ascii(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.ascii = yes;
+        self
+    }
+
+    /// `^` と `$` を、文字列の先頭と末尾に加えて各行の先頭と末尾にもマッチさせるかを設定する。
+    /// 既定値は false
+    ///
+    /// パターン中で `(?m)` と指定した場合と同じになる。行の区切りは `\n` に限る
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.multi_line = yes;
+        self
+    }
+
+    /// 構文を無効にする。無効にした構文を含むパターンは
+    /// [`ParserErrorKind::Disabled`](crate::ParserErrorKind::Disabled) のエラーとする
+    ///
+    /// 利用者が入力したパターンを受け付ける場合に、許す構文を制限するために利用する。
+    /// 複数の構文を無効にする場合は、構文ごとに呼び出す
+    pub fn disable(&mut self, syntax: Syntax) -> &mut RegexBuilder {
+        if !self.config.disabled.contains(&syntax) {
+            self.config.disabled.push(syntax);
+        }
+        self
+    }
+
+    /// 文字クラスに含められる文字数の上限を設定する。既定では上限はない
+    ///
+    /// `\d` や `[^a]` も、含む文字の数で数える。上限を超える文字クラスを含むパターンは
+    /// [`ParserErrorKind::ClassTooLarge`](crate::ParserErrorKind::ClassTooLarge) のエラーとする
+    pub fn class_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.config.class_limit = Some(limit);
+        self
+    }
+
+    /// 寛容なモードでパースするかを設定する。既定値は false
+    ///
+    /// 有効にすると grep のように、`\q` のような誤ったエスケープシーケンスをエラーにせず、
+    /// バックスラッシュを除いた文字として扱う。パターンやグループ、選択肢の先頭にあり、
+    /// 繰り返す式のない `+`、`*`、`?` も文字として扱う
+    pub fn lenient(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.lenient = yes;
+        self
+    }
+
+    /// パースエラーがある場合に、最初のエラーだけでなくすべてのエラーを返すかを設定する。
+    /// 既定値は false
+    ///
+    /// 有効にすると、[`RegexBuilder::build`] は [`Error::Parse`] の代わりに
+    /// すべてのエラーを並べた [`Error::ParseAll`] を返す
+    pub fn collect_errors(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.collect_errors = yes;
+        self
+    }
+
+    /// マッチの開始位置の候補を探す前処理を設定する
+    ///
+    /// パターンから抽出したリテラルによる組み込みの前処理の代わりに利用する。
+    /// 行頭から始まるパターンで次の改行まで読み飛ばすなど、検索対象について分かっていることを
+    /// 前処理に利用できる。前処理がマッチの開始位置を飛ばした場合、そのマッチは見つからない
+    pub fn prefilter(&mut self, prefilter: impl Prefilter + 'static) -> &mut RegexBuilder {
+        self.config.prefilter = Some(CustomPrefilter(Arc::new(prefilter)));
+        self
+    }
+
+    /// 検索に利用するエンジンを指定する。既定では、パターンを調べて自動的に選ぶ
+    ///
+    /// バックトラック型の評価器と Pike VM は、どのパターンにも利用できる。
+    /// DFA を構築できないパターンに [`Engine::Dfa`] を指定した場合や、
+    /// リテラルでないパターンに [`Engine::Literal`] を指定した場合は、自動的に選ぶ。
+    /// 選ばれたエンジンは [`Regex::engine`] で確かめられる。
+    /// DFA 以外のエンジンを指定した場合は、検索のための DFA を構築しない。
+    /// エンジンの間で検索結果を比べるテストでの利用を想定している
+    pub fn engine(&mut self, engine: Engine) -> &mut RegexBuilder {
+        self.config.engine = Some(engine);
+        self
+    }
+
+    /// パターンをパースし、命令列を生成する
+    ///
+    /// パースに失敗した場合は [`Error::Parse`] を、
+    /// 命令列の生成に失敗した場合は [`Error::CodeGen`] を返す
+    pub fn build(&self) -> Result<Regex, Error> {
+        Regex::with_config(&self.pattern, self.config.clone())
+    }
+
+    /// [`RegexBuilder::build`] と同様にコンパイルし、各段階の計測結果もあわせて返す
+    pub fn build_with_metrics(&self) -> Result<(Regex, CompileMetrics), Error> {
+        Regex::compile(&self.pattern, self.config.clone())
+    }
+
+    /// パターンをパースして検査する。命令列は生成しないため、コンパイルより軽い
+    ///
+    /// 入力中のパターンを検査するエディタやフォームでの利用を想定している。
+    /// パターンの長さと入れ子の深さの上限も確かめ、エラーがある場合はすべてのエラーを返す。
+    /// エラーがない場合は [`Regex::warnings`] と同じ警告を返す。
+    /// 命令数の上限は命令列を生成する際に確かめるため、ここでは確かめない
+    pub fn validate(&self) -> Result<Vec<Warning>, ParserErrors> {
+        let ast = parse(&self.pattern, &self.config).map_err(ParserErrors)?;
+        Ok(lint::lint(&self.pattern, &ast))
+    }
+}
+
+/// コンパイルの各段階にかかった時間と、生成したものの大きさ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompileMetrics {
+    pub parse_time: Duration,    // パースと AST の簡約にかかった時間
+    pub codegen_time: Duration,  // 順方向と逆向きの命令列の生成にかかった時間
+    pub optimize_time: Duration, // 命令列の最適化にかかった時間
+    pub ast_nodes: usize,        // パースした AST のノード数
+    pub insts: usize,            // 最適化後の順方向の命令数
+    pub insts_saved: usize,      // 最適化で減った順方向の命令数
+}
+
+/// コンパイル済みの正規表現
+///
+/// 順方向の命令列に加えて、マッチの終了位置から開始位置を求めるための
+/// 逆向きの命令列を保持する。
+/// 順方向の命令列が繰り返しの回数を数える場合、逆向きの命令列と
+/// 状態の集合を進める評価器のための展開した命令列は、初めて利用する際に生成する。
+/// 捕獲グループの位置を捕獲する命令列も、初めて捕獲グループを求める際に生成する。
+/// DFA は検索に DFA を選んだ場合にだけ構築する
+///
+/// コンパイル結果は [`Arc`] で共有するため、`clone` は参照カウントを増やすだけで済む。
+/// `Send` かつ `Sync` なので、1つの Regex を複数のスレッドから同時に利用できる
+#[derive(Debug, Clone)]
+pub struct Regex {
+    inner: Arc<RegexInner>,
+}
+
+/// パースして単純化した AST。命令列を生成する前の段階のコンパイル結果
+struct Front {
+    warnings: Vec<Warning>,  // パターンに対する警告
+    ast: AST,                // 捕獲グループを取り除いて単純化した AST
+    groups_ast: Option<AST>, // 捕獲グループを残した AST。グループがない場合は None
+    ast_nodes: usize,        // パースした AST のノード数
+}
+
+impl Front {
+    /// パターンをパースし、警告を集めて AST を単純化する
+    fn new(pattern: &str, config: &Config) -> Result<Front, Error> {
+        let parsed = parse(pattern, config).map_err(|mut errors| {
+            if config.collect_errors {
+                Error::ParseAll(ParserErrors(errors))
+            } else {
+                Error::Parse(errors.swap_remove(0))
+            }
+        })?;
+        Ok(Front {
+            warnings: lint::lint(pattern, &parsed),
+            ast: simplify::simplify(&parsed, false),
+            groups_ast: (parsed.groups() > 0).then(|| simplify::simplify(&parsed, true)),
+            ast_nodes: parsed.node_count(),
+        })
+    }
+
+    /// 生成する順方向の命令列の命令数が上限以下かを確かめる
+    ///
+    /// 繰り返しを展開した命令列が必要になるかは検索に利用するエンジンで決まるため、
+    /// その命令数はエンジンを選んだ後に [`Front::into_regex`] で確かめる
+    fn check_size(&self, config: &Config) -> Result<(), Error> {
+        let unroll_limit = config.unroll_limit.unwrap_or(DEFAULT_UNROLL_LIMIT);
+        let size_limit = config.size_limit.unwrap_or(DEFAULT_SIZE_LIMIT) as u64;
+        if codegen::code_len(&self.ast, unroll_limit, false) > size_limit {
+            return Err(CodeGenError::CompiledTooBig.into());
+        }
+        Ok(())
+    }
+
+    /// 生成した命令列と DFA を組み合わせ、AST から求める情報を加えて Regex にする
+    ///
+    /// `dfa` がまだ空の場合、DFA は検索に DFA を選んだ場合にだけ構築する。
+    /// 選んだエンジンが利用する、繰り返しを展開した命令列や捕獲グループの位置を捕獲する命令列が
+    /// 命令数の上限を超える場合は [`CodeGenError::CompiledTooBig`] のエラーを返す
+    fn into_regex(
+        self,
+        pattern: &str,
+        config: Config,
+        prog: Program,
+        rev_prog: OnceLock<Program>,
+        dfa: OnceLock<Option<Dfa>>,
+    ) -> Result<Regex, Error> {
+        let ast = self.ast;
+        let literal = literal::literal(&ast);
+        let mut engine = match config.engine {
+            Some(Engine::Backtrack) => Engine::Backtrack,
+            Some(Engine::PikeVm) => Engine::PikeVm,
+            Some(Engine::Dfa) => Engine::Dfa,
+            Some(Engine::Literal) if literal.is_some() => Engine::Literal,
+            _ => meta::select(&ast, &prog),
+        };
+        // DFA を構築できなかった場合は、命令列を評価するエンジンを選び直す
+        if engine == Engine::Dfa && dfa.get_or_init(|| Dfa::new(&prog)).is_none() {
+            engine = meta::evaluator(&ast);
+        }
+
+        // 回数を数える命令を実行できるのはバックトラック型の評価器だけなので、
+        // それ以外のエンジンでは展開した命令列も上限以下でなければならない
+        let size_limit = config.size_limit.unwrap_or(DEFAULT_SIZE_LIMIT) as u64;
+        let unrolled_fits =
+            !prog.has_repeats() || codegen::code_len(&ast, u32::MAX, false) <= size_limit;
+        if engine != Engine::Backtrack && !unrolled_fits {
+            return Err(CodeGenError::CompiledTooBig.into());
+        }
+        let captures_len = self.groups_ast.as_ref().map_or(0, |groups_ast| {
+            codegen::code_len(groups_ast, captures_unroll_limit(&config, engine), true)
+        });
+        if captures_len > size_limit {
+            return Err(CodeGenError::CompiledTooBig.into());
+        }
+
+        let inner = RegexInner {
+            pattern: pattern.to_string(),
+            min_len: length::min_len(&ast),
+            max_len: length::max_len(&ast),
+            prefilter: match &config.prefilter {
+                Some(custom) => Some(custom.0.clone()),
+                None => literal::prefixes(&ast)
+                    .and_then(Teddy::new)
+                    .map(|teddy| Arc::new(teddy) as Arc<dyn Prefilter>),
+            },
+            warnings: self.warnings,
+            // リテラル以外のエンジンを指定した場合は、命令列を評価する
+            literal: literal
+                .filter(|_| engine == Engine::Literal)
+                .and_then(|lit| Memmem::new(&lit)),
+            anchored: literal::anchored(&ast),
+            dfa,
+            engine,
+            pool: Mutex::new(Vec::new()),
+            ast,
+            prog,
+            rev_prog,
+            unrolled: OnceLock::new(),
+            unrolled_fits,
+            groups_ast: self.groups_ast,
+            captures_prog: OnceLock::new(),
+            config,
+        };
+        Ok(Regex {
+            inner: Arc::new(inner),
+        })
+    }
+}
+
+/// 複数の Regex で共有されるコンパイル結果
+#[derive(Debug)]
+struct RegexInner {
+    pattern: String,
+    ast: AST,                         // 捕獲グループを取り除いて単純化した AST
+    prog: Program,                    // 順方向の命令列。繰り返しの回数を数える命令を含みうる
+    rev_prog: OnceLock<Program>,      // 逆向きの命令列
+    unrolled: OnceLock<Program>,      // 繰り返しをすべて展開した順方向の命令列
+    unrolled_fits: bool,              // 繰り返しを展開した命令列の命令数が上限以下か
+    groups_ast: Option<AST>,          // 捕獲グループを残した AST。グループがない場合は None
+    captures_prog: OnceLock<Program>, // 捕獲グループの位置を捕獲する命令列
+    config: Config,
+    min_len: usize,                        // マッチの最小長（バイト）
+    max_len: Option<usize>,                // マッチの最大長（バイト）。上限がない場合は None
+    prefilter: Option<Arc<dyn Prefilter>>, // マッチの開始位置の候補を探す前処理
+    warnings: Vec<Warning>,                // パターンに対する警告
+    literal: Option<Memmem>,               // パターンがリテラルの場合は、それを探す前処理
+    anchored: Option<Anchored>,            // パターンが ^ で始まる場合は、それに続くリテラル
+    dfa: OnceLock<Option<Dfa>>,            // マッチの終了位置を求める DFA。構築できない場合は None
+    engine: Engine,                        // 検索に利用するエンジン
+    pool: Mutex<Vec<Cache>>, // 評価に利用する Cache の置き場。利用中の Cache はスレッドごとに取り出される
+}
+
+impl Regex {
+    /// パターンをパースし、命令列を生成する
+    ///
+    /// 失敗した場合のエラーは [`RegexBuilder::build`] と同じ。
+    /// 設定を変更する場合は [`RegexBuilder`] を利用する
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    /// 設定を指定してパターンをコンパイルする
+    pub(crate) fn with_config(pattern: &str, config: Config) -> Result<Regex, Error> {
+        Regex::compile(pattern, config).map(|(regex, _)| regex)
+    }
+
+    /// 設定を指定してコンパイルし、パース・命令列の生成・最適化のそれぞれを計測する
+    fn compile(pattern: &str, config: Config) -> Result<(Regex, CompileMetrics), Error> {
+        let mut metrics = CompileMetrics::default();
+        let started = Instant::now();
+        let front = Front::new(pattern, &config)?;
+        metrics.parse_time = started.elapsed();
+        metrics.ast_nodes = front.ast_nodes;
+        front.check_size(&config)?;
+
+        let unroll_limit = config.unroll_limit.unwrap_or(DEFAULT_UNROLL_LIMIT);
+        let started = Instant::now();
+        let code = codegen::get_code(&front.ast, unroll_limit)?;
+        metrics.codegen_time += started.elapsed();
+        let started = Instant::now();
+        let prog = optimizer::optimize(&code);
+        metrics.optimize_time += started.elapsed();
+        metrics.insts = prog.insts.len();
+        metrics.insts_saved = code.insts.len().saturating_sub(prog.insts.len());
+
+        let rev_prog = OnceLock::new();
+        if !prog.has_repeats() {
+            let started = Instant::now();
+            let code = codegen::get_code_reverse(&front.ast)?;
+            metrics.codegen_time += started.elapsed();
+            let started = Instant::now();
+            let _ = rev_prog.set(optimizer::optimize(&code));
+            metrics.optimize_time += started.elapsed();
+        }
+        let regex = front.into_regex(pattern, config, prog, rev_prog, OnceLock::new())?;
+        Ok((regex, metrics))
+    }
+
+    /// 元のパターン文字列
+    pub fn as_str(&self) -> &str {
+        &self.inner.pattern
+    }
+
+    /// 検索に利用するエンジン
+    ///
+    /// [`RegexBuilder::engine`] で指定しない場合、エンジンはコンパイル時にパターンを調べて自動的に選ばれる
+    pub fn engine(&self) -> Engine {
+        self.inner.engine
+    }
+
+    /// パターンに対する警告
+    ///
+    /// 同じ選択肢の繰り返しや決して満たされない `$` のように、誤りの可能性が高い書き方を報告する。
+    /// 警告があってもコンパイルは成功する
+    pub fn warnings(&self) -> &[Warning] {
+        &self.inner.warnings
+    }
+
+    /// マッチする文字列の最小の長さ（バイト）
+    pub fn min_len(&self) -> usize {
+        self.inner.min_len
+    }
+
+    /// マッチする文字列の最大の長さ（バイト）
+    ///
+    /// `*` や `+` のように上限なく繰り返す場合は `None` を返す
+    pub fn max_len(&self) -> Option<usize> {
+        self.inner.max_len
+    }
+
+    /// 状態の集合を進める評価器で利用する順方向の命令列
+    ///
+    /// 繰り返しを展開した命令列が命令数の上限を超える場合はパニックする
+    fn set_prog(&self) -> &Program {
+        let inner = &self.inner;
+        if !inner.prog.has_repeats() {
+            return &inner.prog;
+        }
+        if !inner.unrolled_fits {
+            panic!("{}", CodeGenError::CompiledTooBig);
+        }
+        inner
+            .unrolled
+            .get_or_init(|| unrolled(codegen::get_code(&inner.ast, u32::MAX)))
+    }
+
+    /// マッチの終了位置を求める DFA
+    ///
+    /// 検索に DFA を選ばなかった場合は、初めて利用する際に構築する
+    fn dfa(&self) -> Option<&Dfa> {
+        let inner = &self.inner;
+        inner.dfa.get_or_init(|| Dfa::new(&inner.prog)).as_ref()
+    }
+
+    /// 逆向きの命令列
+    fn rev_prog(&self) -> &Program {
+        let inner = &self.inner;
+        inner
+            .rev_prog
+            .get_or_init(|| unrolled(codegen::get_code_reverse(&inner.ast)))
+    }
+
+    /// 置き場から Cache を取り出して `f` を実行し、終了後に置き場へ戻す
+    ///
+    /// 取り出している間は他のスレッドから利用されないため、
+    /// 同時に検索するスレッドの数だけ Cache が作られる
+    fn with_cache<T>(&self, f: impl FnOnce(&mut Cache) -> T) -> T {
+        let pool = &self.inner.pool;
+        let mut cache = pool
+            .lock()
+            .map(|mut caches| caches.pop())
+            .unwrap_or(None)
+            .unwrap_or_default();
+        let result = f(&mut cache);
+        if let Ok(mut caches) = pool.lock() {
+            caches.push(cache);
+        }
+        result
+    }
+
+    /// `haystack` のどこかにマッチするかを判定する
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする。
+    /// 上限を設定した場合は [`Regex::try_is_match`] を利用する
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    /// `haystack` 中で最も左にあるマッチを返す
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする。
+    /// 上限を設定した場合は [`Regex::try_find`] を利用する
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        match self.try_find(haystack) {
+            Ok(m) => m,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// `haystack` のどこかにマッチするかを判定する
+    ///
+    /// 命令数の上限を超えた場合はエラーを返す
+    pub fn try_is_match(&self, haystack: &str) -> Result<bool, EvalError> {
+        Ok(self.try_find(haystack)?.is_some())
+    }
+
+    /// `haystack` 中で最も左にあるマッチを返す
+    ///
+    /// 命令数の上限を超えた場合はエラーを返す
+    pub fn try_find<'h>(&self, haystack: &'h str) -> Result<Option<Match<'h>>, EvalError> {
+        self.with_cache(|cache| self.try_find_at(cache, haystack, 0))
+    }
+
+    /// `haystack` の `start` 以降で最も左にあるマッチを返す
+    ///
+    /// 作業用のメモリとして `cache` を利用するため、同じ Cache を渡して繰り返し呼び出すと
+    /// 検索のたびにメモリを確保し直さずに済む。
+    /// 命令数の上限は呼び出しごとに適用され、超えた場合はエラーを返す
+    ///
+    /// `start` が文字の境界でない場合はパニックする
+    pub fn try_find_at<'h>(
+        &self,
+        cache: &mut Cache,
+        haystack: &'h str,
+        start: usize,
+    ) -> Result<Option<Match<'h>>, EvalError> {
+        self.search(cache, haystack, start, haystack.len() + 1)
+    }
+
+    /// `haystack` 中で最も左にあるマッチを、検索中の統計情報とともに返す
+    ///
+    /// 命令数の上限を超えた場合も、それまでに集めた統計情報を返す
+    pub fn find_with_stats<'h>(
+        &self,
+        haystack: &'h str,
+    ) -> (Result<Option<Match<'h>>, EvalError>, SearchStats) {
+        self.with_cache(|cache| {
+            cache.take_stats();
+            let m = self.try_find_at(cache, haystack, 0);
+            (m, cache.take_stats())
+        })
+    }
+
+    /// 開始位置が `start` 以上 `end` 未満のマッチのうち、最も左にあるものを返す
+    ///
+    /// 実行した命令の数を `cache` の統計情報に加える
+    fn search<'h>(
+        &self,
+        cache: &mut Cache,
+        haystack: &'h str,
+        start: usize,
+        end: usize,
+    ) -> Result<Option<Match<'h>>, EvalError> {
+        let limit = self.inner.config.step_limit.unwrap_or(u64::MAX);
+        let mut fuel = limit;
+        let result = self.search_with_fuel(cache, haystack, start, end, &mut fuel);
+        cache.stats_mut().steps += limit - fuel;
+        result
+    }
+
+    /// [`Regex::search`] の本体。`fuel` は実行できる残りの命令数
+    ///
+    /// 残りの文字列がマッチの最小長より短い位置からは評価しない。
+    /// 開始位置に上限があり、マッチの最大長が分かる場合は、リテラルと前処理、DFA で
+    /// 探す範囲を上限からマッチの最大長の分までに限り、その後ろは読まない
+    fn search_with_fuel<'h>(
+        &self,
+        cache: &mut Cache,
+        haystack: &'h str,
+        start: usize,
+        end: usize,
+        fuel: &mut u64,
+    ) -> Result<Option<Match<'h>>, EvalError> {
+        let Some(last) = haystack.len().checked_sub(self.inner.min_len) else {
+            return Ok(None);
+        };
+        let end = end.min(last + 1);
+
+        // `end` より前から始まるマッチは、`end` にマッチの最大長を加えた位置までに収まる
+        let limit = match self.inner.max_len {
+            Some(max) if end <= haystack.len() => {
+                let mut limit = end.saturating_add(max).min(haystack.len()).max(start);
+                while !haystack.is_char_boundary(limit) {
+                    limit += 1;
+                }
+                limit
+            }
+            _ => haystack.len(),
+        };
+
+        // リテラルの場合は、命令列を評価せずに前処理が返した位置をマッチとする
+        if let Some(lit) = &self.inner.literal {
+            let Some(start) = lit.find(&haystack.as_bytes()[..limit], start) else {
+                return Ok(None);
+            };
+            cache.stats_mut().prefilter_hits += 1;
+            return Ok((start < end).then(|| Match {
+                haystack,
+                start,
+                end: start + lit.needle().len(),
+            }));
+        }
+
+        if let Some(anchored) = &self.inner.anchored {
+            return self.search_anchored(cache, haystack, start, end, anchored, fuel);
+        }
+
+        let mut pos = start;
+        while pos < end {
+            // 前処理がある場合は、候補の位置まで読み飛ばす
+            if let Some(prefilter) = &self.inner.prefilter {
+                match prefilter.find(&haystack.as_bytes()[..limit], pos) {
+                    Some(next) if next < end => pos = next.max(pos),
+                    _ => return Ok(None),
+                }
+                cache.stats_mut().prefilter_hits += 1;
+                while !haystack.is_char_boundary(pos) {
+                    pos += 1;
+                }
+                if pos >= end {
+                    return Ok(None);
+                }
+            }
+
+            // DFA は開始位置の上限を扱えないため、マッチの最大長が分かる場合は `limit` までで
+            // 切り詰めて検索し、上限以降から始まるマッチを捨てる。DFA は位置の判定を含まないため、
+            // 切り詰めても結果は変わらない。最大長が分からない場合は Pike VM を利用する
+            match self.inner.engine {
+                Engine::Dfa if end > haystack.len() || self.inner.max_len.is_some() => {
+                    let m = self.find_dfa(cache, &haystack[..limit], pos);
+                    return Ok(m.filter(|m| m.start < end).map(|m| Match { haystack, ..m }));
+                }
+                Engine::Backtrack => {
+                    let prog = &self.inner.prog;
+                    if let Some(end) = evaluator::eval(prog, haystack, pos, fuel, cache)? {
+                        return Ok(Some(Match {
+                            haystack,
+                            start: pos,
+                            end,
+                        }));
+                    }
+                }
+                Engine::Literal | Engine::Dfa | Engine::PikeVm => {
+                    let prog = self.set_prog();
+                    let m = evaluator::eval_pike(prog, haystack, pos, end, fuel, cache)?;
+                    return Ok(m.map(|(start, end)| Match {
+                        haystack,
+                        start,
+                        end,
+                    }));
+                }
+            }
+            pos += haystack[pos..].chars().next().map_or(1, char::len_utf8);
+        }
+        Ok(None)
+    }
+
+    /// `^` で始まるパターンを、文字列の先頭か、複数行モードでは各行の先頭でだけ評価する
+    ///
+    /// `^` に続くリテラルをまず直接比較し、一致しない位置では命令列を評価しない
+    fn search_anchored<'h>(
+        &self,
+        cache: &mut Cache,
+        haystack: &'h str,
+        start: usize,
+        end: usize,
+        anchored: &Anchored,
+        fuel: &mut u64,
+    ) -> Result<Option<Match<'h>>, EvalError> {
+        let bytes = haystack.as_bytes();
+        let mut pos = start;
+        while pos < end {
+            if pos > 0 && !(anchored.multi_line && bytes[pos - 1] == b'\n') {
+                if !anchored.multi_line {
+                    return Ok(None);
+                }
+                // 次の行の先頭まで読み飛ばす
+                match bytes[pos..].iter().position(|&b| b == b'\n') {
+                    Some(i) => pos += i + 1,
+                    None => return Ok(None),
+                }
+                continue;
+            }
+
+            if bytes[pos..].starts_with(anchored.prefix.as_bytes()) {
+                if anchored.exact {
+                    return Ok(Some(Match {
+                        haystack,
+                        start: pos,
+                        end: pos + anchored.prefix.len(),
+                    }));
+                }
+                let m = match self.inner.engine {
+                    Engine::Backtrack => {
+                        evaluator::eval(&self.inner.prog, haystack, pos, fuel, cache)?
+                            .map(|end| (pos, end))
+                    }
+                    Engine::Literal | Engine::Dfa | Engine::PikeVm => {
+                        evaluator::eval_pike(self.set_prog(), haystack, pos, pos + 1, fuel, cache)?
+                    }
+                };
+                if let Some((start, end)) = m {
+                    return Ok(Some(Match {
+                        haystack,
+                        start,
+                        end,
+                    }));
+                }
+            }
+
+            if !anchored.multi_line {
+                return Ok(None);
+            }
+            pos += 1;
+        }
+        Ok(None)
+    }
+
+    /// DFA の遷移表と、マッチを探す関数を定義する Rust のモジュールのソースを返す
+    ///
+    /// 生成したモジュールはこのクレートに依存せず、`is_match` と、マッチの終了位置を返す
+    /// `find_end` を定義する。`name` はモジュール名になる。
+    /// 検索に DFA を利用しない場合は、ここで DFA を構築する。
+    /// 位置の判定や回数を数える繰り返しを含むなどの理由で DFA を構築できない場合と、
+    /// `name` が Rust の識別子でないかキーワードである場合([`is_rust_ident`] を参照)は `None` を返す
+    pub fn to_rust_dfa(&self, name: &str) -> Option<String> {
+        if !is_rust_ident(name) {
+            return None;
+        }
+        let dfa = self.dfa()?;
+        Some(dfa.to_rust(name, &self.inner.pattern))
+    }
+
+    /// 検索に使う順方向の命令列を、アドレスを付けて1行に1命令ずつ並べた文字列を返す
+    ///
+    /// 分岐の命令は飛び先のアドレスを、文字クラスは範囲を展開して表示する
+    ///
+    /// ```text
+    /// 0000: split 0001, 0003
+    /// 0001: char a
+    /// 0002: jump 0000
+    /// 0003: match
+    /// ```
+    pub fn program(&self) -> String {
+        self.inner.prog.to_string()
+    }
+
+    /// [`Regex::program`] の命令列を状態とし、その間の遷移を表す Graphviz の DOT 形式のグラフを返す
+    ///
+    /// 状態の番号は命令のアドレスと同じ。文字を読む遷移は実線で、入力を読まない遷移は破線で表す。
+    /// グラフの見出しはパターンになる
+    pub fn to_dot(&self) -> String {
+        graph::dot(&self.inner.prog, &self.inner.pattern)
+    }
+
+    /// [`Regex::to_dot`] と同じグラフを、Markdown に貼り付けられる Mermaid の状態遷移図として返す
+    pub fn to_mermaid(&self) -> String {
+        graph::mermaid(&self.inner.prog)
+    }
+
+    /// `start` 以降から始まるマッチのうち最も左にあるものを、DFA で終了位置を求めてから
+    /// 逆向きの命令列で開始位置を求めて返す
+    fn find_dfa<'h>(
+        &self,
+        cache: &mut Cache,
+        haystack: &'h str,
+        start: usize,
+    ) -> Option<Match<'h>> {
+        let end = self.dfa()?.find(haystack, start)?;
+        let rest = &haystack[start..];
+        let begin = evaluator::eval_rev(self.rev_prog(), rest, end - start, cache)?;
+        Some(Match {
+            haystack,
+            start: start + begin,
+            end,
+        })
+    }
+
+    /// `haystack` 中の重なり合わないマッチを左から順に返すイテレータを生成する
+    ///
+    /// イテレータは1つの Cache を保持し、すべての検索で使い回す
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合は、イテレータがパニックする
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> FindMatches<'r, 'h> {
+        FindMatches {
+            regex: self,
+            haystack,
+            cache: Cache::new(),
+            state: IterState::default(),
+        }
+    }
+
+    /// `haystack` 中の重なり合わないマッチをすべて `rep` に置き換えた文字列を返す
+    ///
+    /// `rep` はそのまま挿入する。グループの参照を展開する場合は
+    /// [`Regex::replace_all_template`] を利用する
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする
+    pub fn replace_all(&self, haystack: &str, rep: &str) -> String {
+        let mut out = String::with_capacity(haystack.len());
+        let mut last = 0;
+        for m in self.find_iter(haystack) {
+            out.push_str(&haystack[last..m.start]);
+            out.push_str(rep);
+            last = m.end;
+        }
+        out.push_str(&haystack[last..]);
+        out
+    }
+
+    /// `state` の位置から検索し、重なり合わないマッチのうち次のものを返す
+    ///
+    /// 開始位置が `end` 未満のマッチだけを対象にする。
+    /// 直前のマッチの直後にある空マッチは報告せず、1文字進めて検索し直す
+    fn next_match<'h>(
+        &self,
+        cache: &mut Cache,
+        haystack: &'h str,
+        state: &mut IterState,
+        end: usize,
+    ) -> Result<Option<Match<'h>>, EvalError> {
+        loop {
+            if state.pos > haystack.len() {
+                return Ok(None);
+            }
+            let Some(m) = self.search(cache, haystack, state.pos, end)? else {
+                return Ok(None);
+            };
+
+            if m.start == m.end && Some(m.end) == state.last_end {
+                let Some(c) = haystack[m.end..].chars().next() else {
+                    return Ok(None);
+                };
+                state.pos = m.end + c.len_utf8();
+                continue;
+            }
+
+            state.pos = m.end;
+            state.last_end = Some(m.end);
+            return Ok(Some(m));
+        }
+    }
+
+    /// `end` で終わるマッチのうち、最も左にある開始位置を返す
+    ///
+    /// 逆向きの命令列を `end` から先頭に向かって実行するため、
+    /// マッチの終了位置だけを報告するエンジンと組み合わせて範囲を求めることができる
+    ///
+    /// 繰り返しを展開した命令列が命令数の上限を超える場合は、逆向きの命令列を生成せず、
+    /// 先頭に近い位置から順に、順方向の命令列で `end` で終わるマッチがあるかを確かめる
+    ///
+    /// `end` が文字の境界でない場合はパニックする
+    pub fn find_start(&self, haystack: &str, end: usize) -> Option<usize> {
+        self.with_cache(|cache| {
+            if self.inner.unrolled_fits {
+                return evaluator::eval_rev(self.rev_prog(), haystack, end, cache);
+            }
+            (0..=end)
+                .filter(|&start| haystack.is_char_boundary(start))
+                .find(|&start| {
+                    let prog = &self.inner.prog;
+                    let mut fuel = u64::MAX;
+                    let found = evaluator::eval_captures_backtrack(
+                        prog, haystack, start, end, &mut fuel, cache,
+                    );
+                    matches!(found, Ok(Some(_)))
+                })
+        })
+    }
+}
+
+/// 設定した上限を確かめながら、パターンをパースする
+fn parse(pattern: &str, config: &Config) -> Result<AST, Vec<ParserError>> {
+    if let Some(limit) = config.pattern_limit {
+        parser::check_length(pattern, limit).map_err(|e| vec![e])?;
+    }
+    let flags = Flags {
+        ascii: config.ascii,
+        multi_line: config.multi_line,
+    };
+    let nest_limit = config.nest_limit.unwrap_or(parser::DEFAULT_NEST_LIMIT);
+    let ast = parser::parse_with(pattern, flags, config.lenient, nest_limit)?;
+    if !config.disabled.is_empty() || config.class_limit.is_some() {
+        syntax::check_syntax(pattern, &ast, &config.disabled, config.class_limit)
+            .map_err(|e| vec![e])?;
+    }
+    Ok(ast)
+}
+
+/// 捕獲グループの位置を捕獲する命令列で、`{n,m}` を展開する回数の上限
+///
+/// バックトラック型の評価器で検索する場合は検索に使う命令列と同じく回数を数え、
+/// それ以外の場合は状態の集合を進める評価器で実行するため常に展開する
+fn captures_unroll_limit(config: &Config, engine: Engine) -> u32 {
+    match engine {
+        Engine::Backtrack => config.unroll_limit.unwrap_or(DEFAULT_UNROLL_LIMIT),
+        _ => u32::MAX,
+    }
+}
+
+/// 繰り返しを展開して生成した命令列を最適化する
+///
+/// 展開した命令列の命令数はコンパイル時に確かめているため、失敗することはない。
+/// 失敗した場合はパニックする
+fn unrolled(prog: Result<Program, CodeGenError>) -> Program {
+    match prog {
+        Ok(prog) => optimizer::optimize(&prog),
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// [`Regex::find_iter`] で次に検索を始める位置
+#[derive(Debug, Clone, Copy, Default)]
+struct IterState {
+    pos: usize,              // 次に検索を始める位置
+    last_end: Option<usize>, // 直前のマッチの終了位置
+}
+
+/// [`Regex::find_iter`] が返すイテレータ
+#[derive(Debug)]
+pub struct FindMatches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    cache: Cache,
+    state: IterState,
+}
+
+impl<'h> Iterator for FindMatches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        let end = self.haystack.len() + 1;
+        match self
+            .regex
+            .next_match(&mut self.cache, self.haystack, &mut self.state, end)
+        {
+            Ok(m) => m,
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
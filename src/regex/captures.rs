@@ -0,0 +1,240 @@
+//! マッチの範囲を求めてから、その範囲の中で捕獲グループの位置を求める
+use super::{IterState, Match, Regex, captures_unroll_limit, unrolled};
+use crate::engine::{
+    Program, codegen,
+    evaluator::{self, Cache, EvalError},
+};
+
+/// マッチした範囲と、各捕獲グループにマッチした範囲
+///
+/// 0 番目のグループはマッチ全体を表す
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    slots: Vec<Option<usize>>, // グループ i の開始位置と終了位置を 2 * i と 2 * i + 1 に並べたもの
+}
+
+impl<'h> Captures<'h> {
+    /// `i` 番目のグループにマッチした範囲
+    ///
+    /// グループがマッチに関わらなかった場合や、`i` 番目のグループがない場合は `None` を返す
+    pub fn get(&self, i: usize) -> Option<Match<'h>> {
+        let start = (*self.slots.get(2 * i)?)?;
+        let end = (*self.slots.get(2 * i + 1)?)?;
+        Some(Match {
+            haystack: self.haystack,
+            start,
+            end,
+        })
+    }
+
+    /// 0 番目のグループから順に、各グループにマッチした範囲を返すイテレータを生成する
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.slots.len() / 2).map(|i| self.get(i))
+    }
+
+    /// `template` 中のグループの参照を、そのグループにマッチした文字列に置き換えて `dst` に追加する
+    ///
+    /// 参照は `$` に続く番号か、`${1}` のように `{}` で囲んだ番号で書く。
+    /// `$` の直後の数字はすべて番号とみなすため、後ろに数字を続ける場合は `{}` で囲む。
+    /// マッチに関わらなかったグループやないグループは空文字列に置き換える。
+    /// 名前付きのグループはないため、`$name` や `${name}` も空文字列になる。
+    /// `$$` は `$` 1文字にし、参照として読めない `$` はそのまま残す
+    ///
+    /// ```text
+    /// (\w+)=(\w+) に "a=b" がマッチした場合
+    /// "$2=$1"   → "b=a"
+    /// "${1}0"   → "a0"
+    /// "$$1"     → "$1"
+    /// ```
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = after;
+                continue;
+            }
+            let Some((name, after)) = reference(rest) else {
+                dst.push('$');
+                continue;
+            };
+            if let Some(m) = name.parse().ok().and_then(|i| self.get(i)) {
+                dst.push_str(m.as_str());
+            }
+            rest = after;
+        }
+        dst.push_str(rest);
+    }
+}
+
+/// `$` の直後の `s` から参照するグループの名前を読み、名前と残りの文字列を返す
+///
+/// 名前は `{}` で囲んだ文字列か、数字の列か、英数字と `_` の列。読めない場合は `None`
+fn reference(s: &str) -> Option<(&str, &str)> {
+    if let Some(s) = s.strip_prefix('{') {
+        let end = s.find('}')?;
+        return (end > 0).then(|| (&s[..end], &s[end + 1..]));
+    }
+    let end = if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.find(|c: char| !c.is_ascii_digit())
+    } else {
+        s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+    }
+    .unwrap_or(s.len());
+    (end > 0).then(|| s.split_at(end))
+}
+
+impl Regex {
+    /// マッチ全体を含めた捕獲グループの数
+    pub fn captures_len(&self) -> usize {
+        self.inner
+            .groups_ast
+            .as_ref()
+            .map_or(0, |ast| ast.groups() as usize)
+            + 1
+    }
+
+    /// `haystack` 中で最も左にあるマッチと、その捕獲グループの範囲を返す
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする。
+    /// 上限を設定した場合は [`Regex::try_captures`] を利用する
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        match self.try_captures(haystack) {
+            Ok(caps) => caps,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// `haystack` 中で最も左にあるマッチと、その捕獲グループの範囲を返す
+    ///
+    /// まず捕獲しない評価器でマッチの範囲を求め、捕獲グループがある場合に限り、
+    /// 位置を捕獲する評価器をその範囲だけに対して実行する。
+    /// 命令数の上限はそれぞれの評価器に適用し、超えた場合はエラーを返す
+    pub fn try_captures<'h>(&self, haystack: &'h str) -> Result<Option<Captures<'h>>, EvalError> {
+        self.with_cache(|cache| match self.try_find_at(cache, haystack, 0)? {
+            Some(m) => self.resolve(cache, m),
+            None => Ok(None),
+        })
+    }
+
+    /// `haystack` 中の重なり合わないマッチと、その捕獲グループの範囲を左から順に返す
+    /// イテレータを生成する
+    ///
+    /// マッチは [`Regex::find_iter`] と同じ順に見つけ、捕獲グループの位置は
+    /// マッチした範囲の中だけで求めるため、マッチがまばらな長い文字列でも
+    /// 位置を捕獲する評価器を実行するのはマッチした部分に限られる
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合は、イテレータがパニックする
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h str) -> CaptureMatches<'r, 'h> {
+        CaptureMatches {
+            regex: self,
+            haystack,
+            cache: Cache::new(),
+            state: IterState::default(),
+        }
+    }
+
+    /// `haystack` 中の重なり合わないマッチを、`template` を展開した文字列に置き換えた文字列を返す
+    ///
+    /// `template` の書き方は [`Captures::expand`] を参照。
+    /// 置き換える文字列をそのまま挿入する場合は [`Regex::replace_all`] を利用する
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする
+    pub fn replace_all_template(&self, haystack: &str, template: &str) -> String {
+        let mut out = String::with_capacity(haystack.len());
+        let mut last = 0;
+        for caps in self.captures_iter(haystack) {
+            let m = caps.get(0).expect("group 0 always matches");
+            out.push_str(&haystack[last..m.start]);
+            caps.expand(template, &mut out);
+            last = m.end;
+        }
+        out.push_str(&haystack[last..]);
+        out
+    }
+
+    /// マッチ `m` の範囲の中で、捕獲グループの位置を求める
+    ///
+    /// 位置を捕獲する命令列がその範囲にマッチしない場合は `None` を返す
+    fn resolve<'h>(
+        &self,
+        cache: &mut Cache,
+        m: Match<'h>,
+    ) -> Result<Option<Captures<'h>>, EvalError> {
+        let mut slots = vec![None; 2 * self.captures_len()];
+        slots[0] = Some(m.start);
+        slots[1] = Some(m.end);
+
+        if let Some(prog) = self.captures_prog() {
+            let mut fuel = self.inner.config.step_limit.unwrap_or(u64::MAX);
+            let found = if prog.has_repeats() {
+                evaluator::eval_captures_backtrack(
+                    prog, m.haystack, m.start, m.end, &mut fuel, cache,
+                )?
+            } else {
+                evaluator::eval_captures(prog, m.haystack, m.start, m.end, &mut fuel, cache)?
+            };
+            // マッチした範囲は同じ優先度で選んでいるため、コンパイルした命令列であれば
+            // その範囲で最も優先度の高い経路が必ず見つかる。見つからないのは、
+            // Regex::from_bytes で書き換えた命令列を読み込んだ場合に限られる
+            let Some(found) = found else {
+                return Ok(None);
+            };
+            slots[2..].copy_from_slice(&found[2..]);
+        }
+
+        Ok(Some(Captures {
+            haystack: m.haystack,
+            slots,
+        }))
+    }
+
+    /// 位置を捕獲する命令列。捕獲グループがない場合は `None`
+    fn captures_prog(&self) -> Option<&Program> {
+        let inner = &self.inner;
+        let ast = inner.groups_ast.as_ref()?;
+        let unroll_limit = captures_unroll_limit(&inner.config, inner.engine);
+        Some(
+            inner
+                .captures_prog
+                .get_or_init(|| unrolled(codegen::get_code_captures(ast, unroll_limit))),
+        )
+    }
+}
+
+/// [`Regex::captures_iter`] が返すイテレータ
+#[derive(Debug)]
+pub struct CaptureMatches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    cache: Cache,
+    state: IterState,
+}
+
+impl<'h> Iterator for CaptureMatches<'_, 'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Captures<'h>> {
+        let end = self.haystack.len() + 1;
+        let result = self
+            .regex
+            .next_match(&mut self.cache, self.haystack, &mut self.state, end)
+            .and_then(|m| match m {
+                Some(m) => self.regex.resolve(&mut self.cache, m),
+                None => Ok(None),
+            });
+        match result {
+            Ok(caps) => caps,
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! 1つの大きな文字列を分割し、複数のスレッドで並列に検索する
+use super::{IterState, Match, Regex};
+use crate::engine::evaluator::{Cache, EvalError};
+use std::thread;
+
+/// 1つのチャンクの最小の大きさ（バイト）
+const MIN_CHUNK_LEN: usize = 64 * 1024;
+
+impl Regex {
+    /// `haystack` を複数のチャンクに分割してスレッドごとに並列に検索し、
+    /// [`Regex::find_iter`] と同じマッチをすべて返す
+    ///
+    /// `threads` は利用するスレッドの数で、0 の場合は利用可能な並列度に合わせる。
+    /// 各スレッドは自分のチャンクから始まるマッチだけを探す。そのようなマッチはチャンクの末尾から
+    /// マッチの最大長の分までに収まるため、各スレッドはそれより後ろを読まず、
+    /// 逐次的な検索と同じく DFA やリテラルの検索を利用する。
+    /// 結合時には、チャンクの境界をまたいだマッチと重なる結果を取り除き、
+    /// 逐次的に検索した場合と同じ結果になるよう境界の付近だけを検索し直す
+    ///
+    /// マッチの最大長に上限がないパターンや、短い文字列に対しては、分割せずに検索する
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする
+    pub fn par_find_all<'h>(&self, haystack: &'h str, threads: usize) -> Vec<Match<'h>> {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+
+        // 境界をまたぐマッチを探すために読み進める長さ
+        let Some(overlap) = self.inner.max_len else {
+            return self.find_iter(haystack).collect();
+        };
+        let chunk_len = (haystack.len() / threads)
+            .max(MIN_CHUNK_LEN)
+            .max(overlap * 4);
+        if threads <= 1 || haystack.len() <= chunk_len {
+            return self.find_iter(haystack).collect();
+        }
+
+        let bounds = chunk_bounds(haystack, chunk_len);
+        let results: Vec<Result<Vec<Match<'h>>, EvalError>> = thread::scope(|s| {
+            let handles: Vec<_> = bounds
+                .iter()
+                .map(|&(start, end)| s.spawn(move || self.find_in_chunk(haystack, start, end)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect()
+        });
+
+        let mut chunks = Vec::with_capacity(results.len());
+        for result in results {
+            match result {
+                Ok(matches) => chunks.push(matches),
+                Err(e) => panic!("{e}"),
+            }
+        }
+        match self.merge_chunks(haystack, &bounds, chunks) {
+            Ok(matches) => matches,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// 開始位置が `start` 以上 `end` 未満のマッチを左から順にすべて返す
+    ///
+    /// `^` や `\b` のような位置の判定がチャンクの前後の文字を参照できるよう、`haystack` は
+    /// 切り詰めずに渡す。読む範囲は [`Regex::search`] が開始位置の上限とマッチの最大長から限る
+    fn find_in_chunk<'h>(
+        &self,
+        haystack: &'h str,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<Match<'h>>, EvalError> {
+        let mut cache = Cache::new();
+        let mut state = IterState {
+            pos: start,
+            last_end: None,
+        };
+        let mut matches = Vec::new();
+        while let Some(m) = self.next_match(&mut cache, haystack, &mut state, end)? {
+            matches.push(m);
+        }
+        Ok(matches)
+    }
+
+    /// チャンクごとの検索結果を結合する
+    ///
+    /// 直前までのマッチがチャンクの先頭以降で終わっている場合、そのチャンクの結果は
+    /// 逐次的な検索とずれている可能性がある。この場合は直前のマッチの終了位置から検索し直し、
+    /// チャンクの結果と同じマッチが見つかった時点で、残りの結果をそのまま採用する
+    fn merge_chunks<'h>(
+        &self,
+        haystack: &'h str,
+        bounds: &[(usize, usize)],
+        chunks: Vec<Vec<Match<'h>>>,
+    ) -> Result<Vec<Match<'h>>, EvalError> {
+        let mut cache = Cache::new();
+        let mut result: Vec<Match<'h>> = Vec::new();
+
+        for (&(start, end), chunk) in bounds.iter().zip(chunks) {
+            let mut chunk = chunk.into_iter().peekable();
+
+            if let Some(last_end) = result.last().map(|m| m.end)
+                && last_end >= start
+            {
+                let mut state = IterState {
+                    pos: last_end,
+                    last_end: Some(last_end),
+                };
+                let mut synced = false;
+                while let Some(m) = self.next_match(&mut cache, haystack, &mut state, end)? {
+                    while chunk.next_if(|c| c.start < m.start).is_some() {}
+                    if chunk.peek() == Some(&m) {
+                        synced = true;
+                        break;
+                    }
+                    result.push(m);
+                }
+                // 同じマッチが見つからないままチャンクの末尾に達した場合、
+                // チャンクの結果はすべて逐次的な検索では現れないため捨てる
+                if !synced {
+                    continue;
+                }
+            }
+
+            result.extend(chunk);
+        }
+
+        Ok(result)
+    }
+}
+
+/// 文字の境界で `haystack` をおよそ `chunk_len` バイトごとに分割する
+fn chunk_bounds(haystack: &str, chunk_len: usize) -> Vec<(usize, usize)> {
+    let mut bounds = Vec::new();
+    let mut start = 0;
+    while start < haystack.len() {
+        let mut end = (start + chunk_len).min(haystack.len());
+        while !haystack.is_char_boundary(end) {
+            end += 1;
+        }
+        bounds.push((start, end));
+        start = end;
+    }
+
+    // 末尾の空マッチを対象に含めるため、最後のチャンクは文字列の末尾も含める
+    if let Some(last) = bounds.last_mut() {
+        last.1 = haystack.len() + 1;
+    }
+    bounds
+}
@@ -0,0 +1,173 @@
+//! コンパイル結果をバイト列に書き出し、読み込む
+//!
+//! バイト列は、形式を表す4バイト `RGXP`、形式の版、残りのバイト列のチェックサムに続けて、
+//! パターン、設定、順方向と逆向きの命令列、DFA の遷移表を並べる
+use super::{Config, Front, Regex};
+use crate::{
+    engine::{
+        binary::{self, DecodeError, Reader, Writer},
+        dfa::Dfa,
+        meta::Engine,
+        syntax::Syntax,
+    },
+    error::Error,
+};
+use std::sync::OnceLock;
+
+/// 形式を表す先頭のバイト列
+const MAGIC: &[u8; 4] = b"RGXP";
+
+/// 形式の版。形式を変えた場合は増やす
+const FORMAT_VERSION: u32 = 4;
+
+impl Regex {
+    /// コンパイル結果をバイト列に書き出す
+    ///
+    /// 書き出したバイト列は [`Regex::from_bytes`] で読み込める。
+    /// 順方向と逆向きの命令列、DFA の遷移表を含むため、読み込む際には生成し直さずに済む。
+    /// [`RegexBuilder::prefilter`](crate::RegexBuilder::prefilter) で設定した前処理は書き出さない
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let inner = &self.inner;
+        let mut w = Writer::default();
+        w.str(&inner.pattern);
+        write_config(&mut w, &inner.config);
+        binary::write_program(&mut w, &inner.prog);
+        w.option(inner.rev_prog.get(), binary::write_program);
+        // 構築を試みていない DFA は書き出さず、構築できなかった DFA はそのことを書き出す
+        w.option(inner.dfa.get(), |w, dfa| {
+            w.option(dfa.as_ref(), |w, dfa| dfa.write(w))
+        });
+
+        let mut bytes = Vec::with_capacity(16 + w.bytes.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&binary::checksum(&w.bytes).to_le_bytes());
+        bytes.extend_from_slice(&w.bytes);
+        bytes
+    }
+
+    /// [`Regex::to_bytes`] で書き出したバイト列を読み込む
+    ///
+    /// 命令列と遷移表の内容を確かめたうえで、パターンをパースし直して AST から求める情報を復元する。
+    /// 形式が異なる場合や内容が壊れている場合は [`Error::Decode`] を、
+    /// パターンのパースに失敗した場合は [`Error::Parse`] を返す
+    ///
+    /// 確かめた命令列と遷移表は評価してもパニックや無限ループを起こさないが、
+    /// パターンと同じ意味であるかまでは確かめない。チェックサムを合わせて書き換えたバイト列では
+    /// 検索の結果が誤る場合があり、見つけたマッチの捕獲グループを求められずに
+    /// [`Regex::captures`] が `None` を返す場合もある
+    pub fn from_bytes(bytes: &[u8]) -> Result<Regex, Error> {
+        let mut r = Reader::new(bytes);
+        if r.take(4).map_err(|_| DecodeError::BadMagic)? != MAGIC {
+            return Err(DecodeError::BadMagic.into());
+        }
+        let version = r.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version).into());
+        }
+        let checksum = r.u64()?;
+        let payload = r.take(r.remaining())?;
+        if binary::checksum(payload) != checksum {
+            return Err(DecodeError::Checksum.into());
+        }
+
+        let mut r = Reader::new(payload);
+        let pattern = r.str()?;
+        let config = read_config(&mut r)?;
+        let prog = binary::read_program(&mut r)?;
+        let rev_prog = r.option(binary::read_program)?;
+        let dfa = r.option(|r| r.option(Dfa::read))?;
+        if r.remaining() > 0 || rev_prog.as_ref().is_some_and(|prog| prog.has_repeats()) {
+            return Err(DecodeError::Invalid.into());
+        }
+
+        let front = Front::new(pattern, &config)?;
+        front.check_size(&config)?;
+        let size_limit = config.size_limit.unwrap_or(super::DEFAULT_SIZE_LIMIT);
+        if prog.insts.len() > size_limit {
+            return Err(DecodeError::Invalid.into());
+        }
+        let rev_prog = match rev_prog {
+            Some(rev_prog) => OnceLock::from(rev_prog),
+            None => OnceLock::new(),
+        };
+        let dfa = match dfa {
+            Some(dfa) => OnceLock::from(dfa),
+            None => OnceLock::new(),
+        };
+        front.into_regex(pattern, config, prog, rev_prog, dfa)
+    }
+}
+
+/// 前処理を除く設定を書く
+fn write_config(w: &mut Writer, config: &Config) {
+    w.option(config.step_limit, Writer::u64);
+    w.option(config.unroll_limit, Writer::u32);
+    w.option(config.nest_limit, Writer::u32);
+    w.option(config.size_limit, |w, limit| w.u64(limit as u64));
+    w.option(config.pattern_limit, |w, limit| w.u64(limit as u64));
+    w.bool(config.ascii);
+    w.bool(config.multi_line);
+    w.bool(config.collect_errors);
+    w.option(config.engine, |w, engine| {
+        w.u8(match engine {
+            Engine::Literal => 0,
+            Engine::Backtrack => 1,
+            Engine::PikeVm => 2,
+            Engine::Dfa => 3,
+        })
+    });
+    w.len(config.disabled.len());
+    for syntax in &config.disabled {
+        w.u8(match syntax {
+            Syntax::Alternation => 0,
+            Syntax::Group => 1,
+            Syntax::CountedRepetition => 2,
+            Syntax::NestedRepetition => 3,
+            Syntax::UnboundedRepetition => 4,
+            Syntax::Look => 5,
+        });
+    }
+    w.option(config.class_limit, |w, limit| w.u64(limit as u64));
+    w.bool(config.lenient);
+}
+
+/// [`write_config`] で書いた設定を読む
+fn read_config(r: &mut Reader) -> Result<Config, DecodeError> {
+    Ok(Config {
+        step_limit: r.option(Reader::u64)?,
+        unroll_limit: r.option(Reader::u32)?,
+        nest_limit: r.option(Reader::u32)?,
+        size_limit: r.option(|r| r.u64().map(saturate))?,
+        pattern_limit: r.option(|r| r.u64().map(saturate))?,
+        ascii: r.bool()?,
+        multi_line: r.bool()?,
+        collect_errors: r.bool()?,
+        prefilter: None,
+        engine: r.option(|r| match r.u8()? {
+            0 => Ok(Engine::Literal),
+            1 => Ok(Engine::Backtrack),
+            2 => Ok(Engine::PikeVm),
+            3 => Ok(Engine::Dfa),
+            _ => Err(DecodeError::Invalid),
+        })?,
+        disabled: (0..r.len(1)?)
+            .map(|_| match r.u8()? {
+                0 => Ok(Syntax::Alternation),
+                1 => Ok(Syntax::Group),
+                2 => Ok(Syntax::CountedRepetition),
+                3 => Ok(Syntax::NestedRepetition),
+                4 => Ok(Syntax::UnboundedRepetition),
+                5 => Ok(Syntax::Look),
+                _ => Err(DecodeError::Invalid),
+            })
+            .collect::<Result<_, _>>()?,
+        class_limit: r.option(|r| r.u64().map(saturate))?,
+        lenient: r.bool()?,
+    })
+}
+
+/// 8バイトの整数を usize に変換する。収まらない場合は usize の最大値にする
+fn saturate(value: u64) -> usize {
+    usize::try_from(value).unwrap_or(usize::MAX)
+}
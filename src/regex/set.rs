@@ -0,0 +1,263 @@
+//! 複数のパターンを1つの命令列にまとめ、いずれかにマッチする位置を1回の走査で探す
+use super::{DEFAULT_SIZE_LIMIT, FindMatches, Match, Regex, RegexBuilder};
+use crate::{
+    engine::{Instruction, Program, codegen::CodeGenError, evaluator, parser::DEFAULT_NEST_LIMIT},
+    error::Error,
+};
+use std::sync::Arc;
+
+/// 設定を指定して [`RegexSet`] を構築するビルダー
+///
+/// 設定は [`RegexSetBuilder::options`] が返す [`RegexBuilder`] のメソッドで指定し、
+/// すべてのパターンに適用する。パターンの長さの上限はパターンごとに確かめ、
+/// 命令数の上限はまとめた命令列にも適用する。前処理は、まとめた命令列での検索に利用する
+#[derive(Debug, Clone)]
+pub struct RegexSetBuilder {
+    patterns: Vec<String>,
+    options: RegexBuilder, // パターンを除く設定。パターンは利用しない
+}
+
+impl RegexSetBuilder {
+    /// パターンの列を指定してビルダーを生成する
+    pub fn new<I, S>(patterns: I) -> RegexSetBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RegexSetBuilder {
+            patterns: patterns
+                .into_iter()
+                .map(|p| p.as_ref().to_string())
+                .collect(),
+            options: RegexBuilder::new(""),
+        }
+    }
+
+    /// すべてのパターンに適用する設定
+    ///
+    /// ```
+    /// # use regexp::RegexSetBuilder;
+    /// let mut builder = RegexSetBuilder::new(["a+", "b"]);
+    /// builder.options().ascii(true).step_limit(1000);
+    /// let set = builder.build().unwrap();
+    /// assert_eq!(set.matches("xb"), vec![1]);
+    /// ```
+    pub fn options(&mut self) -> &mut RegexBuilder {
+        &mut self.options
+    }
+
+    /// 各パターンをコンパイルし、すべてのパターンを1つの命令列にまとめる
+    ///
+    /// パターンのコンパイルに失敗した場合は、そのパターンの番号と元のエラーを
+    /// [`Error::Set`] で返す。まとめた命令列が大きすぎる場合は [`Error::CodeGen`] を返す
+    pub fn build(&self) -> Result<RegexSet, Error> {
+        let config = &self.options.config;
+        let regexes = self
+            .patterns
+            .iter()
+            .enumerate()
+            .map(|(i, pattern)| {
+                Regex::with_config(pattern, config.clone()).map_err(|e| Error::Set(i, Box::new(e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // パターンの長さと入れ子の深さは各パターンで確かめたため、まとめたパターンでは緩める
+        let mut union_config = config.clone();
+        union_config.pattern_limit = None;
+        union_config.nest_limit = Some(config.nest_limit.unwrap_or(DEFAULT_NEST_LIMIT) + 1);
+        let regex = Regex::with_config(&union(&self.patterns), union_config)?;
+
+        let size_limit = config.size_limit.unwrap_or(DEFAULT_SIZE_LIMIT);
+        let program = set_program(&regexes)
+            .filter(|program| program.prog.insts.len() <= size_limit)
+            .ok_or(CodeGenError::CompiledTooBig)?;
+        Ok(RegexSet {
+            regex,
+            regexes,
+            program: Arc::new(program),
+        })
+    }
+}
+
+/// 各パターンを非捕獲グループで囲み、選択でつないだパターン
+///
+/// パターン中のフラグの指定はグループの中に限られる。末尾の対になっていない `\` は
+/// 読み飛ばされるため、グループの `)` をエスケープしないよう取り除く。
+/// パターンがない場合は、どの文字列にもマッチしないパターンにする
+fn union(patterns: &[String]) -> String {
+    if patterns.is_empty() {
+        return r"[^\s\S]".to_string();
+    }
+    let groups: Vec<String> = patterns
+        .iter()
+        .map(|pattern| {
+            let backslashes = pattern.bytes().rev().take_while(|&b| b == b'\\').count();
+            let pattern = &pattern[..pattern.len() - backslashes % 2];
+            format!("(?:{pattern})")
+        })
+        .collect();
+    groups.join("|")
+}
+
+/// [`RegexSet::matches`] で利用する、各パターンの命令列を並べた命令列
+#[derive(Debug)]
+struct SetProgram {
+    prog: Program,    // 先頭で各パターンへ分岐し、パターンごとに match 命令を持つ命令列
+    starts: Vec<u32>, // 各パターンの命令列の先頭のアドレス
+}
+
+/// 各パターンの命令列を並べ、パターンごとに match 命令を持つ1つの命令列にする
+///
+/// 先頭の split の列で各パターンの命令列の先頭へ分岐する。状態の集合を進める評価器で実行するため、
+/// 繰り返しを展開した命令列を並べる。展開した命令列が命令数の上限を超えるパターンがある場合と、
+/// 命令数が u32 に収まらない場合は `None` を返す
+fn set_program(regexes: &[Regex]) -> Option<SetProgram> {
+    if regexes.iter().any(|regex| !regex.inner.unrolled_fits) {
+        return None;
+    }
+    let n = regexes.len() as u32;
+    let mut prog = Program::default();
+    let mut starts = Vec::new();
+    let mut base = n;
+    for regex in regexes {
+        starts.push(base);
+        base = base.checked_add(u32::try_from(regex.set_prog().insts.len()).ok()?)?;
+    }
+    for i in 0..n {
+        let inst = if i + 1 < n {
+            Instruction::Split(starts[i as usize], i + 1)
+        } else {
+            Instruction::Jump(starts[i as usize])
+        };
+        prog.insts.push(inst);
+    }
+    for (regex, &start) in regexes.iter().zip(&starts) {
+        let part = regex.set_prog();
+        let classes = prog.classes.len() as u32;
+        let repeats = prog.repeats.len() as u32;
+        let marks = prog.marks as u32;
+        prog.insts.extend(part.insts.iter().map(|&inst| match inst {
+            Instruction::Class(idx) => Instruction::Class(idx + classes),
+            Instruction::Jump(addr) => Instruction::Jump(addr + start),
+            Instruction::Split(addr1, addr2) => Instruction::Split(addr1 + start, addr2 + start),
+            Instruction::RepeatStart(idx) => Instruction::RepeatStart(idx + repeats),
+            Instruction::Repeat(idx, addr) => Instruction::Repeat(idx + repeats, addr + start),
+            Instruction::RepeatEnd(idx, addr) => {
+                Instruction::RepeatEnd(idx + repeats, addr + start)
+            }
+            Instruction::Mark(slot) => Instruction::Mark(slot + marks),
+            Instruction::Progress(slot) => Instruction::Progress(slot + marks),
+            Instruction::Again(slot) => Instruction::Again(slot + marks),
+            inst => inst,
+        }));
+        prog.classes.extend(part.classes.iter().cloned());
+        prog.repeats.extend(&part.repeats);
+        prog.marks += part.marks;
+    }
+    Some(SetProgram { prog, starts })
+}
+
+/// 複数のパターンをまとめてコンパイルした正規表現
+///
+/// すべてのパターンを選択でつないだ1つの命令列で検索するため、
+/// パターンの数によらず検索対象を1回走査するだけでいずれかのマッチを見つけられる。
+/// マッチは、選択でつないだパターンと同じく最も左にあるものを、同じ位置では先に指定した
+/// パターンを優先して選ぶ。捕獲グループの番号は、前のパターンのグループから続けて振る
+#[derive(Debug, Clone)]
+pub struct RegexSet {
+    regex: Regex,             // すべてのパターンを選択でつないだ正規表現
+    regexes: Vec<Regex>,      // 個々のパターン
+    program: Arc<SetProgram>, // 各パターンの命令列を並べた命令列
+}
+
+impl RegexSet {
+    /// 既定の設定で各パターンをコンパイルする
+    ///
+    /// 失敗した場合のエラーは [`RegexSetBuilder::build`] と同じ
+    pub fn new<I, S>(patterns: I) -> Result<RegexSet, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RegexSetBuilder::new(patterns).build()
+    }
+
+    /// パターンの数
+    pub fn len(&self) -> usize {
+        self.regexes.len()
+    }
+
+    /// パターンがないか
+    pub fn is_empty(&self) -> bool {
+        self.regexes.is_empty()
+    }
+
+    /// 指定した順に、元のパターン文字列を返すイテレータを生成する
+    pub fn patterns(&self) -> impl Iterator<Item = &str> + '_ {
+        self.regexes.iter().map(Regex::as_str)
+    }
+
+    /// すべてのパターンを選択でつないだ正規表現
+    ///
+    /// 捕獲グループの位置を求めるなど、[`Regex`] のメソッドで検索する場合に利用する
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    /// `haystack` がいずれかのパターンにマッチするかを判定する
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.regex.is_match(haystack)
+    }
+
+    /// `haystack` 中で、いずれかのパターンにマッチする最も左の範囲を返す
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        self.regex.find(haystack)
+    }
+
+    /// `haystack` 中で、いずれかのパターンにマッチする重なり合わない範囲を左から順に返す
+    /// イテレータを生成する
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合は、イテレータがパニックする
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> FindMatches<'r, 'h> {
+        self.regex.find_iter(haystack)
+    }
+
+    /// `haystack` 中のどこかにマッチするパターンの番号を、小さい順に返す
+    ///
+    /// パターンごとに match 命令を持つ命令列で、すべての位置から始まるマッチを同時に進めるため、
+    /// パターンの数によらず検索対象を1回走査するだけで済む。
+    /// すべてのパターンのマッチが見つかった時点で走査を終える
+    ///
+    /// # Panics
+    ///
+    /// 命令数の上限を超えた場合はパニックする
+    pub fn matches(&self, haystack: &str) -> Vec<usize> {
+        if self.regexes.is_empty() {
+            return Vec::new();
+        }
+        let SetProgram { prog, starts } = &*self.program;
+        let mut fuel = self.regex.inner.config.step_limit.unwrap_or(u64::MAX);
+        let found = self
+            .regex
+            .with_cache(|cache| evaluator::eval_pike_all(prog, haystack, &mut fuel, cache));
+        let mut matched: Vec<usize> = match found {
+            Ok(pcs) => pcs
+                .iter()
+                .map(|&pc| starts.partition_point(|&start| start <= pc) - 1)
+                .collect(),
+            Err(e) => panic!("{e}"),
+        };
+        matched.sort_unstable();
+        matched
+    }
+}
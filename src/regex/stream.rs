@@ -0,0 +1,311 @@
+//! 任意の大きさに区切って与えられるバイト列を、区切りをまたいで検索する
+use super::Regex;
+use crate::engine::{Instruction, evaluator, look::Context};
+
+/// ストリーム中のマッチの範囲を表す型
+///
+/// 位置はストリームの先頭からのバイトオフセットで表す
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamMatch {
+    start: u64,
+    end: u64,
+}
+
+impl StreamMatch {
+    /// マッチの開始位置
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// マッチの終了位置
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+}
+
+/// チャンクを順に与えて検索するための状態
+///
+/// 取りうる状態の集合と、チャンクの末尾で途切れた UTF-8 の文字だけを保持するため、
+/// 与えたチャンクをまとめてバッファに溜めることはない。
+/// マッチの終了位置が見つかるたびに、その位置で終わるマッチのうち最も左の開始位置と組にして報告する。
+/// そのため `a+` のようなパターンでは、同じ開始位置のマッチが終了位置ごとに報告される。
+/// 空文字列へのマッチは報告しない
+///
+/// 不正な UTF-8 のバイト列はどの文字にもマッチしない1文字として扱う
+///
+/// `\b` のような位置の判定は次の文字を読むまで行えないため、判定を含むマッチは
+/// 次の文字を読んだ時点で報告する。ストリームの末尾での判定は [`StreamState::finish`] で行う
+#[derive(Debug, Clone)]
+pub struct StreamState {
+    regex: Regex,
+    pos: u64,           // 次の文字の開始位置
+    prev: Option<char>, // 直前の文字。不正なバイト列は U+FFFD として扱う
+    deferred: bool,     // pos で終わるマッチの報告を、位置の判定の後に回しているか
+    pending: Vec<u8>,   // 前のチャンクの末尾で途切れた文字のバイト列
+    clist: Vec<u32>,    // 現在の状態集合
+    starts: Vec<u64>,   // clist の各スレッドのマッチの開始位置
+    nlist: Vec<u32>,    // 次の状態集合
+    nstarts: Vec<u64>,  // nlist の各スレッドのマッチの開始位置
+    seen: Vec<bool>,    // 状態集合に追加済みの命令
+    closure: Vec<u32>,  // jump と split をたどるための作業用スタック
+}
+
+impl Regex {
+    /// ストリームの先頭から検索を始める [`StreamState`] を生成する
+    ///
+    /// # Panics
+    ///
+    /// 状態の集合を進めて検索するため、回数を数える繰り返しを展開した命令列が
+    /// 命令数の上限を超える場合はパニックする
+    pub fn stream(&self) -> StreamState {
+        let mut state = StreamState {
+            regex: self.clone(),
+            pos: 0,
+            prev: None,
+            deferred: false,
+            pending: Vec::new(),
+            clist: Vec::new(),
+            starts: Vec::new(),
+            nlist: Vec::new(),
+            nstarts: Vec::new(),
+            seen: vec![false; self.set_prog().insts.len()],
+            closure: Vec::new(),
+        };
+        state.start_thread();
+        state
+    }
+}
+
+impl StreamState {
+    /// これまでに与えたバイト数
+    pub fn offset(&self) -> u64 {
+        self.pos + self.pending.len() as u64
+    }
+
+    /// 状態を捨て、新しいストリームの先頭から検索し直す
+    pub fn reset(&mut self) {
+        self.pos = 0;
+        self.prev = None;
+        self.deferred = false;
+        self.pending.clear();
+        self.clist.clear();
+        self.starts.clear();
+        self.seen.fill(false);
+        self.start_thread();
+    }
+
+    /// 次のチャンクを与え、このチャンクの中で終わるマッチを返す
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<StreamMatch> {
+        let mut matches = Vec::new();
+        let mut rest = chunk;
+
+        // 前のチャンクで途切れた文字を、このチャンクの先頭のバイトで補って読む
+        if !self.pending.is_empty() {
+            let n = (4 - self.pending.len()).min(rest.len());
+            let mut buf = self.pending.clone();
+            buf.extend_from_slice(&rest[..n]);
+            let (c, len) = match std::str::from_utf8(&buf) {
+                Ok(s) => (s.chars().next(), s.chars().next().map_or(0, char::len_utf8)),
+                Err(e) if e.valid_up_to() > 0 => {
+                    let c = std::str::from_utf8(&buf[..e.valid_up_to()])
+                        .ok()
+                        .and_then(|s| s.chars().next());
+                    (c, c.map_or(0, char::len_utf8))
+                }
+                Err(e) => match e.error_len() {
+                    Some(len) => (None, len),
+                    // まだ文字が完成しないため、次のチャンクを待つ
+                    None => {
+                        self.pending = buf;
+                        return matches;
+                    }
+                },
+            };
+            rest = &rest[len - self.pending.len()..];
+            self.pending.clear();
+            self.step(c, len, &mut matches);
+        }
+
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    self.step_str(s, &mut matches);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, tail) = rest.split_at(e.valid_up_to());
+                    // valid_up_to までは正しい UTF-8 であることが保証されている
+                    if let Ok(s) = std::str::from_utf8(valid) {
+                        self.step_str(s, &mut matches);
+                    }
+                    match e.error_len() {
+                        Some(len) => {
+                            self.step(None, len, &mut matches);
+                            rest = &tail[len..];
+                        }
+                        None => {
+                            self.pending.extend_from_slice(tail);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        matches
+    }
+
+    /// ストリームの末尾に達したことを伝え、ストリームの末尾で終わるマッチを返す
+    ///
+    /// チャンクの末尾で途切れたままの文字は、不正なバイト列として読み進める。
+    /// 続けて別のストリームを検索する場合は [`StreamState::reset`] を呼び出す
+    pub fn finish(&mut self) -> Vec<StreamMatch> {
+        let mut matches = Vec::new();
+        if !self.pending.is_empty() {
+            let len = self.pending.len();
+            self.pending.clear();
+            self.step(None, len, &mut matches);
+        }
+        self.expand(None, &mut matches);
+        matches
+    }
+
+    /// 正しい UTF-8 の文字列を1文字ずつ読み進める
+    fn step_str(&mut self, s: &str, matches: &mut Vec<StreamMatch>) {
+        for c in s.chars() {
+            self.step(Some(c), c.len_utf8(), matches);
+        }
+    }
+
+    /// `len` バイトの1文字を読み進める。`c` が `None` の場合は不正なバイト列を表す
+    ///
+    /// 状態集合は開始位置の昇順に並べるため、同じ命令に複数の開始位置から到達した場合は
+    /// 先に追加した最も左の開始位置が残る
+    fn step(&mut self, c: Option<char>, len: usize, matches: &mut Vec<StreamMatch>) {
+        let next = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+        self.expand(Some(next), matches);
+
+        let prog = self.regex.set_prog();
+        self.seen.fill(false);
+        self.nlist.clear();
+        self.nstarts.clear();
+        if let Some(c) = c {
+            for (&pc, &start) in self.clist.iter().zip(&self.starts) {
+                let accept = match prog.insts[pc as usize] {
+                    Instruction::Char(x) => x == c,
+                    Instruction::Class(idx) => prog.class_contains(idx, c),
+                    _ => false,
+                };
+                if accept {
+                    evaluator::add_thread(
+                        prog,
+                        &mut self.nlist,
+                        &mut self.seen,
+                        &mut self.closure,
+                        None,
+                        pc + 1,
+                    );
+                    self.nstarts.resize(self.nlist.len(), start);
+                }
+            }
+        }
+        std::mem::swap(&mut self.clist, &mut self.nlist);
+        std::mem::swap(&mut self.starts, &mut self.nstarts);
+        self.pos += len as u64;
+        self.prev = Some(next);
+
+        // 位置の判定が残っている場合は、次の文字を読んでからマッチを報告する
+        if self.has_looks() {
+            self.deferred = true;
+        } else {
+            self.report(matches);
+        }
+
+        self.start_thread();
+    }
+
+    /// 状態集合に残した位置の判定を、次の文字 `next` を使って行う
+    ///
+    /// 判定を満たしたスレッドは元の優先度の位置で先に進め、報告を後回しにしたマッチを報告する。
+    /// `next` が `None` の場合はストリームの末尾を表す
+    fn expand(&mut self, next: Option<char>, matches: &mut Vec<StreamMatch>) {
+        if !self.has_looks() {
+            return;
+        }
+        let prog = self.regex.set_prog();
+        let ctx = Context {
+            prev: self.prev,
+            next,
+        };
+        self.seen.fill(false);
+        self.nlist.clear();
+        self.nstarts.clear();
+        for (&pc, &start) in self.clist.iter().zip(&self.starts) {
+            match prog.insts[pc as usize] {
+                Instruction::Look(look) if look.matches(ctx) => {
+                    evaluator::add_thread(
+                        prog,
+                        &mut self.nlist,
+                        &mut self.seen,
+                        &mut self.closure,
+                        Some(ctx),
+                        pc + 1,
+                    );
+                }
+                Instruction::Look(_) => (),
+                _ if !self.seen[pc as usize] => {
+                    self.seen[pc as usize] = true;
+                    self.nlist.push(pc);
+                }
+                _ => (),
+            }
+            self.nstarts.resize(self.nlist.len(), start);
+        }
+        std::mem::swap(&mut self.clist, &mut self.nlist);
+        std::mem::swap(&mut self.starts, &mut self.nstarts);
+
+        if std::mem::take(&mut self.deferred) {
+            self.report(matches);
+        }
+    }
+
+    /// 現在の位置で終わるマッチのうち、最も左の開始位置から始まるものを報告する
+    ///
+    /// 現在の位置から始まる空文字列へのマッチは報告しない
+    fn report(&self, matches: &mut Vec<StreamMatch>) {
+        let prog = self.regex.set_prog();
+        if let Some(i) = self
+            .clist
+            .iter()
+            .position(|&pc| prog.insts[pc as usize] == Instruction::Match)
+            && self.starts[i] < self.pos
+        {
+            matches.push(StreamMatch {
+                start: self.starts[i],
+                end: self.pos,
+            });
+        }
+    }
+
+    /// 状態集合に位置の判定が残っているかを判定する
+    fn has_looks(&self) -> bool {
+        let prog = self.regex.set_prog();
+        self.clist
+            .iter()
+            .any(|&pc| matches!(prog.insts[pc as usize], Instruction::Look(_)))
+    }
+
+    /// 現在の位置から始まるスレッドを状態集合に追加する
+    fn start_thread(&mut self) {
+        let prog = self.regex.set_prog();
+        evaluator::add_thread(
+            prog,
+            &mut self.clist,
+            &mut self.seen,
+            &mut self.closure,
+            None,
+            0,
+        );
+        self.starts.resize(self.clist.len(), self.pos);
+    }
+}
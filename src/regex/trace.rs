@@ -0,0 +1,86 @@
+//! 評価器が実行した命令を1命令ずつ記録する
+use super::{Match, Regex};
+use crate::engine::{
+    Program,
+    evaluator::{self, EvalError, Tracer},
+};
+
+/// [`Regex::trace`] で評価器が実行した1命令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    pub thread: usize, // 命令を実行した経路の番号。0 から数える
+    pub pc: usize,     // 命令のアドレス
+    pub inst: String,  // 命令。文字クラスは番号で表す
+    pub pos: usize,    // 命令を実行した入力の位置（バイト）
+}
+
+/// [`Regex::trace`] の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace<'h> {
+    pub steps: Vec<TraceStep>,                        // 実行した順の命令
+    pub result: Result<Option<Match<'h>>, EvalError>, // 見つけたマッチ。上限に達した場合はエラー
+}
+
+/// 実行した命令を [`TraceStep`] として集める
+struct Recorder<'p> {
+    prog: &'p Program,
+    steps: Vec<TraceStep>,
+    threads: usize, // これまでに始めた経路の数
+}
+
+impl Tracer for Recorder<'_> {
+    fn thread(&mut self) {
+        self.threads += 1;
+    }
+
+    fn step(&mut self, pc: u32, sp: usize) {
+        self.steps.push(TraceStep {
+            thread: self.threads - 1,
+            pc: pc as usize,
+            inst: self.prog.insts[pc as usize].to_string(),
+            pos: sp,
+        });
+    }
+}
+
+impl Regex {
+    /// `haystack` 中で最も左にあるマッチを探し、評価器が実行した命令を順に記録する
+    ///
+    /// 前処理や DFA は使わず、[`Regex::program`] の命令列をバックトラック型の評価器で
+    /// 先頭の位置から順に評価する。分岐からやり直すたびに、新しい番号の経路として記録する。
+    /// `limit` は実行する命令数の上限で、`None` の場合は [`RegexBuilder::step_limit`] に従う。
+    /// 上限に達した場合は、それまでの記録と [`EvalError::StepLimitExceeded`] を返す
+    ///
+    /// [`RegexBuilder::step_limit`]: super::RegexBuilder::step_limit
+    pub fn trace<'h>(&self, haystack: &'h str, limit: Option<u64>) -> Trace<'h> {
+        let prog = &self.inner.prog;
+        let mut fuel = limit.or(self.inner.config.step_limit).unwrap_or(u64::MAX);
+        let mut recorder = Recorder {
+            prog,
+            steps: Vec::new(),
+            threads: 0,
+        };
+        let result = self.with_cache(|cache| {
+            let mut pos = 0;
+            loop {
+                let end =
+                    evaluator::eval_traced(prog, haystack, pos, &mut fuel, cache, &mut recorder)?;
+                if let Some(end) = end {
+                    return Ok(Some(Match {
+                        haystack,
+                        start: pos,
+                        end,
+                    }));
+                }
+                let Some(c) = haystack[pos..].chars().next() else {
+                    return Ok(None);
+                };
+                pos += c.len_utf8();
+            }
+        });
+        Trace {
+            steps: recorder.steps,
+            result,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! バックトラックの回数が急激に増えうる構造を、解析器が報告することを確かめる
+use regexp::{Reason, Severity, analyze};
+
+/// パターンと、最も深刻な問題の深刻さ
+const CASES: &[(&str, Option<Severity>)] = &[
+    ("(a+)+", Some(Severity::Exponential)),
+    ("(a|ab)*", Some(Severity::Exponential)),
+    ("(a+b)*", Some(Severity::Polynomial)),
+    (r"\d+\d*", Some(Severity::Polynomial)),
+    ("(a{1,2})*$", Some(Severity::Exponential)),
+    ("(([^a]){0,2})*$", Some(Severity::Exponential)),
+    ("(a|aa){0,40}c", Some(Severity::Exponential)),
+    ("(a?){0,1000}c", Some(Severity::Exponential)),
+    ("(ab{2,3})*", Some(Severity::Polynomial)),
+    ("(a?)*", None),
+    ("(a{0,1})*", None),
+    ("a{2,40}b", None),
+    ("(ab)+c", None),
+];
+
+#[test]
+fn severity_of_each_pattern() {
+    for &(pattern, severity) in CASES {
+        let report = analyze(pattern).unwrap();
+        assert_eq!(report.severity(), severity, "{pattern}");
+    }
+}
+
+#[test]
+fn bounded_repeat_is_reported_with_its_span() {
+    let report = analyze("x(a|aa){0,40}c").unwrap();
+    assert_eq!(report.findings.len(), 1);
+    let finding = &report.findings[0];
+    assert_eq!(finding.reason, Reason::OverlappingAlternation);
+    assert_eq!(finding.span, 1..13);
+
+    let report = analyze("(a{1,2})*").unwrap();
+    assert_eq!(report.findings[0].reason, Reason::NestedQuantifier);
+    assert_eq!(report.findings[0].span, 0..9);
+}
@@ -0,0 +1,74 @@
+//! `^` で始まるパターンが、行の先頭でだけ続くリテラルを比べて探され、regex クレートと同じマッチを返すことを確かめる
+use regexp::{Engine, RegexBuilder};
+
+const HAYSTACK: &str = "abc12 abc\nabc3\nxabc\nab\nabc";
+
+#[test]
+fn anchored_matches_like_regex() {
+    for pattern in [
+        "^abc",
+        "^abc[0-9]+",
+        "^ab(c|x)",
+        "^abc$",
+        "^",
+        "(?m)^abc",
+        "(?m)^abc[0-9]+",
+        "(?m)^ab$",
+        "(?m)^",
+        "(?m)^x?abc",
+    ] {
+        let expected: Vec<_> = regex::Regex::new(pattern)
+            .unwrap()
+            .find_iter(HAYSTACK)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        for engine in [Engine::Backtrack, Engine::PikeVm, Engine::Dfa] {
+            let regex = RegexBuilder::new(pattern).engine(engine).build().unwrap();
+            let found: Vec<_> = regex
+                .find_iter(HAYSTACK)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            assert_eq!(found, expected, "{pattern:?} [{engine:?}]");
+        }
+    }
+}
+
+#[test]
+fn anchored_literal_runs_no_instructions() {
+    // `^` とリテラルだけのパターンは、先頭の比較だけで決まる
+    let regex = RegexBuilder::new("^needle").build().unwrap();
+    let haystack = format!("needle{}", " hay".repeat(1000));
+    let (m, stats) = regex.find_with_stats(&haystack);
+    assert_eq!(m.unwrap().unwrap().start(), 0);
+    assert_eq!(stats.steps, 0);
+
+    let haystack = format!("{}needle", "hay ".repeat(1000));
+    let (m, stats) = regex.find_with_stats(&haystack);
+    assert_eq!(m, Ok(None));
+    assert_eq!(stats.steps, 0);
+}
+
+#[test]
+fn anchored_prefix_skips_other_positions() {
+    // 先頭がリテラルと一致しなければ、命令列を評価しない
+    let regex = RegexBuilder::new("^ab[0-9]+")
+        .engine(Engine::Backtrack)
+        .build()
+        .unwrap();
+    let haystack = format!("x{}", "ab1".repeat(1000));
+    let (m, stats) = regex.find_with_stats(&haystack);
+    assert_eq!(m, Ok(None));
+    assert_eq!(stats.steps, 0);
+
+    // 複数行モードでは、各行の先頭だけで評価する
+    let regex = RegexBuilder::new("^ab[0-9]+")
+        .multi_line(true)
+        .engine(Engine::Backtrack)
+        .build()
+        .unwrap();
+    let haystack = format!("{}\nab12", "xab1".repeat(1000));
+    let (m, stats) = regex.find_with_stats(&haystack);
+    let m = m.unwrap().unwrap();
+    assert_eq!(m.as_str(), "ab12");
+    assert!(stats.steps < 20, "{}", stats.steps);
+}
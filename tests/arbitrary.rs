@@ -0,0 +1,68 @@
+//! 任意のバイト列から生成した AST が構造として正しく、書き戻したパターンをパースし直せることを確かめる
+#![cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
+use regexp::{AST, AstNode, Flags, NodeId, RegexBuilder, Visitor, parse, walk};
+
+/// 種から決まる疑似乱数のバイト列
+fn bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+/// 捕獲グループの番号を、開き括弧の順に集める
+struct Check {
+    groups: Vec<u32>,
+}
+
+impl Visitor for Check {
+    fn visit(&mut self, ast: &AST, id: NodeId) {
+        if let AstNode::Group(_, index) = ast[id] {
+            self.groups.push(index);
+        }
+        walk(self, ast, id);
+    }
+}
+
+#[test]
+fn generated_asts_are_well_formed() {
+    let mut nonempty = 0;
+    for seed in 0..2000 {
+        let data = bytes(seed, 256);
+        let ast = AST::arbitrary(&mut Unstructured::new(&data)).unwrap();
+
+        // 捕獲グループには、開き括弧の順に 1 から番号を付ける
+        let mut check = Check { groups: Vec::new() };
+        check.visit(&ast, ast.root());
+        let expected: Vec<_> = (1..=ast.groups()).collect();
+        assert_eq!(check.groups, expected, "seed {seed}");
+        assert!(ast.stats().depth <= 8, "seed {seed}");
+
+        let pattern = ast.to_string();
+        if pattern.is_empty() {
+            continue;
+        }
+        nonempty += 1;
+        let reparsed = parse(&pattern, Flags::default())
+            .unwrap_or_else(|e| panic!("seed {seed}: {pattern:?}: {e}"));
+        assert_eq!(reparsed.to_string(), pattern, "seed {seed}");
+        // 命令数の上限を超えることはあっても、パースのエラーにはならない
+        if let Err(e) = RegexBuilder::new(&pattern).size_limit(1 << 16).build() {
+            assert!(matches!(e, regexp::Error::CodeGen(_)), "seed {seed}: {e}");
+        }
+    }
+    assert!(nonempty > 1000, "{nonempty}");
+}
+
+#[test]
+fn empty_input_gives_a_leaf() {
+    let ast = AST::arbitrary(&mut Unstructured::new(&[])).unwrap();
+    assert_eq!(ast.node_count(), 1);
+    assert_eq!(ast.groups(), 0);
+}
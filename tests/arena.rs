@@ -0,0 +1,63 @@
+//! アリーナに格納した AST が、パースしたノードをすべて根からたどれる形で持ち、深い木でもスタックをあふれさせないことを確かめる
+use regexp::{AST, AstNode, Flags, NodeId, parse};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// ノードの子
+fn children(node: &AstNode) -> Vec<NodeId> {
+    match node {
+        AstNode::Char(_) | AstNode::Class(_) | AstNode::Look(_) => vec![],
+        AstNode::Plus(e)
+        | AstNode::Star(e)
+        | AstNode::Question(e)
+        | AstNode::Repeat(e, ..)
+        | AstNode::Group(e, _) => vec![*e],
+        AstNode::Or(e1, e2) => vec![*e1, *e2],
+        AstNode::Seq(v) => v.clone(),
+        node => panic!("unknown node {node:?}"),
+    }
+}
+
+#[test]
+fn all_nodes_are_reachable() {
+    for pattern in ["a(b|c)*d", "((a)|[b-c]{2,3})+?", "x|y|z", "^(?:ab)+$"] {
+        let ast = parse(pattern, Flags::default()).unwrap();
+        let mut count = 0;
+        let mut stack = vec![ast.root()];
+        while let Some(id) = stack.pop() {
+            count += 1;
+            stack.extend(children(&ast[id]));
+        }
+        assert_eq!(count, ast.node_count(), "{pattern:?}");
+    }
+}
+
+/// 100 万段の入れ子でも、複製、比較、ハッシュ、破棄が再帰しない
+#[test]
+fn deep_tree() {
+    let mut ast = AST::new();
+    let mut id = ast.push_span(AstNode::Char('a'), 0..1);
+    for _ in 0..1_000_000 {
+        id = ast.push_span(AstNode::Star(id), 0..1);
+    }
+    ast.set_root(id);
+
+    let copy = ast.clone();
+    assert_eq!(copy, ast);
+    let hash = |ast: &AST| {
+        let mut hasher = DefaultHasher::new();
+        ast.hash(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash(&copy), hash(&ast));
+    drop(copy);
+    drop(ast);
+}
+
+#[test]
+fn long_pattern() {
+    let pattern = "ab".repeat(50_000);
+    let ast = parse(&pattern, Flags::default()).unwrap();
+    // 文字ごとのノードと、それらを並べた連接
+    assert_eq!(ast.node_count(), pattern.len() + 1);
+    assert!(matches!(&ast[ast.root()], AstNode::Seq(v) if v.len() == pattern.len()));
+}
@@ -0,0 +1,65 @@
+//! ASCII モードで `\d`、`\w`、`\s`、`\b` が ASCII 文字に限られることを、ビルダーとフラグの両方で確かめる
+use regexp::{Regex, RegexBuilder};
+
+const HAYSTACK: &str = "a1 ٣é_x\u{3000}y";
+
+fn found(regex: &Regex) -> Vec<&str> {
+    regex.find_iter(HAYSTACK).map(|m| m.as_str()).collect()
+}
+
+fn ascii(pattern: &str) -> Regex {
+    RegexBuilder::new(pattern).ascii(true).build().unwrap()
+}
+
+#[test]
+fn builder_limits_classes_to_ascii() {
+    assert_eq!(found(&Regex::new(r"\d+").unwrap()), ["1", "٣"]);
+    assert_eq!(found(&ascii(r"\d+")), ["1"]);
+
+    assert_eq!(found(&Regex::new(r"\w+").unwrap()), ["a1", "٣é_x", "y"]);
+    assert_eq!(found(&ascii(r"\w+")), ["a1", "_x", "y"]);
+
+    assert_eq!(found(&Regex::new(r"\s").unwrap()), [" ", "\u{3000}"]);
+    assert_eq!(found(&ascii(r"\s")), [" "]);
+
+    // ASCII モードでは é は単語を構成しないため、その前後も単語の境界になる
+    assert_eq!(found(&Regex::new(r"\b_").unwrap()), Vec::<&str>::new());
+    assert_eq!(found(&ascii(r"\b_")), ["_"]);
+}
+
+#[test]
+fn flag_limits_classes_to_ascii() {
+    assert_eq!(found(&Regex::new(r"(?a)\d+").unwrap()), ["1"]);
+    assert_eq!(found(&Regex::new(r"(?a)\w+").unwrap()), ["a1", "_x", "y"]);
+    assert_eq!(found(&Regex::new(r"(?a:\s)").unwrap()), [" "]);
+    assert_eq!(found(&Regex::new(r"(?a)\b_").unwrap()), ["_"]);
+
+    // フラグはグループの終わりまで有効になる
+    assert_eq!(found(&Regex::new(r"(?a:\d)|\d").unwrap()), ["1", "٣"]);
+    assert_eq!(found(&Regex::new(r"(?:(?a)\d)|٣").unwrap()), ["1", "٣"]);
+    // ビルダーで有効にしたものを、フラグで打ち消せる
+    assert_eq!(found(&ascii(r"(?-a)\d+")), ["1", "٣"]);
+}
+
+#[test]
+fn ascii_classes_match_regex_crate() {
+    let haystack: String = (0..0x100u32).filter_map(char::from_u32).collect();
+    for (ours, theirs) in [
+        (r"\d+", r"(?-u:\d)+"),
+        (r"\w+", r"(?-u:\w)+"),
+        (r"\s+", r"(?-u:\s)+"),
+        (r"\D+", r"[^0-9]+"),
+        (r"[\w\s]+", r"[\w\s&&\x00-\x7f]+"),
+    ] {
+        let found: Vec<_> = ascii(ours)
+            .find_iter(&haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        let want: Vec<_> = regex::Regex::new(theirs)
+            .unwrap()
+            .find_iter(&haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        assert_eq!(found, want, "{ours:?}");
+    }
+}
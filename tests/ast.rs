@@ -0,0 +1,69 @@
+//! AST の比較とハッシュが、パターン中の範囲やノードの並びによらず木の構造で行われることを確かめる
+use regexp::{AST, AstNode, Flags, parse};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+fn ast(pattern: &str) -> AST {
+    parse(pattern, Flags::default()).unwrap()
+}
+
+fn hash(ast: &AST) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    ast.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 同じ木になる書き方の組
+const EQUAL: &[(&str, &str)] = &[
+    ("[]a]", r"[\]a]"),
+    ("[abc]", "[a-c]"),
+    ("[cba]", "[a-c]"),
+    ("a{2}", "a{2,2}"),
+    (r"(?a)\d", "[0-9]"),
+    ("(a)|b", "(a)|b"),
+];
+
+/// 異なる木になるパターンの組
+const UNEQUAL: &[(&str, &str)] = &[
+    ("a|b", "b|a"),
+    ("(a)", "(?:a)"),
+    ("a*", "a+"),
+    ("a{2}", "a{2,}"),
+    ("ab", "a"),
+    ("(a)(b)", "(a)b"),
+];
+
+#[test]
+fn equivalent_spellings_are_equal() {
+    for &(a, b) in EQUAL {
+        assert_eq!(ast(a), ast(b), "{a} {b}");
+        assert_eq!(hash(&ast(a)), hash(&ast(b)), "{a} {b}");
+    }
+}
+
+#[test]
+fn different_trees_are_unequal() {
+    for &(a, b) in UNEQUAL {
+        assert_ne!(ast(a), ast(b), "{a} {b}");
+    }
+}
+
+/// 根からたどれないノードと、ノードを追加した順序は区別しない
+#[test]
+fn arena_layout_is_ignored() {
+    let mut built = AST::new();
+    let unused = built.push_span(AstNode::Char('z'), 0..0);
+    let b = built.push_span(AstNode::Char('b'), 0..0);
+    let a = built.push_span(AstNode::Char('a'), 0..0);
+    let star = built.push_span(AstNode::Star(b), 0..0);
+    let root = built.push_span(AstNode::Seq(vec![a, star]), 0..0);
+    built.set_root(root);
+    assert_ne!(unused, root);
+    assert_eq!(built, ast("ab*"));
+    assert_eq!(hash(&built), hash(&ast("ab*")));
+}
+
+#[test]
+fn empty_ast() {
+    assert_eq!(AST::new(), AST::new());
+    assert_ne!(AST::new(), ast("a"));
+}
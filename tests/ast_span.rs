@@ -0,0 +1,107 @@
+//! パースした AST の各ノードが、そのノードを表すパターン中の範囲を持つことを確かめる
+use regexp::{AST, AstNode, Flags, NodeId, parse};
+
+/// `id` 以下のノードを行きがけ順にたどり、ノードの種類と範囲の文字列を並べる
+fn spans<'p>(pattern: &'p str, ast: &AST, id: NodeId, out: &mut Vec<(&'static str, &'p str)>) {
+    let kind = match &ast[id] {
+        AstNode::Char(_) => "char",
+        AstNode::Class(_) => "class",
+        AstNode::Look(_) => "look",
+        AstNode::Plus(_) => "plus",
+        AstNode::Star(_) => "star",
+        AstNode::Question(_) => "question",
+        AstNode::Repeat(..) => "repeat",
+        AstNode::Group(..) => "group",
+        AstNode::Or(..) => "or",
+        AstNode::Seq(_) => "seq",
+        node => panic!("unexpected node {node:?}"),
+    };
+    out.push((kind, &pattern[ast.span(id)]));
+    match &ast[id] {
+        AstNode::Plus(e)
+        | AstNode::Star(e)
+        | AstNode::Question(e)
+        | AstNode::Repeat(e, _, _)
+        | AstNode::Group(e, _) => spans(pattern, ast, *e, out),
+        AstNode::Or(e1, e2) => {
+            spans(pattern, ast, *e1, out);
+            spans(pattern, ast, *e2, out);
+        }
+        AstNode::Seq(v) => {
+            for e in v {
+                spans(pattern, ast, *e, out);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn check(pattern: &str, expected: &[(&str, &str)]) {
+    let ast = parse(pattern, Flags::default()).unwrap();
+    let mut found = Vec::new();
+    spans(pattern, &ast, ast.root(), &mut found);
+    assert_eq!(found, expected, "{pattern:?}");
+}
+
+/// 選択肢やグループの中身は、要素が1つでも連接のノードになる
+#[test]
+fn nodes_point_back_to_the_pattern() {
+    check(
+        "a(bc)*|[x-z]+",
+        &[
+            ("or", "a(bc)*|[x-z]+"),
+            ("seq", "a(bc)*"),
+            ("char", "a"),
+            ("star", "(bc)*"),
+            ("group", "(bc)"),
+            ("seq", "bc"),
+            ("char", "b"),
+            ("char", "c"),
+            ("seq", "[x-z]+"),
+            ("plus", "[x-z]+"),
+            ("class", "[x-z]"),
+        ],
+    );
+    check(
+        r"^\d{2,3}?\b",
+        &[
+            ("seq", r"^\d{2,3}?\b"),
+            ("look", "^"),
+            ("question", r"\d{2,3}?"),
+            ("repeat", r"\d{2,3}"),
+            ("class", r"\d"),
+            ("look", r"\b"),
+        ],
+    );
+}
+
+#[test]
+fn spans_are_byte_ranges() {
+    check(
+        "日(本|語)+",
+        &[
+            ("seq", "日(本|語)+"),
+            ("char", "日"),
+            ("plus", "(本|語)+"),
+            ("group", "(本|語)"),
+            ("or", "本|語"),
+            ("seq", "本"),
+            ("char", "本"),
+            ("seq", "語"),
+            ("char", "語"),
+        ],
+    );
+}
+
+#[test]
+fn flags_and_escapes() {
+    check(
+        r"(?a:\w)\+",
+        &[
+            ("seq", r"(?a:\w)\+"),
+            ("seq", r"(?a:\w)"),
+            ("class", r"\w"),
+            ("char", r"\+"),
+        ],
+    );
+}
@@ -0,0 +1,68 @@
+//! AST の深さ、ノード数と構文ごとの出現回数を確かめる
+use regexp::{AST, AstStats, Flags, RegexBuilder, parse};
+
+fn ast(pattern: &str) -> AST {
+    parse(pattern, Flags::default()).unwrap()
+}
+
+#[test]
+fn census() {
+    let stats = ast(r"^a(b|c|\d)*x{2}?$").stats();
+    assert_eq!(
+        stats,
+        AstStats {
+            depth: stats.depth,
+            nodes: stats.nodes,
+            chars: 4,
+            classes: 1,
+            looks: 2,
+            plus: 0,
+            star: 1,
+            question: 1,
+            repeats: 1,
+            groups: 1,
+            // 3 個の選択肢は 2 個の OR になる
+            ors: 2,
+            seqs: stats.seqs,
+        }
+    );
+    assert_eq!(stats.quantifiers(), 3);
+    // パースした直後の AST のノードは、すべて根からたどれる
+    assert_eq!(stats.nodes, ast(r"^a(b|c|\d)*x{2}?$").node_count());
+}
+
+#[test]
+fn depth_agrees_with_nest_limit() {
+    for pattern in ["a", "(a)", "((a))+", "(?:a*)*", "a|(b|(c))", "x(y(z)+)*"] {
+        let depth = ast(pattern).stats().depth;
+        // 深さと同じ上限ではコンパイルでき、1つ小さい上限では入れ子が深すぎるエラーになる
+        assert!(
+            RegexBuilder::new(pattern).nest_limit(depth).build().is_ok(),
+            "{pattern:?}"
+        );
+        if depth > 0 {
+            assert!(
+                RegexBuilder::new(pattern)
+                    .nest_limit(depth - 1)
+                    .build()
+                    .is_err(),
+                "{pattern:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn deep_and_wide_patterns() {
+    // 再帰せずに数えるため、深い AST でもスタックはあふれない
+    let n = 200;
+    let deep = format!("{}a{}", "(".repeat(n), ")".repeat(n));
+    let stats = ast(&deep).stats();
+    assert_eq!(stats.groups, n);
+    assert_eq!(stats.depth as usize, n);
+
+    let wide = vec!["ab"; 10_000].join("|");
+    let stats = ast(&wide).stats();
+    assert_eq!(stats.ors, 9_999);
+    assert_eq!(stats.chars, 20_000);
+}
@@ -0,0 +1,238 @@
+//! AT&T の正規表現テストの `.dat` 形式のファイルを実行する
+//!
+//! 各行はタブで区切ったフラグ、パターン、検索対象の文字列、期待する結果からなる。
+//! 結果は最初のマッチと各グループの範囲を `(0,3)(1,2)` の形で、
+//! マッチしない場合は `NOMATCH`、パターンがエラーになる場合は `EPAREN` などのコードで書く。
+//! 拡張正規表現（フラグ `E`）の行のうち、このクレートで書き直せるものを実行し、
+//! 一致しなかった行が POSIX と結果の異なる既知の行だけであることを確かめる。
+//! 一致した数と読み飛ばした行は表示する
+//!
+//! POSIX は最も長いマッチを選ぶが、このクレートは選択肢と繰り返しの優先順位に従って選ぶため、
+//! 結果が異なる行がある
+use regexp::Regex;
+use std::{fs, path::Path};
+
+/// `testdata/att/sample.dat` で、POSIX の最長一致と結果が異なる行の行番号
+const SAMPLE_FAILED: &[usize] = &[
+    13, // (a|ab)(c|bcd) は最初の選択肢を選び、(0,4)(0,1)(1,4) になる
+];
+
+/// 実行した結果
+#[derive(Debug, Default)]
+struct Summary {
+    passed: usize,
+    failed: Vec<usize>,  // 一致しなかった行の行番号
+    skipped: Vec<usize>, // 対応しないフラグや構文を含む行の行番号
+}
+
+/// 期待する結果
+#[derive(Debug, PartialEq, Eq)]
+enum Expected {
+    Match(Vec<Option<(usize, usize)>>), // マッチ全体と各グループの範囲
+    NoMatch,
+    Error, // パターンがエラーになる
+}
+
+#[test]
+fn sample() {
+    let summary = run_file(&Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/att/sample.dat"));
+    println!(
+        "{} passed, {} failed, skipped lines {:?}",
+        summary.passed,
+        summary.failed.len(),
+        summary.skipped
+    );
+    assert_eq!(summary.failed, SAMPLE_FAILED, "failed lines");
+}
+
+/// ファイルのすべての行を実行する。一致しなかった行は、期待する結果と実際の結果を表示する
+fn run_file(path: &Path) -> Summary {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    let mut summary = Summary::default();
+    let mut previous = String::new(); // SAME と書いた場合に使う、直前の行のパターン
+    for (i, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split('\t').filter(|s| !s.is_empty()).collect();
+        if line.starts_with('#') || fields.len() < 4 {
+            continue;
+        }
+        // 先頭の `:名前:` は行の名前なので読み飛ばす
+        let flags = match fields[0].strip_prefix(':') {
+            Some(rest) => rest.split_once(':').map_or(rest, |(_, flags)| flags),
+            None => fields[0],
+        };
+        if !flags.contains(['B', 'E', 'A', 'S', 'K', 'L']) {
+            continue; // NOTE などの行
+        }
+        let pattern = match fields[1] {
+            "SAME" => previous.clone(),
+            pattern => pattern.to_string(),
+        };
+        previous = pattern.clone();
+
+        match run(flags, &pattern, fields[2], fields[3]) {
+            None => summary.skipped.push(i + 1),
+            Some(Ok(())) => summary.passed += 1,
+            Some(Err(found)) => {
+                summary.failed.push(i + 1);
+                let (haystack, expected) = (fields[2], fields[3]);
+                println!("{}:{}: {pattern:?} on {haystack:?}", path.display(), i + 1);
+                println!("    expected {expected}, found {found}");
+            }
+        }
+    }
+    summary
+}
+
+/// 1行を実行する。読み飛ばす場合は `None`、一致しない場合は実際の結果を返す
+fn run(flags: &str, pattern: &str, haystack: &str, result: &str) -> Option<Result<(), String>> {
+    // 拡張正規表現のうち、C のエスケープを展開する `$` 以外のフラグがないものに限る
+    if !flags.contains('E') || flags.chars().any(|c| !matches!(c, 'E' | '$')) {
+        return None;
+    }
+    let expand = flags.contains('$');
+    let pattern = if expand {
+        unescape(pattern)?
+    } else {
+        pattern.to_string()
+    };
+    let haystack = match haystack {
+        "NULL" => String::new(),
+        s if expand => unescape(s)?,
+        s => s.to_string(),
+    };
+    let expected = parse_result(result)?;
+    let translated = translate(&pattern)?;
+
+    let regex = match Regex::new(&translated) {
+        Ok(regex) => regex,
+        // 期待する結果がエラーでない場合は、対応しない構文とみなす
+        Err(_) if expected != Expected::Error => return None,
+        Err(_) => return Some(Ok(())),
+    };
+    let found = match regex.captures(&haystack) {
+        None => Expected::NoMatch,
+        Some(caps) => Expected::Match(
+            caps.iter()
+                .map(|m| m.map(|m| (m.start(), m.end())))
+                .collect(),
+        ),
+    };
+    let ok = match (&expected, &found) {
+        // 結果に書かれたグループだけを比べる
+        (Expected::Match(want), Expected::Match(got)) => {
+            want.len() <= got.len() && want.iter().zip(got).all(|(w, g)| w == g)
+        }
+        _ => expected == found,
+    };
+    Some(if ok {
+        Ok(())
+    } else {
+        Err(format_result(&found))
+    })
+}
+
+/// POSIX の拡張正規表現を、このクレートの構文に書き直す。書き直せない場合は `None`
+///
+/// `.` はすべての文字の文字クラスに、不要なエスケープは文字そのものにする。
+/// ブラケット式の中ではバックスラッシュを文字として扱う
+fn translate(pattern: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => out.push_str(r"[\s\S]"),
+            '\\' => match chars.next()? {
+                c if "\\()|+*?[]{}^$".contains(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                c if c.is_ascii_alphanumeric() => return None, // 後方参照や \w など
+                c => out.push(c),
+            },
+            '[' => {
+                out.push('[');
+                if chars.next_if_eq(&'^').is_some() {
+                    out.push('^');
+                }
+                // 先頭の ] は文字として扱う
+                let mut first = true;
+                loop {
+                    match chars.next()? {
+                        ']' if !first => break,
+                        '[' if matches!(chars.peek(), Some(':' | '.' | '=')) => return None,
+                        c @ ('\\' | '[' | ']' | '^') => {
+                            out.push('\\');
+                            out.push(c);
+                        }
+                        c => out.push(c),
+                    }
+                    first = false;
+                }
+                out.push(']');
+            }
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+/// `\n` や `\t` などの C のエスケープを展開する。対応しないエスケープがある場合は `None`
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            'a' => '\x07',
+            'e' => '\x1b',
+            '\\' => '\\',
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
+/// 期待する結果を読む。対応しない書き方の場合は `None`
+fn parse_result(result: &str) -> Option<Expected> {
+    if result == "NOMATCH" {
+        return Some(Expected::NoMatch);
+    }
+    if result.starts_with('E') || result.starts_with("BAD") {
+        return Some(Expected::Error);
+    }
+    let mut spans = Vec::new();
+    let mut rest = result;
+    while let Some(s) = rest.strip_prefix('(') {
+        let (span, tail) = s.split_once(')')?;
+        let (start, end) = span.split_once(',')?;
+        spans.push(match (start, end) {
+            ("?", "?") => None,
+            _ => Some((start.parse().ok()?, end.parse().ok()?)),
+        });
+        rest = tail;
+    }
+    (rest.is_empty() && !spans.is_empty()).then_some(Expected::Match(spans))
+}
+
+/// 結果を期待する結果と同じ書き方で表す
+fn format_result(result: &Expected) -> String {
+    match result {
+        Expected::NoMatch => "NOMATCH".to_string(),
+        Expected::Error => "error".to_string(),
+        Expected::Match(spans) => spans
+            .iter()
+            .map(|span| match span {
+                Some((start, end)) => format!("({start},{end})"),
+                None => "(?,?)".to_string(),
+            })
+            .collect(),
+    }
+}
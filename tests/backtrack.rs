@@ -0,0 +1,43 @@
+//! バックトラック型の評価器が、深い分岐や長い入力でもスタックをあふれさせないことを確かめる
+//!
+//! 評価器は分岐をヒープ上のスタックに積むため、テストのスレッドの小さいスタックでも終わる
+use regexp::{Engine, RegexBuilder};
+
+fn backtrack(pattern: &str) -> regexp::Regex {
+    let regex = RegexBuilder::new(pattern)
+        .engine(Engine::Backtrack)
+        .build()
+        .unwrap();
+    assert_eq!(regex.engine(), Engine::Backtrack);
+    regex
+}
+
+#[test]
+fn many_optional_branches() {
+    let regex = backtrack("(a?){1000}b");
+    let haystack = format!("{}b", "a".repeat(500));
+    let m = regex.find(&haystack).unwrap();
+    assert_eq!((m.start(), m.end()), (0, 501));
+}
+
+#[test]
+fn long_input() {
+    let haystack = "ab".repeat(500_000);
+    let regex = backtrack("(a|b)*");
+    assert_eq!(regex.find(&haystack).unwrap().end(), haystack.len());
+
+    // 最後まで読んでから失敗し、すべての分岐を戻る
+    let regex = backtrack("^(?:a|b)*c");
+    assert!(regex.find(&haystack).is_none());
+    let with_c = format!("{haystack}c");
+    assert_eq!(regex.find(&with_c).unwrap().end(), with_c.len());
+}
+
+#[test]
+fn captures_on_long_input() {
+    let haystack = "x".repeat(200_000) + "y";
+    let regex = backtrack("(x+)(y)");
+    let caps = regex.captures(&haystack).unwrap();
+    assert_eq!(caps.get(1).unwrap().end(), 200_000);
+    assert_eq!(caps.get(2).unwrap().start(), 200_000);
+}
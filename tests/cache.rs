@@ -0,0 +1,54 @@
+//! パターンのキャッシュが、最近使われていない順にパターンを捨てることを確かめる
+//!
+//! キャッシュはプロセス全体で共有されるため、並列に走るテストどうしで
+//! 統計情報が混ざらないよう、1 つのテストにまとめている
+use regexp::{RegexBuilder, cache_stats, clear_cache, find, is_match, set_cache_capacity};
+
+#[test]
+fn cache_evicts_least_recently_used() {
+    set_cache_capacity(2);
+    clear_cache();
+
+    assert!(is_match("a+", "xaa").unwrap());
+    assert!(is_match("b+", "xbb").unwrap());
+    // a+ を使い直したので、最も長く使われていないのは b+
+    assert_eq!(
+        find("a+", "xaa").unwrap().map(|m| (m.start(), m.end())),
+        Some((1, 3))
+    );
+    assert!(is_match("c+", "xcc").unwrap());
+    let stats = cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 3, 1));
+    assert_eq!((stats.len, stats.capacity), (2, 2));
+
+    // a+ は残っていて、b+ は捨てられている
+    assert!(is_match("a+", "a").unwrap());
+    assert!(is_match("b+", "b").unwrap());
+    let stats = cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 4, 2));
+
+    // 設定が異なれば、同じパターンでも別の項目になる
+    clear_cache();
+    let unicode = RegexBuilder::new(r"\d").build_cached().unwrap();
+    let ascii = RegexBuilder::new(r"\d").ascii(true).build_cached().unwrap();
+    assert!(unicode.is_match("٣"));
+    assert!(!ascii.is_match("٣"));
+    RegexBuilder::new(r"\d").ascii(true).build_cached().unwrap();
+    let stats = cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.len), (1, 2, 2));
+
+    // 容量を減らすと、使われていない順に捨てる
+    set_cache_capacity(1);
+    assert_eq!(cache_stats().len, 1);
+    RegexBuilder::new(r"\d").ascii(true).build_cached().unwrap();
+    assert_eq!(cache_stats().hits, 2);
+
+    // 失敗したパターンはキャッシュしない
+    assert!(is_match("(", "").is_err());
+    assert_eq!(cache_stats().len, 1);
+
+    // 容量 0 ではキャッシュを使わない
+    set_cache_capacity(0);
+    assert!(is_match("a+", "a").unwrap());
+    assert_eq!(cache_stats().len, 0);
+}
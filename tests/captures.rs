@@ -0,0 +1,69 @@
+//! マッチの範囲を先に求め、その範囲だけで捕獲グループの位置を求めた結果が、regex クレートと一致することを確かめる
+use regexp::{Engine, RegexBuilder};
+
+fn groups(caps: impl Iterator<Item = Option<(usize, usize)>>) -> Vec<Option<(usize, usize)>> {
+    caps.collect()
+}
+
+fn check(pattern: &str, haystack: &str) {
+    let expected: Vec<_> = regex::Regex::new(pattern)
+        .unwrap()
+        .captures_iter(haystack)
+        .map(|caps| groups(caps.iter().map(|m| m.map(|m| (m.start(), m.end())))))
+        .collect();
+    for engine in [Engine::Backtrack, Engine::PikeVm, Engine::Dfa] {
+        let regex = RegexBuilder::new(pattern).engine(engine).build().unwrap();
+        let found: Vec<_> = regex
+            .captures_iter(haystack)
+            .map(|caps| groups(caps.iter().map(|m| m.map(|m| (m.start(), m.end())))))
+            .collect();
+        assert_eq!(found, expected, "{pattern:?} [{engine:?}]");
+    }
+}
+
+#[test]
+fn captures_match_regex_crate() {
+    let haystack = "ab abb a-b aab xyz abab 12-34 b";
+    for pattern in [
+        "(a+)(b+)",
+        "(a)|(b)",
+        "(a(b)?)+",
+        "(x)?(y)(z)?",
+        "([0-9]+)-([0-9]+)",
+        "(a|ab)(b*)",
+        "((a)|b)+",
+        "(?:(a)|(-))b",
+    ] {
+        check(pattern, haystack);
+    }
+}
+
+#[test]
+fn sparse_matches_in_long_haystack() {
+    let filler = "x".repeat(5000);
+    let haystack = format!("{filler}k=1 {filler}key=22{filler}k={filler}");
+    check("([a-z]+)=([0-9]+)", &haystack);
+
+    let regex = RegexBuilder::new("([a-z]+)=([0-9]+)").build().unwrap();
+    let found: Vec<_> = regex
+        .captures_iter(&haystack)
+        .map(|caps| {
+            (
+                caps.get(1).unwrap().as_str().len(),
+                caps.get(2).unwrap().as_str(),
+            )
+        })
+        .collect();
+    // 直前に x が続くため、グループ 1 は x を含む長い文字列になる
+    assert_eq!(found, [(5001, "1"), (5003, "22")]);
+}
+
+#[test]
+fn no_groups_returns_only_the_match() {
+    let regex = RegexBuilder::new("a+").build().unwrap();
+    assert_eq!(regex.captures_len(), 1);
+    let caps = regex.captures("baaa").unwrap();
+    let m = caps.get(0).unwrap();
+    assert_eq!((m.start(), m.end()), (1, 4));
+    assert!(caps.get(1).is_none());
+}
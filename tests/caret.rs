@@ -0,0 +1,42 @@
+//! パースエラーを、パターンとその下のエラーの範囲を示す印を付けて表示することを確かめる
+//!
+//! メッセージは言語によるため、英語に設定して比べる
+use regexp::{Flags, Language, parse, set_language};
+
+fn render(pattern: &str) -> String {
+    set_language(Language::English);
+    let error = parse(pattern, Flags::default()).unwrap_err();
+    error.at_pattern(pattern).to_string()
+}
+
+#[test]
+fn caret_under_single_char() {
+    assert_eq!(
+        render("ab)"),
+        "ParseError: invalid right parenthesis: pos = 2\n  |\n  | ab)\n  |   ^\n  = help: escape it as `\\)`"
+    );
+}
+
+#[test]
+fn tildes_cover_the_span() {
+    let rendered = render("a{3,1}b");
+    let lines: Vec<_> = rendered.lines().collect();
+    assert_eq!(lines[2], "  | a{3,1}b");
+    assert_eq!(lines[3], "  |  ^~~~~");
+}
+
+#[test]
+fn wide_chars_take_two_columns() {
+    // 全角の文字は2文字分ずらし、範囲の中の全角の文字は2文字分の印を付ける
+    let rendered = render("日本)");
+    assert_eq!(rendered.lines().nth(3), Some("  |     ^"));
+    let rendered = render("a[本-a]");
+    assert_eq!(rendered.lines().nth(3), Some("  |   ^~~~"));
+}
+
+#[test]
+fn no_help_without_suggestion() {
+    let rendered = render("(?x)a");
+    assert_eq!(rendered.lines().count(), 4, "{rendered}");
+    assert_eq!(rendered.lines().nth(3), Some("  | ^~~~"));
+}
@@ -0,0 +1,62 @@
+//! 文字クラスの判定が、ASCII 文字のビットマップと範囲の二分探索のどちらでも範囲の一覧と一致することを確かめる
+use regexp::{CharClass, Regex};
+
+/// 範囲の一覧を先頭から調べて判定する
+fn linear(ranges: &[(char, char)], c: char) -> bool {
+    ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi)
+}
+
+/// ASCII の境界と、その前後の文字
+fn samples() -> impl Iterator<Item = char> {
+    (0..=0x100u32)
+        .chain([0x3040, 0x3042, 0x30ff, 0x10ffff])
+        .filter_map(char::from_u32)
+}
+
+#[test]
+fn contains_matches_ranges() {
+    let classes = [
+        vec![('a', 'z'), ('0', '9'), ('_', '_')],
+        vec![('\0', '\0'), ('\u{7f}', '\u{80}')],
+        vec![('x', 'あ')],
+        vec![('\u{80}', '\u{10ffff}')],
+        vec![(' ', '~'), ('ぁ', 'ゖ')],
+    ];
+    for ranges in classes {
+        let class = CharClass::new(ranges.clone());
+        let negated = class.negate();
+        for c in samples() {
+            assert_eq!(class.contains(c), linear(&ranges, c), "{ranges:?} {c:?}");
+            assert_eq!(negated.contains(c), !class.contains(c), "{ranges:?} {c:?}");
+        }
+    }
+}
+
+#[test]
+fn ranges_are_normalized() {
+    let class = CharClass::new(vec![('d', 'f'), ('a', 'c'), ('x', 'x'), ('e', 'g')]);
+    assert_eq!(class.ranges(), [('a', 'g'), ('x', 'x')]);
+    assert_eq!(
+        class.negate().ranges(),
+        [('\0', '`'), ('h', 'w'), ('y', '\u{10ffff}')]
+    );
+}
+
+#[test]
+fn class_patterns_match_like_regex() {
+    let haystack: String = samples().collect();
+    for pattern in [
+        r"[a-z0-9_]+",
+        r"[^\x00-\x7f]",
+        "[A-Za-z-]+",
+        r"[^ -~]+",
+        "[x-あ]",
+    ] {
+        let ours = pattern.replace(r"\x00", "\0").replace(r"\x7f", "\u{7f}");
+        let regex = Regex::new(&ours).unwrap();
+        let expected = regex::Regex::new(pattern).unwrap();
+        let found: Vec<_> = regex.find_iter(&haystack).map(|m| m.as_str()).collect();
+        let want: Vec<_> = expected.find_iter(&haystack).map(|m| m.as_str()).collect();
+        assert_eq!(found, want, "{pattern:?}");
+    }
+}
@@ -0,0 +1,580 @@
+//! コマンドラインのオプションごとに、出力と終了コードを確かめる
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+/// 検索する行。`foo` は1行目に1つ、3行目に2つある
+const TEXT: &str = "foo bar\nbaz\nfoo foo\nqux\n";
+
+/// `name` の名前で空の一時ディレクトリを作り、そのパスを返す
+fn dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("regexp-cli-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `dir` に `a.txt` (内容は TEXT) と `b.txt` (マッチしない行だけ) を作る
+fn texts(dir: &Path) {
+    fs::write(dir.join("a.txt"), TEXT).unwrap();
+    fs::write(dir.join("b.txt"), "nothing\n").unwrap();
+}
+
+/// `dir` で `args` を与えて実行し、標準出力と終了コードを返す。標準入力には `stdin` を書き込む
+fn run(dir: &Path, args: &[&str], stdin: &[u8]) -> (String, Option<i32>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_regexp"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    (stdout, output.status.code())
+}
+
+/// `a.txt` と `b.txt` のあるディレクトリで実行し、標準出力と終了コードを返す
+fn search(name: &str, args: &[&str]) -> (String, Option<i32>) {
+    let dir = dir(name);
+    texts(&dir);
+    run(&dir, args, b"")
+}
+
+/// 選んだ行があれば 0、なければ 1 で終わる
+#[test]
+fn exit_status() {
+    assert_eq!(search("exit", &["foo", "a.txt"]).1, Some(0));
+    assert_eq!(search("exit", &["foo", "b.txt"]).1, Some(1));
+    assert_eq!(search("exit", &["(", "a.txt"]).1, Some(2));
+}
+
+/// ファイルを指定しない場合と `-` は、標準入力を検索する
+#[test]
+fn files_and_stdin() {
+    let dir = dir("stdin");
+    texts(&dir);
+    assert_eq!(run(&dir, &["-n", "foo"], b"x\nfoo\n").0, "2:foo\n");
+    assert_eq!(
+        run(&dir, &["foo", "a.txt", "-", "b.txt"], b"foo\n").0,
+        "a.txt:foo bar\na.txt:foo foo\n(standard input):foo\n"
+    );
+}
+
+#[test]
+fn filename() {
+    assert_eq!(search("h", &["-H", "baz", "a.txt"]).0, "a.txt:baz\n");
+    assert_eq!(search("h", &["-h", "baz", "a.txt", "b.txt"]).0, "baz\n");
+}
+
+/// `d` の下に、.gitignore で除くファイルと、除かないファイルを作る
+fn tree(name: &str) -> PathBuf {
+    let dir = dir(name);
+    fs::create_dir_all(dir.join("d/sub")).unwrap();
+    fs::create_dir_all(dir.join("d/skip")).unwrap();
+    fs::write(dir.join("d/.gitignore"), "ignored.rs\n").unwrap();
+    for file in ["d/ignored.rs", "d/sub/y.txt", "d/skip/z.rs"] {
+        fs::write(dir.join(file), "foo\n").unwrap();
+    }
+    dir
+}
+
+#[test]
+fn recursive() {
+    let dir = tree("r");
+    assert_eq!(
+        run(&dir, &["-r", "--no-ignore", "foo", "d"], b"").0,
+        "d/ignored.rs:foo\nd/skip/z.rs:foo\nd/sub/y.txt:foo\n"
+    );
+}
+
+#[test]
+fn color() {
+    assert_eq!(
+        search("color", &["--color=always", "z", "a.txt"]).0,
+        "ba\x1b[1;31mz\x1b[0m\n"
+    );
+    assert_eq!(search("color", &["--color=never", "z", "a.txt"]).0, "baz\n");
+}
+
+#[test]
+fn line_number() {
+    assert_eq!(
+        search("n", &["-n", "foo", "a.txt"]).0,
+        "1:foo bar\n3:foo foo\n"
+    );
+}
+
+#[test]
+fn count() {
+    assert_eq!(
+        search("c", &["-c", "foo", "a.txt", "b.txt"]).0,
+        "a.txt:2\nb.txt:0\n"
+    );
+    assert_eq!(search("c", &["--count-matches", "foo", "a.txt"]).0, "3\n");
+}
+
+#[test]
+fn invert_match() {
+    assert_eq!(search("v", &["-v", "foo", "a.txt"]).0, "baz\nqux\n");
+}
+
+#[test]
+fn only_matching() {
+    assert_eq!(search("o", &["-o", "fo+", "a.txt"]).0, "foo\nfoo\nfoo\n");
+}
+
+#[test]
+fn context() {
+    assert_eq!(search("ctx", &["-A1", "baz", "a.txt"]).0, "baz\nfoo foo\n");
+    assert_eq!(search("ctx", &["-B1", "baz", "a.txt"]).0, "foo bar\nbaz\n");
+    assert_eq!(
+        search("ctx", &["-C1", "-n", "baz", "a.txt"]).0,
+        "1-foo bar\n2:baz\n3-foo foo\n"
+    );
+}
+
+#[test]
+fn json() {
+    assert_eq!(
+        search("json", &["--json", "b(a)z", "a.txt"]).0,
+        r#"{"file":"a.txt","line":2,"start":8,"end":11,"text":"baz","captures":[{"start":9,"end":10,"text":"a"}]}"#
+            .to_string()
+            + "\n"
+    );
+}
+
+#[test]
+fn multiple_patterns() {
+    assert_eq!(
+        search("e", &["-e", "qux", "-e", "baz", "a.txt"]).0,
+        "baz\nqux\n"
+    );
+}
+
+#[test]
+fn pattern_file() {
+    let dir = dir("f");
+    texts(&dir);
+    fs::write(dir.join("patterns"), "# 注釈\nqux\n\nbaz\n").unwrap();
+    assert_eq!(run(&dir, &["-f", "patterns", "a.txt"], b"").0, "baz\nqux\n");
+}
+
+/// `--lenient` では、先頭の `*` と誤ったエスケープシーケンスを文字として扱う
+#[test]
+fn lenient() {
+    let dir = dir("lenient");
+    assert_eq!(run(&dir, &["*a"], b"").1, Some(2));
+    assert_eq!(run(&dir, &["--lenient", "*a"], b"*a\n").0, "*a\n");
+    assert_eq!(run(&dir, &["--lenient", r"\q"], b"p\nq\n").0, "q\n");
+}
+
+#[test]
+fn binary_files() {
+    let dir = dir("binary");
+    fs::write(dir.join("bin"), "foo\0bar\n").unwrap();
+    assert_eq!(
+        run(&dir, &["foo", "bin"], b""),
+        ("Binary file bin matches\n".to_string(), Some(0))
+    );
+    assert_eq!(run(&dir, &["-a", "foo", "bin"], b"").0, "foo\0bar\n");
+    assert_eq!(
+        run(&dir, &["--binary-files=without-match", "foo", "bin"], b""),
+        (String::new(), Some(1))
+    );
+}
+
+#[test]
+fn files_with_matches() {
+    assert_eq!(search("l", &["-l", "foo", "a.txt", "b.txt"]).0, "a.txt\n");
+    assert_eq!(search("l", &["-L", "foo", "a.txt", "b.txt"]).0, "b.txt\n");
+}
+
+#[test]
+fn max_count() {
+    assert_eq!(search("m", &["-m1", "foo", "a.txt"]).0, "foo bar\n");
+}
+
+#[test]
+fn quiet() {
+    assert_eq!(
+        search("q", &["-q", "foo", "a.txt"]),
+        (String::new(), Some(0))
+    );
+    assert_eq!(
+        search("q", &["-q", "foo", "b.txt"]),
+        (String::new(), Some(1))
+    );
+}
+
+#[test]
+fn replace() {
+    assert_eq!(
+        search("replace", &["--replace=[$1]", "f(o+)", "a.txt"]).0,
+        "[oo] bar\n[oo] [oo]\n"
+    );
+}
+
+#[test]
+fn in_place() {
+    let dir = dir("in-place");
+    texts(&dir);
+    run(&dir, &["--replace=x", "-i.bak", "foo", "a.txt"], b"");
+    assert_eq!(
+        fs::read_to_string(dir.join("a.txt")).unwrap(),
+        "x bar\nbaz\nx x\nqux\n"
+    );
+    assert_eq!(fs::read_to_string(dir.join("a.txt.bak")).unwrap(), TEXT);
+}
+
+/// 書き換えたファイルは許可属性を保ち、一時ファイルを残さない。シンボリックリンクはリンク先を書き換える
+#[cfg(unix)]
+#[test]
+fn in_place_replaces_file() {
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    let dir = dir("in-place-replace");
+    texts(&dir);
+    fs::set_permissions(dir.join("a.txt"), fs::Permissions::from_mode(0o640)).unwrap();
+    symlink("a.txt", dir.join("link.txt")).unwrap();
+    run(&dir, &["--replace=x", "-i", "foo", "link.txt"], b"");
+
+    assert_eq!(
+        fs::read_to_string(dir.join("a.txt")).unwrap(),
+        "x bar\nbaz\nx x\nqux\n"
+    );
+    let mode = fs::metadata(dir.join("a.txt"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o640);
+    assert!(
+        fs::symlink_metadata(dir.join("link.txt"))
+            .unwrap()
+            .is_symlink()
+    );
+    let mut names: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a.txt", "b.txt", "link.txt"]);
+}
+
+/// 並列に検索しても、既定ではディレクトリの順に出力する
+#[test]
+fn threads() {
+    let dir = tree("threads");
+    for i in 0..20 {
+        fs::write(dir.join(format!("d/sub/{i:02}.txt")), "foo\n").unwrap();
+    }
+    let sequential = run(&dir, &["-r", "-j1", "foo", "d"], b"").0;
+    assert_eq!(run(&dir, &["-r", "-j4", "foo", "d"], b"").0, sequential);
+    let mut unordered: Vec<_> = run(&dir, &["-r", "-j4", "--no-sort", "foo", "d"], b"")
+        .0
+        .lines()
+        .map(str::to_string)
+        .collect();
+    unordered.sort();
+    assert_eq!(unordered.join("\n") + "\n", sequential);
+}
+
+#[test]
+fn mmap() {
+    assert_eq!(
+        search("mmap", &["--mmap", "foo", "a.txt"]).0,
+        search("mmap", &["--no-mmap", "foo", "a.txt"]).0
+    );
+}
+
+/// .gitignore で除いたファイルは検索しない
+#[test]
+fn gitignore() {
+    let dir = tree("ignore");
+    assert_eq!(
+        run(&dir, &["-r", "foo", "d"], b"").0,
+        "d/skip/z.rs:foo\nd/sub/y.txt:foo\n"
+    );
+    assert_eq!(
+        run(&dir, &["-r", "--no-ignore", "foo", "d"], b"").0,
+        "d/ignored.rs:foo\nd/skip/z.rs:foo\nd/sub/y.txt:foo\n"
+    );
+}
+
+#[test]
+fn globs() {
+    let dir = tree("glob");
+    assert_eq!(
+        run(&dir, &["-r", "--include=*.rs", "foo", "d"], b"").0,
+        "d/skip/z.rs:foo\n"
+    );
+    assert_eq!(
+        run(&dir, &["-r", "--exclude=*.rs", "foo", "d"], b"").0,
+        "d/sub/y.txt:foo\n"
+    );
+    assert_eq!(
+        run(&dir, &["-r", "--exclude-dir=skip", "foo", "d"], b"").0,
+        "d/sub/y.txt:foo\n"
+    );
+}
+
+#[test]
+fn encoding() {
+    let dir = dir("encoding");
+    fs::write(dir.join("u16"), b"\xff\xfef\0o\0o\0\n\0").unwrap();
+    fs::write(dir.join("latin1"), b"caf\xe9\n").unwrap();
+    assert_eq!(
+        run(&dir, &["--encoding=utf-16", "fo+", "u16"], b"").0,
+        "foo\n"
+    );
+    assert_eq!(
+        run(&dir, &["--encoding=latin1", "-o", "é", "latin1"], b"").0,
+        "é\n"
+    );
+}
+
+#[test]
+fn debug_ast() {
+    let dir = dir("debug-ast");
+    assert_eq!(
+        run(&dir, &["--debug-ast", "a|b"], b"").0,
+        "Or\n├─ Seq\n│  └─ Char 'a'\n└─ Seq\n   └─ Char 'b'\n"
+    );
+}
+
+/// 選択肢の多いパターンでも、出力は選択肢の数に比例する長さに収まる
+#[test]
+fn debug_ast_wide_alternation() {
+    let dir = dir("debug-ast-wide");
+    let n = 3000;
+    fs::write(dir.join("patterns"), vec!["ab"; n].join("|")).unwrap();
+    let (stdout, status) = run(&dir, &["--debug-ast", "-f", "patterns"], b"");
+    assert_eq!(status, Some(0));
+    assert_eq!(stdout.lines().count(), 1 + 3 * n);
+    assert!(stdout.lines().all(|line| line.chars().count() <= 16));
+}
+
+#[test]
+fn debug_program() {
+    let dir = dir("debug-program");
+    assert_eq!(
+        run(&dir, &["--debug-program", "ab"], b"").0,
+        "0000: char a\n0001: char b\n0002: match\n"
+    );
+}
+
+#[test]
+fn trace() {
+    let dir = dir("trace");
+    assert_eq!(
+        run(&dir, &["--trace=ab", "a"], b"").0,
+        "thread    pos  instruction\n     0      0  0000: char a\n     0      1  0001: match\nmatch 0..1: a\n"
+    );
+    assert!(
+        run(&dir, &["--trace=ab", "--trace-limit=1", "a"], b"")
+            .0
+            .ends_with("stopped after 1 instructions\n")
+    );
+}
+
+#[test]
+fn emit_dot() {
+    let dir = dir("dot");
+    let dot = run(&dir, &["--emit-dot", "a"], b"").0;
+    assert!(dot.starts_with("digraph regex {\n"), "{dot}");
+    assert!(dot.contains("0 -> 1 [label=\"a\"];"), "{dot}");
+}
+
+#[test]
+fn emit_mermaid() {
+    let dir = dir("mermaid");
+    let mermaid = run(&dir, &["--emit-mermaid", "a"], b"").0;
+    assert!(mermaid.starts_with("stateDiagram-v2\n"), "{mermaid}");
+    assert!(mermaid.contains("s0 --> s1: a"), "{mermaid}");
+}
+
+#[test]
+fn repl() {
+    let dir = dir("repl");
+    let (stdout, status) = run(&dir, &["repl", "fo+"], b"xfoo\nbar\n");
+    assert_eq!(status, Some(0));
+    assert!(stdout.contains("1..4 \"foo\"\n"), "{stdout}");
+    assert!(stdout.contains("no match\n"), "{stdout}");
+}
+
+/// かかった時間は標準エラー出力に書き、標準出力には検索結果だけを書く
+#[test]
+fn time() {
+    let dir = dir("time");
+    texts(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_regexp"))
+        .args(["--time", "foo", "a.txt"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"foo bar\nfoo foo\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("compile time: "), "{stderr}");
+    assert!(stderr.contains("search time: "), "{stderr}");
+}
+
+#[test]
+fn stats() {
+    let (stdout, _) = search("stats", &["--stats", "foo", "a.txt"]);
+    assert!(stdout.starts_with("foo bar\nfoo foo\n\n"), "{stdout}");
+    assert!(
+        stdout.contains("\n2 lines selected\n3 matches\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn passthru() {
+    assert_eq!(
+        search("passthru", &["--passthru", "-n", "baz", "a.txt"]).0,
+        "1-foo bar\n2:baz\n3-foo foo\n4-qux\n"
+    );
+}
+
+/// 標準入力は、終わるまで追記された行を検索する
+#[test]
+fn follow() {
+    let dir = dir("follow");
+    assert_eq!(
+        run(&dir, &["--follow", "foo"], b"foo\nbar\nfoo2\n").0,
+        "foo\nfoo2\n"
+    );
+    assert_eq!(run(&dir, &["--follow", "-c", "foo"], b"").1, Some(2));
+}
+
+#[test]
+fn engine() {
+    let dir = dir("engine");
+    texts(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_regexp"))
+        .args(["--engine=pikevm", "baz", "a.txt"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"baz\n");
+    assert_eq!(output.stderr, b"regexp: engine: pikevm\n");
+    assert_eq!(
+        search("engine", &["--engine=nope", "baz", "a.txt"]).1,
+        Some(2)
+    );
+}
+
+/// パースエラーは、パターンとその下にエラーの範囲を示す印を付けて標準エラー出力に書く
+#[test]
+fn parse_error_caret() {
+    let dir = dir("caret");
+    texts(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_regexp"))
+        .args(["foo)", "a.txt"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("\n  | foo)\n  |    ^\n"), "{stderr}");
+}
+
+#[test]
+fn multiline() {
+    assert_eq!(
+        search("U", &["-U", "-n", r"bar\sbaz", "a.txt"]).0,
+        "1:foo bar\n2:baz\n"
+    );
+}
+
+#[test]
+fn format() {
+    assert_eq!(
+        search(
+            "format",
+            &["--format={line}:{column}:{match}", "foo", "a.txt"]
+        )
+        .0,
+        "1:1:foo\n3:1:foo\n3:5:foo\n"
+    );
+}
+
+/// CRC-32
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &b| {
+        (0..8).fold(crc ^ u32::from(b), |crc, _| {
+            if crc & 1 != 0 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// `data` を圧縮しないブロックに入れた gzip
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff, 1];
+    let len = data.len() as u16;
+    out.extend(len.to_le_bytes());
+    out.extend((!len).to_le_bytes());
+    out.extend(data);
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+#[test]
+fn search_gzip() {
+    let dir = dir("gzip");
+    fs::write(dir.join("a.gz"), gzip(b"foo\nbar\n")).unwrap();
+    assert_eq!(run(&dir, &["-z", "foo", "a.gz"], b"").0, "foo\n");
+    assert_eq!(
+        run(&dir, &["foo", "a.gz"], b"").0,
+        "Binary file a.gz matches\n"
+    );
+}
+
+/// `name` のファイルだけを含む ustar 形式の tar
+fn tar(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut header = [0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+    header[148..156].fill(b' ');
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+    let mut out = header.to_vec();
+    out.extend(data);
+    out.resize(out.len().next_multiple_of(512) + 1024, 0);
+    out
+}
+
+#[test]
+fn search_archive() {
+    let dir = dir("archive");
+    fs::write(dir.join("a.tar"), tar("in/a.txt", b"bar\nfoo\n")).unwrap();
+    assert_eq!(
+        run(&dir, &["-z", "-n", "foo", "a.tar"], b"").0,
+        "a.tar!in/a.txt:2:foo\n"
+    );
+}
+
+#[test]
+fn null_data() {
+    let dir = dir("null-data");
+    assert_eq!(
+        run(&dir, &["--null-data", "ba"], b"foo\0bar\0baz").0,
+        "bar\0baz\0"
+    );
+    assert_eq!(
+        search("null", &["-lZ", "foo", "a.txt", "b.txt"]).0,
+        "a.txt\0"
+    );
+}
@@ -0,0 +1,62 @@
+//! jump と split をたどる並びをコンパイル時に求めても、状態の集合を進める評価器の結果が変わらないことを確かめる
+//!
+//! 並びを求めない命令列 (位置の判定を含むものや、並びが長くなりすぎるもの) も同じ結果になることを確かめる
+use regexp::{Engine, Regex, RegexBuilder};
+
+fn spans(regex: &Regex, haystack: &str) -> Vec<(usize, usize)> {
+    regex
+        .find_iter(haystack)
+        .map(|m| (m.start(), m.end()))
+        .collect()
+}
+
+fn check(pattern: &str, haystack: &str) {
+    let expected: Vec<_> = regex::Regex::new(pattern)
+        .unwrap()
+        .find_iter(haystack)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    let pike = RegexBuilder::new(pattern)
+        .engine(Engine::PikeVm)
+        .build()
+        .unwrap();
+    assert_eq!(spans(&pike, haystack), expected, "{pattern:?}");
+
+    // 読み込んだ命令列についても並びを求め直す
+    let loaded = Regex::from_bytes(&pike.to_bytes()).unwrap();
+    assert_eq!(spans(&loaded, haystack), expected, "{pattern:?}");
+
+    let backtrack = RegexBuilder::new(pattern)
+        .engine(Engine::Backtrack)
+        .build()
+        .unwrap();
+    assert_eq!(spans(&backtrack, haystack), expected, "{pattern:?}");
+}
+
+#[test]
+fn split_heavy_patterns() {
+    let haystack = "abcabc aab ba cccb abab xyzzy a1b2c3 zzz";
+    for pattern in [
+        "(a|b|c)+",
+        "(?:a*b*)*c",
+        "((a|b)*|c)+b",
+        "(?:ab|a|b)+",
+        "(?:x|y|z){2,4}",
+        "a?b?c?a?b?c?",
+        "(?:[a-c][0-9])+",
+        "(?:a|ab)(?:c|bcd)?",
+    ] {
+        check(pattern, haystack);
+    }
+}
+
+#[test]
+fn patterns_without_closures() {
+    let haystack = "abc ab\nbc abab\ncab";
+    // 位置の判定を含む
+    for pattern in [r"\bab", "(?m)^(a|b)+$", r"(a|b)+\B", "(?m)(ab)*$"] {
+        check(pattern, haystack);
+    }
+    // 分岐が多く、並びが長くなりすぎる
+    check("(?:a?b?c?){8}(?:a|b|c)*", haystack);
+}
@@ -0,0 +1,88 @@
+//! パースを続けて、互いに独立したパースエラーをすべて返すことを確かめる
+use regexp::{Error, Flags, ParserErrorKind, RegexBuilder, parse, parse_all};
+
+/// パターンと、見つかるエラーの種類と開始位置（バイト）
+const CASES: &[(&str, &[(ParserErrorKind, usize)])] = &[
+    (
+        r"a)b\q+c*",
+        &[
+            (ParserErrorKind::InvalidRightParen, 1),
+            (ParserErrorKind::InvalidEscape('q'), 3),
+        ],
+    ),
+    (
+        r"(a\y)|+b{3,1}",
+        &[
+            (ParserErrorKind::InvalidEscape('y'), 2),
+            (ParserErrorKind::NoPrev, 6),
+            (ParserErrorKind::InvalidRepeat, 8),
+        ],
+    ),
+    (
+        "[z-a]x)",
+        &[
+            (ParserErrorKind::InvalidRange, 1),
+            (ParserErrorKind::InvalidRightParen, 6),
+        ],
+    ),
+    // 閉じ括弧が足りない場合は、内側の開き括弧から順に報告する
+    (
+        "((a",
+        &[
+            (ParserErrorKind::NoRightParen, 1),
+            (ParserErrorKind::NoRightParen, 0),
+        ],
+    ),
+    (
+        r"\q(",
+        &[
+            (ParserErrorKind::InvalidEscape('q'), 0),
+            (ParserErrorKind::NoRightParen, 2),
+        ],
+    ),
+];
+
+#[test]
+fn parse_all_collects_errors() {
+    for &(pattern, expected) in CASES {
+        let errors = parse_all(pattern, Flags::default()).unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind(), e.span().byte_start))
+            .collect();
+        assert_eq!(found, expected, "{pattern:?}");
+        // 最初のエラーは、エラーで止まるパースと同じ
+        assert_eq!(
+            parse(pattern, Flags::default()).unwrap_err().kind(),
+            expected[0].0,
+            "{pattern:?}"
+        );
+    }
+}
+
+#[test]
+fn derived_errors_are_omitted() {
+    // 閉じ括弧がないために空になったパターンは、空のパターンのエラーにしない
+    let errors = parse_all("(", Flags::default()).unwrap_err();
+    let kinds: Vec<_> = errors.iter().map(|e| e.kind()).collect();
+    assert_eq!(kinds, [ParserErrorKind::NoRightParen]);
+    assert!(parse_all("a(b)c", Flags::default()).is_ok());
+}
+
+#[test]
+fn builder_collects_errors() {
+    let pattern = r"(a\y)|+b{3,1}";
+    let Err(Error::ParseAll(errors)) = RegexBuilder::new(pattern).collect_errors(true).build()
+    else {
+        panic!("expected all parse errors");
+    };
+    assert_eq!(errors.0.len(), 3);
+    // 各エラーを1行ずつ表示する
+    assert_eq!(errors.to_string().lines().count(), 3);
+
+    // 無効にした場合は、最初のエラーだけを返す
+    let Err(Error::Parse(error)) = RegexBuilder::new(pattern).build() else {
+        panic!("expected a parse error");
+    };
+    assert_eq!(error, errors.0[0]);
+}
@@ -0,0 +1,64 @@
+//! 最小化した DFA が、同じ言語を表すパターンで同じ遷移表になることを確かめる
+//!
+//! DFA は [`Regex::to_rust_dfa`] で書き出した遷移表で比べる
+use regexp::{Engine, Regex};
+
+/// 書き出したソースのうち、パターンを書いたコメント以外
+fn tables(pattern: &str) -> String {
+    let source = Regex::new(pattern).unwrap().to_rust_dfa("m").unwrap();
+    source.lines().skip(1).collect::<Vec<_>>().join("\n")
+}
+
+/// 状態の数。0 番目の状態は、どの入力でもマッチしなくなった状態
+fn states(pattern: &str) -> usize {
+    let tables = tables(pattern);
+    let line = tables
+        .lines()
+        .find(|line| line.contains("const ACCEPT"))
+        .unwrap();
+    let len = line.split(['[', ']', ';']).nth(2).unwrap().trim();
+    len.parse().unwrap()
+}
+
+#[test]
+fn equivalent_patterns_share_tables() {
+    for (a, b) in [
+        ("a|b|c", "[a-c]"),
+        ("x(ab|ab|ac)", "x(a(b|c))"),
+        ("(a|b)*c", "[ab]*c"),
+        ("aa*", "a+"),
+        ("(ab)?ab", "ab(ab)?"),
+    ] {
+        assert_eq!(tables(a), tables(b), "{a:?} and {b:?}");
+    }
+}
+
+#[test]
+fn minimal_state_counts() {
+    // 停止状態、開始状態、マッチした状態
+    assert_eq!(states("[0-9]+"), 3);
+    assert_eq!(states("[a-c]"), 3);
+    // 停止状態と開始状態に、読んだ文字数ごとの状態が加わる
+    assert_eq!(states("a{5}"), 7);
+    assert_eq!(states("a{1,5}"), 7);
+    assert_eq!(states("(a|b)*c"), 3);
+}
+
+#[test]
+fn dfa_finds_the_same_matches() {
+    let haystack = "xab xac 123 aaaa abab cab";
+    for pattern in ["x(ab|ab|ac)", "[0-9]+", "a{1,5}", "(ab)?ab", "[ab]*c"] {
+        let regex = Regex::new(pattern).unwrap();
+        assert_eq!(regex.engine(), Engine::Dfa, "{pattern:?}");
+        let expected = regex::Regex::new(pattern).unwrap();
+        let found: Vec<_> = regex
+            .find_iter(haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        let want: Vec<_> = expected
+            .find_iter(haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        assert_eq!(found, want, "{pattern:?}");
+    }
+}
@@ -0,0 +1,72 @@
+//! `miette` フィーチャで、エラーが報告するコードと範囲のラベル、提案を確かめる
+//!
+//! 文言は言語によるため、英語に設定して比べる
+#![cfg(feature = "miette")]
+use miette::{Diagnostic, LabeledSpan};
+use regexp::{Language, Regex, RegexBuilder, set_language};
+
+/// ラベルの説明と範囲 (開始位置, 長さ) を集める
+fn labels(diagnostic: &dyn Diagnostic) -> Vec<(Option<String>, usize, usize)> {
+    diagnostic
+        .labels()
+        .into_iter()
+        .flatten()
+        .map(|label: LabeledSpan| {
+            (
+                label.label().map(str::to_string),
+                label.offset(),
+                label.len(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn parse_error() {
+    set_language(Language::English);
+    let pattern = "ab)c";
+    let error = Regex::new(pattern).unwrap_err();
+    assert_eq!(error.code().unwrap().to_string(), "E0002");
+    let expected = vec![(Some("invalid right parenthesis".to_string()), 2, 1)];
+    assert_eq!(labels(&error), expected);
+    // パターンを持たないため、提案とソースは報告しない
+    assert!(error.help().is_none());
+    assert!(error.source_code().is_none());
+
+    let error = error.with_pattern(pattern);
+    assert_eq!(error.code().unwrap().to_string(), "E0002");
+    assert_eq!(labels(&error), expected);
+    assert_eq!(error.help().unwrap().to_string(), r"escape it as `\)`");
+    let source = error.source_code().unwrap();
+    let contents = source.read_span(&(2..3).into(), 0, 0).unwrap();
+    assert_eq!(contents.data(), b")");
+}
+
+#[test]
+fn all_parse_errors() {
+    set_language(Language::English);
+    let pattern = "+a(b";
+    let error = RegexBuilder::new(pattern)
+        .collect_errors(true)
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        labels(&error),
+        [
+            (Some("no previous expression".to_string()), 0, 1),
+            (Some("no right parenthesis".to_string()), 2, 2),
+        ]
+    );
+    // 複数のエラーがある場合は、提案を添えない
+    assert!(error.with_pattern(pattern).help().is_none());
+}
+
+#[test]
+fn other_errors() {
+    let error = RegexBuilder::new("a{1000}{1000}")
+        .size_limit(100)
+        .build()
+        .unwrap_err();
+    assert!(error.code().is_none());
+    assert!(labels(&error).is_empty());
+}
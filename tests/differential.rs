@@ -0,0 +1,246 @@
+//! regex クレートと検索結果を比べる差分テスト
+//!
+//! 対応する構文の範囲でランダムにパターンと検索対象の文字列を生成し、
+//! マッチの範囲、捕獲グループの範囲、置換の結果が regex クレートと一致することを確かめる。
+//! 一致しない場合は、パターンと文字列を表示して失敗する。
+//! 既定では決まったシードで少しのパターンを試し、環境変数でパターンの数とシードを変えられる
+//!
+//! ```text
+//! REGEXP_DIFFERENTIAL_COUNT=100000 REGEXP_DIFFERENTIAL_SEED=1 cargo test --release --test differential
+//! ```
+use regexp::{Cache, EvalError, Regex, RegexBuilder};
+use std::env;
+
+/// 生成に使う部品。(このクレートの構文, regex クレートの構文, ASCII モードでの regex クレートの構文)
+const ATOMS: &[(&str, &str, &str)] = &[
+    ("a", "a", "a"),
+    ("b", "b", "b"),
+    ("é", "é", "é"),
+    ("ab", "ab", "ab"),
+    ("[a-c]", "[a-c]", "[a-c]"),
+    ("[^a]", "[^a]", "[^a]"),
+    ("[]a]", "[]a]", "[]a]"),
+    ("[b-]", "[b-]", "[b-]"),
+    (r"\d", r"\d", "[0-9]"),
+    (r"\D", r"\D", "[^0-9]"),
+    (r"\w", r"\w", "[0-9A-Za-z_]"),
+    (r"\W", r"\W", "[^0-9A-Za-z_]"),
+    (r"\s", r"\s", r"[\t-\r ]"),
+    (r"\S", r"\S", r"[^\t-\r ]"),
+    (r"\b", r"\b", r"(?-u:\b)"),
+    (r"\B", r"\B", r"(?-u:\B)"),
+    (r"[\w-]", r"[\w-]", "[0-9A-Za-z_-]"),
+    (r"[^\s\d]", r"[^\s\d]", r"[^\t-\r 0-9]"),
+    ("^", "^", "^"),
+    ("$", "$", "$"),
+    (r"\^", r"\^", r"\^"),
+];
+
+/// 既定で試すパターンの数
+const DEFAULT_COUNT: usize = 50;
+
+/// 既定のシード
+const DEFAULT_SEED: u64 = 88172645463325252;
+
+/// グループの参照を展開して置き換える場合の置換文字列
+const TEMPLATE: &str = "<${1}|$0|$$>";
+
+/// 検索対象の文字列に使う文字。パターンの部品と重なるものを多めに含める
+const ALPHABET: &[char] = &[
+    'a', 'b', 'a', 'b', 'c', 'é', 'ê', 'x', '-', ']', '^', '$', ' ', '1', '٣', '\n', '_',
+];
+
+/// xorshift による疑似乱数
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// 0 以上 `n` 未満の整数
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// 深さ `depth` までのパターンを、このクレートと regex クレートの構文の組で生成する
+fn pattern(rng: &mut Rng, depth: u32, ascii: bool) -> (String, String) {
+    let atom = |rng: &mut Rng| {
+        let (ours, theirs, theirs_ascii) = ATOMS[rng.below(ATOMS.len())];
+        let theirs = if ascii { theirs_ascii } else { theirs };
+        (ours.to_string(), theirs.to_string())
+    };
+    if depth == 0 {
+        return atom(rng);
+    }
+    let (a, b) = pattern(rng, depth - 1, ascii);
+    match rng.below(9) {
+        0 => {
+            let (c, d) = pattern(rng, depth - 1, ascii);
+            (format!("{a}{c}"), format!("{b}{d}"))
+        }
+        1 => {
+            let (c, d) = pattern(rng, depth - 1, ascii);
+            (format!("({a}|{c})"), format!("({b}|{d})"))
+        }
+        2 => (format!("({a})?"), format!("({b})?")),
+        3 | 4 => {
+            let op = if rng.below(2) == 0 { "+" } else { "*" };
+            (format!("({a}){op}"), format!("({b}){op}"))
+        }
+        5 => {
+            let n = rng.below(4);
+            let q = match rng.below(3) {
+                0 => format!("{{{n}}}"),
+                1 => format!("{{{n},}}"),
+                _ => format!("{{{n},{}}}", n + rng.below(4)),
+            };
+            (format!("({a}){q}"), format!("({b}){q}"))
+        }
+        6 => {
+            let (c, d) = pattern(rng, depth - 1, ascii);
+            (format!("(({a})?({c})*)"), format!("(({b})?({d})*)"))
+        }
+        _ => {
+            let (c, d) = atom(rng);
+            (format!("{a}{c}"), format!("{b}{d}"))
+        }
+    }
+}
+
+/// マッチの範囲の列
+type Spans = Vec<(usize, usize)>;
+
+/// 捕獲グループの範囲の列
+type Groups = Vec<Vec<Option<(usize, usize)>>>;
+
+/// [`Regex::find_iter`] と同じ順に、重なり合わないマッチの範囲を `try_find_at` で求める
+///
+/// 命令数の上限を超えた場合はエラーを返す。超えずに終わった場合は、
+/// `find_iter` や置換も同じ位置から検索するため、上限を超えない
+fn try_spans(regex: &Regex, haystack: &str) -> Result<Spans, EvalError> {
+    let mut cache = Cache::new();
+    let mut spans = Vec::new();
+    let (mut pos, mut last_end) = (0, None);
+    while pos <= haystack.len() {
+        let Some(m) = regex.try_find_at(&mut cache, haystack, pos)? else {
+            break;
+        };
+        // 直前のマッチの直後にある空マッチは報告せず、1文字進めて検索し直す
+        if m.start() == m.end() && Some(m.end()) == last_end {
+            let Some(c) = haystack[m.end()..].chars().next() else {
+                break;
+            };
+            pos = m.end() + c.len_utf8();
+            continue;
+        }
+        spans.push((m.start(), m.end()));
+        pos = m.end();
+        last_end = Some(m.end());
+    }
+    Ok(spans)
+}
+
+/// 環境変数 `name` の値を数として読む。ない場合は `default` とする
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).map_or(default, |s| {
+        s.parse()
+            .unwrap_or_else(|_| panic!("invalid {name}: {s:?}"))
+    })
+}
+
+#[test]
+fn matches_regex_crate() {
+    let count = env_number("REGEXP_DIFFERENTIAL_COUNT", DEFAULT_COUNT);
+    let seed = env_number("REGEXP_DIFFERENTIAL_SEED", DEFAULT_SEED);
+    let mut rng = Rng(seed.max(1));
+
+    let (mut checked, mut skipped) = (0, 0);
+    for i in 0..count {
+        let ascii = i % 3 == 0;
+        let multi_line = rng.below(3) == 0;
+        let (ours, mut theirs) = pattern(&mut rng, 3, ascii);
+        if multi_line {
+            theirs = format!("(?m){theirs}");
+        }
+        let regex = RegexBuilder::new(&ours)
+            .ascii(ascii)
+            .multi_line(multi_line)
+            .step_limit(20_000_000)
+            .build()
+            .unwrap_or_else(|e| panic!("{ours}: {e}"));
+        let expected = regex::Regex::new(&theirs).unwrap_or_else(|e| panic!("{theirs}: {e}"));
+
+        for _ in 0..10 {
+            let len = rng.below(300);
+            let haystack: String = (0..len)
+                .map(|_| ALPHABET[rng.below(ALPHABET.len())])
+                .collect();
+            // 命令数の上限を超えた組み合わせだけを比較から除き、それ以外のパニックはテストの失敗とする
+            let tried = try_spans(&regex, &haystack)
+                .and_then(|spans| Ok((spans, regex.try_captures(&haystack)?)));
+            let tried_spans = match tried {
+                Ok((spans, _)) => spans,
+                Err(EvalError::StepLimitExceeded) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            let mismatch = |what: &str| -> ! {
+                panic!(
+                    "{what} mismatch: pattern = {ours:?} (regex: {theirs:?}), haystack = {haystack:?}"
+                );
+            };
+            let spans: Spans = regex
+                .find_iter(&haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            if spans != tried_spans {
+                mismatch("find_iter");
+            }
+            let groups: Groups = regex
+                .captures_iter(&haystack)
+                .map(|caps| {
+                    caps.iter()
+                        .map(|m| m.map(|m| (m.start(), m.end())))
+                        .collect()
+                })
+                .collect();
+            let replaced = regex.replace_all(&haystack, "<>");
+            let expanded = regex.replace_all_template(&haystack, TEMPLATE);
+
+            let want: Spans = expected
+                .find_iter(&haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            if spans != want {
+                mismatch("match");
+            }
+            // regex クレートは {0} で繰り返すグループを数えない場合があるため、番号を指定して比べる
+            let want: Groups = expected
+                .captures_iter(&haystack)
+                .map(|caps| {
+                    (0..regex.captures_len())
+                        .map(|i| caps.get(i).map(|m| (m.start(), m.end())))
+                        .collect()
+                })
+                .collect();
+            if groups != want {
+                mismatch("captures");
+            }
+            if replaced != expected.replace_all(&haystack, regex::NoExpand("<>")) {
+                mismatch("replace");
+            }
+            if expanded != expected.replace_all(&haystack, TEMPLATE) {
+                mismatch("template");
+            }
+            checked += 1;
+        }
+    }
+    println!("{checked} checked, {skipped} skipped by the step limit");
+}
@@ -0,0 +1,45 @@
+//! 空文字列にマッチしうる式の繰り返しが、すべての評価器で止まり、regex クレートと同じマッチを返すことを確かめる
+use regexp::{Engine, RegexBuilder};
+
+const PATTERNS: &[&str] = &[
+    "(a*)*",
+    "(a*)+b",
+    "()*",
+    "(?:a?)*b",
+    "(|a)+",
+    "(a|b*)*c",
+    "((a*)*)*x",
+    "(a*){3,}",
+    "(?:a*|b)+?c",
+];
+
+#[test]
+fn empty_loops_terminate() {
+    let haystack = "aaab bbc aax c";
+    for pattern in PATTERNS {
+        let expected: Vec<_> = regex::Regex::new(pattern)
+            .unwrap()
+            .find_iter(haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        for engine in [Engine::Backtrack, Engine::PikeVm, Engine::Dfa] {
+            let regex = RegexBuilder::new(pattern).engine(engine).build().unwrap();
+            let found: Vec<_> = regex
+                .find_iter(haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            assert_eq!(found, expected, "{pattern:?} [{engine:?}]");
+        }
+    }
+}
+
+#[test]
+fn empty_loops_on_long_input() {
+    // 空文字列の繰り返しを打ち切らないと、入力の長さに比例して深く再帰する
+    let haystack = "a".repeat(10_000);
+    for engine in [Engine::Backtrack, Engine::PikeVm] {
+        let regex = RegexBuilder::new("^(a*)*$").engine(engine).build().unwrap();
+        let m = regex.find(&haystack).unwrap();
+        assert_eq!((m.start(), m.end()), (0, haystack.len()), "{engine:?}");
+    }
+}
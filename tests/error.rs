@@ -0,0 +1,76 @@
+//! コンパイルと検索のエラーが [`Error`] にまとめられ、元のエラーを `source` でたどれることを確かめる
+use regexp::{CodeGenError, Error, EvalError, ParserErrorKind, Regex, RegexBuilder, RegexSet};
+use std::error::Error as _;
+
+/// 元のエラーと表示が同じで、`source` が元のエラーを指すことを確かめる
+fn check_source(error: &Error) {
+    let source = error.source().expect("every variant has a source");
+    assert_eq!(error.to_string(), source.to_string());
+}
+
+#[test]
+fn compile_errors() {
+    let Err(error) = Regex::new("a(") else {
+        panic!("expected an error");
+    };
+    assert!(matches!(&error, Error::Parse(e) if e.kind() == ParserErrorKind::NoRightParen));
+    check_source(&error);
+
+    let Err(error) = RegexBuilder::new("a)(").collect_errors(true).build() else {
+        panic!("expected an error");
+    };
+    assert!(matches!(&error, Error::ParseAll(_)));
+    check_source(&error);
+
+    let Err(error) = RegexBuilder::new("abcdef").size_limit(3).build() else {
+        panic!("expected an error");
+    };
+    assert!(matches!(
+        &error,
+        Error::CodeGen(CodeGenError::CompiledTooBig)
+    ));
+    check_source(&error);
+
+    let Err(error) = Regex::from_bytes(b"not a compiled regex") else {
+        panic!("expected an error");
+    };
+    assert!(matches!(&error, Error::Decode(_)));
+    check_source(&error);
+}
+
+#[test]
+fn set_error_chains_to_pattern_error() {
+    let Err(error) = RegexSet::new(["a", "b)", "c"]) else {
+        panic!("expected an error");
+    };
+    let Error::Set(1, inner) = &error else {
+        panic!("expected an error of the second pattern: {error:?}");
+    };
+    assert!(matches!(**inner, Error::Parse(_)));
+    assert_eq!(error.to_string(), format!("pattern 1: {inner}"));
+
+    // Set → Parse → ParserError の順にたどれる
+    let source = error.source().unwrap();
+    assert_eq!(source.to_string(), inner.to_string());
+    let parser_error = source.source().unwrap();
+    assert_eq!(parser_error.to_string(), inner.to_string());
+    assert!(parser_error.source().is_none());
+}
+
+/// 検索のエラーは `?` で Error に変換できる
+fn search(regex: &Regex, haystack: &str) -> Result<bool, Error> {
+    Ok(regex.try_is_match(haystack)?)
+}
+
+#[test]
+fn eval_error_converts() {
+    let regex = RegexBuilder::new("(a|aa)*b")
+        .engine(regexp::Engine::Backtrack)
+        .step_limit(100)
+        .build()
+        .unwrap();
+    let error = search(&regex, &"a".repeat(30)).unwrap_err();
+    assert!(matches!(error, Error::Eval(EvalError::StepLimitExceeded)));
+    check_source(&error);
+    assert!(search(&regex, "ab").unwrap());
+}
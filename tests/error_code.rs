@@ -0,0 +1,88 @@
+//! パースエラーの種類ごとのコードが、表示を解析しなくても区別できる固定の値であることを確かめる
+use regexp::{Error, ParserErrorKind, RegexBuilder, Syntax};
+
+/// パターン、ビルダーの設定、エラーの種類、コード
+type Case = (
+    &'static str,
+    fn(&mut RegexBuilder),
+    ParserErrorKind,
+    &'static str,
+);
+
+const CASES: &[Case] = &[
+    (r"\q", |_| (), ParserErrorKind::InvalidEscape('q'), "E0001"),
+    ("a)", |_| (), ParserErrorKind::InvalidRightParen, "E0002"),
+    ("*", |_| (), ParserErrorKind::NoPrev, "E0003"),
+    ("(a", |_| (), ParserErrorKind::NoRightParen, "E0004"),
+    ("[a", |_| (), ParserErrorKind::NoRightBracket, "E0005"),
+    ("a{2", |_| (), ParserErrorKind::NoRightBrace, "E0006"),
+    ("a{3,1}", |_| (), ParserErrorKind::InvalidRepeat, "E0007"),
+    ("(?x)", |_| (), ParserErrorKind::InvalidFlag, "E0008"),
+    ("[z-a]", |_| (), ParserErrorKind::InvalidRange, "E0009"),
+    ("", |_| (), ParserErrorKind::Empty, "E0010"),
+    (
+        "((a))",
+        |b| {
+            b.nest_limit(1);
+        },
+        ParserErrorKind::NestingTooDeep,
+        "E0011",
+    ),
+    (
+        "abcd",
+        |b| {
+            b.pattern_limit(3);
+        },
+        ParserErrorKind::PatternTooLong,
+        "E0012",
+    ),
+    (
+        "a|b",
+        |b| {
+            b.disable(Syntax::Alternation);
+        },
+        ParserErrorKind::Disabled(Syntax::Alternation),
+        "E0013",
+    ),
+    (
+        "[a-z]",
+        |b| {
+            b.class_limit(10);
+        },
+        ParserErrorKind::ClassTooLarge,
+        "E0014",
+    ),
+];
+
+#[test]
+fn codes_for_each_kind() {
+    for &(pattern, configure, kind, code) in CASES {
+        let mut builder = RegexBuilder::new(pattern);
+        configure(&mut builder);
+        let Err(Error::Parse(error)) = builder.build() else {
+            panic!("{pattern:?}: expected a parse error");
+        };
+        assert_eq!(error.kind(), kind, "{pattern:?}");
+        assert_eq!(error.code(), code, "{pattern:?}");
+        assert_eq!(kind.code(), code, "{pattern:?}");
+    }
+
+    // コードは種類ごとに異なる
+    let mut codes: Vec<_> = CASES.iter().map(|(_, _, kind, _)| kind.code()).collect();
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), CASES.len());
+}
+
+#[test]
+fn code_ignores_payload() {
+    // 種類に含まれる値はコードに影響しない
+    assert_eq!(
+        ParserErrorKind::InvalidEscape('q').code(),
+        ParserErrorKind::InvalidEscape('y').code()
+    );
+    assert_eq!(
+        ParserErrorKind::Disabled(Syntax::Group).code(),
+        ParserErrorKind::Disabled(Syntax::Look).code()
+    );
+}
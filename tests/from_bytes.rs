@@ -0,0 +1,88 @@
+//! チェックサムを合わせて書き換えたコンパイル結果を読み込んでも、検索がパニックしないことを確かめる
+//!
+//! [`Regex::to_bytes`] で書き出したバイト列の1バイトずつを別の値に書き換え、
+//! [`Regex::from_bytes`] が受け付けたものをいくつかの文字列で検索する
+use regexp::{Engine, Regex, RegexBuilder};
+
+/// 書き換える元のパターン。捕獲グループを含み、書き出したバイト列が短いものを選ぶ
+const PATTERNS: &[&str] = &["(a)(b+)c", "(a|b)*(c)", "x(y?)z{2,5}", "([0-9]+)-([a-z_])"];
+
+/// 検索に利用するエンジン。None は自動的に選ぶ
+const ENGINES: &[Option<Engine>] = &[None, Some(Engine::Backtrack), Some(Engine::PikeVm)];
+
+/// 書き換えた正規表現で検索する文字列
+const HAYSTACKS: &[&str] = &[
+    "",
+    "abbc abc ac bc",
+    "ababc cc",
+    "xyzz xzzzzz xyz",
+    "12-a 3-b -c",
+    "abcdefghijklmnopqrstuvwxyz 0123456789 ABCXYZ",
+];
+
+/// 形式を表す4バイト、形式の版とチェックサムからなる先頭の部分の長さ
+const HEADER_LEN: usize = 16;
+
+/// 書き出したバイト列と同じ 64 ビットの FNV-1a ハッシュ
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// `b` を書き換える値
+fn mutations(b: u8) -> [u8; 8] {
+    [
+        0x00,
+        0x01,
+        0x7f,
+        0xff,
+        b ^ 0x01,
+        b ^ 0x80,
+        b.wrapping_add(1),
+        b.wrapping_sub(1),
+    ]
+}
+
+/// 読み込んだ正規表現のすべての検索を実行する
+fn search(regex: &Regex) {
+    for haystack in HAYSTACKS {
+        regex.is_match(haystack);
+        regex.find(haystack);
+        regex.find_iter(haystack).count();
+        regex.captures(haystack);
+        regex.captures_iter(haystack).count();
+        regex.replace_all_template(haystack, "[$1|$2]");
+    }
+}
+
+#[test]
+fn mutated_bytes_do_not_panic() {
+    let mut loaded = 0;
+    for pattern in PATTERNS {
+        for &engine in ENGINES {
+            let mut builder = RegexBuilder::new(pattern);
+            if let Some(engine) = engine {
+                builder.engine(engine);
+            }
+            let bytes = builder.build().unwrap().to_bytes();
+            for i in HEADER_LEN..bytes.len() {
+                for b in mutations(bytes[i]) {
+                    if b == bytes[i] {
+                        continue;
+                    }
+                    let mut mutated = bytes.clone();
+                    mutated[i] = b;
+                    let sum = checksum(&mutated[HEADER_LEN..]);
+                    mutated[8..HEADER_LEN].copy_from_slice(&sum.to_le_bytes());
+                    if let Ok(regex) = Regex::from_bytes(&mutated) {
+                        search(&regex);
+                        loaded += 1;
+                    }
+                }
+            }
+        }
+    }
+    // 書き換えの多くは読み込む際に見つかるが、命令の文字などは書き換えても読み込める
+    assert!(loaded > 0);
+}
@@ -0,0 +1,198 @@
+//! JSON ファイルに書いたテストケースを、すべてのエンジンで実行する
+//!
+//! `testdata/golden` 以下の `.json` ファイルを読む。各ファイルにはテストケースの配列を書く。
+//! 期待するエラーを書いたケースはコンパイルが失敗することを、
+//! それ以外のケースはマッチと捕獲グループの範囲が一致することを確かめる。
+//! パターンに対して利用できないエンジンは読み飛ばし、一致した数と読み飛ばした数を表示する
+//!
+//! ```text
+//! [
+//!   { "pattern": "a(b)?", "haystack": "xaab", "matches": [[1, 2], [2, 4]],
+//!     "captures": [[[1, 2], null], [[2, 4], [3, 4]]] },
+//!   { "pattern": "(a", "error": "E0004" }
+//! ]
+//! ```
+use regexp::{Engine, Error, RegexBuilder};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// 実行するエンジン
+const ENGINES: [Engine; 4] = [
+    Engine::Literal,
+    Engine::Backtrack,
+    Engine::PikeVm,
+    Engine::Dfa,
+];
+
+/// マッチの範囲
+type Span = (usize, usize);
+
+/// 1つのテストケース
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    name: Option<String>, // 失敗した場合に表示する名前。省略した場合はパターンを表示する
+    pattern: String,
+    #[serde(default)]
+    haystack: String,
+    #[serde(default)]
+    ascii: bool, // RegexBuilder::ascii
+    #[serde(default)]
+    multi_line: bool, // RegexBuilder::multi_line
+    matches: Option<Vec<Span>>, // find_iter が返すマッチの範囲
+    captures: Option<Vec<Vec<Option<Span>>>>, // captures_iter が返す各グループの範囲
+    error: Option<String>,      // パースエラーのコード。命令列の生成に失敗する場合は "codegen"
+}
+
+/// 実行した結果
+#[derive(Debug, Default)]
+struct Summary {
+    passed: usize,
+    failed: Vec<String>, // 一致しなかったケースの説明
+    skipped: usize,      // パターンに対して利用できないエンジン
+}
+
+#[test]
+fn golden() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden");
+    let mut files = Vec::new();
+    collect(&root, &mut files);
+    files.sort();
+
+    let mut summary = Summary::default();
+    for file in &files {
+        let text = fs::read_to_string(file).unwrap_or_else(|e| fail(file, &e.to_string()));
+        let cases: Vec<Case> =
+            serde_json::from_str(&text).unwrap_or_else(|e| fail(file, &e.to_string()));
+        for case in &cases {
+            run(file, case, &mut summary);
+        }
+    }
+    println!(
+        "{} passed, {} skipped in {} files",
+        summary.passed,
+        summary.skipped,
+        files.len()
+    );
+    assert!(
+        summary.failed.is_empty(),
+        "{} failed:\n{}",
+        summary.failed.len(),
+        summary.failed.join("\n")
+    );
+}
+
+/// `path` がディレクトリの場合は、その下の `.json` ファイルを集める
+fn collect(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let entries = fs::read_dir(path).unwrap_or_else(|e| fail(path, &e.to_string()));
+    for entry in entries {
+        let path = entry.unwrap_or_else(|e| fail(path, &e.to_string())).path();
+        if path.is_dir() || path.extension().is_some_and(|ext| ext == "json") {
+            collect(&path, files);
+        }
+    }
+}
+
+/// ファイルを読めない場合は、理由を表示して失敗する
+fn fail(path: &Path, message: &str) -> ! {
+    panic!("{}: {message}", path.display());
+}
+
+/// ケースをすべてのエンジンで実行する
+fn run(file: &Path, case: &Case, summary: &mut Summary) {
+    let name = case.name.as_deref().unwrap_or(&case.pattern);
+    let report = |summary: &mut Summary, engine: Option<Engine>, message: String| {
+        let failure = match engine {
+            Some(engine) => format!("{}: {name} [{engine:?}]: {message}", file.display()),
+            None => format!("{}: {name}: {message}", file.display()),
+        };
+        summary.failed.push(failure);
+    };
+    let mut builder = RegexBuilder::new(&case.pattern);
+    builder.ascii(case.ascii).multi_line(case.multi_line);
+
+    if let Some(expected) = &case.error {
+        match builder.build() {
+            Ok(_) => report(
+                summary,
+                None,
+                format!("expected error {expected}, but compiled"),
+            ),
+            Err(e) => {
+                let code = match &e {
+                    Error::Parse(e) => e.code(),
+                    Error::CodeGen(_) => "codegen",
+                    _ => "other",
+                };
+                if code == expected {
+                    summary.passed += 1;
+                } else {
+                    report(
+                        summary,
+                        None,
+                        format!("expected error {expected}, found {code}: {e}"),
+                    );
+                }
+            }
+        }
+        return;
+    }
+
+    for engine in ENGINES {
+        let regex = match builder.engine(engine).build() {
+            Ok(regex) => regex,
+            Err(e) => {
+                report(summary, Some(engine), format!("failed to compile: {e}"));
+                continue;
+            }
+        };
+        if regex.engine() != engine {
+            summary.skipped += 1;
+            continue;
+        }
+        let haystack = &case.haystack;
+        let mut ok = true;
+        if let Some(expected) = &case.matches {
+            let found: Vec<Span> = regex
+                .find_iter(haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            if &found != expected {
+                report(
+                    summary,
+                    Some(engine),
+                    format!("matches {found:?}, expected {expected:?}"),
+                );
+                ok = false;
+            }
+        }
+        if let Some(expected) = &case.captures {
+            let found: Vec<Vec<Option<Span>>> = regex
+                .captures_iter(haystack)
+                .map(|caps| {
+                    caps.iter()
+                        .map(|m| m.map(|m| (m.start(), m.end())))
+                        .collect()
+                })
+                .collect();
+            if &found != expected {
+                report(
+                    summary,
+                    Some(engine),
+                    format!("captures {found:?}, expected {expected:?}"),
+                );
+                ok = false;
+            }
+        }
+        if ok {
+            summary.passed += 1;
+        }
+    }
+}
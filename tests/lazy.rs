@@ -0,0 +1,53 @@
+//! LazyRegex が初回の利用時に一度だけコンパイルし、Regex と同じく検索できることを確かめる
+use regexp::{LazyRegex, Regex};
+use std::{sync::LazyLock, thread};
+
+static DATE: LazyRegex = LazyRegex::new(r"([0-9]{4})-([0-9]{2})");
+
+/// ビルドスクリプトで埋め込む場合と同じく、書き出したコンパイル結果から読み込む
+static WORD: LazyLock<LazyRegex> = LazyLock::new(|| {
+    let bytes = Regex::new("[a-z]+").unwrap().to_bytes();
+    LazyRegex::from_bytes(Box::leak(bytes.into_boxed_slice()))
+});
+
+#[test]
+fn compiles_once_across_threads() {
+    let regexes: Vec<usize> = thread::scope(|s| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| s.spawn(|| DATE.get() as *const Regex as usize))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert!(regexes.iter().all(|&r| r == regexes[0]));
+}
+
+#[test]
+fn derefs_to_regex() {
+    let m = DATE.find("on 2024-05").unwrap();
+    assert_eq!((m.start(), m.end()), (3, 10));
+    assert_eq!(
+        DATE.captures("2024-05").unwrap().get(2).unwrap().as_str(),
+        "05"
+    );
+    assert_eq!(DATE.as_str(), r"([0-9]{4})-([0-9]{2})");
+}
+
+#[test]
+fn from_bytes() {
+    let found: Vec<_> = WORD.find_iter("ab 12 cd").map(|m| m.as_str()).collect();
+    assert_eq!(found, ["ab", "cd"]);
+}
+
+#[test]
+#[should_panic(expected = "invalid pattern")]
+fn invalid_pattern_panics_on_first_use() {
+    static INVALID: LazyRegex = LazyRegex::new("(");
+    INVALID.is_match("");
+}
+
+#[test]
+#[should_panic(expected = "invalid compiled regex")]
+fn invalid_bytes_panic_on_first_use() {
+    static INVALID: LazyRegex = LazyRegex::from_bytes(b"not a compiled regex");
+    INVALID.is_match("");
+}
@@ -0,0 +1,48 @@
+//! マッチの最小長と最大長の解析と、それより短い入力を評価せずに退けることを確かめる
+use regexp::{Engine, Regex, RegexBuilder};
+
+/// パターン、最小長、最大長 (バイト)
+const CASES: &[(&str, usize, Option<usize>)] = &[
+    ("abc", 3, Some(3)),
+    ("a|bcd", 1, Some(3)),
+    ("a?b{2,4}", 2, Some(5)),
+    ("x*", 0, None),
+    ("(ab)+c", 3, None),
+    ("é[a-z]", 3, Some(3)),
+    ("[aé]", 1, Some(2)),
+    ("日本|語", 3, Some(6)),
+    (r"^\bab$", 2, Some(2)),
+    ("a{3,}", 3, None),
+];
+
+#[test]
+fn min_and_max_len() {
+    for &(pattern, min, max) in CASES {
+        let regex = Regex::new(pattern).unwrap();
+        assert_eq!(regex.min_len(), min, "{pattern:?}");
+        assert_eq!(regex.max_len(), max, "{pattern:?}");
+        if let Some(m) = regex.find("xx abc éz 日本 bbbb aaaa ab") {
+            let len = m.end() - m.start();
+            assert!(
+                min <= len && max.is_none_or(|max| len <= max),
+                "{pattern:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn short_haystack_is_rejected_without_evaluating() {
+    let regex = RegexBuilder::new("(a|b)+cd")
+        .engine(Engine::PikeVm)
+        .build()
+        .unwrap();
+    assert_eq!(regex.min_len(), 3);
+    let (m, stats) = regex.find_with_stats("acd");
+    assert!(m.unwrap().is_some());
+    assert!(stats.steps > 0);
+
+    let (m, stats) = regex.find_with_stats("cd");
+    assert!(m.unwrap().is_none());
+    assert_eq!(stats.steps, 0);
+}
@@ -0,0 +1,53 @@
+//! 寛容なモードで、誤ったエスケープシーケンスと繰り返す式のない量指定子を文字として扱うことを確かめる
+use regexp::{Flags, ParserErrorKind, RegexBuilder, parse, parse_all_lenient};
+
+/// パターン、寛容なモードでの意味を厳密なモードで書いたパターン、厳密なモードでのエラー
+const CASES: &[(&str, &str, ParserErrorKind)] = &[
+    (r"\q", "q", ParserErrorKind::InvalidEscape('q')),
+    (r"a\yb+", "ayb+", ParserErrorKind::InvalidEscape('y')),
+    (r"[\q-]", "[q-]", ParserErrorKind::InvalidEscape('q')),
+    ("*a", r"\*a", ParserErrorKind::NoPrev),
+    ("+", r"\+", ParserErrorKind::NoPrev),
+    ("(+b)", r"(\+b)", ParserErrorKind::NoPrev),
+    ("a|*b", r"a|\*b", ParserErrorKind::NoPrev),
+    ("(?:+x)+", r"(?:\+x)+", ParserErrorKind::NoPrev),
+];
+
+#[test]
+fn lenient_parse() {
+    for &(pattern, strict, kind) in CASES {
+        let expected = parse(strict, Flags::default()).unwrap();
+        assert_eq!(
+            parse_all_lenient(pattern, Flags::default()).unwrap(),
+            expected,
+            "{pattern:?}"
+        );
+        assert_eq!(
+            parse(pattern, Flags::default()).unwrap_err().kind(),
+            kind,
+            "{pattern:?}"
+        );
+    }
+}
+
+#[test]
+fn lenient_builder() {
+    let regex = RegexBuilder::new(r"*\q+").lenient(true).build().unwrap();
+    let found: Vec<_> = regex.find_iter("*q *qqq q").map(|m| m.as_str()).collect();
+    assert_eq!(found, ["*q", "*qqq"]);
+    assert!(RegexBuilder::new(r"*\q+").build().is_err());
+}
+
+/// 寛容なモードでも、文字として扱えない誤りはエラーにする
+#[test]
+fn lenient_errors() {
+    for (pattern, kind) in [
+        ("{2}", ParserErrorKind::NoPrev),
+        ("a)", ParserErrorKind::InvalidRightParen),
+        ("(a", ParserErrorKind::NoRightParen),
+        ("[b-a]", ParserErrorKind::InvalidRange),
+    ] {
+        let errors = parse_all_lenient(pattern, Flags::default()).unwrap_err();
+        assert_eq!(errors[0].kind(), kind, "{pattern:?}");
+    }
+}
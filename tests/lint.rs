@@ -0,0 +1,66 @@
+//! 疑わしいパターンに対する警告を、コンパイルした Regex とともに返すことを確かめる
+//!
+//! 警告はコンパイルを妨げない
+use regexp::{Language, Regex, WarningKind, set_language};
+
+/// パターンと、見つかる警告の種類と範囲（バイト）
+type Case = (&'static str, &'static [(WarningKind, usize, usize)]);
+
+const CASES: &[Case] = &[
+    ("a|b|a", &[(WarningKind::DuplicateBranch, 4, 5)]),
+    ("ab|ab", &[(WarningKind::DuplicateBranch, 3, 5)]),
+    ("a$b", &[(WarningKind::MisplacedAnchor, 1, 2)]),
+    ("a^b", &[(WarningKind::MisplacedAnchor, 1, 2)]),
+    ("(a*)*", &[(WarningKind::NestedQuantifier, 0, 5)]),
+    ("(?:a+)+", &[(WarningKind::NestedQuantifier, 0, 7)]),
+    ("x()", &[(WarningKind::EmptyGroup, 1, 3)]),
+    ("(^)", &[(WarningKind::EmptyGroup, 0, 3)]),
+    (
+        "a$b|c|c",
+        &[
+            (WarningKind::MisplacedAnchor, 1, 2),
+            (WarningKind::DuplicateBranch, 6, 7),
+        ],
+    ),
+];
+
+#[test]
+fn suspicious_patterns_warn() {
+    for &(pattern, expected) in CASES {
+        let regex = Regex::new(pattern).unwrap();
+        let found: Vec<_> = regex
+            .warnings()
+            .iter()
+            .map(|w| (w.kind, w.span.byte_start, w.span.byte_end))
+            .collect();
+        assert_eq!(found, expected, "{pattern:?}");
+    }
+}
+
+#[test]
+fn ordinary_patterns_do_not_warn() {
+    for pattern in [
+        "a|b",
+        "a$|b",
+        "^a|b$",
+        "(a{2}){3}",
+        r"a\bb",
+        "(ab)+",
+        "a(?:)b",
+    ] {
+        let regex = Regex::new(pattern).unwrap();
+        assert_eq!(regex.warnings(), [], "{pattern:?}");
+    }
+}
+
+#[test]
+fn warning_display() {
+    set_language(Language::English);
+    let regex = Regex::new("日本|日本").unwrap();
+    let warning = &regex.warnings()[0];
+    // 位置は文字数で表示する
+    assert_eq!(
+        warning.to_string(),
+        "Warning: duplicate alternation branch: pos = 3"
+    );
+}
@@ -0,0 +1,51 @@
+//! リテラルだけのパターンが、命令列を評価せずに部分文字列の検索で処理されることを確かめる
+use regexp::{Engine, Regex};
+
+#[test]
+fn literal_engine_is_selected() {
+    // 捕獲グループは検索に影響しないため、グループで囲んだリテラルもリテラルとして探す
+    for pattern in ["abc", "日本語", r"a\+b", "(?:ab)c", "(ab)"] {
+        assert_eq!(
+            Regex::new(pattern).unwrap().engine(),
+            Engine::Literal,
+            "{pattern:?}"
+        );
+    }
+    for pattern in ["a+", "a|b", "^ab", r"\bab", "[ab]"] {
+        assert_ne!(
+            Regex::new(pattern).unwrap().engine(),
+            Engine::Literal,
+            "{pattern:?}"
+        );
+    }
+}
+
+#[test]
+fn literal_find_iter_and_replace_all() {
+    let regex = Regex::new("aa").unwrap();
+    let found: Vec<_> = regex
+        .find_iter("aaaaa baa")
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    assert_eq!(found, [(0, 2), (2, 4), (7, 9)]);
+    assert_eq!(regex.replace_all("aaaaa baa", "x"), "xxa bx");
+
+    let regex = Regex::new("日本").unwrap();
+    assert_eq!(
+        regex.replace_all_template("日本語と日本", "<$0>"),
+        "<日本>語と<日本>"
+    );
+
+    let regex = Regex::new("x(ab)").unwrap();
+    let caps = regex.captures("zxab").unwrap();
+    assert_eq!(caps.get(1).map(|m| (m.start(), m.end())), Some((2, 4)));
+}
+
+#[test]
+fn literal_search_runs_no_instructions() {
+    let regex = Regex::new("needle").unwrap();
+    let haystack = format!("{}needle", "hay ".repeat(1000));
+    let (m, stats) = regex.find_with_stats(&haystack);
+    assert_eq!(m.unwrap().unwrap().start(), 4000);
+    assert_eq!(stats.steps, 0);
+}
@@ -0,0 +1,78 @@
+//! エラーと警告の文言を英語と日本語で切り替えられ、種類とコードは言語によらないことを確かめる
+//!
+//! 言語はプロセス全体の設定のため、1つのテストの中で切り替える
+use regexp::{Error, Language, Regex, RegexBuilder, Syntax, language, set_language};
+
+fn compile_error(builder: &RegexBuilder) -> String {
+    builder.build().unwrap_err().to_string()
+}
+
+#[test]
+fn switch_language() {
+    // 既定の言語は ja フィーチャーで決まる
+    let default = if cfg!(feature = "ja") {
+        Language::Japanese
+    } else {
+        Language::English
+    };
+    assert_eq!(language(), default);
+
+    let parse = RegexBuilder::new("a)");
+    let disabled = {
+        let mut builder = RegexBuilder::new("a|b");
+        builder.disable(Syntax::Alternation);
+        builder
+    };
+    let too_big = {
+        let mut builder = RegexBuilder::new("abcdef");
+        builder.size_limit(2);
+        builder
+    };
+    let code = |builder: &RegexBuilder| match builder.build() {
+        Err(Error::Parse(e)) => e.code(),
+        other => panic!("expected a parse error, got {other:?}"),
+    };
+
+    set_language(Language::English);
+    assert_eq!(language(), Language::English);
+    assert_eq!(
+        compile_error(&parse),
+        "ParseError: invalid right parenthesis: pos = 1"
+    );
+    assert_eq!(
+        compile_error(&disabled),
+        "ParseError: disabled syntax: pos = 0, syntax = alternation"
+    );
+    assert!(compile_error(&too_big).contains("compiled program too big"));
+    let decode = Regex::from_bytes(b"nope").unwrap_err().to_string();
+    assert!(decode.contains("not a serialized regex"), "{decode}");
+    let warning = Regex::new("a|a").unwrap().warnings()[0].to_string();
+    assert!(
+        warning.contains("duplicate alternation branch"),
+        "{warning}"
+    );
+    let english_code = code(&parse);
+
+    set_language(Language::Japanese);
+    assert_eq!(language(), Language::Japanese);
+    assert_eq!(
+        compile_error(&parse),
+        "ParseError: 対応する開き括弧のない閉じ括弧: pos = 1"
+    );
+    assert_eq!(
+        compile_error(&disabled),
+        "ParseError: 無効にした構文: pos = 0, syntax = 選択"
+    );
+    assert!(compile_error(&too_big).contains("命令数が上限を超える"));
+    let decode = Regex::from_bytes(b"nope").unwrap_err().to_string();
+    assert!(
+        decode.contains("正規表現を書き出したバイト列でない"),
+        "{decode}"
+    );
+    let warning = Regex::new("a|a").unwrap().warnings()[0].to_string();
+    assert!(warning.contains("重複した選択肢"), "{warning}");
+    // コードは言語によらない
+    assert_eq!(code(&parse), english_code);
+
+    set_language(default);
+}
@@ -0,0 +1,54 @@
+//! バックトラックの回数が指数的に増えうるパターンに、Pike VM が選ばれることを確かめる
+//!
+//! 上限のある繰り返しも繰り返しとして扱われなければ、これらのパターンは
+//! バックトラック型の評価器に渡され、40 文字ほどの入力でも検索が終わらなくなる
+use regexp::{Engine, RegexBuilder};
+
+/// 評価する命令数の上限。入力の長さに比例する検索なら十分に収まる
+const STEP_LIMIT: u64 = 1_000_000;
+
+/// パターンと、先頭の位置からはマッチしない入力
+const CASES: &[(&str, &str)] = &[
+    ("(a{1,2})*$", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab"),
+    (
+        "(([^a]){0,2})*$",
+        "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbba",
+    ),
+    ("(a|aa){0,40}c", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"),
+    ("(a?){0,1000}c", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"),
+];
+
+#[test]
+fn bounded_repetition_selects_pike_vm() {
+    for &(pattern, haystack) in CASES {
+        let regex = RegexBuilder::new(pattern)
+            .step_limit(STEP_LIMIT)
+            .build()
+            .unwrap();
+        assert_eq!(regex.engine(), Engine::PikeVm, "{pattern}");
+        assert!(regex.try_find(haystack).is_ok(), "{pattern}");
+    }
+}
+
+#[test]
+fn bounded_repetition_still_matches() {
+    let cases = [
+        ("(a{1,2})*$", "aaab", Some((4, 4))),
+        ("(a|aa){0,40}c", "xaaac", Some((1, 5))),
+        ("(a?){0,1000}c", "aac", Some((0, 3))),
+    ];
+    for (pattern, haystack, expected) in cases {
+        let regex = RegexBuilder::new(pattern)
+            .step_limit(STEP_LIMIT)
+            .build()
+            .unwrap();
+        let found = regex.try_find(haystack).unwrap();
+        assert_eq!(found.map(|m| (m.start(), m.end())), expected, "{pattern}");
+    }
+}
+
+#[test]
+fn bounded_repetition_without_ambiguity_keeps_backtracking() {
+    let regex = RegexBuilder::new("a{2,40}b").build().unwrap();
+    assert_ne!(regex.engine(), Engine::PikeVm);
+}
@@ -0,0 +1,55 @@
+//! コンパイルの計測結果が、生成した命令列と AST の大きさに対応していることを確かめる
+use regexp::{Error, RegexBuilder};
+
+#[test]
+fn insts_match_program() {
+    for pattern in ["a", "abc", "a|b", "(a|b)*c", "x(ab|ab|ac)", "a{3}"] {
+        let (regex, metrics) = RegexBuilder::new(pattern).build_with_metrics().unwrap();
+        assert_eq!(
+            metrics.insts,
+            regex.program().lines().count(),
+            "{pattern:?}"
+        );
+        // 計測しない場合と同じ命令列を生成する
+        let plain = RegexBuilder::new(pattern).build().unwrap();
+        assert_eq!(regex.program(), plain.program(), "{pattern:?}");
+    }
+}
+
+#[test]
+fn insts_saved_by_optimizer() {
+    // 文字だけの選択は文字クラスにまとめられ、分岐の命令が減る
+    let (_, metrics) = RegexBuilder::new("a|b|c").build_with_metrics().unwrap();
+    assert_eq!(metrics.insts, 2);
+    assert!(metrics.insts_saved > 0);
+
+    // 分岐のないパターンでは減らない
+    let (_, metrics) = RegexBuilder::new("abc").build_with_metrics().unwrap();
+    assert_eq!(metrics.insts_saved, 0);
+}
+
+#[test]
+fn ast_nodes_grow_with_pattern() {
+    let count = |pattern: &str| {
+        let (_, metrics) = RegexBuilder::new(pattern).build_with_metrics().unwrap();
+        metrics.ast_nodes
+    };
+    assert!(count("ab") < count("abc"));
+    assert!(count("abc") < count("a|b|c"));
+    // 簡約する前の AST を数えるため、同じ命令列になるパターンでも数が異なる
+    assert!(count("a") < count("(?:(?:a))"));
+}
+
+#[test]
+fn errors_are_returned() {
+    assert!(matches!(
+        RegexBuilder::new("a(").build_with_metrics(),
+        Err(Error::Parse(_))
+    ));
+    assert!(matches!(
+        RegexBuilder::new(&"ab".repeat(100))
+            .size_limit(100)
+            .build_with_metrics(),
+        Err(Error::CodeGen(_))
+    ));
+}
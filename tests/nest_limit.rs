@@ -0,0 +1,65 @@
+//! 入れ子の深すぎるパターンが、スタックをあふれさせずに入れ子の深さのエラーになることを確かめる
+use regexp::{Error, Flags, ParserErrorKind, RegexBuilder, parse_all};
+
+/// `n` 重の捕獲グループで `a` を囲んだパターン
+fn groups(n: usize) -> String {
+    format!("{}a{}", "(".repeat(n), ")".repeat(n))
+}
+
+fn nest_error(result: Result<regexp::Regex, Error>) -> usize {
+    match result {
+        Err(Error::Parse(e)) if e.kind() == ParserErrorKind::NestingTooDeep => e.span().char_start,
+        other => panic!("expected a nesting error, got {other:?}"),
+    }
+}
+
+#[test]
+fn limit_is_configurable() {
+    for limit in [1, 2, 10] {
+        let n = limit as usize;
+        assert!(
+            RegexBuilder::new(&groups(n))
+                .nest_limit(limit)
+                .build()
+                .is_ok(),
+            "{limit}"
+        );
+        // 上限を超えた最も外側のグループをエラーの位置とする
+        let start = nest_error(RegexBuilder::new(&groups(n + 1)).nest_limit(limit).build());
+        assert_eq!(start, n, "{limit}");
+    }
+}
+
+#[test]
+fn repetitions_count_as_nesting() {
+    let nested = "(?:(?:(?:a)*)*)*";
+    assert!(RegexBuilder::new(nested).nest_limit(6).build().is_ok());
+    nest_error(RegexBuilder::new(nested).nest_limit(5).build());
+}
+
+#[test]
+fn default_limit_rejects_deep_patterns() {
+    // 既定の上限は 250
+    assert!(RegexBuilder::new(&groups(250)).build().is_ok());
+    assert_eq!(nest_error(RegexBuilder::new(&groups(251)).build()), 250);
+
+    // 非常に深いパターンでもスタックはあふれない
+    let deep = groups(100_000);
+    assert_eq!(nest_error(RegexBuilder::new(&deep).build()), 250);
+    let errors = parse_all(&deep, Flags::default()).unwrap_err();
+    assert_eq!(errors[0].kind(), ParserErrorKind::NestingTooDeep);
+    let deep = format!("{}a{}", "(?:".repeat(100_000), ")*".repeat(100_000));
+    nest_error(RegexBuilder::new(&deep).build());
+}
+
+#[test]
+fn alternation_does_not_nest() {
+    // 連続した選択肢は1段として数える
+    let alternation = vec!["a"; 10_000].join("|");
+    assert!(
+        RegexBuilder::new(&alternation)
+            .nest_limit(1)
+            .build()
+            .is_ok()
+    );
+}
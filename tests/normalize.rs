@@ -0,0 +1,77 @@
+//! パターンを単純化した等価なパターンに書き直し、同じ意味のパターンが同じ文字列になることを確かめる
+use regexp::{Flags, Regex, normalize};
+
+fn norm(pattern: &str) -> String {
+    normalize(pattern, Flags::default()).unwrap()
+}
+
+#[test]
+fn redundant_syntax_is_removed() {
+    for (pattern, expected) in [
+        ("(?:ab){1}|(?:ab)", "ab"),
+        (r"(a+)+\d{0,}", r"(a+)+\d*"),
+        ("x{2,2}", "x{2}"),
+        ("(?:)a", "a"),
+        ("(?:a|b)|(?:b|a)", "a|b"),
+        ("[abc]|[a-c]", "[a-c]"),
+    ] {
+        assert_eq!(norm(pattern), expected, "{pattern:?}");
+    }
+}
+
+#[test]
+fn flags_are_spelled_out() {
+    // 書き直したパターンは既定のフラグで解釈されるため、必要な箇所だけにフラグを付ける
+    assert_eq!(norm("(?m)^a$"), "(?m)^(?-m)a(?m)$(?-m)");
+    assert_eq!(norm(r"(?a:\b)x"), r"(?a)\b(?-a)x");
+    assert_eq!(norm(r"(?a)\d\w"), "[0-9][0-9A-Z_a-z]");
+    let flags = Flags {
+        ascii: true,
+        multi_line: true,
+    };
+    assert_eq!(normalize(r"^\d", flags).unwrap(), "(?m)^(?-m)[0-9]");
+}
+
+#[test]
+fn captures_are_kept() {
+    assert_eq!(norm("((a))"), "((a))");
+    let pattern = "(?:(a)|b){1}(c{1,1})";
+    let normalized = norm(pattern);
+    let haystack = "xacbc";
+    let groups = |pattern: &str| {
+        let caps = Regex::new(pattern).unwrap().captures(haystack).unwrap();
+        caps.iter()
+            .map(|m| m.map(|m| (m.start(), m.end())))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(groups(&normalized), groups(pattern));
+}
+
+#[test]
+fn normalizing_is_idempotent_and_equivalent() {
+    let haystack = "aab abab\nxyz 123 aaaa b";
+    for pattern in [
+        "(?:ab){1}|(?:ab)",
+        "(?:a*)*b",
+        "(?m)^(?:x|y)+z$",
+        r"(?a)\w{1,}",
+        "(?:a?)+|b",
+        "a{0,1}b{1}",
+    ] {
+        let normalized = norm(pattern);
+        assert_eq!(norm(&normalized), normalized, "{pattern:?}");
+        let found = |pattern: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .find_iter(haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found(&normalized), found(pattern), "{pattern:?}");
+    }
+}
+
+#[test]
+fn errors_are_returned() {
+    assert!(normalize("a(", Flags::default()).is_err());
+}
@@ -0,0 +1,62 @@
+//! のぞき穴最適化で命令列が小さくなり、検索結果は変わらないことを確かめる
+use regexp::RegexBuilder;
+
+#[test]
+fn alternation_of_chars_becomes_class() {
+    let (regex, metrics) = RegexBuilder::new("a|b|c").build_with_metrics().unwrap();
+    assert_eq!(regex.program(), "0000: class #0 [a-c]\n0001: match\n");
+    assert_eq!(metrics.insts, 2);
+    assert!(metrics.insts_saved > 0);
+}
+
+#[test]
+fn loop_body_is_merged() {
+    let (regex, metrics) = RegexBuilder::new("x(a|b)*y").build_with_metrics().unwrap();
+    assert_eq!(
+        regex.program(),
+        "0000: char x\n\
+         0001: split 0002, 0004\n\
+         0002: class #0 [a-b]\n\
+         0003: jump 0001\n\
+         0004: char y\n\
+         0005: match\n"
+    );
+    assert_eq!(metrics.insts, 6);
+    assert_eq!(metrics.insts_saved, 3);
+}
+
+/// 同じ文字クラスは1つの表を共有し、共通の後続は1つにまとめる
+#[test]
+fn identical_subprograms_are_shared() {
+    let regex = RegexBuilder::new("(?:a|b)(?:a|b)").build().unwrap();
+    assert_eq!(
+        regex.program(),
+        "0000: class #0 [a-b]\n0001: class #0 [a-b]\n0002: match\n"
+    );
+    let program = RegexBuilder::new("abc|abd").build().unwrap().program();
+    assert_eq!(program.matches("match").count(), 1, "{program}");
+}
+
+#[test]
+fn optimized_program_matches_the_same() {
+    let haystack = "xaby xy foo baz bar abcd abd ab";
+    for pattern in [
+        "a|b|c",
+        "x(a|b)*y",
+        "foo|ba(r|z)",
+        "ab(c|d)|abd",
+        "(a|ab)(c|bcd)?",
+    ] {
+        let regex = RegexBuilder::new(pattern).build().unwrap();
+        let expected = regex::Regex::new(pattern).unwrap();
+        let found: Vec<_> = regex
+            .find_iter(haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        let expected: Vec<_> = expected
+            .find_iter(haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        assert_eq!(found, expected, "{pattern:?}");
+    }
+}
@@ -0,0 +1,66 @@
+//! 並列に検索した結果が、逐次的に検索した結果と一致することを確かめる
+use regexp::{Engine, Regex, RegexBuilder};
+
+/// チャンクの境界がマッチの途中に来るよう、周期が 2 のべきと揃わない文字列を繰り返す
+fn haystack() -> String {
+    "ab12 foo_bar 日本語 x9y, aab ".repeat(20_000)
+}
+
+#[test]
+fn par_find_all_matches_find_iter() {
+    let haystack = haystack();
+    for pattern in [
+        "foo",
+        "[0-9]{2}",
+        "a+b",
+        r"\bfoo_bar\b",
+        "日本",
+        "x?",
+        "(ab|b1)2",
+        "[a-z]*",
+    ] {
+        let regex = Regex::new(pattern).unwrap();
+        let expected: Vec<_> = regex
+            .find_iter(&haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        for threads in [0, 2, 3, 8] {
+            let found: Vec<_> = regex
+                .par_find_all(&haystack, threads)
+                .into_iter()
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            assert_eq!(found, expected, "{pattern} on {threads} threads");
+        }
+    }
+}
+
+#[test]
+fn par_find_all_short_haystack() {
+    let regex = Regex::new("[0-9]+").unwrap();
+    let found: Vec<_> = regex
+        .par_find_all("a1b22", 4)
+        .into_iter()
+        .map(|m| m.as_str())
+        .collect();
+    assert_eq!(found, ["1", "22"]);
+}
+
+/// DFA を選ぶパターンは、チャンクごとの検索でも DFA を使い、命令を評価しない。
+/// Pike VM に切り替わると、チャンクを読む間に命令数の上限を超えてパニックする
+#[test]
+fn par_find_all_uses_dfa() {
+    let haystack = haystack();
+    let regex = RegexBuilder::new("[0-9]{2}y")
+        .step_limit(1000)
+        .build()
+        .unwrap();
+    assert_eq!(regex.engine(), Engine::Dfa);
+    let expected: Vec<_> = regex.find_iter(&haystack).map(|m| m.start()).collect();
+    let found: Vec<_> = regex
+        .par_find_all(&haystack, 4)
+        .into_iter()
+        .map(|m| m.start())
+        .collect();
+    assert_eq!(found, expected);
+}
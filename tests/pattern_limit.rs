@@ -0,0 +1,60 @@
+//! パターンの長さの上限を、パースする前にバイト数で確かめることを確かめる
+use regexp::{Error, ParserError, ParserErrorKind, Regex, RegexBuilder, RegexSetBuilder};
+
+fn too_long(result: Result<Regex, Error>) -> ParserError {
+    match result {
+        Err(Error::Parse(e)) if e.kind() == ParserErrorKind::PatternTooLong => e,
+        other => panic!("expected a length error, got {other:?}"),
+    }
+}
+
+#[test]
+fn limit_counts_bytes() {
+    assert!(RegexBuilder::new("abc").pattern_limit(3).build().is_ok());
+    let error = too_long(RegexBuilder::new("abcd").pattern_limit(3).build());
+    assert_eq!(error.span().byte_start, 3);
+    assert_eq!(error.span().byte_end, 4);
+
+    // "日本" は 6 バイト
+    assert!(RegexBuilder::new("日本").pattern_limit(6).build().is_ok());
+    let error = too_long(RegexBuilder::new("日本").pattern_limit(5).build());
+    // 上限の位置を含む文字からパターンの末尾までを範囲とする
+    assert_eq!(error.span().byte_start, 3);
+    assert_eq!(error.span().char_start, 1);
+}
+
+#[test]
+fn length_is_checked_before_parsing() {
+    // 誤ったパターンでも、長すぎる場合は長さのエラーになる
+    too_long(RegexBuilder::new("a(((").pattern_limit(2).build());
+    let Err(Error::ParseAll(errors)) = RegexBuilder::new("a(((")
+        .pattern_limit(2)
+        .collect_errors(true)
+        .build()
+    else {
+        panic!("expected all parse errors");
+    };
+    assert_eq!(errors.0.len(), 1);
+    assert_eq!(errors.0[0].kind(), ParserErrorKind::PatternTooLong);
+    let errors = RegexBuilder::new("a(((")
+        .pattern_limit(2)
+        .validate()
+        .unwrap_err();
+    assert_eq!(errors.0.len(), 1);
+    assert_eq!(errors.0[0].kind(), ParserErrorKind::PatternTooLong);
+}
+
+#[test]
+fn set_checks_each_pattern() {
+    // まとめたパターンは上限より長くなるが、各パターンが上限以下であればよい
+    let mut builder = RegexSetBuilder::new(["abc", "def", "ghi"]);
+    builder.options().pattern_limit(3);
+    assert!(builder.build().is_ok());
+
+    let mut builder = RegexSetBuilder::new(["abc", "defg"]);
+    builder.options().pattern_limit(3);
+    let Err(Error::Set(1, error)) = builder.build() else {
+        panic!("expected an error of the second pattern");
+    };
+    too_long(Err(*error));
+}
@@ -0,0 +1,58 @@
+//! `--pre` のコマンドを実行できない場合と失敗した場合に、終了コード 2 で終わることを確かめる
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{self, Command},
+};
+
+/// エラーがあった場合の終了コード
+const EXIT_ERROR: i32 = 2;
+
+/// `foo` を含むファイルを一時ディレクトリに作り、そのパスを返す
+fn input(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("regexp-pre-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, "foo\n").unwrap();
+    path
+}
+
+/// `--pre` に `command` を指定して `paths` を検索し、終了コードを返す
+fn status(command: &str, paths: &[PathBuf]) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_regexp"))
+        .arg(format!("--pre={command}"))
+        .arg("foo")
+        .args(paths)
+        .output()
+        .unwrap()
+        .status
+        .code()
+}
+
+#[test]
+fn spawn_failure() {
+    let path = input("spawn.txt");
+    assert_eq!(status("./no-such-preprocessor", &[path]), Some(EXIT_ERROR));
+}
+
+#[cfg(unix)]
+#[test]
+fn non_zero_exit() {
+    let path = input("exit.txt");
+    assert_eq!(status("false", &[path]), Some(EXIT_ERROR));
+}
+
+/// ほかのファイルに選んだ行があっても、エラーがあれば終了コードは 2 になる
+#[cfg(unix)]
+#[test]
+fn failure_with_match() {
+    let failing = input("failing.txt");
+    let matching = input("matching.txt");
+    let output = Command::new(env!("CARGO_BIN_EXE_regexp"))
+        .args(["--pre=false", "--pre-glob=failing.txt", "foo"])
+        .args([&failing, &matching])
+        .output()
+        .unwrap();
+    assert!(!output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(EXIT_ERROR));
+}
@@ -0,0 +1,92 @@
+//! 選択、連接、後置演算子の結合の強さと、グループやフラグの区切りに従って AST が組み立てられることを確かめる
+use regexp::{AST, AstNode, Flags, NodeId, ParserErrorKind, parse};
+
+/// AST を S 式で書き出す。要素が1つの連接は省き、結合の仕方だけが見えるようにする
+fn sexp(ast: &AST, id: NodeId) -> String {
+    match &ast[id] {
+        AstNode::Char(c) => c.to_string(),
+        AstNode::Class(_) => "class".to_string(),
+        AstNode::Plus(e) => format!("(+ {})", sexp(ast, *e)),
+        AstNode::Star(e) => format!("(* {})", sexp(ast, *e)),
+        AstNode::Question(e) => format!("(? {})", sexp(ast, *e)),
+        AstNode::Repeat(e, min, max) => match max {
+            Some(max) => format!("({{{min},{max}}} {})", sexp(ast, *e)),
+            None => format!("({{{min},}} {})", sexp(ast, *e)),
+        },
+        AstNode::Look(look) => format!("{look:?}"),
+        AstNode::Group(e, index) => format!("(group{index} {})", sexp(ast, *e)),
+        AstNode::Or(a, b) => format!("(| {} {})", sexp(ast, *a), sexp(ast, *b)),
+        AstNode::Seq(seq) if seq.is_empty() => "()".to_string(),
+        AstNode::Seq(seq) if seq.len() == 1 => sexp(ast, seq[0]),
+        AstNode::Seq(seq) => {
+            let items: Vec<_> = seq.iter().map(|&e| sexp(ast, e)).collect();
+            format!("(seq {})", items.join(" "))
+        }
+        other => format!("{other:?}"),
+    }
+}
+
+fn tree(pattern: &str) -> String {
+    let ast = parse(pattern, Flags::default()).unwrap();
+    sexp(&ast, ast.root())
+}
+
+#[test]
+fn binding_strength() {
+    for (pattern, expected) in [
+        // 選択は連接より、連接は後置演算子より弱く結合する
+        ("ab|cd", "(| (seq a b) (seq c d))"),
+        ("ab*", "(seq a (* b))"),
+        ("a|b*c", "(| a (seq (* b) c))"),
+        // 選択は右に結合する
+        ("a|b|c", "(| a (| b c))"),
+        // 後置演算子は左から順に重ねる
+        ("a**", "(* (* a))"),
+        ("a+?", "(? (+ a))"),
+        ("a{2}?", "(? ({2,2} a))"),
+        ("ab{2,}", "(seq a ({2,} b))"),
+    ] {
+        assert_eq!(tree(pattern), expected, "{pattern:?}");
+    }
+}
+
+#[test]
+fn groups_delimit_operands() {
+    for (pattern, expected) in [
+        ("(ab)+", "(+ (group1 (seq a b)))"),
+        ("(?:a|b)c", "(seq (| a b) c)"),
+        ("(a|b)(c)", "(seq (group1 (| a b)) (group2 c))"),
+        ("((a)|b)", "(group1 (| (group2 a) b))"),
+        // 空の選択肢も1つの式として並べる
+        ("a||b", "(| a (| () b))"),
+        ("|a", "(| () a)"),
+        ("(|)", "(group1 (| () ()))"),
+        // フラグはどの結合にも加わらない
+        ("(?m)a|b", "(| a b)"),
+        ("a(?a)b|c", "(| (seq a b) c)"),
+        ("(?a:a|b)c", "(seq (| a b) c)"),
+        (r"^a|b$", "(| (seq Start a) (seq b End))"),
+    ] {
+        assert_eq!(tree(pattern), expected, "{pattern:?}");
+    }
+}
+
+#[test]
+fn misplaced_operators_are_errors() {
+    for (pattern, kind, start) in [
+        ("*a", ParserErrorKind::NoPrev, 0),
+        ("a|*", ParserErrorKind::NoPrev, 2),
+        ("(+)", ParserErrorKind::NoPrev, 1),
+        ("(?m)*", ParserErrorKind::NoPrev, 4),
+        ("(?:a", ParserErrorKind::NoRightParen, 0),
+        ("a)b", ParserErrorKind::InvalidRightParen, 1),
+        ("a{3,2}", ParserErrorKind::InvalidRepeat, 1),
+    ] {
+        let e = parse(pattern, Flags::default()).unwrap_err();
+        assert_eq!(
+            (e.kind(), e.span().byte_start),
+            (kind, start),
+            "{pattern:?}"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! マッチの開始位置の候補を探す前処理を確かめる
+//!
+//! リテラルのパターンが [`Memmem`] を通して検索されること、
+//! [`RegexBuilder::prefilter`] で与えた前処理が組み込みの前処理の代わりに使われることを確かめる
+use regexp::{Engine, Memmem, Prefilter, Regex, RegexBuilder};
+
+/// マッチの範囲を集める
+fn spans(regex: &Regex, haystack: &str) -> Vec<(usize, usize)> {
+    regex
+        .find_iter(haystack)
+        .map(|m| (m.start(), m.end()))
+        .collect()
+}
+
+#[test]
+fn memmem_finds_literal() {
+    let memmem = Memmem::new("ab").unwrap();
+    assert_eq!(memmem.needle(), b"ab");
+    assert_eq!(memmem.find(b"xxabab", 0), Some(2));
+    assert_eq!(memmem.find(b"xxabab", 3), Some(4));
+    assert_eq!(memmem.find(b"xxabab", 5), None);
+    assert_eq!(memmem.find(b"xxabab", 7), None);
+    assert!(Memmem::new("").is_none());
+}
+
+#[test]
+fn literal_engine_uses_prefilter() {
+    let regex = Regex::new("日本").unwrap();
+    assert_eq!(regex.engine(), Engine::Literal);
+    let (m, stats) = regex.find_with_stats("x日本語");
+    let m = m.unwrap().unwrap();
+    assert_eq!((m.start(), m.end()), (1, 7));
+    assert_eq!(stats.prefilter_hits, 1);
+    assert_eq!(stats.steps, 0);
+
+    assert_eq!(spans(&regex, "日本日本 日本"), [(0, 6), (6, 12), (13, 19)]);
+    let (m, stats) = regex.find_with_stats("日曜");
+    assert!(m.unwrap().is_none());
+    assert_eq!(stats.prefilter_hits, 0);
+}
+
+#[test]
+fn builtin_prefilter_skips_to_candidates() {
+    for engine in [Engine::Backtrack, Engine::PikeVm, Engine::Dfa] {
+        let regex = RegexBuilder::new("(foo|bar)!+")
+            .engine(engine)
+            .build()
+            .unwrap();
+        let (m, stats) = regex.find_with_stats("xx foo bar! foo!");
+        let m = m.unwrap().unwrap();
+        assert_eq!((m.start(), m.end()), (7, 11), "{engine:?}");
+        assert!(stats.prefilter_hits > 0, "{engine:?}");
+    }
+}
+
+/// 次の行の先頭を候補として返す
+#[derive(Debug)]
+struct LineStart;
+
+impl Prefilter for LineStart {
+    fn find(&self, haystack: &[u8], at: usize) -> Option<usize> {
+        if at == 0 {
+            return Some(0);
+        }
+        let newline = haystack.get(at - 1..)?.iter().position(|&b| b == b'\n')?;
+        Some(at + newline)
+    }
+}
+
+#[test]
+fn custom_prefilter() {
+    let haystack = "key: 1\n  key: 2\nkey: 3";
+    let builtin = Regex::new("key: [0-9]").unwrap();
+    assert_eq!(spans(&builtin, haystack), [(0, 6), (9, 15), (16, 22)]);
+
+    // 候補の位置ごとに評価するバックトラック型の評価器では、
+    // 行頭だけを候補にすると、字下げした行のマッチは見つからない
+    let line_start = RegexBuilder::new("key: [0-9]")
+        .prefilter(LineStart)
+        .engine(Engine::Backtrack)
+        .build()
+        .unwrap();
+    assert_eq!(spans(&line_start, haystack), [(0, 6), (16, 22)]);
+
+    // 先頭のリテラルを探す前処理を与えても、結果は変わらない
+    let memmem = RegexBuilder::new("key: [0-9]")
+        .prefilter(Memmem::new("key").unwrap())
+        .build()
+        .unwrap();
+    assert_eq!(spans(&memmem, haystack), spans(&builtin, haystack));
+}
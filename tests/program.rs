@@ -0,0 +1,45 @@
+//! 命令列の一覧で、文字クラスが表のインデックスで参照され、アドレスが連番になることを確かめる
+use regexp::{Engine, RegexBuilder};
+
+#[test]
+fn classes_are_stored_in_a_table() {
+    let regex = RegexBuilder::new("[a-c]x[d-f]y[a-c]").build().unwrap();
+    assert_eq!(
+        regex.program(),
+        "0000: class #0 [a-c]\n\
+         0001: char x\n\
+         0002: class #1 [d-f]\n\
+         0003: char y\n\
+         0004: class #0 [a-c]\n\
+         0005: match\n"
+    );
+}
+
+#[test]
+fn counted_repeat_listing() {
+    let regex = RegexBuilder::new("(?:ab){2,100000}").build().unwrap();
+    assert_eq!(regex.engine(), Engine::Backtrack);
+    let program = regex.program();
+    assert!(program.contains("repeat #0, "), "{program}");
+    assert!(program.contains("{2,100000}"), "{program}");
+}
+
+/// 4 桁を超えるアドレスも、そのまま表示して評価できる
+#[test]
+fn large_program() {
+    let pattern = format!("{}z", "ab".repeat(6000));
+    let regex = RegexBuilder::new(&pattern)
+        .engine(Engine::PikeVm)
+        .build()
+        .unwrap();
+    let program = regex.program();
+    let lines: Vec<&str> = program.lines().collect();
+    assert_eq!(lines.len(), 12_002);
+    assert_eq!(lines[10_000], "10000: char a");
+    assert_eq!(lines[12_001], "12001: match");
+
+    let haystack = format!("x{pattern}");
+    let m = regex.find(&haystack).unwrap();
+    assert_eq!((m.start(), m.end()), (1, haystack.len()));
+    assert!(!regex.is_match(&haystack[..haystack.len() - 1]));
+}
@@ -0,0 +1,73 @@
+//! 回数を数える繰り返しの命令数の上限と、その命令列での検索を確かめる
+use regexp::{CodeGenError, Engine, Error, RegexBuilder};
+
+/// 展開すると命令数の上限を超えるが、回数を数えれば収まるパターン
+const LARGE: &str = "(a){0,1000000}b";
+
+#[test]
+fn counted_program_is_checked_instead_of_unrolled() {
+    let regex = RegexBuilder::new(LARGE).build().unwrap();
+    assert_eq!(regex.engine(), Engine::Backtrack);
+    let m = regex.find("xaaab").unwrap();
+    assert_eq!((m.start(), m.end()), (1, 5));
+    assert!(regex.program().contains("repeat"));
+}
+
+#[test]
+fn captures_and_start_of_counted_program() {
+    let regex = RegexBuilder::new(LARGE).build().unwrap();
+    let caps = regex.captures("xaaab").unwrap();
+    assert_eq!(caps.get(0).map(|m| (m.start(), m.end())), Some((1, 5)));
+    assert_eq!(caps.get(1).map(|m| (m.start(), m.end())), Some((3, 4)));
+    assert_eq!(regex.find_start("xaaab", 5), Some(1));
+}
+
+#[test]
+fn counted_captures_agree_with_unrolled() {
+    let cases = [
+        ("(a|ab)(c|bcd){0,40}(d*)", "abcdbcd"),
+        ("x((y+)?){0,35}z", "xyyz"),
+        ("((a)|(b)){33,40}", "abababababababababababababababababab"),
+    ];
+    for (pattern, haystack) in cases {
+        let counted = RegexBuilder::new(pattern)
+            .engine(Engine::Backtrack)
+            .build()
+            .unwrap();
+        let unrolled = RegexBuilder::new(pattern)
+            .engine(Engine::Backtrack)
+            .unroll_limit(u32::MAX)
+            .build()
+            .unwrap();
+        let groups = |regex: &regexp::Regex| {
+            regex.captures(haystack).map(|caps| {
+                (0..regex.captures_len())
+                    .map(|i| caps.get(i).map(|m| (m.start(), m.end())))
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert!(groups(&counted).is_some(), "{pattern}");
+        assert_eq!(groups(&counted), groups(&unrolled), "{pattern}");
+    }
+}
+
+#[test]
+fn ambiguous_counted_body_is_rejected_when_unrolling_is_too_big() {
+    let err = RegexBuilder::new("(a?){0,1000000}c").build().unwrap_err();
+    assert!(matches!(err, Error::CodeGen(CodeGenError::CompiledTooBig)));
+    let err = RegexBuilder::new(LARGE)
+        .engine(Engine::PikeVm)
+        .build()
+        .unwrap_err();
+    assert!(matches!(err, Error::CodeGen(CodeGenError::CompiledTooBig)));
+}
+
+#[test]
+fn counted_program_over_the_limit_is_rejected() {
+    let err = RegexBuilder::new("(abc){100,200}")
+        .unroll_limit(10)
+        .size_limit(4)
+        .build()
+        .unwrap_err();
+    assert!(matches!(err, Error::CodeGen(CodeGenError::CompiledTooBig)));
+}
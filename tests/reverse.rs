@@ -0,0 +1,39 @@
+//! 逆向きの命令列で求めたマッチの開始位置が、終了位置で終わる最も左のマッチの開始位置と一致することを確かめる
+//!
+//! 各終了位置について、その位置で終わるマッチの開始位置を regex クレートで総当たりに求めて比べる
+use regexp::Regex;
+
+const PATTERNS: &[&str] = &["a+b", "(ab|a)(bc)?", "[a-c]*c", "é+", "x?y*", "(a|b)*abb"];
+
+const HAYSTACK: &str = "aabbcabcéé xy abbabb";
+
+#[test]
+fn find_start_is_leftmost_start() {
+    for pattern in PATTERNS {
+        let regex = Regex::new(pattern).unwrap();
+        let anchored = regex::Regex::new(&format!("^(?:{pattern})$")).unwrap();
+        for end in (0..=HAYSTACK.len()).filter(|&i| HAYSTACK.is_char_boundary(i)) {
+            let expected = (0..=end)
+                .filter(|&start| HAYSTACK.is_char_boundary(start))
+                .find(|&start| anchored.is_match(&HAYSTACK[start..end]));
+            assert_eq!(
+                regex.find_start(HAYSTACK, end),
+                expected,
+                "{pattern:?} ending at {end}"
+            );
+        }
+    }
+}
+
+/// 位置の判定は、`end` の前後の文字を見て判定する
+#[test]
+fn find_start_with_looks() {
+    let regex = Regex::new(r"\bab+").unwrap();
+    assert_eq!(regex.find_start("xab ab", 3), None);
+    assert_eq!(regex.find_start("xab abb", 7), Some(4));
+    assert_eq!(regex.find_start("xab abb", 6), Some(4));
+
+    let regex = Regex::new("^a+").unwrap();
+    assert_eq!(regex.find_start("aaa", 2), Some(0));
+    assert_eq!(regex.find_start("baa", 3), None);
+}
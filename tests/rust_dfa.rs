@@ -0,0 +1,20 @@
+//! DFA から生成する Rust のソースが、モジュール名として使える名前にだけ生成されることを確かめる
+use regexp::{Regex, is_rust_ident};
+
+#[test]
+fn to_rust_dfa_rejects_invalid_names() {
+    let regex = Regex::new("[a-z]+[0-9]").unwrap();
+    for name in ["fn", "a-b", "1x", "_", "", "self", "mod"] {
+        assert!(!is_rust_ident(name), "{name}");
+        assert_eq!(regex.to_rust_dfa(name), None, "{name}");
+    }
+}
+
+#[test]
+fn to_rust_dfa_accepts_identifiers() {
+    let regex = Regex::new("[a-z]+[0-9]").unwrap();
+    for name in ["word", "_word", "word_2", "単語"] {
+        let source = regex.to_rust_dfa(name).unwrap();
+        assert!(source.contains(&format!("pub mod {name} {{")), "{name}");
+    }
+}
@@ -0,0 +1,65 @@
+//! 作業用のメモリの Cache を使い回しても、検索結果が変わらないことを確かめる
+//!
+//! 1つの Cache を、命令数や捕獲グループの数が異なる複数の Regex の検索に続けて渡す
+use regexp::{Cache, Engine, Regex, RegexBuilder};
+
+/// `cache` を使って、`try_find_at` でマッチを順に集める
+fn find_all(regex: &Regex, cache: &mut Cache, haystack: &str) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    while start <= haystack.len() {
+        let Some(m) = regex.try_find_at(cache, haystack, start).unwrap() else {
+            break;
+        };
+        found.push((m.start(), m.end()));
+        start = if m.end() > m.start() {
+            m.end()
+        } else {
+            m.end() + haystack[m.end()..].chars().next().map_or(1, char::len_utf8)
+        };
+    }
+    found
+}
+
+#[test]
+fn shared_cache() {
+    let haystack = "foo 123 bar 4567 日本 baz 89";
+    let mut cache = Cache::new();
+    for _ in 0..3 {
+        for (pattern, engine) in [
+            (r"[0-9]+", Engine::PikeVm),
+            (r"(\w+) (\d+)", Engine::Backtrack),
+            ("a|b|z", Engine::Dfa),
+            (r"\b\w", Engine::PikeVm),
+            ("(x?){40}[0-9]", Engine::Backtrack),
+        ] {
+            let regex = RegexBuilder::new(pattern).engine(engine).build().unwrap();
+            let expected: Vec<_> = regex
+                .find_iter(haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            assert_eq!(
+                find_all(&regex, &mut cache, haystack),
+                expected,
+                "{pattern:?}"
+            );
+        }
+    }
+}
+
+/// 統計情報は取り出すまで Cache に積み上がる
+#[test]
+fn stats_accumulate_in_cache() {
+    let regex = RegexBuilder::new("a+b")
+        .engine(Engine::PikeVm)
+        .build()
+        .unwrap();
+    let mut cache = Cache::new();
+    regex.try_find_at(&mut cache, "xaab", 0).unwrap();
+    let once = cache.take_stats().steps;
+    assert!(once > 0);
+    regex.try_find_at(&mut cache, "xaab", 0).unwrap();
+    regex.try_find_at(&mut cache, "xaab", 0).unwrap();
+    assert_eq!(cache.take_stats().steps, 2 * once);
+    assert_eq!(cache.take_stats().steps, 0);
+}
@@ -0,0 +1,70 @@
+//! `serde` フィーチャで AST を JSON に書き出し、読み戻せることを確かめる
+//!
+//! 読み戻した AST は元の AST と等しく、各ノードの範囲も保つ。
+//! 子ノードの参照やグループの番号、繰り返し回数、文字クラスの範囲が誤った入力は受け付けない
+#![cfg(feature = "serde")]
+use regexp::{AST, Flags, parse};
+
+#[test]
+fn round_trip() {
+    for pattern in [
+        "a",
+        "(a|[b-d])+",
+        r"\b{2,3}x*?",
+        r"(?m)^[^\d\s]$",
+        r"(?a)\w+(x(y))?",
+        "日本|語{3,}",
+    ] {
+        let ast = parse(pattern, Flags::default()).unwrap();
+        let json = serde_json::to_string(&ast).unwrap();
+        let read: AST = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, ast, "{pattern:?}: {json}");
+        assert_eq!(read.to_string(), ast.to_string(), "{pattern:?}");
+        assert_eq!(read.span(read.root()), ast.span(ast.root()), "{pattern:?}");
+        assert_eq!(serde_json::to_string(&read).unwrap(), json, "{pattern:?}");
+    }
+}
+
+/// `(a|[b-d])+` を書き出した JSON の `from` を `to` に置き換えて読む
+fn read_edited(from: &str, to: &str) -> Result<AST, serde_json::Error> {
+    let ast = parse("(a|[b-d])+", Flags::default()).unwrap();
+    let json = serde_json::to_string(&ast).unwrap();
+    assert!(json.contains(from), "{json}");
+    serde_json::from_str(&json.replacen(from, to, 1))
+}
+
+#[test]
+fn rejects_malformed_input() {
+    // 置き換えずに読めば受け付ける
+    assert!(read_edited(r#""root""#, r#""root""#).is_ok());
+
+    let cases = [
+        (r#""root":7"#, r#""root":8"#, "root is out of range"),
+        (r#"{"Seq":[0]}"#, r#"{"Seq":[1]}"#, "invalid node at 1"),
+        (r#"{"Plus":5}"#, r#"{"Plus":6}"#, "invalid node at 6"),
+        (r#""groups":1"#, r#""groups":0"#, "invalid node at 5"),
+        (
+            r#"{"Plus":5}"#,
+            r#"{"Repeat":[5,3,2]}"#,
+            "invalid node at 6",
+        ),
+        (
+            r#"[["b","d"]]"#,
+            r#"[["d","b"]]"#,
+            "range start is greater than end",
+        ),
+        (
+            r#",{"start":0,"end":10}"#,
+            "",
+            "the number of spans differs from nodes",
+        ),
+        (r#"{"Char":"a"}"#, r#"{"Dot":"a"}"#, "unknown variant"),
+    ];
+    for (from, to, message) in cases {
+        let error = match read_edited(from, to) {
+            Ok(ast) => panic!("{from} -> {to}: accepted {ast:?}"),
+            Err(e) => e.to_string(),
+        };
+        assert!(error.contains(message), "{from} -> {to}: {error}");
+    }
+}
@@ -0,0 +1,76 @@
+//! 複数のパターンをまとめた RegexSet の検索を、個々のパターンでの検索と比べる
+use regexp::{CodeGenError, Error, Regex, RegexSet, RegexSetBuilder};
+
+const PATTERNS: &[&str] = &[
+    "a+b",
+    "^b",
+    "c$",
+    r"\bfoo\b",
+    "(x|y){2,40}z",
+    r"\d{3}",
+    "é",
+    "z*",
+];
+
+const HAYSTACKS: &[&str] = &[
+    "",
+    "aab",
+    "bc",
+    "xfoo foo",
+    "xyxyz 123",
+    "café",
+    "b\nc\n",
+    "zzz",
+];
+
+#[test]
+fn matches_agree_with_each_pattern() {
+    let set = RegexSet::new(PATTERNS).unwrap();
+    let regexes: Vec<Regex> = PATTERNS.iter().map(|p| Regex::new(p).unwrap()).collect();
+    for haystack in HAYSTACKS {
+        let want: Vec<usize> = (0..regexes.len())
+            .filter(|&i| regexes[i].is_match(haystack))
+            .collect();
+        assert_eq!(set.matches(haystack), want, "{haystack:?}");
+        assert_eq!(set.is_match(haystack), !want.is_empty(), "{haystack:?}");
+    }
+}
+
+#[test]
+fn find_prefers_the_earlier_pattern() {
+    let set = RegexSet::new(["ab", "a"]).unwrap();
+    let m = set.find("xab").unwrap();
+    assert_eq!((m.start(), m.end()), (1, 3));
+    assert_eq!(set.patterns().collect::<Vec<_>>(), ["ab", "a"]);
+}
+
+#[test]
+fn options_apply_to_every_pattern() {
+    let mut builder = RegexSetBuilder::new([r"^\d$", r"\w"]);
+    builder.options().multi_line(true).ascii(true);
+    let set = builder.build().unwrap();
+    assert_eq!(set.matches("x\n٣\n"), vec![1]);
+    assert_eq!(set.matches("x\n3\n"), vec![0, 1]);
+}
+
+#[test]
+fn failing_pattern_reports_its_index() {
+    let err = RegexSet::new(["a", "(b"]).unwrap_err();
+    assert!(matches!(err, Error::Set(1, _)));
+}
+
+#[test]
+fn size_limit_applies_to_the_combined_program() {
+    let mut builder = RegexSetBuilder::new(["abcdef", "ghijkl"]);
+    builder.options().size_limit(10);
+    let err = builder.build().unwrap_err();
+    assert!(matches!(err, Error::CodeGen(CodeGenError::CompiledTooBig)));
+}
+
+#[test]
+fn empty_set_matches_nothing() {
+    let set = RegexSet::new(Vec::<&str>::new()).unwrap();
+    assert!(set.is_empty());
+    assert!(!set.is_match("abc"));
+    assert!(set.matches("abc").is_empty());
+}
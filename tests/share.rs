@@ -0,0 +1,44 @@
+//! Regex を複製し、複数のスレッドで同時に検索できることを確かめる
+use regexp::{Regex, RegexSet};
+use std::thread;
+
+fn assert_send_sync<T: Send + Sync + Clone>() {}
+
+#[test]
+fn regex_is_send_sync_clone() {
+    assert_send_sync::<Regex>();
+    assert_send_sync::<RegexSet>();
+}
+
+#[test]
+fn search_from_many_threads() {
+    let regex = Regex::new(r"(\w+)@(\w+).com").unwrap();
+    let haystack = "a@b.com, foo@example.com; x@y.org ".repeat(200);
+    let expected: Vec<_> = regex
+        .captures_iter(&haystack)
+        .map(|caps| caps.get(2).unwrap().as_str().to_string())
+        .collect();
+    assert_eq!(expected.len(), 400);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                // 複製と、元の Regex への参照の両方で検索する
+                let clone = regex.clone();
+                let (shared, haystack) = (&regex, &haystack);
+                scope.spawn(move || {
+                    let found: Vec<_> = clone
+                        .captures_iter(haystack)
+                        .map(|caps| caps.get(2).unwrap().as_str().to_string())
+                        .collect();
+                    (found, shared.find_iter(haystack).count())
+                })
+            })
+            .collect();
+        for handle in handles {
+            let (found, count) = handle.join().unwrap();
+            assert_eq!(found, expected);
+            assert_eq!(count, 400);
+        }
+    });
+}
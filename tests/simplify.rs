@@ -0,0 +1,43 @@
+//! 単純化で入れ子になった限量子がまとめられ、まとめられない構造は残ることを確かめる
+use regexp::{Engine, Flags, Regex, normalize};
+
+#[test]
+fn nested_quantifiers_are_merged() {
+    let cases = [
+        ("(?:a*)*", "a*"),
+        ("(?:a+)+", "a+"),
+        ("(?:a?)+", "a*"),
+        ("(?:a+)?", "a*"),
+        ("a{1}", "a"),
+        ("a{0,1}", "a?"),
+        ("(?:ab)(?:cd)", "abcd"),
+        ("a|b|a", "a|b"),
+    ];
+    for (pattern, expected) in cases {
+        assert_eq!(
+            normalize(pattern, Flags::default()).unwrap(),
+            expected,
+            "{pattern}"
+        );
+    }
+}
+
+#[test]
+fn merged_loop_does_not_check_progress() {
+    // まとめる前は、空文字列にマッチしうる本体の繰り返しとして位置が進んだかを確かめる
+    let program = Regex::new("(?:a*)*").unwrap().program();
+    assert!(!program.contains("progress"), "{program}");
+    assert!(!program.contains("again"), "{program}");
+}
+
+#[test]
+fn exponential_structures_survive_and_select_pike_vm() {
+    for pattern in ["(a{1,2})*$", "(a?){0,1000}c"] {
+        let regex = Regex::new(pattern).unwrap();
+        assert_eq!(regex.engine(), Engine::PikeVm, "{pattern}");
+    }
+    // 繰り返しの入れ子は1つの限量子にまとめられずに残る
+    let normalized = normalize("(?:a{1,2})*", Flags::default()).unwrap();
+    assert_eq!(normalized, "a{1,2}*");
+    assert_ne!(Regex::new(&normalized).unwrap().engine(), Engine::Backtrack);
+}
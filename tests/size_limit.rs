@@ -0,0 +1,46 @@
+//! 生成する命令数が上限を超えるパターンを、命令列を生成する前に [`CodeGenError::CompiledTooBig`] にすることを確かめる
+use regexp::{CodeGenError, Error, RegexBuilder};
+
+fn too_big(builder: &RegexBuilder) -> bool {
+    matches!(
+        builder.build(),
+        Err(Error::CodeGen(CodeGenError::CompiledTooBig))
+    )
+}
+
+#[test]
+fn limit_is_the_unoptimized_size() {
+    for pattern in ["abc", "a|b|c", "(?:a|b)*c", "x(?:ab|ac)+", "[a-z]{3}"] {
+        let (_, metrics) = RegexBuilder::new(pattern).build_with_metrics().unwrap();
+        // 上限は最適化する前の命令数に適用する
+        let size = metrics.insts + metrics.insts_saved;
+        let mut builder = RegexBuilder::new(pattern);
+        assert!(!too_big(builder.size_limit(size)), "{pattern:?}");
+        assert!(too_big(builder.size_limit(size - 1)), "{pattern:?}");
+    }
+
+    // 捕獲グループの位置を捕獲する命令列は、位置を記録する命令の分だけ長い
+    let (_, metrics) = RegexBuilder::new("(a|b)*c").build_with_metrics().unwrap();
+    let size = metrics.insts + metrics.insts_saved;
+    assert!(too_big(RegexBuilder::new("(a|b)*c").size_limit(size)));
+}
+
+#[test]
+fn unrolled_repetition_is_rejected_up_front() {
+    // 展開すると数百万の命令になる繰り返しも、命令列を生成せずにエラーにする
+    let mut builder = RegexBuilder::new("(?:abcdefgh){1000000}");
+    builder.unroll_limit(u32::MAX);
+    assert!(too_big(&builder));
+
+    // 回数を数える命令で表す場合は、上限を超えない
+    assert!(!too_big(&RegexBuilder::new("(?:abcdefgh){1000000}")));
+}
+
+#[test]
+fn large_alternation_is_rejected() {
+    let words: Vec<_> = (0..1000).map(|i| format!("w{i}")).collect();
+    let pattern = words.join("|");
+    let mut builder = RegexBuilder::new(&pattern);
+    assert!(!too_big(&builder));
+    assert!(too_big(builder.size_limit(1000)));
+}
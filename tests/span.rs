@@ -0,0 +1,44 @@
+//! 多バイト文字を含むパターンで、パースエラーの範囲がバイトオフセットと文字数の両方で正しいことを確かめる
+use regexp::{Error, Flags, ParserErrorKind, Regex, parse};
+
+/// パターン、エラーの種類、範囲の文字列、開始位置（文字数）
+const CASES: &[(&str, ParserErrorKind, &str, usize)] = &[
+    ("日本)", ParserErrorKind::InvalidRightParen, ")", 2),
+    (r"日本\q", ParserErrorKind::InvalidEscape('q'), r"\q", 2),
+    ("é(ab", ParserErrorKind::NoRightParen, "(ab", 1),
+    ("[日本", ParserErrorKind::NoRightBracket, "[日本", 0),
+    ("あ{2", ParserErrorKind::NoRightBrace, "{2", 1),
+    ("[本-日]", ParserErrorKind::InvalidRange, "本-日", 1),
+    ("日{3,1}", ParserErrorKind::InvalidRepeat, "{3,1}", 1),
+    ("日(?x)", ParserErrorKind::InvalidFlag, "(?x)", 1),
+    ("🎉|+日", ParserErrorKind::NoPrev, "+", 2),
+];
+
+#[test]
+fn spans_slice_the_pattern() {
+    for &(pattern, kind, text, char_start) in CASES {
+        let error = parse(pattern, Flags::default()).unwrap_err();
+        assert_eq!(error.kind(), kind, "{pattern:?}");
+        let span = error.span();
+        assert_eq!(
+            &pattern[span.byte_start..span.byte_end],
+            text,
+            "{pattern:?}"
+        );
+        assert_eq!(span.char_start, char_start, "{pattern:?}");
+        assert_eq!(
+            pattern[..span.byte_start].chars().count(),
+            span.char_start,
+            "{pattern:?}"
+        );
+    }
+}
+
+#[test]
+fn display_uses_char_position() {
+    // 表示する位置は文字数で数える
+    let Err(Error::Parse(error)) = Regex::new("日本)") else {
+        panic!("expected a parse error");
+    };
+    assert!(error.to_string().ends_with("pos = 2"), "{error}");
+}
@@ -0,0 +1,78 @@
+//! 検索中に集めた統計情報が、評価器ごとに数えるべき値を数えていることを確かめる
+use regexp::{Cache, Engine, EvalError, Regex, RegexBuilder};
+
+fn build(pattern: &str, engine: Engine) -> Regex {
+    RegexBuilder::new(pattern).engine(engine).build().unwrap()
+}
+
+#[test]
+fn backtracks_are_counted() {
+    let regex = build("(a|ab)(c|bcd)d", Engine::Backtrack);
+    let (m, stats) = regex.find_with_stats("abcd");
+    assert_eq!(m.unwrap().unwrap().as_str(), "abcd");
+    assert!(stats.steps > 0);
+    assert!(stats.backtracks > 0);
+    // バックトラック型の評価器はスレッドを持たない
+    assert_eq!(stats.peak_threads, 0);
+
+    // 最初の経路でマッチすれば、バックトラックしない
+    let (_, stats) = regex.find_with_stats("acd");
+    assert_eq!(stats.backtracks, 0);
+}
+
+#[test]
+fn peak_threads_are_counted() {
+    let regex = build("(?:a|b|c)*d", Engine::PikeVm);
+    let (m, stats) = regex.find_with_stats("abcabcd");
+    assert_eq!(m.unwrap().unwrap().as_str(), "abcabcd");
+    assert!(stats.steps > 0);
+    assert!(stats.peak_threads > 1);
+    assert_eq!(stats.backtracks, 0);
+
+    // 分岐のないパターンでは、スレッドは1つしか持たない
+    let regex = build("abc", Engine::PikeVm);
+    let (_, stats) = regex.find_with_stats("xxabc");
+    assert_eq!(stats.peak_threads, 1);
+}
+
+#[test]
+fn prefilter_hits_are_counted() {
+    // 前処理は候補の位置ごとに数える
+    let regex = build("foo!+", Engine::Backtrack);
+    let (m, stats) = regex.find_with_stats("foo fooa foo!!");
+    assert_eq!(m.unwrap().unwrap().as_str(), "foo!!");
+    assert_eq!(stats.prefilter_hits, 3);
+
+    let (m, stats) = regex.find_with_stats("no match here");
+    assert_eq!(m, Ok(None));
+    assert_eq!(stats.prefilter_hits, 0);
+    assert_eq!(stats.steps, 0);
+}
+
+#[test]
+fn stats_survive_step_limit() {
+    let regex = RegexBuilder::new("(a|aa)*b")
+        .engine(Engine::Backtrack)
+        .step_limit(1000)
+        .build()
+        .unwrap();
+    let haystack = "a".repeat(40);
+    let (m, stats) = regex.find_with_stats(&haystack);
+    assert_eq!(m, Err(EvalError::StepLimitExceeded));
+    assert!(stats.steps >= 1000);
+    assert!(stats.backtracks > 0);
+}
+
+#[test]
+fn cache_accumulates_until_taken() {
+    let regex = build("(a|ab)(c|bcd)d", Engine::Backtrack);
+    let mut cache = Cache::new();
+    regex.try_find_at(&mut cache, "abcd", 0).unwrap();
+    let once = cache.take_stats();
+    regex.try_find_at(&mut cache, "abcd", 0).unwrap();
+    regex.try_find_at(&mut cache, "abcd", 0).unwrap();
+    let twice = cache.take_stats();
+    assert_eq!(twice.steps, 2 * once.steps);
+    assert_eq!(twice.backtracks, 2 * once.backtracks);
+    assert_eq!(cache.take_stats().steps, 0);
+}
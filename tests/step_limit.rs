@@ -0,0 +1,65 @@
+//! 命令数の上限を超えた検索が、`try_` で始まるメソッドでエラーになることを確かめる
+use regexp::{Engine, EvalError, RegexBuilder};
+
+/// バックトラックの回数が指数的に増えるパターン
+const EXPONENTIAL: &str = "(a|aa)*b";
+
+#[test]
+fn step_limit_exceeded() {
+    let regex = RegexBuilder::new(EXPONENTIAL)
+        .engine(Engine::Backtrack)
+        .step_limit(10_000)
+        .build()
+        .unwrap();
+    let haystack = "a".repeat(40);
+    assert_eq!(regex.try_find(&haystack), Err(EvalError::StepLimitExceeded));
+    assert_eq!(
+        regex.try_is_match(&haystack),
+        Err(EvalError::StepLimitExceeded)
+    );
+    assert!(regex.try_captures(&haystack).is_err());
+
+    // 上限は検索ごとに適用されるため、短い入力では成功する
+    assert_eq!(regex.try_is_match("aab"), Ok(true));
+    assert_eq!(regex.try_is_match("aaa"), Ok(false));
+}
+
+#[test]
+fn find_panics_over_limit() {
+    let regex = RegexBuilder::new(EXPONENTIAL)
+        .engine(Engine::Backtrack)
+        .step_limit(10_000)
+        .build()
+        .unwrap();
+    let haystack = "a".repeat(40);
+    let result = std::panic::catch_unwind(|| regex.find(&haystack));
+    assert!(result.is_err());
+}
+
+/// 上限はすべての評価器に適用され、実行した命令数は統計情報で分かる
+#[test]
+fn step_limit_on_each_engine() {
+    let haystack = "ab".repeat(1000);
+    for engine in [Engine::Backtrack, Engine::PikeVm] {
+        let regex = RegexBuilder::new("(a|b)*c")
+            .engine(engine)
+            .step_limit(100)
+            .build()
+            .unwrap();
+        assert_eq!(
+            regex.try_find(&haystack),
+            Err(EvalError::StepLimitExceeded),
+            "{engine:?}"
+        );
+
+        let unlimited = RegexBuilder::new("(a|b)*c").engine(engine).build().unwrap();
+        let (m, stats) = unlimited.find_with_stats("abc");
+        assert!(m.unwrap().is_some());
+        let limited = RegexBuilder::new("(a|b)*c")
+            .engine(engine)
+            .step_limit(stats.steps)
+            .build()
+            .unwrap();
+        assert!(limited.try_find("abc").unwrap().is_some(), "{engine:?}");
+    }
+}
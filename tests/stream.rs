@@ -0,0 +1,88 @@
+//! チャンクに区切って与えても、区切り方によらず同じマッチが報告されることを確かめる
+use regexp::Regex;
+
+const HAYSTACK: &str = "foo 日本語 foofoo\nbar123 xfoo foo";
+
+/// `chunk_len` バイトずつ与えたときに報告されるマッチの (開始, 終了) の列
+fn stream(regex: &Regex, haystack: &[u8], chunk_len: usize) -> Vec<(u64, u64)> {
+    let mut state = regex.stream();
+    let mut found: Vec<_> = haystack
+        .chunks(chunk_len)
+        .flat_map(|chunk| state.push(chunk))
+        .collect();
+    found.extend(state.finish());
+    found.into_iter().map(|m| (m.start(), m.end())).collect()
+}
+
+#[test]
+fn literal_matches_find_iter() {
+    let regex = Regex::new("foo").unwrap();
+    let expected: Vec<_> = regex
+        .find_iter(HAYSTACK)
+        .map(|m| (m.start() as u64, m.end() as u64))
+        .collect();
+    assert_eq!(stream(&regex, HAYSTACK.as_bytes(), 1), expected);
+}
+
+/// 文字の途中や位置の判定の直前で区切っても結果は変わらない
+#[test]
+fn chunking_does_not_change_matches() {
+    for pattern in [
+        "foo", r"\bfoo\b", "日本", "[0-9]+", "o+$", "(?m)^bar", "本語 f",
+    ] {
+        let regex = Regex::new(pattern).unwrap();
+        let whole = stream(&regex, HAYSTACK.as_bytes(), HAYSTACK.len());
+        assert!(!whole.is_empty(), "{pattern}");
+        for chunk_len in 1..8 {
+            assert_eq!(
+                stream(&regex, HAYSTACK.as_bytes(), chunk_len),
+                whole,
+                "{pattern} in chunks of {chunk_len}"
+            );
+        }
+    }
+}
+
+/// 終了位置ごとに、最も左の開始位置と組にして報告する
+#[test]
+fn reports_each_end() {
+    let regex = Regex::new("[0-9]+").unwrap();
+    assert_eq!(stream(&regex, b"a123", 2), [(1, 2), (1, 3), (1, 4)]);
+}
+
+/// 末尾での位置の判定は finish で行う
+#[test]
+fn finish_checks_end_of_stream() {
+    let regex = Regex::new(r"foo\b").unwrap();
+    let mut state = regex.stream();
+    assert!(state.push(b"xx foo").is_empty());
+    let found: Vec<_> = state
+        .finish()
+        .iter()
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    assert_eq!(found, [(3, 6)]);
+}
+
+#[test]
+fn reset_starts_a_new_stream() {
+    let regex = Regex::new("ab").unwrap();
+    let mut state = regex.stream();
+    state.push(b"xa");
+    state.reset();
+    let found: Vec<_> = state
+        .push(b"b ab")
+        .iter()
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    assert_eq!(found, [(2, 4)]);
+}
+
+/// 不正なバイト列はどの文字にもマッチしない
+#[test]
+fn invalid_utf8() {
+    let regex = Regex::new("a[^x]b").unwrap();
+    for chunk_len in 1..4 {
+        assert_eq!(stream(&regex, b"a\xffb a\xc3\xa9b", chunk_len), [(4, 8)]);
+    }
+}
@@ -0,0 +1,44 @@
+//! よくある誤りに対するパースエラーの修正の提案を確かめる
+//!
+//! 提案を適用したパターンがパースできることも確かめる。
+//! 説明の文言は言語によるため、英語に設定して比べる
+use regexp::{Flags, Language, parse, set_language};
+
+/// パターン、提案の説明、提案を適用したパターン
+const CASES: &[(&str, &str, &str)] = &[
+    ("+a", r"escape it as `\+`", r"\+a"),
+    ("a)", r"escape it as `\)`", r"a\)"),
+    ("a{2,1}", r"escape it as `\{2,1\}`", r"a\{2,1\}"),
+    (r"\Aa", "use `^` for the start of the text", "^a"),
+    (r"a\z", "use `$` for the end of the text", "a$"),
+    (r"a\tb", "write a literal tab", "a\tb"),
+    (r"a\nb", "write a literal newline", "a\nb"),
+    (r"a\rb", "write a literal carriage return", "a\rb"),
+    (r"\-", "`-` needs no escape; remove the backslash", "-"),
+    ("[z-a]", "swap the bounds as `a-z`", "[a-z]"),
+    ("(ab", "close it with `)`", "(ab)"),
+    ("[ab", "close it with `]`", "[ab]"),
+    ("a{2", "close it with `}`", "a{2}"),
+];
+
+#[test]
+fn suggestions() {
+    set_language(Language::English);
+    for &(pattern, message, fixed) in CASES {
+        let error = parse(pattern, Flags::default()).unwrap_err();
+        let suggestion = error
+            .suggestion(pattern)
+            .unwrap_or_else(|| panic!("{pattern:?}: no suggestion for {error}"));
+        assert_eq!(suggestion.message, message, "{pattern:?}");
+        assert_eq!(suggestion.apply(pattern), fixed, "{pattern:?}");
+        assert!(parse(fixed, Flags::default()).is_ok(), "{fixed:?}");
+    }
+}
+
+#[test]
+fn no_suggestion() {
+    for pattern in ["(?x)a", r"\q"] {
+        let error = parse(pattern, Flags::default()).unwrap_err();
+        assert_eq!(error.suggestion(pattern), None, "{pattern:?}");
+    }
+}
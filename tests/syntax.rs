@@ -0,0 +1,107 @@
+//! ビルダーで無効にした構文と、上限を超える文字クラスを含むパターンがエラーになることを確かめる
+use regexp::{Error, Language, ParserError, ParserErrorKind, RegexBuilder, Syntax, set_language};
+
+/// 構文と、他の構文を使わずにその構文を使うパターン、エラーの範囲の文字列
+const CASES: &[(Syntax, &str, &str)] = &[
+    (Syntax::Alternation, "ab|cd", "ab|cd"),
+    (Syntax::Group, "a(b)c", "(b)"),
+    (Syntax::CountedRepetition, "ab{2,3}", "b{2,3}"),
+    (Syntax::NestedRepetition, "(?:a?b)?", "a?"),
+    (Syntax::UnboundedRepetition, "ab+", "b+"),
+    (Syntax::Look, r"a\b", r"\b"),
+];
+
+fn error(builder: &RegexBuilder) -> ParserError {
+    match builder.build() {
+        Err(Error::Parse(e)) => e,
+        other => panic!("expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn disabled_syntax_is_rejected() {
+    for &(syntax, pattern, text) in CASES {
+        let mut builder = RegexBuilder::new(pattern);
+        builder.disable(syntax);
+        let e = error(&builder);
+        assert_eq!(e.kind(), ParserErrorKind::Disabled(syntax), "{pattern:?}");
+        let span = e.span();
+        assert_eq!(
+            &pattern[span.byte_start..span.byte_end],
+            text,
+            "{pattern:?}"
+        );
+
+        // 他の構文を無効にしても影響しない
+        for &(other, _, _) in CASES {
+            if other != syntax {
+                let mut builder = RegexBuilder::new(pattern);
+                builder.disable(other);
+                assert!(builder.build().is_ok(), "{pattern:?} without {other:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn one_node_may_use_several_syntaxes() {
+    // {2,} は回数を指定した繰り返しで、上限のない繰り返しでもある
+    for syntax in [Syntax::CountedRepetition, Syntax::UnboundedRepetition] {
+        let e = error(RegexBuilder::new("ab{2,}").disable(syntax));
+        assert_eq!(e.kind(), ParserErrorKind::Disabled(syntax));
+    }
+}
+
+#[test]
+fn leftmost_violation_is_reported() {
+    let e = error(
+        RegexBuilder::new("a(b)|c*")
+            .disable(Syntax::Group)
+            .disable(Syntax::UnboundedRepetition),
+    );
+    assert_eq!(e.kind(), ParserErrorKind::Disabled(Syntax::Group));
+    assert_eq!(e.span().byte_start, 1);
+
+    // 非捕獲グループは捕獲グループとして数えない
+    assert!(
+        RegexBuilder::new("a(?:b)")
+            .disable(Syntax::Group)
+            .build()
+            .is_ok()
+    );
+}
+
+#[test]
+fn error_names_the_syntax() {
+    set_language(Language::English);
+    let e = error(RegexBuilder::new("a(b)").disable(Syntax::Group));
+    assert_eq!(
+        e.to_string(),
+        "ParseError: disabled syntax: pos = 1, syntax = capture group"
+    );
+}
+
+#[test]
+fn class_limit_counts_chars() {
+    let build = |pattern: &str, limit| {
+        RegexBuilder::new(pattern)
+            .class_limit(limit)
+            .build()
+            .map(|_| ())
+            .map_err(|e| match e {
+                Error::Parse(e) => e.kind(),
+                e => panic!("{e}"),
+            })
+    };
+    assert_eq!(build("[a-z]", 26), Ok(()));
+    assert_eq!(build("[a-z]", 25), Err(ParserErrorKind::ClassTooLarge));
+    assert_eq!(build("[a-cx]", 4), Ok(()));
+    // 否定や定義済みの文字クラスも、含む文字の数で数える
+    assert_eq!(build("[^a]", 1000), Err(ParserErrorKind::ClassTooLarge));
+    assert_eq!(build(r"\d", 10), Err(ParserErrorKind::ClassTooLarge));
+    assert_eq!(build(r"(?a)\d", 10), Ok(()));
+    // サロゲートの範囲は文字でないため数えない
+    assert_eq!(build("[\u{d7ff}-\u{e000}]", 2), Ok(()));
+    // 文字は文字クラスとして数えない
+    assert_eq!(build("abc", 0), Ok(()));
+}
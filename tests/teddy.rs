@@ -0,0 +1,51 @@
+//! 複数のリテラルを探す前処理を使った検索が、regex クレートと同じマッチを返すことを確かめる
+//!
+//! 16 バイトずつ調べる場合の境界と末尾の端数を通るよう、リテラルの位置をずらして試す。
+//! `simd` フィーチャを有効にして実行すると、SSSE3 で探す経路を確かめられる
+use regexp::Regex;
+
+const PATTERNS: &[&str] = &[
+    "foo|bar|baz",
+    "(ab|cd)e",
+    "x[yz]w",
+    "日本|中国|한국",
+    "(hello|world)!?",
+    "abc|ab|a9",
+];
+
+#[test]
+fn multi_literal_search() {
+    let needles = [
+        "foo", "bar", "baz", "cde", "xzw", "日本", "한국", "world!", "ab", "a9",
+    ];
+    for pattern in PATTERNS {
+        let regex = Regex::new(pattern).unwrap();
+        let expected = regex::Regex::new(pattern).unwrap();
+        for pad in 0..40 {
+            let mut haystack = ".".repeat(pad);
+            for (i, needle) in needles.iter().enumerate() {
+                haystack.push_str(needle);
+                haystack.push_str(&"-".repeat(i * 3 % 17));
+            }
+            let found: Vec<_> = regex
+                .find_iter(&haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            let want: Vec<_> = expected
+                .find_iter(&haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            assert_eq!(found, want, "{pattern:?} in {haystack:?}");
+        }
+    }
+}
+
+#[test]
+fn prefilter_skips_to_literals() {
+    let regex = Regex::new("(foo|bar)x+").unwrap();
+    let haystack = format!("{}barx", "-".repeat(1000));
+    let (m, stats) = regex.find_with_stats(&haystack);
+    let m = m.unwrap().unwrap();
+    assert_eq!((m.start(), m.end()), (1000, 1004));
+    assert_eq!(stats.prefilter_hits, 1);
+}
@@ -0,0 +1,93 @@
+//! パターンを種類ごとの字句に分け、その範囲がパターン全体を重ならずに覆うことを確かめる
+use regexp::{Token, TokenKind, tokenize};
+
+/// 字句の種類と範囲の文字列を並べる
+fn tokens(pattern: &str) -> Vec<(TokenKind, &str)> {
+    tokenize(pattern)
+        .into_iter()
+        .map(|t| (t.kind, &pattern[t.span.byte_start..t.span.byte_end]))
+        .collect()
+}
+
+/// 字句の範囲をつなげるとパターン全体になり、文字数で数えた開始位置も合っていることを確かめる
+fn check_cover(pattern: &str, tokens: &[Token]) {
+    let mut end = 0;
+    for token in tokens {
+        assert_eq!(token.span.byte_start, end, "{pattern:?}");
+        assert_eq!(
+            token.span.char_start,
+            pattern[..end].chars().count(),
+            "{pattern:?}"
+        );
+        end = token.span.byte_end;
+    }
+    assert_eq!(end, pattern.len(), "{pattern:?}");
+}
+
+#[test]
+fn classified_tokens() {
+    use TokenKind::*;
+    assert_eq!(
+        tokens(r"a(?:b|\d)+[x-z]{2}"),
+        [
+            (Literal, "a"),
+            (GroupOpen, "(?:"),
+            (Literal, "b"),
+            (Metachar, "|"),
+            (Escape, r"\d"),
+            (GroupClose, ")"),
+            (Quantifier, "+"),
+            (Class, "[x-z]"),
+            (Quantifier, "{2}"),
+        ]
+    );
+    assert_eq!(
+        tokens(r"(?m)^(?a:\w*)$"),
+        [
+            (Flags, "(?m)"),
+            (Metachar, "^"),
+            (GroupOpen, "(?a:"),
+            (Escape, r"\w"),
+            (Quantifier, "*"),
+            (GroupClose, ")"),
+            (Metachar, "$"),
+        ]
+    );
+    // 先頭の ] と、否定の直後の ] は文字クラスの中の文字
+    assert_eq!(tokens("[]a][^]b]"), [(Class, "[]a]"), (Class, "[^]b]")]);
+    assert_eq!(tokens(r"[\]]?"), [(Class, r"[\]]"), (Quantifier, "?")]);
+}
+
+#[test]
+fn multibyte_literals() {
+    let pattern = "日本[語国]+";
+    let found = tokenize(pattern);
+    check_cover(pattern, &found);
+    assert_eq!(
+        tokens(pattern),
+        [
+            (TokenKind::Literal, "日"),
+            (TokenKind::Literal, "本"),
+            (TokenKind::Class, "[語国]"),
+            (TokenKind::Quantifier, "+"),
+        ]
+    );
+}
+
+#[test]
+fn invalid_patterns_are_tokenized_to_the_end() {
+    // 閉じられていない文字クラスや繰り返し回数の指定は、末尾までを1つの字句とする
+    assert_eq!(
+        tokens("a[bc"),
+        [(TokenKind::Literal, "a"), (TokenKind::Class, "[bc")]
+    );
+    assert_eq!(
+        tokens("a{2,"),
+        [(TokenKind::Literal, "a"), (TokenKind::Quantifier, "{2,")]
+    );
+    for pattern in [
+        "a)", "(?", "(?x", "\\", "[", "a{", "**", "((", "[a-", "日\\",
+    ] {
+        check_cover(pattern, &tokenize(pattern));
+    }
+}
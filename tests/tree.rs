@@ -0,0 +1,42 @@
+//! AST を木の形で出力した文字列が、選択肢の数に比例する長さに収まることを確かめる
+use regexp::{Flags, parse};
+
+#[test]
+fn tree_layout() {
+    let ast = parse("a(b|c)*", Flags::default()).unwrap();
+    assert_eq!(
+        ast.tree(),
+        "\
+Seq
+├─ Char 'a'
+└─ Star
+   └─ Group #1
+      └─ Or
+         ├─ Seq
+         │  └─ Char 'b'
+         └─ Seq
+            └─ Char 'c'
+"
+    );
+}
+
+/// 連なった選択肢は同じ深さに並べる
+#[test]
+fn alternation_is_flat() {
+    let ast = parse("a|b|c", Flags::default()).unwrap();
+    assert_eq!(
+        ast.tree(),
+        "Or\n├─ Seq\n│  └─ Char 'a'\n├─ Seq\n│  └─ Char 'b'\n└─ Seq\n   └─ Char 'c'\n"
+    );
+}
+
+/// 選択肢が増えても、1つの選択肢あたりの出力の長さは変わらない
+#[test]
+fn wide_alternation_is_linear() {
+    let n = 20_000;
+    let pattern = vec!["ab"; n].join("|");
+    let tree = parse(&pattern, Flags::default()).unwrap().tree();
+    let per_alternative = "├─ Seq\n│  ├─ Char 'a'\n│  └─ Char 'b'\n".len();
+    assert!(tree.len() <= n * per_alternative + 16, "{}", tree.len());
+    assert_eq!(tree.lines().count(), 1 + 3 * n);
+}
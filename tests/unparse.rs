@@ -0,0 +1,100 @@
+//! AST を書き戻したパターンをパースし直すと、同じ構造の AST になることを確かめる
+use regexp::{AST, Flags, parse};
+
+/// 書き戻す際にエスケープやフラグが必要になるパターン
+const CASES: &[&str] = &[
+    "[]a]",
+    "[a-]",
+    "[-a]",
+    "[a^]",
+    "[^]a]",
+    r"[\\\[]",
+    "(?m)^a$",
+    r"[\d]",
+    r"[^\d]",
+    r"(?a)\d",
+    r"(?a)\w+",
+    r"(?a)\bx\B",
+    r"\s\S\w\W\d\D",
+    r"\(\)\|\+\*\?\{\}\^\$",
+    "(a|)|(?:b|c)*",
+    "(?:ab)+",
+    "a{0,}b{2}c{1,3}",
+    "(a(b(c)))",
+    "(?:)",
+    "[^\0-\u{10ffff}]",
+];
+
+fn ast(pattern: &str) -> AST {
+    parse(pattern, Flags::default()).unwrap()
+}
+
+/// 書き戻したパターンをパースし直した AST が元と等しいことを確かめる
+fn assert_round_trip(ast: &AST, pattern: &str) {
+    let printed = ast.to_string();
+    let reparsed =
+        parse(&printed, Flags::default()).unwrap_or_else(|e| panic!("{pattern} -> {printed}: {e}"));
+    assert_eq!(&reparsed, ast, "{pattern} -> {printed}");
+}
+
+#[test]
+fn round_trip() {
+    for pattern in CASES {
+        assert_round_trip(&ast(pattern), pattern);
+    }
+}
+
+/// 複数行モードや ASCII モードでパースした AST も、既定のフラグでパースし直せる
+#[test]
+fn round_trip_with_flags() {
+    let flags = Flags {
+        multi_line: true,
+        ascii: true,
+    };
+    for pattern in ["^a$", r"\d\w\b", r"[\s\d]"] {
+        let ast = parse(pattern, flags).unwrap();
+        assert_round_trip(&ast, pattern);
+    }
+}
+
+/// xorshift による疑似乱数
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// 深さ `depth` までのパターンを生成する
+fn pattern(rng: &mut Rng, depth: u32) -> String {
+    const ATOMS: &[&str] = &[
+        "a", "]", "-", "^", "$", "[]a]", "[a-]", "[^b]", r"\d", r"\W", r"\b", r"(?a)\d", r"(?m)^",
+        r"[\d-]", r"\^", "é",
+    ];
+    if depth == 0 {
+        return ATOMS[rng.below(ATOMS.len())].to_string();
+    }
+    let a = pattern(rng, depth - 1);
+    match rng.below(7) {
+        0 => format!("{a}{}", pattern(rng, depth - 1)),
+        1 => format!("{a}|{}", pattern(rng, depth - 1)),
+        2 => format!("({a})*"),
+        3 => format!("(?:{a})+"),
+        4 => format!("(?:{a}){{1,3}}"),
+        5 => format!("({a})?"),
+        _ => a,
+    }
+}
+
+#[test]
+fn round_trip_random() {
+    let mut rng = Rng(88172645463325252);
+    for _ in 0..2000 {
+        let pattern = pattern(&mut rng, 4);
+        assert_round_trip(&ast(&pattern), &pattern);
+    }
+}
@@ -0,0 +1,65 @@
+//! DFA が UTF-8 のバイト列の範囲に変換した文字クラスで、文字を復号する評価器と同じマッチを返すことを確かめる
+//!
+//! 符号化したバイト数が変わる境界とサロゲートの前後を、範囲の端と検索対象の文字に含める
+use regexp::{Engine, RegexBuilder};
+
+/// 符号化したバイト数が変わる境界と、サロゲートの前後の文字
+const EDGES: &[u32] = &[
+    0x0, 0x7f, 0x80, 0x7ff, 0x800, 0xd7ff, 0xe000, 0xfffd, 0xffff, 0x10000, 0x10ffff,
+];
+
+fn spans(pattern: &str, engine: Engine, haystack: &str) -> Vec<(usize, usize)> {
+    let regex = RegexBuilder::new(pattern).engine(engine).build().unwrap();
+    assert_eq!(regex.engine(), engine, "{pattern:?}");
+    regex
+        .find_iter(haystack)
+        .map(|m| (m.start(), m.end()))
+        .collect()
+}
+
+#[test]
+fn byte_ranges_across_encoding_lengths() {
+    let mut chars: Vec<char> = EDGES
+        .iter()
+        .flat_map(|&c| [c.saturating_sub(1), c, c + 1])
+        .filter_map(char::from_u32)
+        .collect();
+    chars.sort();
+    chars.dedup();
+    let haystack: String = chars.iter().flat_map(|&c| [c, 'z']).collect();
+    for (i, &lo) in chars.iter().enumerate() {
+        for &hi in &chars[i..] {
+            for pattern in [format!("[{lo}-{hi}]"), format!("[^{lo}-{hi}]+")] {
+                // 文字クラスの中で特殊な意味を持つ文字は、範囲の端にしない
+                if "-]^\\".contains([lo, hi]) {
+                    continue;
+                }
+                assert_eq!(
+                    spans(&pattern, Engine::Dfa, &haystack),
+                    spans(&pattern, Engine::PikeVm, &haystack),
+                    "{pattern:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn multibyte_literals_and_classes() {
+    let haystack = "aé日本🎉xü𝄞 日本語";
+    for pattern in [
+        "日本",
+        "é|ü",
+        "[日-本]+",
+        r"\w+",
+        r"[🎉𝄞]",
+        r"\S+",
+        "[^a-z ]",
+    ] {
+        assert_eq!(
+            spans(pattern, Engine::Dfa, haystack),
+            spans(pattern, Engine::PikeVm, haystack),
+            "{pattern:?}"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! 命令列を生成せずにパターンを検査し、すべてのエラーか警告を返すことを確かめる
+use regexp::{ParserErrorKind, Regex, RegexBuilder, Syntax};
+
+#[test]
+fn valid_patterns_return_warnings() {
+    for pattern in ["abc", "a|b|a", "(a*)*", "a$b", "[0-9]+"] {
+        let warnings = RegexBuilder::new(pattern).validate().unwrap();
+        assert_eq!(
+            warnings,
+            Regex::new(pattern).unwrap().warnings(),
+            "{pattern:?}"
+        );
+    }
+}
+
+#[test]
+fn invalid_patterns_return_all_errors() {
+    let errors = RegexBuilder::new(r"(a\y)|+b{3,1}").validate().unwrap_err();
+    let kinds: Vec<_> = errors.0.iter().map(|e| e.kind()).collect();
+    assert_eq!(
+        kinds,
+        [
+            ParserErrorKind::InvalidEscape('y'),
+            ParserErrorKind::NoPrev,
+            ParserErrorKind::InvalidRepeat,
+        ]
+    );
+}
+
+#[test]
+fn builder_settings_apply() {
+    let errors = RegexBuilder::new("((a))")
+        .nest_limit(1)
+        .validate()
+        .unwrap_err();
+    assert_eq!(errors.0[0].kind(), ParserErrorKind::NestingTooDeep);
+
+    let errors = RegexBuilder::new("a|b")
+        .disable(Syntax::Alternation)
+        .validate()
+        .unwrap_err();
+    assert_eq!(
+        errors.0[0].kind(),
+        ParserErrorKind::Disabled(Syntax::Alternation)
+    );
+
+    let errors = RegexBuilder::new("[a-z]")
+        .class_limit(3)
+        .validate()
+        .unwrap_err();
+    assert_eq!(errors.0[0].kind(), ParserErrorKind::ClassTooLarge);
+
+    // 寛容なモードでは、誤ったエスケープシーケンスもエラーにしない
+    assert!(RegexBuilder::new(r"\q").validate().is_err());
+    assert!(RegexBuilder::new(r"\q").lenient(true).validate().is_ok());
+}
+
+#[test]
+fn size_limit_is_not_checked() {
+    // 命令数の上限は命令列を生成する際に確かめる
+    let mut builder = RegexBuilder::new("abcdef");
+    builder.size_limit(2);
+    assert!(builder.validate().is_ok());
+    assert!(builder.build().is_err());
+}
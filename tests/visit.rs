@@ -0,0 +1,113 @@
+//! 既定の実装で子ノードをたどる Visitor と Fold を、関心のあるノードの処理だけで実装できることを確かめる
+use regexp::{AST, AstNode, Flags, Fold, NodeId, Visitor, fold_children, parse, walk};
+use std::ops::Range;
+
+fn ast(pattern: &str) -> AST {
+    parse(pattern, Flags::default()).unwrap()
+}
+
+/// 文字のノードを左から順に集める
+#[derive(Default)]
+struct Chars(String);
+
+impl Visitor for Chars {
+    fn visit(&mut self, ast: &AST, id: NodeId) {
+        if let AstNode::Char(c) = ast[id] {
+            self.0.push(c);
+        }
+        walk(self, ast, id);
+    }
+}
+
+/// 捕獲グループの中に入らずに、グループの数を数える
+#[derive(Default)]
+struct OuterGroups(usize);
+
+impl Visitor for OuterGroups {
+    fn visit(&mut self, ast: &AST, id: NodeId) {
+        match ast[id] {
+            AstNode::Group(..) => self.0 += 1,
+            _ => walk(self, ast, id),
+        }
+    }
+}
+
+#[test]
+fn visitor_walks_children_in_order() {
+    let mut chars = Chars::default();
+    let ast = ast("a(b|c(d))*e{2}|f");
+    chars.visit(&ast, ast.root());
+    assert_eq!(chars.0, "abcdef");
+
+    let mut groups = OuterGroups::default();
+    groups.visit(&ast, ast.root());
+    assert_eq!(groups.0, 1);
+}
+
+#[test]
+fn visitor_does_not_recurse_into_long_alternations() {
+    // 選択肢の連なりは再帰せずにたどる
+    let pattern = vec!["x"; 100_000].join("|");
+    let ast = ast(&pattern);
+    let mut chars = Chars::default();
+    chars.visit(&ast, ast.root());
+    assert_eq!(chars.0.len(), 100_000);
+}
+
+/// 文字を大文字にし、それ以外のノードはそのまま組み立てる
+struct Upper;
+
+impl Fold for Upper {
+    fn build(&mut self, out: &mut AST, node: AstNode, span: Range<usize>) -> NodeId {
+        let node = match node {
+            AstNode::Char(c) => AstNode::Char(c.to_ascii_uppercase()),
+            node => node,
+        };
+        out.push_span(node, span)
+    }
+}
+
+/// 1回以上の繰り返しを、1回と0回以上の繰り返しの連接に書き換える
+struct ExpandPlus;
+
+impl Fold for ExpandPlus {
+    fn fold(&mut self, ast: &AST, id: NodeId, out: &mut AST) -> NodeId {
+        let AstNode::Plus(e) = ast[id] else {
+            return fold_children(self, ast, id, out);
+        };
+        let once = self.fold(ast, e, out);
+        let again = self.fold(ast, e, out);
+        let star = out.push_span(AstNode::Star(again), ast.span(id));
+        out.push_span(AstNode::Seq(vec![once, star]), ast.span(id))
+    }
+}
+
+/// `folder` で `pattern` の AST を書き換える
+fn fold(folder: &mut impl Fold, pattern: &str) -> AST {
+    let ast = ast(pattern);
+    let mut out = AST::new();
+    let root = folder.fold(&ast, ast.root(), &mut out);
+    out.set_root(root);
+    out.set_groups(ast.groups());
+    out
+}
+
+#[test]
+fn fold_rebuilds_the_tree() {
+    assert_eq!(fold(&mut Upper, "a(b|c)*d"), ast("A(B|C)*D"));
+    assert_eq!(
+        fold(&mut ExpandPlus, "a+(?:bc)+").to_string(),
+        "(?:aa*)(?:(?:bc)(?:bc)*)"
+    );
+
+    // 書き換えたノードにも元のノードの範囲が残る
+    let out = fold(&mut Upper, "xy");
+    assert_eq!(out.span(out.root()), 0..2);
+}
+
+#[test]
+fn fold_does_not_recurse_into_long_alternations() {
+    let pattern = vec!["x"; 100_000].join("|");
+    let expected = vec!["X"; 100_000].join("|");
+    assert_eq!(fold(&mut Upper, &pattern), ast(&expected));
+}